@@ -0,0 +1,269 @@
+//! Fragmentation and space-usage reports.
+//!
+//! Everything here is computed by walking the FAT and the directory tree; per
+//! file results are handed to a callback as soon as they are known instead of
+//! being collected, so a report over a large volume doesn't hold an entry per
+//! file. Directories are read one sector at a time, and the walk only keeps
+//! the directories it has yet to visit, so memory grows with the number of
+//! subdirectories along the current path and their siblings, not with the
+//! number of files.
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+use crate::api::{Directory, DirectoryEntry, Metadata};
+use crate::{PathBuf, Result, VfatFS, VfatMetadataTrait, fat_table};
+
+/// Number of buckets in [`FreeSpaceStats::histogram`].
+pub const FREE_RUN_BUCKETS: usize = 32;
+
+/// Allocation details of a single file or directory.
+#[derive(Debug, Clone)]
+pub struct FileUsage {
+    /// Full path of the entry.
+    pub path: PathBuf,
+    /// `true` for directories.
+    pub is_dir: bool,
+    /// Size recorded in the directory entry, in bytes (always 0 for directories).
+    pub size: u64,
+    /// Number of clusters in the entry's chain.
+    pub clusters: u32,
+    /// Number of runs of physically consecutive clusters. A contiguous entry
+    /// has one fragment, an empty file has none.
+    pub fragments: u32,
+    /// Bytes allocated past the end of the data, lost to cluster rounding.
+    /// Always 0 for directories.
+    pub slack_bytes: u64,
+}
+
+/// Layout of the free space of a volume.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FreeSpaceStats {
+    /// Number of free clusters.
+    pub free_clusters: u32,
+    /// Number of maximal runs of consecutive free clusters.
+    pub free_runs: u32,
+    /// Length, in clusters, of the largest free run.
+    pub largest_free_run: u32,
+    /// Free runs bucketed by length: bucket `i` counts runs whose length is in
+    /// `2^i..2^(i+1)` clusters.
+    pub histogram: [u32; FREE_RUN_BUCKETS],
+}
+
+/// Volume-wide totals returned by [`VfatFS::analyze`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VolumeUsage {
+    /// Number of regular files.
+    pub files: u32,
+    /// Number of directories, including the root.
+    pub directories: u32,
+    /// Number of files and directories made of more than one fragment.
+    pub fragmented_entries: u32,
+    /// Sum of the fragments of every file and directory.
+    pub fragments: u64,
+    /// Clusters owned by files and directories.
+    pub used_clusters: u64,
+    /// Sum of [`FileUsage::slack_bytes`] over all files.
+    pub slack_bytes: u64,
+    /// Free space layout.
+    pub free: FreeSpaceStats,
+}
+
+/// Recursive usage of a directory, as computed by [`VfatFS::disk_usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// Number of regular files below the directory.
+    pub files: u32,
+    /// Number of directories below the directory, not counting itself.
+    pub directories: u32,
+    /// Sum of the file sizes, in bytes.
+    pub apparent_bytes: u64,
+    /// Bytes of clusters owned by the files and directories, including the
+    /// directory itself.
+    pub allocated_bytes: u64,
+}
+
+impl DiskUsage {
+    fn add(&mut self, other: &DiskUsage) {
+        self.files += other.files;
+        self.directories += other.directories;
+        self.apparent_bytes += other.apparent_bytes;
+        self.allocated_bytes += other.allocated_bytes;
+    }
+}
+
+impl VfatFS {
+    /// Scan the FAT and report how the free space is laid out.
+    pub fn free_space_stats(&self) -> Result<FreeSpaceStats> {
+        let _guard = self.fs_lock.read();
+        self.free_space_stats_unlocked()
+    }
+
+    fn free_space_stats_unlocked(&self) -> Result<FreeSpaceStats> {
+        let mut stats = FreeSpaceStats::default();
+        self.for_each_free_run(|_, len| {
+            stats.free_clusters += len;
+            stats.free_runs += 1;
+            stats.largest_free_run = stats.largest_free_run.max(len);
+            stats.histogram[len.ilog2() as usize] += 1;
+            ControlFlow::Continue(())
+        })?;
+        Ok(stats)
+    }
+
+    /// Walk the whole volume and report its fragmentation and space usage.
+    ///
+    /// `visit` is called once for every file and directory (the root included)
+    /// with its own allocation details; the returned value holds the totals.
+    /// The volume stays locked during the walk, so the totals are consistent.
+    pub fn analyze<F>(&mut self, mut visit: F) -> Result<VolumeUsage>
+    where
+        F: FnMut(&FileUsage),
+    {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+
+        let mut totals = VolumeUsage::default();
+        let root = self.get_root_unlocked()?;
+        let mut pending: Vec<Directory> = vec![root];
+        let mut record = |usage: &FileUsage, totals: &mut VolumeUsage| {
+            if usage.is_dir {
+                totals.directories += 1;
+            } else {
+                totals.files += 1;
+            }
+            if usage.fragments > 1 {
+                totals.fragmented_entries += 1;
+            }
+            totals.fragments += usage.fragments as u64;
+            totals.used_clusters += usage.clusters as u64;
+            totals.slack_bytes += usage.slack_bytes;
+            visit(usage);
+        };
+
+        let root_usage = self.file_usage(&pending[0].metadata, true)?;
+        record(&root_usage, &mut totals);
+        while let Some(directory) = pending.pop() {
+            for entry in directory.iter_unlocked() {
+                let entry = entry?;
+                if !is_real_entry(&entry) {
+                    continue;
+                }
+                let usage = self.file_usage(&entry.metadata, entry.is_dir())?;
+                record(&usage, &mut totals);
+                if entry.is_dir() {
+                    pending.push(entry.into_directory_unchecked());
+                }
+            }
+        }
+        totals.free = self.free_space_stats_unlocked()?;
+        Ok(totals)
+    }
+
+    /// Recursive space usage of the directory at `path`, like `du`.
+    ///
+    /// `visit` is called for every directory below `path` (and for `path`
+    /// itself, last) as soon as the totals of its subtree are known.
+    /// The volume stays locked during the walk.
+    pub fn disk_usage<F>(&mut self, path: PathBuf, mut visit: F) -> Result<DiskUsage>
+    where
+        F: FnMut(&PathBuf, &DiskUsage),
+    {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+
+        let root = self
+            .get_from_absolute_path_unlocked(path)?
+            .into_directory_or_not_found()?;
+
+        // Each frame holds a directory, the subdirectories still to descend
+        // into and the totals gathered so far.
+        struct Frame {
+            directory: Directory,
+            subdirectories: Vec<DirectoryEntry>,
+            usage: DiskUsage,
+        }
+        let open = |fs: &Self, directory: Directory| -> Result<Frame> {
+            let mut usage = DiskUsage {
+                allocated_bytes: fs.allocated_bytes(&directory.metadata)?,
+                ..Default::default()
+            };
+            let mut subdirectories = Vec::new();
+            for entry in directory.iter_unlocked() {
+                let entry = entry?;
+                if !is_real_entry(&entry) {
+                    continue;
+                }
+                if entry.is_dir() {
+                    usage.directories += 1;
+                    subdirectories.push(entry);
+                } else {
+                    usage.files += 1;
+                    usage.apparent_bytes += entry.metadata.size() as u64;
+                    usage.allocated_bytes += fs.allocated_bytes(&entry.metadata)?;
+                }
+            }
+            Ok(Frame {
+                directory,
+                subdirectories,
+                usage,
+            })
+        };
+
+        let mut stack = vec![open(self, root)?];
+        loop {
+            let top = stack.last_mut().expect("stack is never empty here");
+            if let Some(child) = top.subdirectories.pop() {
+                let frame = open(self, child.into_directory_unchecked())?;
+                stack.push(frame);
+                continue;
+            }
+            let done = stack.pop().expect("stack is never empty here");
+            visit(done.directory.metadata.full_path(), &done.usage);
+            match stack.last_mut() {
+                Some(parent) => parent.usage.add(&done.usage),
+                None => return Ok(done.usage),
+            }
+        }
+    }
+
+    fn file_usage(&self, metadata: &Metadata, is_dir: bool) -> Result<FileUsage> {
+        let mut clusters = 0;
+        let mut fragments = 0;
+        if !metadata.has_no_cluster_allocated() {
+            fat_table::for_each_run(metadata.cluster, self.device.clone(), |_, len| {
+                clusters += len;
+                fragments += 1;
+            })?;
+        }
+        let size = if is_dir { 0 } else { metadata.size() as u64 };
+        let allocated = clusters as u64 * self.bytes_per_cluster() as u64;
+        Ok(FileUsage {
+            path: metadata.full_path().clone(),
+            is_dir,
+            size,
+            clusters,
+            fragments,
+            slack_bytes: if is_dir {
+                0
+            } else {
+                allocated.saturating_sub(size)
+            },
+        })
+    }
+
+    fn allocated_bytes(&self, metadata: &Metadata) -> Result<u64> {
+        let mut clusters = 0u64;
+        if !metadata.has_no_cluster_allocated() {
+            fat_table::for_each_run(metadata.cluster, self.device.clone(), |_, len| {
+                clusters += len as u64;
+            })?;
+        }
+        Ok(clusters * self.bytes_per_cluster() as u64)
+    }
+}
+
+/// Skip the `.`/`..` pseudo entries and the volume label.
+pub(crate) fn is_real_entry(entry: &DirectoryEntry) -> bool {
+    !(entry.metadata.attributes.is_volume_id() || entry.name() == "." || entry.name() == "..")
+}
@@ -0,0 +1,50 @@
+//! Case folding of entry names.
+//!
+//! FAT compares names case-insensitively: Windows uppercases both names with a
+//! one-to-one table before comparing them. Characters are folded the same way
+//! here, with their simple uppercase mapping. Characters whose uppercase form is
+//! longer than one character (like `ß`) are left alone, as Windows does.
+use alloc::string::String;
+
+fn fold_char(ch: char) -> char {
+    let mut upper = ch.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(folded), None) => folded,
+        _ => ch,
+    }
+}
+
+/// Fold `name` for use as a lookup key.
+pub(crate) fn fold(name: &str) -> String {
+    name.chars().map(fold_char).collect()
+}
+
+/// Returns `true` if `a` and `b` name the same entry.
+pub(crate) fn names_match(a: &str, b: &str, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        return a == b;
+    }
+    a.chars().map(fold_char).eq(b.chars().map(fold_char))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_names_match_ignoring_case() {
+        assert!(names_match("readme.txt", "README.TXT", false));
+        assert!(names_match("Foo", "fOO", false));
+        assert!(!names_match("foo", "foo2", false));
+        assert!(!names_match("Foo", "foo", true));
+        assert!(names_match("Foo", "Foo", true));
+    }
+
+    #[test]
+    fn test_unicode_names_match_ignoring_case() {
+        assert!(names_match("Ärger.txt", "äRGER.TXT", false));
+        assert!(names_match("σοφία", "ΣΟΦΊΑ", false));
+        assert_eq!(fold("straße"), "STRAßE");
+        assert!(!names_match("straße", "STRASSE", false));
+    }
+}
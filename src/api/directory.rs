@@ -3,17 +3,22 @@ use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::mem;
 
-use log::{debug, info};
+use log::{debug, error, info};
 use snafu::ensure;
 
+use crate::api::case_folding;
+use crate::api::directory_iter::RawEntries;
 use crate::api::raw_directory_entry::EntryId::Deleted;
 use crate::api::raw_directory_entry::{
-    Attributes, LongFileNameEntry, RegularDirectoryEntry, UnknownDirectoryEntry,
-    VfatDirectoryEntry, unknown_entry_convert_to_bytes_2,
+    Attributes, LFN_CHARACTERS, LongFileNameEntry, RegularDirectoryEntry, UnknownDirectoryEntry,
+    VfatDirectoryEntry, attribute, unknown_entry_convert_to_bytes_2,
 };
-use crate::api::{DirectoryEntry, File, Metadata, VfatMetadataTrait};
+use crate::api::{AllocateMode, DirectoryEntry, DirectoryIter, File, Metadata, VfatMetadataTrait};
 use crate::cluster::cluster_reader::ClusterChainReader;
-use crate::{ClusterId, VfatFS};
+use crate::dentry_cache::EntryLocation;
+use crate::locks::SharedLock;
+use crate::open_files::EntrySlot;
+use crate::{ClusterId, VfatFS, VfatTimestamp, WipePattern};
 use crate::{PathBuf, error};
 
 use crate::SECTOR_SIZE;
@@ -73,39 +78,108 @@ impl Directory {
     /// Returns true if an entry called "name" is contained in this directory
     ///
     pub fn contains(&self, name: &str) -> error::Result<bool> {
-        let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.read();
+        let _guard = self.vfat_filesystem.fs_lock.read();
+        let lock = self.lock();
+        let _dir_guard = lock.read();
         self.contains_unlocked(name)
     }
 
     pub(crate) fn contains_unlocked(&self, name: &str) -> error::Result<bool> {
-        for entry in self.contents_unlocked()? {
-            if entry.name() == name {
-                return Ok(true);
-            }
-        }
-        Ok(false)
+        Ok(self.find_unlocked(name)?.is_some())
+    }
+
+    /// Find the entry called `name`, stopping at the first match.
+    pub(crate) fn find_unlocked(&self, name: &str) -> error::Result<Option<DirectoryEntry>> {
+        Ok(self.locate(name)?.map(|location| {
+            self.entry_from_regular(location.name, &location.regular, location.index)
+        }))
     }
+
+    /// Open the entry at `path`, relative to this directory unless it's
+    /// absolute, like `openat`. `.` and `..` are resolved lexically: `..`
+    /// from the root stays at the root.
+    pub fn open<P: Into<crate::PathBuf>>(&self, path: P) -> error::Result<DirectoryEntry> {
+        let path = self.metadata.full_path().join(path.into());
+        self.vfat_filesystem.clone().get_from_absolute_path(path)
+    }
+
+    /// Open the file at `path`, resolved like in [`Directory::open`], with
+    /// `options`. See [`VfatFS::open_with`](crate::VfatFS::open_with).
+    pub fn open_with<P: Into<crate::PathBuf>>(
+        &self,
+        path: P,
+        options: &crate::OpenOptions,
+    ) -> error::Result<File> {
+        let path = self.metadata.full_path().join(path.into());
+        self.vfat_filesystem.clone().open_with(path, options)
+    }
+
     /// Create a new file in this directory
     ///
     pub fn create_file(&mut self, name: String) -> error::Result<File> {
         let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.write();
+        let _guard = lock.read();
+        let dir_lock = self.lock();
+        let _dir_guard = dir_lock.write();
         Ok(self.create(name, EntryType::File)?.into_file_unchecked())
     }
 
+    /// Create a new file of `size` bytes whose clusters form a single
+    /// contiguous run. The content reads back as zeros.
+    ///
+    /// If no free run is large enough the file is not created and
+    /// [`VfatRsError::ContiguousSpaceNotFound`](error::VfatRsError::ContiguousSpaceNotFound)
+    /// is returned.
+    pub fn create_file_contiguous(&mut self, name: String, size: u32) -> error::Result<File> {
+        let lock = self.vfat_filesystem.fs_lock.clone();
+        let _guard = lock.read();
+        let dir_lock = self.lock();
+        let mut file = {
+            let _dir_guard = dir_lock.write();
+            self.create(name.clone(), EntryType::File)?
+                .into_file_unchecked()
+        };
+        let mode = AllocateMode {
+            contiguous: true,
+            keep_size: false,
+        };
+        // Allocating updates the entry, under the directory lock: the file
+        // lock comes first.
+        let allocated = {
+            let io_lock = file.io_lock.clone();
+            let _io_guard = io_lock.write();
+            file.allocate_unlocked(size, mode)
+        };
+        if let Err(err) = allocated {
+            drop(file);
+            let _dir_guard = dir_lock.write();
+            // The allocation error is the one to report: it says why.
+            if let Err(rollback) = self.delete_unlocked(name, None) {
+                error!("Failed to remove a file left by a failed allocation: {rollback}");
+            }
+            return Err(err);
+        }
+        Ok(file)
+    }
+
     /// Create a new directory in this directory
     ///
     pub fn create_directory(&mut self, name: String) -> error::Result<Directory> {
         let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.write();
+        let _guard = lock.read();
+        let dir_lock = self.lock();
+        let _dir_guard = dir_lock.write();
         Ok(self
             .create(name, EntryType::Directory)?
             .into_directory_unchecked())
     }
 
     /// Used to create a new entry in this directory
-    fn create(&mut self, name: String, entry_type: EntryType) -> error::Result<DirectoryEntry> {
+    pub(crate) fn create(
+        &mut self,
+        name: String,
+        entry_type: EntryType,
+    ) -> error::Result<DirectoryEntry> {
         info!(
             "Creating {:?} entry with name '{:?}' in directory '{:?}'",
             entry_type,
@@ -117,25 +191,31 @@ impl Directory {
         }
 
         // 1. Create metadata:
-        let metadata = self.create_metadata_for_new_entry(name.as_str(), &entry_type)?;
+        let mut metadata = self.create_metadata_for_new_entry(name.as_str(), &entry_type)?;
 
         // 2. Based on the name, create one or more LFN and the Regular entry.
         let existing_short_names = self.collect_short_names()?;
+        let utc_offset = self.vfat_filesystem.options.utc_offset;
         let entries: Vec<UnknownDirectoryEntry> = VfatDirectoryEntry::new_vfat_entry(
             name.as_str(),
             metadata.cluster,
             Self::attributes_from_entry(&entry_type),
             metadata.size,
-            metadata.created(),
-            metadata.modified(),
+            utc_offset.to_local(metadata.created()),
+            utc_offset.to_local(metadata.modified()),
             &existing_short_names,
         )?;
         let entries_len = entries.len();
+        metadata.short_name = Self::short_name_of(&entries);
         let first_empty_spot_offset = if let Some(spot) = self.last_entry_spot {
             spot
         } else {
             self.find_first_empty_spot_offset(entries_len)?
         };
+        metadata.slot = Some(self.slot(Self::short_entry_index(
+            first_empty_spot_offset,
+            entries_len,
+        )));
 
         info!(
             "Going to use as metadata: {:?}. self metadatapath= '{}', selfmetadata name = '{}'. My attributes: {:?}, cluster: {:?}",
@@ -159,10 +239,14 @@ impl Directory {
             let entry: [u8; size_of::<UnknownDirectoryEntry>()] = unknown_entry.into();
             ccw.write(&entry)?;
         }
+        self.invalidate_lookups();
 
         if let EntryType::Directory = entry_type {
-            let entries =
-                VfatDirectoryEntry::create_pseudo_dir_entries(metadata.cluster, ClusterId::new(0));
+            let entries = VfatDirectoryEntry::create_pseudo_dir_entries(
+                metadata.cluster,
+                self.metadata.cluster,
+                self.vfat_filesystem.root_cluster,
+            );
             let pseudo = unknown_entry_convert_to_bytes_2(entries);
             // Zero-initialise the whole first cluster, not just the `.`/`..` entries.
             // The cluster handed out by the allocator may be a recycled one that still
@@ -238,10 +322,17 @@ impl Directory {
         entry_name: &str,
         entry_type: &EntryType,
     ) -> error::Result<Metadata> {
+        // Directory paths end with a separator, matching `contents_unlocked`, so
+        // entries created inside the returned handle get a well-formed path.
         let path = PathBuf::from(format!(
-            "{}{}",
+            "{}{}{}",
             self.metadata.full_path().display(),
-            entry_name
+            entry_name,
+            if let EntryType::Directory = entry_type {
+                "/"
+            } else {
+                ""
+            }
         ));
         let attributes = Self::attributes_from_entry(entry_type);
         let cluster_id = match entry_type {
@@ -252,13 +343,12 @@ impl Directory {
         };
         debug!("Going to use as cluster id: {}", cluster_id);
         let size = 0;
-        let metadata = Metadata::new(
-            self.vfat_filesystem
-                .time_manager
-                .get_current_vfat_timestamp(),
-            self.vfat_filesystem
-                .time_manager
-                .get_current_vfat_timestamp(),
+        let time_manager = &self.vfat_filesystem.time_manager;
+        let now = time_manager.get_current_vfat_timestamp();
+        let utc_offset = self.vfat_filesystem.options.utc_offset;
+        let mut metadata = Metadata::new(
+            now,
+            now,
             entry_name,
             size,
             path,
@@ -266,35 +356,71 @@ impl Directory {
             self.metadata.full_path().clone(),
             attributes,
         );
+        // The odd second lost to the 2 second resolution.
+        metadata.creation_centiseconds = (time_manager.get_current_timestamp() % 2 * 100) as u8;
+        metadata.last_access = utc_offset.local_date(now);
         Ok(metadata)
     }
 
     /// Returns an entry from inside this directory.
     fn get_entry(&mut self, target_filename: &str) -> error::Result<DirectoryEntry> {
-        self.contents_unlocked()?
-            .into_iter()
-            .find(|name| {
-                debug!(
-                    "Checking name: {} == {}",
-                    name.metadata.name(),
-                    target_filename
-                );
-                name.metadata.name() == target_filename
-            })
+        self.find_unlocked(target_filename)?
             .ok_or_else(|| error::VfatRsError::FileNotFound {
                 target: target_filename.to_string(),
             })
     }
 
     /// Delete the entry named `target_name` from this directory.
+    ///
+    /// If the volume was mounted with [`MountOptions::secure_delete`](crate::MountOptions::secure_delete),
+    /// this behaves like [`Directory::secure_delete`].
     pub fn delete(&mut self, target_name: String) -> error::Result<()> {
+        let wipe = self.vfat_filesystem.options.secure_delete;
+        self.delete_locked(target_name, wipe)
+    }
+
+    /// Delete the entry named `target_name` from this directory, overwriting
+    /// its clusters with `pattern` before freeing them and scrubbing its
+    /// directory slots, so neither the content nor the name can be recovered.
+    pub fn secure_delete(
+        &mut self,
+        target_name: String,
+        pattern: WipePattern,
+    ) -> error::Result<()> {
+        self.delete_locked(target_name, Some(pattern))
+    }
+
+    /// Delete `target_name` under the lock of this directory if it is a
+    /// file. A directory may be in use by another thread, through a handle
+    /// or a path: it is deleted under the exclusive volume lock instead.
+    fn delete_locked(
+        &mut self,
+        target_name: String,
+        wipe: Option<WipePattern>,
+    ) -> error::Result<()> {
         let lock = self.vfat_filesystem.fs_lock.clone();
+        {
+            let _guard = lock.read();
+            let dir_lock = self.lock();
+            let _dir_guard = dir_lock.write();
+            let is_dir = self
+                .find_unlocked(&target_name)?
+                .is_some_and(|entry| entry.is_dir());
+            if !is_dir {
+                return self.delete_unlocked(target_name, wipe);
+            }
+        }
         let _guard = lock.write();
-        self.delete_unlocked(target_name)
+        self.delete_unlocked(target_name, wipe)
     }
 
-    fn delete_unlocked(&mut self, target_name: String) -> error::Result<()> {
+    pub(crate) fn delete_unlocked(
+        &mut self,
+        target_name: String,
+        wipe: Option<WipePattern>,
+    ) -> error::Result<()> {
         info!("Starting delete routine for entry: '{}'. ", target_name);
+        self.vfat_filesystem.release_orphans()?;
 
         const PSEUDO_CURRENT_FOLDER: &str = ".";
         const PSEUDO_PARENT_FOLDER: &str = "..";
@@ -308,6 +434,8 @@ impl Directory {
         );
 
         let mut target_entry = self.get_entry(&target_name)?;
+        self.vfat_filesystem
+            .ensure_modifiable(&target_entry.metadata)?;
 
         if target_entry.is_dir() {
             let directory = target_entry.into_directory_unchecked();
@@ -327,8 +455,16 @@ impl Directory {
         }
         info!("Found target entry: {:?}", target_entry);
 
-        self.delete_cluster_chain(&target_entry)?;
-        self.delete_entry(target_name)?;
+        // The chain of a file still open is freed by its last handle.
+        let open = !target_entry.is_dir()
+            && target_entry
+                .metadata
+                .slot
+                .is_some_and(|slot| self.vfat_filesystem.open_files.lock().unlink(slot, wipe));
+        if !open {
+            self.delete_cluster_chain(&target_entry, wipe)?;
+        }
+        self.delete_entry(target_name, wipe.is_some())?;
         Ok(())
     }
 
@@ -337,42 +473,146 @@ impl Directory {
         &self,
         target_name: &str,
     ) -> error::Result<(usize, RegularDirectoryEntry, Vec<LongFileNameEntry>)> {
-        let entries = self.contents_direntry()?;
-        let mut lfn_name_buff: Vec<(u8, String)> = Vec::new();
-        let mut lfn_entries_buff: Vec<LongFileNameEntry> = Vec::new();
+        let location =
+            self.locate(target_name)?
+                .ok_or_else(|| error::VfatRsError::FileNotFound {
+                    target: target_name.to_string(),
+                })?;
+        let lfn_entries = self
+            .read_slots(location.index - location.lfn_count, location.lfn_count)?
+            .into_iter()
+            .map(LongFileNameEntry::from)
+            .collect();
+        Ok((location.index, location.regular, lfn_entries))
+    }
 
-        for (index, dir_entry) in entries.into_iter().enumerate() {
+    /// Locate the entry called `target_name`, going through the volume's
+    /// dentry cache. The `.` and `..` pseudo entries are never cached.
+    fn locate(&self, target_name: &str) -> error::Result<Option<EntryLocation>> {
+        let cacheable = !matches!(target_name, "." | "..");
+        let key = if self.vfat_filesystem.options.case_sensitive {
+            target_name.to_string()
+        } else {
+            case_folding::fold(target_name)
+        };
+        let dentry_cache = &self.vfat_filesystem.dentry_cache;
+        if cacheable && let Some(cached) = dentry_cache.lock().get(self.metadata.cluster, &key) {
+            return Ok(cached);
+        }
+        let location = self.scan_for(target_name)?;
+        if cacheable {
+            dentry_cache
+                .lock()
+                .insert(self.metadata.cluster, &key, location.clone());
+        }
+        Ok(location)
+    }
+
+    /// Scan the slots of this directory for `target_name`, stopping at the
+    /// first match. Case is ignored unless the volume is mounted with
+    /// [`MountOptions::case_sensitive`](crate::MountOptions::case_sensitive).
+    fn scan_for(&self, target_name: &str) -> error::Result<Option<EntryLocation>> {
+        let case_sensitive = self.vfat_filesystem.options.case_sensitive;
+        let mut lfn_name_buff: Vec<(u8, [u16; LFN_CHARACTERS])> = Vec::new();
+
+        for slot in self.raw_entries() {
+            let (index, dir_entry) = slot?;
             match dir_entry {
                 VfatDirectoryEntry::LongFileName(lfn) => {
-                    lfn_name_buff.push((lfn.sequence_number.get_position(), lfn.collect_name()));
-                    lfn_entries_buff.push(lfn);
+                    lfn_name_buff.push((lfn.sequence_number.get_position(), lfn.name_units()));
                 }
                 VfatDirectoryEntry::Deleted(_) => {
                     lfn_name_buff.clear();
-                    lfn_entries_buff.clear();
                 }
                 VfatDirectoryEntry::Regular(regular) => {
+                    let lfn_count = lfn_name_buff.len();
                     let name = if !lfn_name_buff.is_empty() {
                         Self::string_from_lfn(mem::take(&mut lfn_name_buff))
                     } else {
                         regular.full_name()
                     };
-                    if name == target_name {
-                        return Ok((index, regular, mem::take(&mut lfn_entries_buff)));
+                    // Entries with a long name can also be opened by their
+                    // 8.3 alias, e.g. `PROGRA~1`.
+                    if case_folding::names_match(&name, target_name, case_sensitive)
+                        || (lfn_count > 0
+                            && case_folding::names_match(
+                                &regular.full_name(),
+                                target_name,
+                                case_sensitive,
+                            ))
+                    {
+                        return Ok(Some(EntryLocation {
+                            name,
+                            index,
+                            lfn_count,
+                            regular,
+                        }));
                     }
-                    lfn_entries_buff.clear();
                 }
                 VfatDirectoryEntry::EndOfEntries(_) => break,
             }
         }
-        Err(error::VfatRsError::FileNotFound {
-            target: target_name.to_string(),
-        })
+        Ok(None)
+    }
+
+    /// Read `count` raw slots starting at slot `first`.
+    fn read_slots(&self, first: usize, count: usize) -> error::Result<Vec<UnknownDirectoryEntry>> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let slot_size = size_of::<UnknownDirectoryEntry>();
+        let mut reader = self.cluster_chain_reader();
+        reader.seek(first * slot_size)?;
+        let mut buf = alloc::vec![0u8; count * slot_size];
+        ensure!(
+            reader.read(&mut buf)? == buf.len(),
+            error::FilesystemCorruptedSnafu {
+                reason: "Directory ended before a cached entry"
+            }
+        );
+        Ok(buf
+            .chunks_exact(slot_size)
+            .map(|slot| {
+                let slot: [u8; size_of::<UnknownDirectoryEntry>()] =
+                    slot.try_into().expect("slot size mismatch");
+                UnknownDirectoryEntry::from(slot)
+            })
+            .collect())
     }
 
-    fn delete_entry(&mut self, target_name: String) -> error::Result<()> {
+    /// Drop the cached lookups of this directory, after its slots changed.
+    fn invalidate_lookups(&self) {
+        self.vfat_filesystem
+            .dentry_cache
+            .lock()
+            .invalidate_dir(self.metadata.cluster);
+    }
+
+    /// Mark the slots of `target_name` as deleted. With `scrub`, the rest of
+    /// each slot is zeroed too, erasing the name, the start cluster and the
+    /// timestamps.
+    fn delete_entry(&mut self, target_name: String, scrub: bool) -> error::Result<()> {
         info!("Running delete entry");
-        let (index, regular, lfn_entries) = self.find_entry_index(&target_name)?;
+        let slots = self.find_entry_index(&target_name)?;
+        self.delete_slots(slots, scrub)
+    }
+
+    /// Mark the slots returned by [`Self::find_entry_index`] as deleted.
+    fn delete_slots(
+        &mut self,
+        (index, regular, lfn_entries): (usize, RegularDirectoryEntry, Vec<LongFileNameEntry>),
+        scrub: bool,
+    ) -> error::Result<()> {
+        if scrub {
+            let mut scrubbed =
+                UnknownDirectoryEntry::from([0u8; size_of::<UnknownDirectoryEntry>()]);
+            scrubbed.set_id(Deleted);
+            for slot in index - lfn_entries.len()..=index {
+                self.update_entry_by_index(scrubbed, slot)?;
+            }
+            // Like wiped clusters, the scrubbed slots must reach the device.
+            return self.vfat_filesystem.device.flush();
+        }
 
         // set all the lfn entries as deleted.
         for (i, lfn) in lfn_entries.into_iter().rev().enumerate() {
@@ -385,130 +625,291 @@ impl Directory {
         self.update_entry_by_index(unknown, index)?;
         Ok(())
     }
-    fn delete_cluster_chain(&mut self, entry: &DirectoryEntry) -> error::Result<()> {
+    fn delete_cluster_chain(
+        &mut self,
+        entry: &DirectoryEntry,
+        wipe: Option<WipePattern>,
+    ) -> error::Result<()> {
         info!(
             "Deleting entry's associated clusters starting at {:?}",
             entry.metadata.cluster
         );
         self.vfat_filesystem
-            .delete_fat_cluster_chain(entry.metadata.cluster)
+            .delete_fat_cluster_chain(entry.metadata.cluster, wipe)
     }
 
-    fn contents_direntry(&self) -> error::Result<Vec<VfatDirectoryEntry>> {
-        info!("Directory contents, cluster: {:?}", self.metadata.cluster);
-
-        let mut buf = [0; BUF_SIZE];
-        let filter_invalid =
-            |entry: &VfatDirectoryEntry| !matches!(*entry, VfatDirectoryEntry::EndOfEntries(_));
-        let mut cluster_chain_reader = self.cluster_chain_reader();
+    /// Iterate over the raw slots of this directory, without taking the lock.
+    pub(crate) fn raw_entries(&self) -> RawEntries {
+        RawEntries::new(self.cluster_chain_reader(), None)
+    }
 
-        let mut entries = Vec::new();
-        while cluster_chain_reader.read(&mut buf)? > 0 {
-            let unknown_entries: [UnknownDirectoryEntry; ENTRIES_AMOUNT] =
-                unknown_entry_convert_from_bytes_entries(buf);
-            entries.extend(
-                unknown_entries
-                    .iter()
-                    .map(VfatDirectoryEntry::from)
-                    .filter(filter_invalid),
-            );
+    /// The volume label entry of the root directory and its slot, if any.
+    pub(crate) fn volume_label_entry(
+        &self,
+    ) -> error::Result<Option<(usize, RegularDirectoryEntry)>> {
+        for slot in self.raw_entries() {
+            if let (index, VfatDirectoryEntry::Regular(regular)) = slot?
+                && regular.is_volume_id()
+            {
+                return Ok(Some((index, regular)));
+            }
         }
-        Ok(entries)
+        Ok(None)
+    }
+
+    /// Store `label` in the volume label entry of the root directory, adding
+    /// one if there is none, or remove the entry if `label` is `None`.
+    pub(crate) fn write_volume_label(&mut self, label: Option<[u8; 11]>) -> error::Result<()> {
+        let existing = self.volume_label_entry()?;
+        let Some(label) = label else {
+            if let Some((index, regular)) = existing {
+                self.delete_slots((index, regular, Vec::new()), false)?;
+            }
+            return Ok(());
+        };
+        let fs = &self.vfat_filesystem;
+        let now = fs
+            .options
+            .utc_offset
+            .to_local(fs.time_manager.get_current_vfat_timestamp());
+        let (index, mut regular) = match existing {
+            Some(existing) => existing,
+            None => {
+                let offset = self.find_first_empty_spot_offset(1)?;
+                let regular = RegularDirectoryEntry {
+                    file_name: [b' '; 8],
+                    file_ext: [b' '; 3],
+                    attributes: Attributes(attribute::VOLUME_ID),
+                    _reseverd_win_nt: 0,
+                    creation_millis: 0,
+                    creation_time: now,
+                    last_access_date: now.date_bits(),
+                    high_16bits: 0,
+                    last_modification_time: now,
+                    low_16bits: 0,
+                    file_size: 0,
+                };
+                (offset / size_of::<UnknownDirectoryEntry>(), regular)
+            }
+        };
+        regular.file_name.copy_from_slice(&label[..8]);
+        regular.file_ext.copy_from_slice(&label[8..]);
+        regular.last_modification_time = now;
+        self.last_entry_spot = None;
+        self.update_entry_by_index(regular.into(), index)
     }
 
     /// Collect the 8.3 short names of all regular entries in this directory.
     fn collect_short_names(&self) -> error::Result<Vec<[u8; 8]>> {
-        Ok(self
-            .contents_direntry()?
-            .into_iter()
-            .filter_map(|e| {
-                if let VfatDirectoryEntry::Regular(r) = e {
-                    Some(r.file_name)
-                } else {
-                    None
-                }
+        self.raw_entries()
+            .filter_map(|slot| match slot {
+                Ok((_, VfatDirectoryEntry::Regular(r))) => Some(Ok(r.file_name)),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
             })
-            .collect())
+            .collect()
     }
 
     /// Returns the total number of raw directory entry slots in use (regular,
     /// LFN, and deleted — everything except end-of-entries markers).
     /// Useful for verifying that deleted slots are being reclaimed.
     pub fn raw_entry_count(&self) -> error::Result<usize> {
-        Ok(self.contents_direntry()?.len())
+        self.raw_entries()
+            .try_fold(0, |count, slot| slot.map(|_| count + 1))
     }
 
     /// Returns all entries (files and subdirectories) contained in this directory.
     pub fn contents(&self) -> error::Result<Vec<DirectoryEntry>> {
-        let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.read();
+        let _guard = self.vfat_filesystem.fs_lock.read();
+        let lock = self.lock();
+        let _dir_guard = lock.read();
         self.contents_unlocked()
     }
 
     pub(crate) fn contents_unlocked(&self) -> error::Result<Vec<DirectoryEntry>> {
         info!("Directory contents, cluster: {:?}", self.metadata.cluster);
+        self.iter_unlocked().collect()
+    }
 
-        let entries = self.contents_direntry()?;
-        let mut contents = Vec::new();
+    /// Lazily iterate over the entries (files and subdirectories) of this
+    /// directory, including the `.` and `..` pseudo entries.
+    ///
+    /// The directory is read one sector at a time, and the locks are only
+    /// held while reading: like `readdir`, entries created or deleted during
+    /// the iteration may or may not be returned.
+    pub fn iter(&self) -> DirectoryIter<'_> {
+        let locks = (self.vfat_filesystem.fs_lock.clone(), self.lock());
+        DirectoryIter::new(
+            self,
+            RawEntries::new(self.cluster_chain_reader(), Some(locks)),
+        )
+    }
 
-        let mut lfn_buff: Vec<(u8, String)> = Vec::new();
-        for dir_entry in entries {
-            debug!("Found entry: {:?}", dir_entry);
-            match dir_entry {
-                VfatDirectoryEntry::LongFileName(lfn) => {
-                    lfn_buff.push((lfn.sequence_number.get_position(), lfn.collect_name()))
-                }
-                VfatDirectoryEntry::Deleted(_) => {
-                    lfn_buff.clear();
-                }
-                VfatDirectoryEntry::Regular(regular) => {
-                    let name = if !lfn_buff.is_empty() {
-                        Self::string_from_lfn(mem::take(&mut lfn_buff))
-                    } else {
-                        regular.full_name()
-                    };
+    /// Like [`Directory::iter`], for callers already holding the lock.
+    pub(crate) fn iter_unlocked(&self) -> DirectoryIter<'_> {
+        DirectoryIter::new(self, self.raw_entries())
+    }
 
-                    let path = PathBuf::from(format!(
-                        "{}{name}{}",
-                        self.metadata.full_path().display(),
-                        if regular.is_dir() { "/" } else { "" }
-                    ));
-
-                    let metadata = Metadata::new(
-                        regular.creation_time,
-                        regular.last_modification_time,
-                        name,
-                        regular.file_size,
-                        path,
-                        regular.cluster(),
-                        self.metadata.full_path().clone(),
-                        regular.attributes,
-                    );
-
-                    debug!("Metadata: {:?}", metadata);
-
-                    let new_fn = if regular.is_dir() {
-                        DirectoryEntry::new_directory
-                    } else {
-                        DirectoryEntry::new_file
-                    };
+    /// Build the entry for `regular`, whose (long) name is `name` and which
+    /// is in slot `index`.
+    pub(crate) fn entry_from_regular(
+        &self,
+        name: String,
+        regular: &RegularDirectoryEntry,
+        index: usize,
+    ) -> DirectoryEntry {
+        let path = PathBuf::from(format!(
+            "{}{name}{}",
+            self.metadata.full_path().display(),
+            if regular.is_dir() { "/" } else { "" }
+        ));
 
-                    contents.push(new_fn(metadata, self.vfat_filesystem.clone()));
-                }
-                // The for loop stops on EndOfEntries
-                VfatDirectoryEntry::EndOfEntries(_) => {
-                    panic!("This cannot happen! Found EndOfEntries")
-                }
-            }
-        }
-        Ok(contents)
+        let utc_offset = self.vfat_filesystem.options.utc_offset;
+        let mut metadata = Metadata::new(
+            utc_offset.to_utc(regular.creation_time),
+            utc_offset.to_utc(regular.last_modification_time),
+            name,
+            regular.file_size,
+            path,
+            regular.cluster(),
+            self.metadata.full_path().clone(),
+            regular.attributes,
+        );
+        metadata.short_name = regular.full_name();
+        metadata.slot = Some(self.slot(index));
+        metadata.creation_centiseconds = regular.creation_millis.min(199);
+        metadata.last_access =
+            utc_offset.to_utc(VfatTimestamp::from_date(regular.last_access_date));
+
+        debug!("Metadata: {:?}", metadata);
+
+        let new_fn = if regular.is_dir() {
+            DirectoryEntry::new_directory
+        } else {
+            DirectoryEntry::new_file
+        };
+        new_fn(metadata, self.vfat_filesystem.clone())
     }
 
-    pub(crate) fn update_entry(&mut self, metadata: Metadata) -> error::Result<()> {
+    pub(crate) fn update_entry(&self, metadata: Metadata) -> error::Result<()> {
         let target_name = metadata.name().to_string();
         info!("Running update entry on target name: {}", target_name);
-        let regular: RegularDirectoryEntry = metadata.into();
-        self.update_entry_inner(target_name, regular.into())
+        let (index, current, _) = self.find_entry_index(&target_name)?;
+        Self::rewrite_entry(&self.vfat_filesystem, self.slot(index), current, metadata)
+    }
+
+    /// Rewrite the short entry at `slot` with `metadata`. Unlike its path,
+    /// the slot of an open file is kept up to date when a directory above
+    /// it is renamed. The caller holds the lock of the slot's directory.
+    pub(crate) fn update_entry_at(
+        vfat: &VfatFS,
+        slot: EntrySlot,
+        metadata: Metadata,
+    ) -> error::Result<()> {
+        let current = Self::read_short_entry(vfat, slot.directory, slot.index)?;
+        Self::rewrite_entry(vfat, slot, current, metadata)
+    }
+
+    /// The short entry at slot `index` of the directory starting at
+    /// `dir_cluster`.
+    fn read_short_entry(
+        vfat: &VfatFS,
+        dir_cluster: ClusterId,
+        index: usize,
+    ) -> error::Result<RegularDirectoryEntry> {
+        let mut buf = [0u8; size_of::<UnknownDirectoryEntry>()];
+        let mut reader = vfat.cluster_chain_reader(dir_cluster);
+        reader.seek(index * size_of::<UnknownDirectoryEntry>())?;
+        ensure!(
+            reader.read(&mut buf)? == buf.len(),
+            error::FilesystemCorruptedSnafu {
+                reason: "Directory ended before the entry"
+            }
+        );
+        Ok(UnknownDirectoryEntry::from(buf).into())
+    }
+
+    /// Whether the directory starting at `directory` is the one starting at
+    /// `ancestor` or lies below it, following the ".." entries up.
+    fn is_within(
+        vfat: &VfatFS,
+        mut directory: ClusterId,
+        ancestor: ClusterId,
+    ) -> error::Result<bool> {
+        // On a sane volume the walk is shorter than the number of clusters.
+        for _ in 0..=vfat.total_clusters {
+            if directory == ancestor {
+                return Ok(true);
+            }
+            if directory == vfat.root_cluster {
+                return Ok(false);
+            }
+            // ".." is always the second entry, and holds 0 for the root.
+            directory = match Self::read_short_entry(vfat, directory, 1)?.cluster() {
+                cluster if cluster == ClusterId::new(0) => vfat.root_cluster,
+                cluster => cluster,
+            };
+        }
+        Err(error::VfatRsError::FilesystemCorrupted {
+            reason: "The \"..\" entries form a loop",
+        })
+    }
+
+    /// Replace `current`, the short entry at `slot`, with `metadata`.
+    fn rewrite_entry(
+        vfat: &VfatFS,
+        slot: EntrySlot,
+        current: RegularDirectoryEntry,
+        metadata: Metadata,
+    ) -> error::Result<()> {
+        let mut open_files = vfat.open_files.lock();
+        if metadata.attributes.is_directory() {
+            if current.cluster() != metadata.cluster {
+                open_files.directory_moved(current.cluster(), metadata.cluster);
+            }
+        } else {
+            open_files.entry_updated(slot, &metadata);
+        }
+        drop(open_files);
+        let utc_offset = vfat.options.utc_offset;
+        let mut regular = RegularDirectoryEntry::from_metadata(metadata, utc_offset);
+        // Keep the alias on disk: the LFN checksum covers it, and it may have
+        // a tail other than ~1.
+        regular.file_name = current.file_name;
+        regular.file_ext = current.file_ext;
+        let buf: [u8; size_of::<UnknownDirectoryEntry>()] =
+            UnknownDirectoryEntry::from(regular).into();
+        vfat.dentry_cache.lock().invalidate_dir(slot.directory);
+        let mut writer = vfat.cluster_chain_writer(slot.directory);
+        writer.seek(slot.index * size_of::<UnknownDirectoryEntry>())?;
+        writer.write(&buf)?;
+        Ok(())
+    }
+
+    /// The lock of this directory.
+    pub(crate) fn lock(&self) -> SharedLock {
+        self.vfat_filesystem.directory_lock(self.metadata.cluster)
+    }
+
+    /// The location of slot `index` of this directory.
+    fn slot(&self, index: usize) -> EntrySlot {
+        EntrySlot {
+            directory: self.metadata.cluster,
+            index,
+        }
+    }
+
+    /// Index of the short entry of `entries_len` slots written at byte
+    /// `offset`: the short entry is the last one.
+    fn short_entry_index(offset: usize, entries_len: usize) -> usize {
+        offset / size_of::<UnknownDirectoryEntry>() + entries_len - 1
+    }
+
+    /// The short name of the entry made of `entries`, whose last slot is the
+    /// regular one.
+    fn short_name_of(entries: &[UnknownDirectoryEntry]) -> String {
+        let regular: RegularDirectoryEntry = (*entries.last().expect("no regular entry")).into();
+        regular.full_name()
     }
 
     fn cluster_chain_reader(&self) -> ClusterChainReader {
@@ -517,18 +918,33 @@ impl Directory {
     }
 
     // create a string from a vec
-    fn string_from_lfn(mut lfn_vec: Vec<(u8, String)>) -> String {
+    pub(crate) fn string_from_lfn(mut lfn_vec: Vec<(u8, [u16; LFN_CHARACTERS])>) -> String {
         // lfn are not assumed to be created in order, hence we need to
         // sort using the sequence number
         lfn_vec.sort();
-        // Build the string.
-        lfn_vec
-            .into_iter()
-            .map(|(_, s)| s)
-            .fold(String::new(), |mut acc, s| {
-                acc.push_str(&s);
-                acc
-            })
+        // Decode the name as a whole: a surrogate pair may span two entries.
+        LongFileNameEntry::decode_name(lfn_vec.into_iter().flat_map(|(_, units)| units))
+    }
+
+    /// Replace the attributes of this directory, see
+    /// [`File::set_attributes`]. The root directory has none.
+    pub fn set_attributes(&mut self, attributes: Attributes) -> error::Result<()> {
+        if self.metadata.cluster == self.vfat_filesystem.root_cluster {
+            return Err(error::VfatRsError::AccessDenied {
+                target: self.metadata.full_path().display().to_string(),
+                reason: "the root directory has no attributes",
+            });
+        }
+        let lock = self.vfat_filesystem.fs_lock.clone();
+        let _guard = lock.read();
+        let parent = self
+            .vfat_filesystem
+            .get_from_absolute_path_unlocked(self.metadata.parent().clone())?
+            .into_directory_unchecked();
+        let dir_lock = parent.lock();
+        let _dir_guard = dir_lock.write();
+        self.metadata.set_attributes(attributes);
+        parent.update_entry(self.metadata.clone())
     }
 
     /// Rename or move `target_name` to `destination_path`.
@@ -542,7 +958,7 @@ impl Directory {
         self.rename_unlocked(target_name, destination_path)
     }
 
-    fn rename_unlocked(
+    pub(crate) fn rename_unlocked(
         &mut self,
         target_name: String,
         destination_path: crate::PathBuf,
@@ -567,54 +983,72 @@ impl Directory {
         let dest_parent: crate::PathBuf = dest_parent_str.as_str().into();
 
         let target_entry = self.get_entry(&target_name)?;
+        self.vfat_filesystem
+            .ensure_modifiable(&target_entry.metadata)?;
         let mut metadata = target_entry.metadata;
 
-        // Determine if this is a same-directory rename or cross-directory move
+        // Determine if this is a same-directory rename or cross-directory move.
+        // Directory paths may or may not end with a separator.
         let source_parent_path = self.metadata.full_path();
-        if dest_parent == *source_parent_path {
+        let source_parent_str = source_parent_path.display().to_string();
+        if dest_parent_str.trim_end_matches('/') == source_parent_str.trim_end_matches('/') {
+            // POSIX semantics: replace another entry already called new_name.
+            // new_name can also name the target itself: a case-only rename, or
+            // a rename from the short alias to the long name.
+            let target_index = self.locate(&target_name)?.map(|location| location.index);
+            if let Some(existing) = self.locate(&new_name)?
+                && Some(existing.index) != target_index
+            {
+                let wipe = self.vfat_filesystem.options.secure_delete;
+                self.delete_unlocked(existing.name, wipe)?;
+            }
             // Same directory: use existing in-place rename
             return self.inner_rename(target_name, new_name, &mut metadata);
         }
 
         // Cross-directory move
-        // If moving a directory, guard against circular moves
-        if metadata.attributes.is_directory() {
-            let parent_str = source_parent_path.display().to_string();
-            let sep = if parent_str.ends_with('/') { "" } else { "/" };
-            let source_entry_path: crate::PathBuf =
-                alloc::format!("{}{}{}", parent_str, sep, target_name).into();
-            if destination_path.starts_with(&source_entry_path) {
-                return Err(error::VfatRsError::CircularMove {
-                    source_path: source_entry_path.display().to_string(),
-                    destination_path: dest_str,
-                });
-            }
-        }
-
         // Resolve destination directory
         let dest_dir_entry = self
             .vfat_filesystem
             .get_from_absolute_path_unlocked(dest_parent.clone())?;
         let mut dest_dir = dest_dir_entry.into_directory_or_not_found()?;
 
+        // If moving a directory, guard against circular moves. The paths
+        // can't tell: names match regardless of case, or by their alias.
+        if metadata.attributes.is_directory()
+            && Self::is_within(
+                &self.vfat_filesystem,
+                dest_dir.metadata.cluster,
+                metadata.cluster,
+            )?
+        {
+            return Err(error::VfatRsError::CircularMove {
+                source_path: metadata.full_path().display().to_string(),
+                destination_path: dest_str,
+            });
+        }
+
         // POSIX semantics: if destination name already exists, delete it
         if dest_dir.contains_unlocked(&new_name)? {
-            dest_dir.delete_unlocked(new_name.clone())?;
+            let wipe = dest_dir.vfat_filesystem.options.secure_delete;
+            dest_dir.delete_unlocked(new_name.clone(), wipe)?;
         }
 
         // Write new entries in the destination directory
         let attributes = metadata.attributes;
         let existing_short_names = dest_dir.collect_short_names()?;
+        let utc_offset = dest_dir.vfat_filesystem.options.utc_offset;
         let entries: Vec<UnknownDirectoryEntry> = VfatDirectoryEntry::new_vfat_entry(
             new_name.as_str(),
             metadata.cluster,
             attributes,
             metadata.size,
-            metadata.created(),
-            metadata.modified(),
+            utc_offset.to_local(metadata.created()),
+            utc_offset.to_local(metadata.modified()),
             &existing_short_names,
         )?;
         let entries_len = entries.len();
+        metadata.short_name = Self::short_name_of(&entries);
         let first_empty_spot_offset = if let Some(spot) = dest_dir.last_entry_spot {
             spot
         } else {
@@ -628,10 +1062,23 @@ impl Directory {
             let entry: [u8; size_of::<UnknownDirectoryEntry>()] = unknown_entry.into();
             ccw.write(&entry)?;
         }
+        dest_dir.invalidate_lookups();
         dest_dir.last_entry_spot = None;
+        if let Some(source) = metadata.slot {
+            self.vfat_filesystem.open_files.lock().rename(
+                source,
+                dest_dir.slot(Self::short_entry_index(
+                    first_empty_spot_offset,
+                    entries_len,
+                )),
+                new_name.clone(),
+                dest_dir.metadata.full_path().clone(),
+            );
+        }
 
         // Delete old entries from source directory
-        self.delete_entry(target_name)?;
+        let scrub = self.vfat_filesystem.options.secure_delete.is_some();
+        self.delete_entry(target_name, scrub)?;
 
         // For directory moves, update the ".." pseudo-entry to point to new parent
         if metadata.attributes.is_directory() && !metadata.has_no_cluster_allocated() {
@@ -647,33 +1094,39 @@ impl Directory {
     }
 
     /// Update the ".." pseudo-entry inside a directory to point to a new parent cluster.
-    fn update_dotdot_cluster(
+    pub(crate) fn update_dotdot_cluster(
         vfat: &VfatFS,
         dir_cluster: ClusterId,
         new_parent_cluster: ClusterId,
     ) -> error::Result<()> {
-        // ".." is always the second entry (index 1)
-        let dotdot_index = 1;
-        let index_offset = size_of::<UnknownDirectoryEntry>() * dotdot_index;
-
-        // Read the existing ".." entry
-        let mut buf = [0u8; size_of::<UnknownDirectoryEntry>()];
-        let mut reader = vfat.cluster_chain_reader(dir_cluster);
-        // Skip the "." entry
-        let mut skip_buf = [0u8; size_of::<UnknownDirectoryEntry>()];
-        reader.read(&mut skip_buf)?;
-        reader.read(&mut buf)?;
-
-        let unknown = UnknownDirectoryEntry::from(buf);
-        let mut regular: RegularDirectoryEntry = unknown.into();
-
-        // Update cluster to new parent (root cluster 2 maps to 0 per FAT convention)
-        let new_parent = if new_parent_cluster == ClusterId::new(2) {
+        // Update cluster to new parent (the root directory maps to 0 per FAT convention)
+        let new_parent = if new_parent_cluster == vfat.root_cluster {
             ClusterId::new(0)
         } else {
             new_parent_cluster
         };
-        let (high, low) = new_parent.into_high_low();
+        // ".." is always the second entry (index 1)
+        Self::update_pseudo_entry_cluster(vfat, dir_cluster, 1, new_parent)
+    }
+
+    /// Update the "." pseudo-entry inside a directory after the directory
+    /// itself moved to `dir_cluster`.
+    pub(crate) fn update_dot_cluster(vfat: &VfatFS, dir_cluster: ClusterId) -> error::Result<()> {
+        // "." is always the first entry (index 0)
+        Self::update_pseudo_entry_cluster(vfat, dir_cluster, 0, dir_cluster)
+    }
+
+    fn update_pseudo_entry_cluster(
+        vfat: &VfatFS,
+        dir_cluster: ClusterId,
+        index: usize,
+        target: ClusterId,
+    ) -> error::Result<()> {
+        let index_offset = size_of::<UnknownDirectoryEntry>() * index;
+
+        // Read the existing entry
+        let mut regular = Self::read_short_entry(vfat, dir_cluster, index)?;
+        let (high, low) = target.into_high_low();
         regular.high_16bits = high;
         regular.low_16bits = low;
 
@@ -694,18 +1147,31 @@ impl Directory {
     ) -> error::Result<()> {
         // create lfn from existing file
         // delete old file
+        // Located before writing the new entries: when only the case changes,
+        // new_name would match the new slots too.
+        let old_slots = self.find_entry_index(&target_name)?;
         let attributes = metadata.attributes;
-        let existing_short_names = self.collect_short_names()?;
+        let mut existing_short_names = self.collect_short_names()?;
+        // The old short name is about to be freed, so `foo` -> `FOO` doesn't
+        // need a `FOO~1` alias.
+        if let Some(pos) = existing_short_names
+            .iter()
+            .position(|short| *short == old_slots.1.file_name)
+        {
+            existing_short_names.remove(pos);
+        }
+        let utc_offset = self.vfat_filesystem.options.utc_offset;
         let entries: Vec<UnknownDirectoryEntry> = VfatDirectoryEntry::new_vfat_entry(
             new_name.as_str(),
             metadata.cluster,
             attributes,
             metadata.size,
-            metadata.created(),
-            metadata.modified(),
+            utc_offset.to_local(metadata.created()),
+            utc_offset.to_local(metadata.modified()),
             &existing_short_names,
         )?;
         let entries_len = entries.len();
+        metadata.short_name = Self::short_name_of(&entries);
         let first_empty_spot_offset = if let Some(spot) = self.last_entry_spot {
             spot
         } else {
@@ -720,25 +1186,25 @@ impl Directory {
             let entry: [u8; size_of::<UnknownDirectoryEntry>()] = unknown_entry.into();
             ccw.write(&entry)?;
         }
+        self.invalidate_lookups();
+        self.vfat_filesystem.open_files.lock().rename(
+            self.slot(old_slots.0),
+            self.slot(Self::short_entry_index(
+                first_empty_spot_offset,
+                entries_len,
+            )),
+            new_name.clone(),
+            self.metadata.full_path().clone(),
+        );
         metadata.name = new_name;
 
         // Invalidate cached spot so next operation re-scans for deleted entries
         self.last_entry_spot = None;
-        self.delete_entry(target_name)?;
+        let scrub = self.vfat_filesystem.options.secure_delete.is_some();
+        self.delete_slots(old_slots, scrub)?;
         Ok(())
     }
 
-    // TODO: Currently this doesn't support renaming file, just updating/replacing metadatas...
-    fn update_entry_inner(
-        &mut self,
-        target_name: String,
-        new_entry: UnknownDirectoryEntry,
-    ) -> error::Result<()> {
-        debug!("Running update entry routine...");
-        let (index, _, _) = self.find_entry_index(&target_name)?;
-        self.update_entry_by_index(new_entry, index)
-    }
-
     // Replace entry with index `index` with input `entry`.
     // TODO: when reading the file, keep the index around to avoid scanning to locate the file again.
     pub(crate) fn update_entry_by_index(
@@ -748,6 +1214,7 @@ impl Directory {
     ) -> error::Result<()> {
         let index_offset = size_of::<UnknownDirectoryEntry>() * index;
         let buf: [u8; size_of::<UnknownDirectoryEntry>()] = entry.into();
+        self.invalidate_lookups();
 
         let mut ccw = self
             .vfat_filesystem
@@ -760,7 +1227,7 @@ impl Directory {
     fn attributes_from_entry(entry: &EntryType) -> Attributes {
         match entry {
             EntryType::Directory => Attributes::new_directory(),
-            EntryType::File => Attributes(0),
+            EntryType::File => Attributes::new_file(),
         }
     }
 }
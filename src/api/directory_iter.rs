@@ -0,0 +1,146 @@
+//! Lazy iteration over the entries of a directory.
+//!
+//! Entries are decoded one sector at a time, so iterating never holds more
+//! than a sector of raw entries plus the long name being reassembled, whatever
+//! the size of the directory.
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::api::raw_directory_entry::{LFN_CHARACTERS, UnknownDirectoryEntry, VfatDirectoryEntry};
+use crate::api::{Directory, DirectoryEntry};
+use crate::cluster::cluster_reader::ClusterChainReader;
+use crate::locks::SharedLock;
+use crate::{SECTOR_SIZE, error};
+
+const ENTRY_SIZE: usize = size_of::<UnknownDirectoryEntry>();
+
+/// Iterator over the raw slots of a directory, up to the end-of-entries
+/// marker. Yields each slot with its index in the directory.
+pub(crate) struct RawEntries {
+    reader: ClusterChainReader,
+    buf: [u8; SECTOR_SIZE],
+    /// Number of slots in `buf`, and the next one to decode.
+    filled: usize,
+    position: usize,
+    index: usize,
+    done: bool,
+    /// Volume and directory locks taken while reading, if the caller doesn't
+    /// hold them.
+    locks: Option<(SharedLock, SharedLock)>,
+}
+
+impl RawEntries {
+    pub(crate) fn new(reader: ClusterChainReader, locks: Option<(SharedLock, SharedLock)>) -> Self {
+        Self {
+            reader,
+            buf: [0; SECTOR_SIZE],
+            filled: 0,
+            position: 0,
+            index: 0,
+            done: false,
+            locks,
+        }
+    }
+
+    fn refill(&mut self) -> error::Result<()> {
+        let _guards = self
+            .locks
+            .as_ref()
+            .map(|(volume, directory)| (volume.read(), directory.read()));
+        self.filled = self.reader.read(&mut self.buf)? / ENTRY_SIZE;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl Iterator for RawEntries {
+    type Item = error::Result<(usize, VfatDirectoryEntry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.position == self.filled {
+            if let Err(err) = self.refill() {
+                self.done = true;
+                return Some(Err(err));
+            }
+            if self.filled == 0 {
+                self.done = true;
+                return None;
+            }
+        }
+        let start = self.position * ENTRY_SIZE;
+        let bytes: [u8; ENTRY_SIZE] = self.buf[start..start + ENTRY_SIZE]
+            .try_into()
+            .expect("slot size mismatch");
+        let entry = VfatDirectoryEntry::from(UnknownDirectoryEntry::from(bytes));
+        self.position += 1;
+        self.index += 1;
+        if let VfatDirectoryEntry::EndOfEntries(_) = entry {
+            self.done = true;
+            return None;
+        }
+        Some(Ok((self.index - 1, entry)))
+    }
+}
+
+/// Lazy iterator over the entries of a [`Directory`], returned by
+/// [`Directory::iter`].
+///
+/// Long names are reassembled from their LFN slots, deleted slots are
+/// skipped. A read error is returned as an item and ends the iteration.
+pub struct DirectoryIter<'a> {
+    directory: &'a Directory,
+    raw: RawEntries,
+    lfn_buff: Vec<(u8, [u16; LFN_CHARACTERS])>,
+}
+
+impl<'a> DirectoryIter<'a> {
+    pub(crate) fn new(directory: &'a Directory, raw: RawEntries) -> Self {
+        Self {
+            directory,
+            raw,
+            lfn_buff: Vec::new(),
+        }
+    }
+}
+
+impl Iterator for DirectoryIter<'_> {
+    type Item = error::Result<DirectoryEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, entry) = match self.raw.next()? {
+                Ok(slot) => slot,
+                Err(err) => return Some(Err(err)),
+            };
+            match entry {
+                VfatDirectoryEntry::LongFileName(lfn) => self
+                    .lfn_buff
+                    .push((lfn.sequence_number.get_position(), lfn.name_units())),
+                VfatDirectoryEntry::Deleted(_) => self.lfn_buff.clear(),
+                VfatDirectoryEntry::Regular(regular) => {
+                    let name = if !self.lfn_buff.is_empty() {
+                        Directory::string_from_lfn(mem::take(&mut self.lfn_buff))
+                    } else {
+                        regular.full_name()
+                    };
+                    return Some(Ok(self.directory.entry_from_regular(name, &regular, index)));
+                }
+                VfatDirectoryEntry::EndOfEntries(_) => {
+                    unreachable!("RawEntries stops on EndOfEntries")
+                }
+            }
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Directory {
+    type Item = error::Result<DirectoryEntry>;
+    type IntoIter = DirectoryIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -1,14 +1,106 @@
 use crate::io::{SeekFrom, Write};
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::fmt::Formatter;
 use core::{cmp, fmt};
 
-use log::{debug, info};
+use log::{debug, error, info};
 
-use crate::api::Metadata;
+use crate::api::raw_directory_entry::{Attributes, attribute};
+use crate::api::{Directory, Metadata, OpenOptions};
 use crate::cluster::cluster_writer::ClusterChainWriter;
-use crate::{ClusterId, PathBuf, Result, VfatFS, VfatMetadataTrait};
+use crate::locks::{Mutex, SharedLock};
+use crate::open_files::{SharedFile, SharedFileRef};
+use crate::{
+    AccessTimePolicy, ClusterId, FileTimes, PathBuf, Result, SectorId, VfatFS, VfatMetadataTrait,
+    VfatRsError, VfatTimestamp, WipePattern, fat_table,
+};
+
+/// How [`File::allocate`] reserves space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocateMode {
+    /// The whole file must end up in a single run of clusters. Fails with
+    /// [`VfatRsError::ContiguousSpaceNotFound`](crate::VfatRsError::ContiguousSpaceNotFound)
+    /// instead of fragmenting the file.
+    pub contiguous: bool,
+    /// Reserve the clusters without changing the file size, like Linux's
+    /// `FALLOC_FL_KEEP_SIZE`. Later writes fill the reserved clusters.
+    pub keep_size: bool,
+}
+
+/// A run of physically consecutive sectors holding part of a file, as
+/// returned by [`File::extents`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    /// Offset in the file, in bytes, of the first byte of the extent.
+    pub file_offset: u64,
+    /// Absolute sector on the block device where the extent starts.
+    pub start_sector: SectorId,
+    /// Length of the extent in bytes. Always a whole number of clusters.
+    pub length: u64,
+}
+
+/// Number of cluster chain positions a [`File`] remembers.
+const CHAIN_HINTS: usize = 8;
+
+/// Recently visited positions in a file's cluster chain, shared by
+/// [`File::read_at`] and [`File::write_at`].
+///
+/// Finding the cluster that holds a byte offset means following the FAT from
+/// the head of the chain, one lookup per cluster. Each positional access
+/// remembers the cluster it stopped in, and the next one walks forward from
+/// the closest remembered position before its offset instead of from the
+/// head: random but clustered access stays cheap, and sequential access
+/// costs at most one lookup per cluster crossed. An access that started from
+/// a hint moves that hint forward, so up to `CHAIN_HINTS` interleaved streams
+/// keep their own position.
+#[derive(Debug, Default)]
+struct ChainHints {
+    /// (index of the cluster in the chain, cluster) pairs.
+    slots: [Option<(usize, ClusterId)>; CHAIN_HINTS],
+    /// Slot to overwrite next when no slot was used.
+    next: usize,
+    /// Generation of the chain the hints were taken on, see
+    /// [`SharedFile::generation`].
+    generation: u64,
+}
+
+impl ChainHints {
+    /// The slot holding the furthest position at or before `index`. Hints
+    /// taken on an older `generation` of the chain are dropped first.
+    fn nearest(&mut self, generation: u64, index: usize) -> Option<(usize, (usize, ClusterId))> {
+        if self.generation != generation {
+            *self = Self {
+                generation,
+                ..Self::default()
+            };
+        }
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, hint)| hint.map(|hint| (slot, hint)))
+            .filter(|(_, (hint_index, _))| *hint_index <= index)
+            .max_by_key(|(_, (hint_index, _))| *hint_index)
+    }
+
+    fn remember(&mut self, slot: Option<usize>, index: usize, cluster: ClusterId) {
+        let slot = slot.unwrap_or_else(|| {
+            let slot = self.next;
+            self.next = (self.next + 1) % CHAIN_HINTS;
+            slot
+        });
+        self.slots[slot] = Some((index, cluster));
+    }
+}
 
 /// A File representation in a VfatFilesystem.
+///
+/// All the handles of a file share its size, first cluster and timestamps,
+/// so a write through one is seen by the others. A file deleted while open
+/// disappears from its directory right away, but keeps its clusters, and
+/// stays readable and writable, until its last handle is dropped.
 //#[derive(Clone)]
 pub struct File {
     pub(crate) vfat_filesystem: VfatFS,
@@ -27,11 +119,9 @@ pub struct File {
     /// the byte offset the writer is currently positioned at.
     ///
     /// The cache is only valid for this handle's own sequential use: it is
-    /// cleared on `seek`, on `truncate`, and when the first cluster is allocated.
-    /// Like a POSIX file descriptor, a `File` assumes exclusive access to its own
-    /// cluster chain while open; mutating the same on-disk entry through a second
-    /// handle concurrently is unsupported (this already holds for the cached
-    /// `metadata` today).
+    /// cleared on `seek`, and whenever the chain shrinks or changes head,
+    /// through this handle or another one of the same file (see
+    /// [`SharedFile::generation`]).
     writer: Option<(usize, ClusterChainWriter)>,
     /// A `ClusterChainReader` kept warm across sequential reads.
     ///
@@ -41,8 +131,22 @@ pub struct File {
     /// When the next read continues exactly where the previous one stopped we
     /// reuse the reader, which is already positioned there. The stored `usize` is
     /// the byte offset the reader is currently positioned at. Cleared on `seek`,
-    /// `truncate`, and when the first cluster is allocated.
+    /// and with the writer when the chain shrinks or changes head.
     reader: Option<(usize, crate::cluster::cluster_reader::ClusterChainReader)>,
+    /// Positions in the cluster chain for `read_at` and `write_at`. Cleared
+    /// whenever the chain may shrink or change head.
+    hints: Mutex<ChainHints>,
+    /// State shared with the other handles of this file, registered in the
+    /// volume's open file table. `metadata` is a copy of its metadata, taken
+    /// at the start of each operation.
+    shared: SharedFileRef,
+    /// The chain generation `writer` and `reader` were built on.
+    generation: u64,
+    /// The I/O lock of the file, from `shared`: writes through any handle
+    /// exclude each other and the reads.
+    pub(crate) io_lock: SharedLock,
+    /// The access mode, see [`OpenOptions`].
+    pub(crate) mode: OpenOptions,
 }
 impl fmt::Debug for File {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -56,20 +160,69 @@ impl fmt::Debug for File {
 
 impl File {
     /// Create a new [`File`] handle.
+    ///
+    /// If the file is already open, the new handle shares the size, first
+    /// cluster and timestamps of the existing ones rather than `metadata`'s.
     pub fn new(vfat_filesystem: VfatFS, metadata: Metadata) -> Self {
+        let locks = vfat_filesystem.options.locks.clone();
+        let shared = match metadata.slot {
+            Some(slot) => vfat_filesystem.open_files.lock().open(slot, &metadata),
+            None => Arc::new(Mutex::new(SharedFile::new(metadata, &*locks), &*locks)),
+        };
+        let (metadata, generation, io_lock) = {
+            let shared = shared.lock();
+            (
+                shared.metadata.clone(),
+                shared.generation,
+                shared.io_lock.clone(),
+            )
+        };
         File {
             vfat_filesystem,
             metadata,
             offset: 0,
             writer: None,
             reader: None,
+            hints: Mutex::new(ChainHints::default(), &*locks),
+            shared,
+            generation,
+            io_lock,
+            mode: OpenOptions::read_write(),
         }
     }
-    /// Returns a reference to this file's [`Metadata`].
+    /// Returns a reference to this file's [`Metadata`], as of the last
+    /// operation on this handle. See [`File::size`] for the current size.
     pub fn metadata(&self) -> &Metadata {
         &self.metadata
     }
 
+    /// Returns the current size of the file in bytes, including the writes
+    /// made through other handles.
+    pub fn size(&self) -> u64 {
+        self.shared.lock().metadata.size as u64
+    }
+
+    /// Pick up the changes made through the other handles of this file.
+    fn sync(&mut self) {
+        let shared = self.shared.lock();
+        if shared.generation != self.generation {
+            self.generation = shared.generation;
+            self.writer = None;
+            self.reader = None;
+        }
+        self.metadata.clone_from(&shared.metadata);
+    }
+
+    /// The chain may have shrunk or changed head: drop the cached chain
+    /// positions, here and in the other handles of this file.
+    fn chain_changed(&mut self) {
+        self.writer = None;
+        self.reader = None;
+        let mut shared = self.shared.lock();
+        shared.generation += 1;
+        self.generation = shared.generation;
+    }
+
     fn update_file_size(&mut self, amount_written: usize) -> Result<()> {
         if self.offset + amount_written <= self.metadata.size as usize {
             return Ok(());
@@ -87,42 +240,170 @@ impl File {
         self.update_metadata()
     }
 
+    /// Share the metadata of this handle with the other ones, and write it
+    /// to the directory entry.
     fn update_metadata(&mut self) -> Result<()> {
+        let slot = {
+            let mut shared = self.shared.lock();
+            shared.metadata.clone_from(&self.metadata);
+            shared.dirty = true;
+            match shared.metadata.slot {
+                Some(slot) if !shared.unlinked => slot,
+                // There is no directory entry left to update.
+                _ => return Ok(()),
+            }
+        };
         debug!("Going to update metadata on disk...");
-        self.vfat_filesystem
-            .get_from_absolute_path_unlocked(self.metadata.parent().clone())?
-            .into_directory_unchecked()
-            .update_entry(self.metadata.clone())
+        // Renames take the volume lock exclusively, so the slot can't move
+        // while it's held shared.
+        let lock = self.vfat_filesystem.directory_lock(slot.directory);
+        let _guard = lock.write();
+        // Deleting the entry takes the directory lock too: once it is held,
+        // the slot is still this file's unless it was unlinked.
+        if self.shared.lock().unlinked {
+            return Ok(());
+        }
+        Directory::update_entry_at(&self.vfat_filesystem, slot, self.metadata.clone())?;
+        self.shared.lock().dirty = false;
+        Ok(())
     }
     fn full_path(&self) -> &PathBuf {
         self.metadata.full_path()
     }
 
+    fn ensure_readable(&self) -> Result<()> {
+        if self.mode.read {
+            return Ok(());
+        }
+        Err(VfatRsError::AccessDenied {
+            target: self.full_path().display().to_string(),
+            reason: "the file is not open for reading",
+        })
+    }
+
+    fn ensure_writable(&self) -> Result<()> {
+        if !self.mode.writable() {
+            return Err(VfatRsError::AccessDenied {
+                target: self.full_path().display().to_string(),
+                reason: "the file is not open for writing",
+            });
+        }
+        self.vfat_filesystem.ensure_modifiable(&self.metadata)
+    }
+
+    /// Update the last access date before a read, if
+    /// [`MountOptions::access_time`](crate::MountOptions::access_time) says
+    /// so. Returns the new date if it was updated.
+    ///
+    /// Expects the volume lock and the file's I/O lock held, shared at
+    /// least: writers, which could write back an older date, are excluded.
+    fn record_access(&self) -> Result<Option<VfatTimestamp>> {
+        let fs = &self.vfat_filesystem;
+        let policy = fs.options.access_time;
+        if policy == AccessTimePolicy::Never {
+            return Ok(None);
+        }
+        let utc_offset = fs.options.utc_offset;
+        let today = utc_offset.local_date(fs.time_manager.get_current_vfat_timestamp());
+        {
+            let shared = self.shared.lock();
+            if shared.unlinked || !policy.should_update(&shared.metadata, today, utc_offset) {
+                return Ok(None);
+            }
+        }
+        let Some(slot) = self.shared.lock().metadata.slot else {
+            return Ok(None);
+        };
+        let lock = fs.directory_lock(slot.directory);
+        let _guard = lock.write();
+        let metadata = {
+            let mut shared = self.shared.lock();
+            if shared.unlinked {
+                return Ok(None);
+            }
+            shared.metadata.last_access = today;
+            shared.metadata.clone()
+        };
+        Directory::update_entry_at(fs, slot, metadata)?;
+        Ok(Some(today))
+    }
+
+    /// Set the archive bit, as the content is about to change: backup tools
+    /// look for it to find what changed since they cleared it.
+    fn mark_modified(&mut self) -> Result<()> {
+        if self.metadata.attributes.is_archive() {
+            return Ok(());
+        }
+        self.metadata.attributes.set(attribute::ARCHIVE, true);
+        self.update_metadata()
+    }
+
+    /// Give the file its first cluster, if it has none yet.
+    fn allocate_first_cluster(&mut self) -> Result<()> {
+        if !self.metadata.has_no_cluster_allocated() {
+            return Ok(());
+        }
+        debug!("{:?}: has no cluster allocated.", self.full_path());
+        self.metadata.cluster = self.vfat_filesystem.allocate_cluster_new_entry()?;
+        debug!(
+            "{:?}: allocated Cluster('{}'), updating metadata...",
+            self.full_path(),
+            self.metadata.cluster
+        );
+        // The just-allocated cluster invalidates any previously cached writer.
+        self.chain_changed();
+        self.update_metadata()
+    }
+
+    /// Walk the cluster chain starting at `head` to the cluster with index
+    /// `index`, starting from the closest hint. Returns the hint slot used,
+    /// and the index and id of the furthest cluster reached: `index` itself,
+    /// unless the chain is shorter than that.
+    fn walk_chain_to(
+        &self,
+        head: ClusterId,
+        index: usize,
+    ) -> Result<(Option<usize>, usize, ClusterId)> {
+        let generation = self.shared.lock().generation;
+        let nearest = self.hints.lock().nearest(generation, index);
+        let (slot, (mut reached, mut cluster)) = match nearest {
+            Some((slot, hint)) => (Some(slot), hint),
+            None => (None, (0, head)),
+        };
+        while reached < index {
+            match fat_table::next_cluster(cluster, self.vfat_filesystem.device.clone())? {
+                Some(next) => {
+                    cluster = next;
+                    reached += 1;
+                }
+                None => break,
+            }
+        }
+        Ok((slot, reached, cluster))
+    }
+
     /// Write `buf` to this file at the current offset. Returns the number of bytes written.
     pub fn write(&mut self, buf: &[u8]) -> Result<usize> {
         let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.write();
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.write();
         self.write_unlocked(buf)
     }
 
-    fn write_unlocked(&mut self, buf: &[u8]) -> Result<usize> {
+    pub(crate) fn write_unlocked(&mut self, buf: &[u8]) -> Result<usize> {
+        // The attributes may have been changed through another handle.
+        self.sync();
+        self.ensure_writable()?;
         if buf.is_empty() {
             return Ok(0);
         }
-        debug!("{:?}: requested write", self.full_path(),);
-        if self.metadata.has_no_cluster_allocated() {
-            debug!("{:?}: has no cluster allocated.", self.full_path());
-            self.metadata.cluster = self.vfat_filesystem.allocate_cluster_new_entry()?;
-            debug!(
-                "{:?}: allocated Cluster('{}'), updating metadata...",
-                self.full_path(),
-                self.metadata.cluster
-            );
-            self.update_metadata()?;
-            // The just-allocated cluster invalidates any previously cached writer.
-            self.writer = None;
-            self.reader = None;
+        self.mark_modified()?;
+        if self.mode.append {
+            self.offset = self.metadata.size();
         }
+        debug!("{:?}: requested write", self.full_path(),);
+        self.allocate_first_cluster()?;
 
         // Reuse the warm writer if it is positioned exactly at the current offset,
         // otherwise build a fresh one and seek it (walking the chain from the start).
@@ -161,7 +442,17 @@ impl File {
     /// Flush any buffered data to the underlying block device.
     pub fn flush(&mut self) -> Result<()> {
         let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.write();
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.write();
+        self.sync();
+        let dirty = {
+            let shared = self.shared.lock();
+            shared.dirty && !shared.unlinked
+        };
+        if dirty {
+            self.update_metadata()?;
+        }
         // TODO, should flush only data wrt this file..
         self.vfat_filesystem.device.flush()
     }
@@ -169,7 +460,10 @@ impl File {
     /// Seek to a position in this file. Returns the new offset from the start.
     pub fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
         let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.write();
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.read();
+        self.sync();
         // Any explicit seek breaks the sequential-write fast path.
         self.writer = None;
         self.reader = None;
@@ -208,10 +502,17 @@ impl File {
     pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         let lock = self.vfat_filesystem.fs_lock.clone();
         let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.read();
         self.read_unlocked(buf)
     }
 
-    fn read_unlocked(&mut self, mut buf: &mut [u8]) -> Result<usize> {
+    pub(crate) fn read_unlocked(&mut self, mut buf: &mut [u8]) -> Result<usize> {
+        self.ensure_readable()?;
+        self.sync();
+        if let Some(accessed) = self.record_access()? {
+            self.metadata.last_access = accessed;
+        }
         // TODO: if cluster is deleted, it should fail.
         // it should read at most the buf size or the missing file data.
         let amount_to_read = cmp::min(buf.len(), self.metadata.size().saturating_sub(self.offset));
@@ -250,6 +551,111 @@ impl File {
         Ok(amount_read)
     }
 
+    /// Read from this file at `offset` into `buf`, without using or moving
+    /// the current offset. Returns the number of bytes read, 0 at or past the
+    /// end of the file.
+    ///
+    /// Only needs `&self`, so a file can be read from several places at once.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let _guard = self.vfat_filesystem.fs_lock.read();
+        let _io_guard = self.io_lock.read();
+        self.read_at_unlocked(offset, buf)
+    }
+
+    pub(crate) fn read_at_unlocked(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.ensure_readable()?;
+        self.record_access()?;
+        let (size, head) = {
+            let shared = self.shared.lock();
+            (shared.metadata.size as u64, shared.metadata.cluster)
+        };
+        if buf.is_empty() || offset >= size || head == ClusterId::new(0) {
+            return Ok(0);
+        }
+        let amount_to_read = cmp::min(buf.len() as u64, size - offset) as usize;
+        let bytes_per_cluster = self.vfat_filesystem.bytes_per_cluster() as u64;
+        let index = (offset / bytes_per_cluster) as usize;
+        let (slot, reached, cluster) = self.walk_chain_to(head, index)?;
+        if reached < index {
+            return Err(VfatRsError::FilesystemCorrupted {
+                reason: "cluster chain is shorter than the file size",
+            });
+        }
+        let mut reader = self.vfat_filesystem.cluster_chain_reader(cluster);
+        reader.seek((offset % bytes_per_cluster) as usize)?;
+        let amount_read = reader.read(&mut buf[..amount_to_read])?;
+        if amount_read > 0
+            && let Some(cluster) = reader.current_cluster()
+        {
+            let last = ((offset + amount_read as u64 - 1) / bytes_per_cluster) as usize;
+            self.hints.lock().remember(slot, last, cluster);
+        }
+        Ok(amount_read)
+    }
+
+    /// Write `buf` to this file at `offset`, without using or moving the
+    /// current offset. Returns the number of bytes written.
+    ///
+    /// Writing past the end of the file first fills the gap with zeros, since
+    /// FAT has no sparse files. In append mode `offset` is ignored and `buf`
+    /// goes to the end of the file.
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+        let lock = self.vfat_filesystem.fs_lock.clone();
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.write();
+        self.write_at_unlocked(offset, buf)
+    }
+
+    pub(crate) fn write_at_unlocked(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+        self.sync();
+        self.ensure_writable()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.mark_modified()?;
+        let mut offset = offset;
+        if self.mode.append {
+            offset = self.metadata.size as u64;
+        }
+        self.allocate_first_cluster()?;
+        if (self.metadata.size as u64) < offset {
+            let zeros = vec![0u8; self.vfat_filesystem.bytes_per_cluster() as usize];
+            while (self.metadata.size as u64) < offset {
+                let size = self.metadata.size as u64;
+                let chunk = cmp::min(zeros.len() as u64, offset - size) as usize;
+                self.write_chain_at(size, &zeros[..chunk])?;
+            }
+        }
+        self.write_chain_at(offset, buf)
+    }
+
+    /// Write `buf` at `offset`, which must not be past the end of the file,
+    /// growing the chain and the size as needed.
+    fn write_chain_at(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+        let bytes_per_cluster = self.vfat_filesystem.bytes_per_cluster() as u64;
+        let index = (offset / bytes_per_cluster) as usize;
+        let (slot, reached, cluster) = self.walk_chain_to(self.metadata.cluster, index)?;
+        // The writer allocates the clusters between the end of the chain and
+        // `offset`, if any.
+        let mut writer =
+            ClusterChainWriter::new(self.vfat_filesystem.clone(), cluster, SectorId::from(0), 0);
+        writer.seek((offset - reached as u64 * bytes_per_cluster) as usize)?;
+        let amount_written = writer.write(buf)?;
+        if amount_written > 0 {
+            let last = ((offset + amount_written as u64 - 1) / bytes_per_cluster) as usize;
+            self.hints
+                .get_mut()
+                .remember(slot, last, writer.current_cluster);
+        }
+        let end = offset + amount_written as u64;
+        if end > self.metadata.size as u64 {
+            self.metadata.size = end as u32;
+            self.update_metadata()?;
+        }
+        Ok(amount_written)
+    }
+
     fn _sync(&mut self) -> Result<()> {
         self.flush()
     }
@@ -259,29 +665,68 @@ impl File {
     /// If `new_size` is greater than or equal to the current size, this is a no-op.
     /// If `new_size` is 0, the entire cluster chain is freed.
     /// Otherwise, excess clusters are freed and the metadata is updated.
+    ///
+    /// If the volume was mounted with [`MountOptions::secure_delete`](crate::MountOptions::secure_delete),
+    /// this behaves like [`File::secure_truncate`].
     pub fn truncate(&mut self, new_size: u32) -> Result<()> {
         let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.write();
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.write();
+        let wipe = self.vfat_filesystem.options.secure_delete;
+        self.truncate_unlocked(new_size, wipe)
+    }
+
+    /// Truncate the file to `new_size` bytes like [`File::truncate`], first
+    /// overwriting with `pattern` everything past the new end: the freed
+    /// clusters and the rest of the last kept one.
+    pub fn secure_truncate(&mut self, new_size: u32, pattern: WipePattern) -> Result<()> {
+        let lock = self.vfat_filesystem.fs_lock.clone();
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.write();
+        self.truncate_unlocked(new_size, Some(pattern))
+    }
 
+    pub(crate) fn truncate_unlocked(
+        &mut self,
+        new_size: u32,
+        wipe: Option<WipePattern>,
+    ) -> Result<()> {
+        self.sync();
+        self.ensure_writable()?;
         if new_size >= self.metadata.size {
             return Ok(());
         }
+        self.mark_modified()?;
 
         // Freeing clusters can make a cached writer point at a released cluster.
-        self.writer = None;
-        self.reader = None;
+        self.chain_changed();
 
         if new_size == 0 {
             if !self.metadata.has_no_cluster_allocated() {
                 self.vfat_filesystem
-                    .delete_fat_cluster_chain(self.metadata.cluster)?;
+                    .delete_fat_cluster_chain(self.metadata.cluster, wipe)?;
                 self.metadata.cluster = ClusterId::new(0);
             }
         } else {
             let bpc = self.vfat_filesystem.bytes_per_cluster();
             let keep_count = (new_size as u64).div_ceil(bpc as u64);
-            self.vfat_filesystem
-                .truncate_cluster_chain(self.metadata.cluster, keep_count as u32)?;
+            self.vfat_filesystem.truncate_cluster_chain(
+                self.metadata.cluster,
+                keep_count as u32,
+                wipe,
+            )?;
+            let slack = (keep_count * bpc as u64 - new_size as u64) as usize;
+            if let Some(pattern) = wipe
+                && slack > 0
+            {
+                let mut writer = self
+                    .vfat_filesystem
+                    .cluster_chain_writer(self.metadata.cluster);
+                writer.seek(new_size as usize)?;
+                writer.write(&vec![pattern.byte(); slack])?;
+            }
         }
 
         self.metadata.size = new_size;
@@ -291,24 +736,201 @@ impl File {
         self.update_metadata()
     }
 
+    /// Reserve clusters for the first `len` bytes of this file, like `fallocate`.
+    ///
+    /// Clusters the file already owns are kept; allocation never shrinks a file.
+    /// Unless [`AllocateMode::keep_size`] is set, the file size grows to `len`
+    /// and the new bytes read back as zeros (they are written to the device).
+    /// The reservation is all-or-nothing: on error no clusters are leaked.
+    ///
+    /// Note that clusters reserved past the end of the file with `keep_size`
+    /// are reported as a size/chain mismatch by `fsck.fat`.
+    pub fn allocate(&mut self, len: u32, mode: AllocateMode) -> Result<()> {
+        let lock = self.vfat_filesystem.fs_lock.clone();
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.write();
+        self.allocate_unlocked(len, mode)
+    }
+
+    pub(crate) fn allocate_unlocked(&mut self, len: u32, mode: AllocateMode) -> Result<()> {
+        self.sync();
+        self.ensure_writable()?;
+        let bytes_per_cluster = self.vfat_filesystem.bytes_per_cluster() as u64;
+        let clusters = (len as u64).div_ceil(bytes_per_cluster) as u32;
+        let mut changed = false;
+        if clusters > 0 {
+            let head = (!self.metadata.has_no_cluster_allocated()).then_some(self.metadata.cluster);
+            let head = self
+                .vfat_filesystem
+                .reserve_clusters(head, clusters, mode.contiguous)?;
+            if head != self.metadata.cluster {
+                self.metadata.cluster = head;
+                self.chain_changed();
+                changed = true;
+            }
+        }
+        if !mode.keep_size && len > self.metadata.size {
+            // Clear whatever the reserved clusters contained before, so growing
+            // the file never exposes stale data.
+            let mut writer = self
+                .vfat_filesystem
+                .cluster_chain_writer(self.metadata.cluster);
+            writer.seek(self.metadata.size as usize)?;
+            let zeros = alloc::vec![0u8; bytes_per_cluster as usize];
+            let mut remaining = (len - self.metadata.size) as usize;
+            while remaining > 0 {
+                let chunk = cmp::min(remaining, zeros.len());
+                writer.write(&zeros[..chunk])?;
+                remaining -= chunk;
+            }
+            self.writer = None;
+            self.metadata.size = len;
+            changed = true;
+        }
+        if changed {
+            self.metadata.attributes.set(attribute::ARCHIVE, true);
+            self.update_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// Map the file's cluster chain to runs of consecutive sectors, in file
+    /// order, without reading any data.
+    ///
+    /// Extents cover every allocated cluster, so the last one may extend past
+    /// the end of the file (and further with [`AllocateMode::keep_size`]). An
+    /// empty file has no extents.
+    pub fn extents(&self) -> Result<Vec<Extent>> {
+        let _guard = self.vfat_filesystem.fs_lock.read();
+        let _io_guard = self.io_lock.read();
+        let mut extents = Vec::new();
+        self.for_each_extent(|extent| extents.push(extent))?;
+        Ok(extents)
+    }
+
+    /// Return the device sector holding the byte at `offset`, or `None` if
+    /// no cluster is allocated at that offset.
+    pub fn bmap(&self, offset: u64) -> Result<Option<SectorId>> {
+        let _guard = self.vfat_filesystem.fs_lock.read();
+        let _io_guard = self.io_lock.read();
+        let head = self.shared.lock().metadata.cluster;
+        if head == ClusterId::new(0) {
+            return Ok(None);
+        }
+        let fs = &self.vfat_filesystem;
+        let bytes_per_cluster = fs.bytes_per_cluster() as u64;
+        let index = (offset / bytes_per_cluster) as usize;
+        // Only the chain up to the cluster holding `offset` is walked.
+        let (slot, reached, cluster) = self.walk_chain_to(head, index)?;
+        self.hints.lock().remember(slot, reached, cluster);
+        if reached < index {
+            return Ok(None);
+        }
+        let sector_size = fs.device.sector_size as u64;
+        let within = (offset % bytes_per_cluster) / sector_size;
+        Ok(Some(fs.device.cluster_to_sector(cluster) + within as u32))
+    }
+
+    fn for_each_extent<F: FnMut(Extent)>(&self, mut f: F) -> Result<()> {
+        let head = self.shared.lock().metadata.cluster;
+        if head == ClusterId::new(0) {
+            return Ok(());
+        }
+        let fs = &self.vfat_filesystem;
+        let bytes_per_cluster = fs.bytes_per_cluster() as u64;
+        let mut file_offset = 0;
+        fat_table::for_each_run(head, fs.device.clone(), |start, len| {
+            let length = len as u64 * bytes_per_cluster;
+            f(Extent {
+                file_offset,
+                start_sector: fs.device.cluster_to_sector(start),
+                length,
+            });
+            file_offset += length;
+        })
+    }
+
     /// Overwrite this file's creation and/or last-modification timestamps and
     /// flush the change to the on-disk directory entry. A `None` argument leaves
-    /// the corresponding timestamp unchanged.
+    /// the corresponding timestamp unchanged. See [`File::set_times`] to also
+    /// set the access date and the creation time to the hundredth of a second.
     ///
     /// Timestamps are stored with VFAT's 2-second resolution and a 1980 epoch
     /// floor (see [`VfatTimestamp`](crate::VfatTimestamp)), so the persisted
-    /// value may be rounded relative to the requested one. FAT has no separate
-    /// last-access *time* (only a date), so it is not represented here.
+    /// value may be rounded relative to the requested one.
     pub fn set_timestamps(
         &mut self,
         creation: Option<crate::VfatTimestamp>,
         modification: Option<crate::VfatTimestamp>,
     ) -> Result<()> {
+        self.set_times(FileTimes {
+            created: creation,
+            modified: modification,
+            ..FileTimes::default()
+        })
+    }
+
+    /// Overwrite the timestamps of this file set in `times` and flush the
+    /// change to the on-disk directory entry. Only the date of the last
+    /// access is stored.
+    pub fn set_times(&mut self, times: FileTimes) -> Result<()> {
         let lock = self.vfat_filesystem.fs_lock.clone();
-        let _guard = lock.write();
-        self.metadata.set_timestamps(creation, modification);
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.write();
+        self.sync();
+        let utc_offset = self.vfat_filesystem.options.utc_offset;
+        self.metadata.set_times(&FileTimes {
+            accessed: times
+                .accessed
+                .map(|accessed| utc_offset.local_date(accessed)),
+            ..times
+        });
         self.update_metadata()
     }
+
+    /// Replace the read-only, hidden, system and archive attributes of this
+    /// file and write them to its directory entry. The directory and volume
+    /// label bits can't be changed.
+    ///
+    /// The archive bit is set again by the next change to the content. The
+    /// attributes of a read-only file can be changed, to clear the bit, and
+    /// through a handle not open for writing.
+    pub fn set_attributes(&mut self, attributes: Attributes) -> Result<()> {
+        let lock = self.vfat_filesystem.fs_lock.clone();
+        let _guard = lock.read();
+        let io_lock = self.io_lock.clone();
+        let _io_guard = io_lock.write();
+        self.sync();
+        self.metadata.set_attributes(attributes);
+        self.update_metadata()
+    }
+}
+
+impl Drop for File {
+    /// Close the handle. The last handle of a file deleted while open frees
+    /// its clusters.
+    fn drop(&mut self) {
+        let fs = &self.vfat_filesystem;
+        let Some((cluster, wipe)) = fs.open_files.lock().close(&self.shared) else {
+            return;
+        };
+        // The handle may be dropped by a caller holding the volume lock
+        // exclusively: never wait for it, leave the chain to the next one
+        // instead.
+        match fs.fs_lock.try_read() {
+            Some(_guard) => {
+                let freed = fs
+                    .delete_fat_cluster_chain(cluster, wipe)
+                    .and_then(|()| fs.release_orphans());
+                if let Err(err) = freed {
+                    error!("Freeing the clusters of a deleted file failed: {err}");
+                }
+            }
+            None => fs.open_files.lock().push_orphan(cluster, wipe),
+        }
+    }
 }
 
 impl Write for File {
@@ -1,16 +1,23 @@
 use crate::ClusterId;
 use crate::PathBuf;
-use crate::api::raw_directory_entry::Attributes;
-use crate::api::timestamp::VfatTimestamp;
+use crate::api::raw_directory_entry::{Attributes, attribute};
+use crate::api::timestamp::{FileTimes, Milliseconds, VfatTimestamp};
+use crate::open_files::EntrySlot;
+use alloc::format;
 use alloc::string::String;
 
 /// Metadatas are common to every entry type.
 #[derive(Debug, Clone)]
 pub struct Metadata {
     creation: VfatTimestamp,
+    /// Hundredths of a second past `creation`, 0 to 199.
+    pub(crate) creation_centiseconds: Milliseconds,
     last_update: VfatTimestamp,
-    //last_access: VfatTimestamp,
+    /// Only the date is kept: the start of the local day, in UTC.
+    pub(crate) last_access: VfatTimestamp,
     pub(crate) name: String,
+    /// The 8.3 alias, see [`Metadata::short_name`].
+    pub(crate) short_name: String,
     /// Size of this file in bytes. For directories, it should be the sum of the sizes
     /// occupied by the metadatas of the contained files.
     pub(crate) size: u32,
@@ -21,13 +28,14 @@ pub struct Metadata {
     /// The path to this file - it doesn't include the file name.
     parent: PathBuf,
     pub(crate) attributes: Attributes,
+    /// Where the entry lives in its parent directory, `None` for the root.
+    pub(crate) slot: Option<EntrySlot>,
 }
 
 impl Metadata {
     pub(crate) fn new<S: AsRef<str>>(
         creation: VfatTimestamp,
         last_update: VfatTimestamp,
-        //last_access: VfatTimestamp,
         name: S,
         size: u32,
         path: PathBuf,
@@ -37,14 +45,17 @@ impl Metadata {
     ) -> Self {
         Self {
             creation,
+            creation_centiseconds: 0,
             last_update,
-            //last_access,
+            last_access: last_update.date(),
             name: String::from(name.as_ref()),
+            short_name: String::new(),
             size,
             path,
             cluster,
             parent,
             attributes,
+            slot: None,
         }
     }
 }
@@ -54,13 +65,6 @@ impl Metadata {
         self.size as usize
     }
 
-    /*
-    fn last_access(&self) -> Option<VfatTimestamp> {
-        //Some(self.last_access)
-        None
-    }
-    */
-
     pub(crate) fn last_update(&self) -> Option<VfatTimestamp> {
         Some(self.last_update)
     }
@@ -74,11 +78,44 @@ impl Metadata {
         self.creation
     }
 
+    /// Returns the hundredths of a second past [`Metadata::created`], 0 to
+    /// 199: creation times have a 10 ms resolution.
+    pub fn creation_centiseconds(&self) -> Milliseconds {
+        self.creation_centiseconds
+    }
+
+    /// Returns the creation time in milliseconds since the Unix epoch, with
+    /// its full 10 ms resolution.
+    pub fn created_unix_millis(&self) -> u64 {
+        self.creation.to_unix_timestamp() * 1000 + self.creation_centiseconds as u64 * 10
+    }
+
     /// Returns the entry's last-modification timestamp.
     pub fn modified(&self) -> VfatTimestamp {
         self.last_update
     }
 
+    /// Returns the entry's last access date, at local midnight: FAT doesn't
+    /// keep the time. See [`MountOptions::access_time`](crate::MountOptions::access_time)
+    /// for when reads update it.
+    pub fn accessed(&self) -> VfatTimestamp {
+        self.last_access
+    }
+
+    /// Returns the entry's FAT attributes: read-only, hidden, system and
+    /// archive bits, and whether it is a directory.
+    pub fn attributes(&self) -> Attributes {
+        self.attributes
+    }
+
+    /// Replace the attribute bits of this entry, keeping the ones telling
+    /// what it is (directory, volume label). In-memory only, like
+    /// [`Metadata::set_timestamps`].
+    pub(crate) fn set_attributes(&mut self, attributes: Attributes) {
+        self.attributes =
+            Attributes((attributes.0 & !attribute::TYPE) | (self.attributes.0 & attribute::TYPE));
+    }
+
     /// Returns the full path to this entry (including the entry name).
     pub fn full_path(&self) -> &PathBuf {
         &self.path
@@ -90,24 +127,47 @@ impl Metadata {
     pub fn name(&self) -> &str {
         &self.name
     }
+    /// Returns the entry's 8.3 short name (e.g. `PROGRA~1`), like `dir /x`
+    /// shows it. Equal to [`Metadata::name`] for entries without a long name,
+    /// and empty for the root directory.
+    pub fn short_name(&self) -> &str {
+        &self.short_name
+    }
+    /// The entry was renamed to `name`, in the directory whose path is
+    /// `parent`.
+    pub(crate) fn relocate(&mut self, name: String, parent: PathBuf) {
+        self.path = PathBuf::from(format!("{}{name}", parent.display()));
+        self.name = name;
+        self.parent = parent;
+    }
     pub(crate) fn has_no_cluster_allocated(&self) -> bool {
         self.cluster == ClusterId::new(0)
     }
 
-    /// Overwrite the creation and/or last-modification timestamps. A `None`
-    /// argument leaves the corresponding field unchanged. This only mutates the
-    /// in-memory metadata; callers must flush it to disk (e.g. via
-    /// [`File::set_timestamps`]).
-    pub(crate) fn set_timestamps(
-        &mut self,
-        creation: Option<VfatTimestamp>,
-        last_update: Option<VfatTimestamp>,
-    ) {
-        if let Some(creation) = creation {
+    /// All the timestamps of this entry.
+    pub(crate) fn times(&self) -> FileTimes {
+        FileTimes {
+            created: Some(self.creation),
+            created_centiseconds: self.creation_centiseconds,
+            modified: Some(self.last_update),
+            accessed: Some(self.last_access),
+        }
+    }
+
+    /// Overwrite the timestamps set in `times`, leaving the `None` ones
+    /// unchanged. This only mutates the in-memory metadata; callers must
+    /// flush it to disk (e.g. via [`File::set_times`](crate::File::set_times)).
+    /// The access date is kept as given, already truncated to the day.
+    pub(crate) fn set_times(&mut self, times: &FileTimes) {
+        if let Some(creation) = times.created {
             self.creation = creation;
+            self.creation_centiseconds = times.created_centiseconds.min(199);
         }
-        if let Some(last_update) = last_update {
+        if let Some(last_update) = times.modified {
             self.last_update = last_update;
         }
+        if let Some(last_access) = times.accessed {
+            self.last_access = last_access;
+        }
     }
 }
@@ -1,13 +1,20 @@
+pub(crate) mod case_folding;
 mod directory;
 mod directory_entry;
+mod directory_iter;
 mod file;
 mod metadata;
+mod open_options;
 /// Raw 32-byte FAT directory entry types and parsing.
 pub mod raw_directory_entry;
 /// VFAT timestamp representation and conversion.
 pub mod timestamp;
+mod undelete;
 
 pub use directory::*;
 pub use directory_entry::*;
+pub use directory_iter::DirectoryIter;
 pub use file::*;
 pub use metadata::*;
+pub use open_options::OpenOptions;
+pub use undelete::*;
@@ -0,0 +1,96 @@
+use crate::io::{Error, ErrorKind};
+use crate::{Result, VfatRsError};
+
+/// Options to open a [`File`](crate::File) with, like `std::fs::OpenOptions`.
+///
+/// Used by [`VfatFS::open_with`](crate::VfatFS::open_with) and
+/// [`Directory::open_with`](crate::Directory::open_with). The returned
+/// `File` enforces the access mode: reading a handle not opened for reading,
+/// or writing, truncating or allocating through one not opened for writing,
+/// fails with [`VfatRsError::AccessDenied`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenOptions {
+    pub(crate) read: bool,
+    pub(crate) write: bool,
+    pub(crate) append: bool,
+    pub(crate) truncate: bool,
+    pub(crate) create: bool,
+    pub(crate) create_new: bool,
+}
+
+impl OpenOptions {
+    /// All options set to `false`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options of a handle open for reading and writing, the mode of the
+    /// handles made from directory entries.
+    pub(crate) fn read_write() -> Self {
+        Self {
+            read: true,
+            write: true,
+            ..Self::default()
+        }
+    }
+
+    /// Open for reading.
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Open for writing.
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Open for appending: every write goes to the current end of the file,
+    /// whatever the offset of the handle. Implies `write`.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Truncate the file to 0 bytes when opening it. Needs `write`.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Create the file if it doesn't exist. Needs `write` or `append`.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Create the file, failing with [`VfatRsError::NameAlreadyInUse`] if it
+    /// already exists. `create` and `truncate` are then ignored. Needs `write`
+    /// or `append`.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    pub(crate) fn writable(&self) -> bool {
+        self.write || self.append
+    }
+
+    /// Reject the combinations `std` rejects, with `InvalidInput`.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let reason = if !self.read && !self.writable() {
+            "the file must be opened for reading, writing or appending"
+        } else if !self.writable() && (self.truncate || self.create || self.create_new) {
+            "creating or truncating needs write or append access"
+        } else if self.append && self.truncate && !self.create_new {
+            "append and truncate can't be combined"
+        } else {
+            return Ok(());
+        };
+        Err(VfatRsError::from(Error::new(
+            ErrorKind::InvalidInput,
+            reason,
+        )))
+    }
+}
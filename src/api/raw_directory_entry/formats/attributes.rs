@@ -18,10 +18,13 @@ pub mod attribute {
     pub const ARCHIVE: u8 = 0x20;
     /// Long file name entry (combination of READ_ONLY | HIDDEN | SYSTEM | VOLUME_ID).
     pub const LFN: u8 = READ_ONLY | HIDDEN | SYSTEM | VOLUME_ID;
+    /// The attributes describing what an entry is rather than flags set on
+    /// it, which can't be changed.
+    pub(crate) const TYPE: u8 = VOLUME_ID | DIRECTORY;
 }
 
 /// A FAT directory entry's attributes byte.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct Attributes(pub u8);
 impl Attributes {
@@ -29,6 +32,19 @@ impl Attributes {
     pub fn new_directory() -> Self {
         Self(attribute::DIRECTORY)
     }
+    /// Create attributes for a new file: only the archive bit is set, as
+    /// the file wasn't backed up yet.
+    pub fn new_file() -> Self {
+        Self(attribute::ARCHIVE)
+    }
+    /// Set or clear `attribute`, one of the [`attribute`] constants.
+    pub fn set(&mut self, attribute: u8, value: bool) {
+        if value {
+            self.0 |= attribute;
+        } else {
+            self.0 &= !attribute;
+        }
+    }
     fn matches(&self, attribute: u8) -> bool {
         self.0 & attribute == attribute
     }
@@ -5,6 +5,9 @@ use alloc::string::String;
 use core::fmt;
 use core::fmt::{Debug, Formatter};
 
+/// Number of name characters stored in a single LFN entry.
+pub(crate) const LFN_CHARACTERS: usize = 13;
+
 // Sequence Number
 // Bit 6 set: last logical LFN entry.
 // Bit 5 clear: first physical LFN entry
@@ -100,30 +103,28 @@ impl LongFileNameEntry {
         self.attributes.is_lfn()
     }
 
-    // Returns a subset of the input, early stopping according to LFN name rules.
-    fn early_terminate_pos(name_array: &[u16]) -> &[u16] {
-        let get_pos = |string: &[u16]| {
-            for (pos, ch) in string.iter().enumerate() {
-                if *ch == 0x00 || *ch == 0xFFFF {
-                    return pos;
-                }
-            }
-            string.len()
-        };
-        &name_array[..get_pos(name_array)]
+    /// The name characters stored in this entry, padding included.
+    ///
+    /// A character outside the Basic Multilingual Plane takes two UTF-16
+    /// units, which may be stored in different entries: join the units of all
+    /// the entries of a name before decoding them with [`Self::decode_name`].
+    pub fn name_units(&self) -> [u16; LFN_CHARACTERS] {
+        let mut units = [0u16; LFN_CHARACTERS];
+        units[..5].copy_from_slice(&{ self.name_characters });
+        units[5..11].copy_from_slice(&{ self.second_set_name });
+        units[11..].copy_from_slice(&{ self.third_set_name });
+        units
     }
 
-    /// Collect the name fragment stored in this LFN entry.
-    pub fn collect_name(&self) -> String {
-        let name_characters = { self.name_characters };
-        let second_set_name = { self.second_set_name };
-        let third_set_name = { self.third_set_name };
-        alloc::format!(
-            "{}{}{}",
-            String::from_utf16_lossy(Self::early_terminate_pos(&name_characters)),
-            String::from_utf16_lossy(Self::early_terminate_pos(&second_set_name)),
-            String::from_utf16_lossy(Self::early_terminate_pos(&third_set_name)),
-        )
+    /// Decode the UTF-16 `units` of a long name, stopping at the first 0x0000
+    /// or 0xFFFF character, which terminate it early.
+    pub fn decode_name(units: impl IntoIterator<Item = u16>) -> String {
+        let units = units
+            .into_iter()
+            .take_while(|&unit| unit != 0x0000 && unit != 0xFFFF);
+        char::decode_utf16(units)
+            .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
     }
     /// If the sequence number is 0x00, the previous entry was the last entry.
     pub fn was_last_entry_last(&self) -> bool {
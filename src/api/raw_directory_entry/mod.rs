@@ -10,6 +10,7 @@ use log::{debug, info};
 
 use crate::ClusterId;
 pub use crate::api::raw_directory_entry::formats::{Attributes, EntryId, attribute};
+pub(crate) use crate::api::raw_directory_entry::long_file_name_entry::LFN_CHARACTERS;
 pub use crate::api::raw_directory_entry::long_file_name_entry::{
     LongFileNameEntry, SequenceNumber,
 };
@@ -73,9 +74,19 @@ impl VfatDirectoryEntry {
     pub(crate) fn create_pseudo_dir_entries(
         current_dir: ClusterId,
         parent_dir: ClusterId,
+        root_dir: ClusterId,
     ) -> [UnknownDirectoryEntry; 2] {
         let (current_high, current_low) = current_dir.into_high_low();
-        let current_name = [DOT_CHARACTER, 0, 0, 0, 0, 0, 0, 0];
+        let current_name = [
+            DOT_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+        ];
         let file_ext = [PADDING_CHARACTER; 3];
         let attributes = Attributes::new_directory();
         let new_regular_dir_entry = |name, high, low| RegularDirectoryEntry {
@@ -93,13 +104,22 @@ impl VfatDirectoryEntry {
         };
         let current_entry = new_regular_dir_entry(current_name, current_high, current_low);
 
-        let parent_name = [DOT_CHARACTER, DOT_CHARACTER, 0, 0, 0, 0, 0, 0];
+        let parent_name = [
+            DOT_CHARACTER,
+            DOT_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+            PADDING_CHARACTER,
+        ];
 
         // According to experiments against Linux fat32 driver, when I create a directory under root
-        // it uses ClusterId(0) instead of ClusterId(2).
+        // it uses ClusterId(0) instead of the root's cluster, whichever cluster the root starts at.
         // I'm not sure the reason behind it, but I need this otherwise the directory is not interpreted
         // correctly.
-        let (parent_high, parent_low) = if parent_dir == ClusterId::new(2) {
+        let (parent_high, parent_low) = if parent_dir == root_dir {
             ClusterId::new(0)
         } else {
             parent_dir
@@ -173,6 +193,9 @@ impl VfatDirectoryEntry {
             .filter(|&ch| ch != ' ' && ch != '.')
             .map(replace_invalid_dos_char)
             .flat_map(char::to_uppercase)
+            // Short names are single-byte: characters outside ASCII have no
+            // OEM code page mapping here.
+            .map(|ch| if ch.is_ascii() { ch } else { '_' })
             .take(prefix_len)
             .collect();
         let regular_filename_bytes = regular_filename_substr.as_bytes();
@@ -210,7 +233,7 @@ impl VfatDirectoryEntry {
     ///         sum = (((sum&1)<<7)|((sum&0xfe)>>1)) + name[i]
     ///  }
     /// ```
-    fn checksum(name: &[u8], ext: &[u8]) -> u8 {
+    pub(crate) fn checksum(name: &[u8], ext: &[u8]) -> u8 {
         let mut sum = 0u8;
         for ch in name.iter().chain(ext) {
             sum = ((sum & 1) << 7)
@@ -219,10 +242,16 @@ impl VfatDirectoryEntry {
         }
         sum
     }
+    #[cfg(test)]
     pub(crate) fn convert<const T: usize>(buf: &[u8]) -> [u16; T] {
+        Self::lfn_characters(&buf.iter().map(|v| *v as u16).collect::<Vec<u16>>())
+    }
+    /// Pad a slice of UTF-16 code units to a name field of an LFN entry.
+    pub(crate) fn lfn_characters<const T: usize>(units: &[u16]) -> [u16; T] {
         let padding = || iter::repeat(0x0000u16);
-        buf.iter()
-            .map(|v| *v as u16)
+        units
+            .iter()
+            .copied()
             .chain(padding())
             .take(T)
             .collect::<Vec<u16>>()
@@ -245,10 +274,12 @@ impl VfatDirectoryEntry {
         existing_short_names: &[[u8; 8]],
     ) -> crate::error::Result<Vec<UnknownDirectoryEntry>> {
         const MAX_LFN_NAME_LEN: usize = 255;
-        if name.len() > MAX_LFN_NAME_LEN {
+        // Long names are stored as UTF-16.
+        let units: Vec<u16> = name.encode_utf16().collect();
+        if units.len() > MAX_LFN_NAME_LEN {
             return Err(crate::error::VfatRsError::NameTooLong {
                 name: String::from(name),
-                length: name.len(),
+                length: units.len(),
             });
         }
 
@@ -284,10 +315,9 @@ impl VfatDirectoryEntry {
             file_size,
         };
         let mut ret = vec![];
-        let mut buff_b = name;
+        let mut buff_b = units.as_slice();
         // Calculate how many lfns we will need.
-        const SINGLE_LFN_SIZE: usize = 5 + 6 + 2;
-        let required_lfns = name.len().div_ceil(SINGLE_LFN_SIZE) as u8;
+        let required_lfns = units.len().div_ceil(LFN_CHARACTERS) as u8;
         debug!("Required LFNS: {}", required_lfns);
         // Other then for stopping the loop below, it's also useful for the SequenceNumber attribute.
 
@@ -301,9 +331,9 @@ impl VfatDirectoryEntry {
                 "LongFileName: full name:'{:?}', first_set: '{:?}' second_set: '{:?}', third_set: '{:?}'",
                 name, first_set_str, second_set_str, third_set_str
             );
-            let first_set = Self::convert(first_set_str.as_bytes());
-            let second_set = Self::convert(second_set_str.as_bytes());
-            let third_set = Self::convert(third_set_str.as_bytes());
+            let first_set = Self::lfn_characters(first_set_str);
+            let second_set = Self::lfn_characters(second_set_str);
+            let third_set = Self::lfn_characters(third_set_str);
             info!(
                 "final sets: {:?}, {:?}, {:?}",
                 first_set, second_set, third_set
@@ -403,7 +433,7 @@ mod test {
         let expecte_ext = b"EXT";
         assert!(!given.is_empty());
 
-        let lfn: LongFileNameEntry = VfatDirectoryEntry::from(given.get(0).unwrap())
+        let lfn: LongFileNameEntry = VfatDirectoryEntry::from(given.first().unwrap())
             .into_long_file_name()
             .unwrap();
         let first_set: [u16; 5] = VfatDirectoryEntry::convert(b"4char");
@@ -6,7 +6,7 @@ use core::fmt::{Debug, Formatter};
 use crate::api::Metadata;
 use crate::api::raw_directory_entry::{Attributes, VfatDirectoryEntry};
 use crate::api::timestamp::{Milliseconds, VfatTimestamp};
-use crate::{ClusterId, const_assert_size};
+use crate::{ClusterId, UtcOffset, const_assert_size};
 
 /// A standard 8.3 FAT directory entry (32 bytes).
 #[derive(Copy, Clone)]
@@ -58,8 +58,10 @@ impl Debug for RegularDirectoryEntry {
     }
 }
 
-impl From<Metadata> for RegularDirectoryEntry {
-    fn from(metadata: Metadata) -> Self {
+impl RegularDirectoryEntry {
+    /// The entry for `metadata`, with its timestamps in the local time of
+    /// `utc_offset`.
+    pub(crate) fn from_metadata(metadata: Metadata, utc_offset: UtcOffset) -> Self {
         let file_name = VfatDirectoryEntry::regular_filename_from(metadata.name());
         let file_ext = VfatDirectoryEntry::get_regular_filename_ext(metadata.name());
         let (high_16bits, low_16bits) = metadata.cluster.into_high_low();
@@ -69,12 +71,12 @@ impl From<Metadata> for RegularDirectoryEntry {
             high_16bits,
             low_16bits,
             attributes: metadata.attributes,
-            creation_time: metadata.creation().unwrap(),
-            last_modification_time: metadata.last_update().unwrap(),
+            creation_time: utc_offset.to_local(metadata.creation().unwrap()),
+            last_modification_time: utc_offset.to_local(metadata.last_update().unwrap()),
             file_size: metadata.size,
             _reseverd_win_nt: 0,
-            creation_millis: Default::default(),
-            last_access_date: 0,
+            creation_millis: metadata.creation_centiseconds,
+            last_access_date: utc_offset.to_local(metadata.last_access).date_bits(),
         }
     }
 }
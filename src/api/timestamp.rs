@@ -9,10 +9,25 @@ use crate::defbit;
 use core::cmp::max;
 use core::fmt::Display;
 
-/// Tenths of a second. Range 0-199 inclusive,
-/// as represented in FAT32 on-disk structures.
+/// Hundredths of a second past a [`VfatTimestamp`], for the creation time.
+/// Range 0-199 inclusive, as represented in FAT32 on-disk structures.
 pub type Milliseconds = u8;
 
+/// Timestamps to set with [`File::set_times`](crate::File::set_times).
+/// `None` fields are left unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileTimes {
+    /// Creation time.
+    pub created: Option<VfatTimestamp>,
+    /// Hundredths of a second past `created`, 0 to 199: FAT keeps creation
+    /// times with a 10 ms resolution. Only used with `created`.
+    pub created_centiseconds: Milliseconds,
+    /// Last modification time.
+    pub modified: Option<VfatTimestamp>,
+    /// Last access date: its time of day isn't stored.
+    pub accessed: Option<VfatTimestamp>,
+}
+
 defbit!(
     VfatTimestamp,
     u32,
@@ -67,6 +82,29 @@ impl VfatTimestamp {
         self.get_value(Self::SECONDS) * 2
     }
 
+    /// The same day at midnight: the precision of the last access date.
+    pub fn date(&self) -> VfatTimestamp {
+        VfatTimestamp::new(self.get() & 0xFFFF_0000)
+    }
+
+    /// A date in the on-disk format of the last access date.
+    pub(crate) fn from_date(date: u16) -> VfatTimestamp {
+        VfatTimestamp::new((date as u32) << 16)
+    }
+
+    /// The date, in the on-disk format of the last access date.
+    pub(crate) fn date_bits(&self) -> u16 {
+        (self.get() >> 16) as u16
+    }
+
+    /// Split `millis` since the Unix epoch into a timestamp and the
+    /// hundredths of a second past it, as creation times are stored.
+    pub fn from_unix_millis(millis: u64) -> (VfatTimestamp, Milliseconds) {
+        let seconds = millis / 1000;
+        let centiseconds = (seconds % 2) * 100 + (millis % 1000) / 10;
+        (VfatTimestamp::from(seconds), centiseconds as Milliseconds)
+    }
+
     /// Convert this VFAT timestamp into seconds since the Unix epoch
     /// (1970-01-01 00:00:00 UTC). This is the inverse of the
     /// [`From<u64>`](VfatTimestamp) conversion.
@@ -112,6 +150,17 @@ impl VfatTimestamp {
             + self.minute() as u64 * SECONDS_IN_MINUTE
             + self.second() as u64
     }
+
+    /// This timestamp moved by `seconds`, clamped to 1980-01-01. Unset
+    /// timestamps, with a zero day or month, are kept as they are.
+    pub(crate) fn shifted(self, seconds: i64) -> VfatTimestamp {
+        const FAT_EPOCH: i64 = 315_532_800;
+        if seconds == 0 || self.month() == 0 || self.day() == 0 {
+            return self;
+        }
+        let unix = (self.to_unix_timestamp() as i64 + seconds).max(FAT_EPOCH);
+        VfatTimestamp::from(unix as u64)
+    }
 }
 
 type UnixTimestamp = u64;
@@ -238,6 +287,28 @@ mod tests {
         assert_eq!(timestamp.second(), 16);
     }
 
+    #[test]
+    fn unix_millis_keep_the_creation_precision() {
+        // 2022-06-07 05:06:17.340 UTC.
+        let millis = 1_654_578_377_340;
+        let (timestamp, centiseconds) = VfatTimestamp::from_unix_millis(millis);
+        assert_eq!(timestamp.second(), 16);
+        assert_eq!(centiseconds, 134);
+        assert_eq!(
+            timestamp.to_unix_timestamp() * 1000 + centiseconds as u64 * 10,
+            millis
+        );
+    }
+
+    #[test]
+    fn dates_drop_the_time_of_day() {
+        let timestamp = VfatTimestamp::from(1_654_578_377);
+        let date = timestamp.date();
+        assert_eq!((date.year(), date.month(), date.day()), (2022, 6, 7));
+        assert_eq!((date.hour(), date.minute(), date.second()), (0, 0, 0));
+        assert_eq!(VfatTimestamp::from_date(timestamp.date_bits()), date);
+    }
+
     #[test]
     fn test_vfattimestamp_from_unixtimestamp() {
         // TODO
@@ -0,0 +1,246 @@
+//! Listing and recovering deleted directory entries.
+//!
+//! Deleting an entry only overwrites the first byte of its slots with `0xE5`
+//! and frees its cluster chain: the start cluster, the size and the timestamps
+//! stay in the short entry, and the characters of the long name stay in the
+//! LFN slots. The short name loses its first character, which is recovered by
+//! matching the LFN checksum whenever the long name survived.
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use log::info;
+use snafu::ensure;
+
+use crate::api::raw_directory_entry::{
+    Attributes, LongFileNameEntry, RegularDirectoryEntry, UnknownDirectoryEntry, VfatDirectoryEntry,
+};
+use crate::api::timestamp::{FileTimes, VfatTimestamp};
+use crate::api::{Directory, EntryType, File};
+use crate::fat_table::FatEntry;
+use crate::{ClusterId, error, fat_table};
+
+/// Placeholder for the first character of a short name that could not be
+/// recovered.
+const UNKNOWN_FIRST_CHARACTER: u8 = b'_';
+
+/// A deleted entry found by [`Directory::deleted_entries`].
+#[derive(Debug, Clone)]
+pub struct DeletedEntry {
+    name: String,
+    name_complete: bool,
+    size: u32,
+    cluster: ClusterId,
+    attributes: Attributes,
+    creation: VfatTimestamp,
+    last_update: VfatTimestamp,
+    /// Slot of the short entry in the directory, and its raw content, used to
+    /// notice that the slot was reused before [`Directory::undelete`].
+    index: usize,
+    raw: [u8; size_of::<UnknownDirectoryEntry>()],
+}
+
+impl DeletedEntry {
+    /// The reconstructed name. If [`DeletedEntry::is_name_complete`] is
+    /// `false`, its first character is a `_` placeholder.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// `true` if the whole name was recovered, either from the surviving long
+    /// name or thanks to its checksum.
+    pub fn is_name_complete(&self) -> bool {
+        self.name_complete
+    }
+
+    /// Size of the file in bytes, as recorded when it was deleted.
+    pub fn size(&self) -> usize {
+        self.size as usize
+    }
+
+    /// First cluster of the deleted data, or 0 for an empty file.
+    pub fn first_cluster(&self) -> u32 {
+        u32::from(self.cluster)
+    }
+
+    /// `true` if the deleted entry was a directory.
+    pub fn is_dir(&self) -> bool {
+        self.attributes.is_directory()
+    }
+
+    /// Creation timestamp of the deleted entry.
+    pub fn created(&self) -> VfatTimestamp {
+        self.creation
+    }
+
+    /// Last modification timestamp of the deleted entry.
+    pub fn modified(&self) -> VfatTimestamp {
+        self.last_update
+    }
+}
+
+impl Directory {
+    /// List the deleted entries still present in this directory's slots, in
+    /// slot order.
+    ///
+    /// Nothing guarantees that the data of a deleted entry is still intact:
+    /// its clusters may have been reused since.
+    pub fn deleted_entries(&self) -> error::Result<Vec<DeletedEntry>> {
+        let _guard = self.vfat_filesystem.fs_lock.read();
+        let lock = self.lock();
+        let _dir_guard = lock.read();
+        self.deleted_entries_unlocked()
+    }
+
+    fn deleted_entries_unlocked(&self) -> error::Result<Vec<DeletedEntry>> {
+        let utc_offset = self.vfat_filesystem.options.utc_offset;
+        let mut deleted = Vec::new();
+        // Deleted LFN slots seen right before the current slot, in physical order.
+        let mut lfn_run: Vec<LongFileNameEntry> = Vec::new();
+        for slot in self.raw_entries() {
+            let (index, entry) = slot?;
+            let VfatDirectoryEntry::Deleted(unknown) = entry else {
+                lfn_run.clear();
+                continue;
+            };
+            let raw: [u8; size_of::<UnknownDirectoryEntry>()] = unknown.into();
+            if raw[1..].iter().all(|&byte| byte == 0) {
+                // Scrubbed by a secure delete: nothing left to recover.
+                lfn_run.clear();
+                continue;
+            }
+            if unknown.is_lfn() {
+                lfn_run.push(unknown.into());
+                continue;
+            }
+            let regular: RegularDirectoryEntry = unknown.into();
+            if !regular.is_volume_id() {
+                let mut entry = Self::reconstruct(index, unknown, regular, &lfn_run);
+                entry.creation = utc_offset.to_utc(entry.creation);
+                entry.last_update = utc_offset.to_utc(entry.last_update);
+                deleted.push(entry);
+            }
+            lfn_run.clear();
+        }
+        Ok(deleted)
+    }
+
+    fn reconstruct(
+        index: usize,
+        unknown: UnknownDirectoryEntry,
+        mut regular: RegularDirectoryEntry,
+        lfn_run: &[LongFileNameEntry],
+    ) -> DeletedEntry {
+        // Only the LFN slots right before the short entry, sharing its
+        // checksum, belong to it. They are stored last fragment first.
+        let checksum = lfn_run.last().map(|lfn| lfn.checksum_dos_filename);
+        let fragments = lfn_run
+            .iter()
+            .rev()
+            .take_while(|lfn| Some(lfn.checksum_dos_filename) == checksum);
+        let long_name =
+            LongFileNameEntry::decode_name(fragments.flat_map(LongFileNameEntry::name_units));
+
+        let first_character = checksum.and_then(|checksum| {
+            // The short name is usually derived from the long one: try its first
+            // character before every other printable one.
+            let hint = long_name
+                .chars()
+                .next()
+                .filter(char::is_ascii)
+                .map(|ch| ch.to_ascii_uppercase() as u8);
+            hint.into_iter().chain(0x21..=0x7E).find(|candidate| {
+                let mut name = regular.file_name;
+                name[0] = *candidate;
+                VfatDirectoryEntry::checksum(&name, &regular.file_ext) == checksum
+            })
+        });
+        regular.file_name[0] = first_character.unwrap_or(UNKNOWN_FIRST_CHARACTER);
+        let (name, name_complete) = match first_character {
+            Some(_) if !long_name.is_empty() => (long_name, true),
+            Some(_) => (regular.full_name(), true),
+            None => (regular.full_name(), false),
+        };
+
+        DeletedEntry {
+            name,
+            name_complete,
+            size: regular.file_size,
+            cluster: regular.cluster(),
+            attributes: regular.attributes,
+            creation: regular.creation_time,
+            last_update: regular.last_modification_time,
+            index,
+            raw: unknown.into(),
+        }
+    }
+
+    /// Restore a deleted file as `new_name`, like classic undelete tools.
+    ///
+    /// The file is assumed to have been stored contiguously: the clusters
+    /// starting at [`DeletedEntry::first_cluster`] and covering its size must
+    /// all still be free, and are linked back into a chain. Nothing checks that
+    /// they still hold the original data. Directories can't be restored.
+    pub fn undelete(&mut self, entry: &DeletedEntry, new_name: String) -> error::Result<File> {
+        let lock = self.vfat_filesystem.fs_lock.clone();
+        let _guard = lock.write();
+        info!("Undeleting {:?} as '{}'", entry.name, new_name);
+
+        let fail = |reason| error::UndeleteFailedSnafu {
+            target: entry.name.clone(),
+            reason,
+        };
+        ensure!(!entry.is_dir(), fail("directories can't be undeleted"));
+        let slot = self.raw_entries().nth(entry.index).transpose()?;
+        let slot_unchanged = slot.is_some_and(|(_, slot)| {
+            let raw: [u8; size_of::<UnknownDirectoryEntry>()] =
+                slot.transmute_into_unknown_dir_entry().into();
+            raw == entry.raw
+        });
+        ensure!(slot_unchanged, fail("the directory slot was reused"));
+        ensure!(
+            !self.contains_unlocked(&new_name)?,
+            error::NameAlreadyInUseSnafu { target: new_name }
+        );
+
+        let fs = &self.vfat_filesystem;
+        let clusters = entry.size.div_ceil(fs.bytes_per_cluster());
+        let first = u32::from(entry.cluster);
+        if clusters > 0 {
+            let in_bounds = first >= 2 && first.saturating_add(clusters) <= 2 + fs.cluster_count();
+            ensure!(in_bounds, fail("the recorded first cluster is invalid"));
+            for cid in first..first + clusters {
+                let fat_entry = fat_table::read_fat_entry(ClusterId::new(cid), fs.device.clone())?;
+                ensure!(
+                    fat_entry == FatEntry::Unused,
+                    fail("its clusters are in use again")
+                );
+            }
+            fs.allocate_run(entry.cluster, clusters)?;
+        }
+
+        let restored = self
+            .create(new_name, EntryType::File)
+            .map(|created| created.into_file_unchecked())
+            .and_then(|mut file| {
+                file.metadata.cluster = if clusters > 0 {
+                    entry.cluster
+                } else {
+                    ClusterId::new(0)
+                };
+                file.metadata.size = entry.size;
+                file.metadata.attributes = entry.attributes;
+                file.metadata.set_times(&FileTimes {
+                    created: Some(entry.creation),
+                    modified: Some(entry.last_update),
+                    ..FileTimes::default()
+                });
+                self.update_entry(file.metadata().clone())?;
+                Ok(file)
+            });
+        if restored.is_err() && clusters > 0 {
+            self.vfat_filesystem
+                .delete_fat_cluster_chain(entry.cluster, None)?;
+        }
+        restored
+    }
+}
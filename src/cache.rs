@@ -4,12 +4,12 @@ use alloc::vec;
 use alloc::vec::Vec;
 
 use log::info;
-use spin::mutex::SpinMutex;
 
 use crate::SectorId;
 use crate::error::Result;
 use crate::formats::cluster_id::ClusterId;
-use crate::traits::BlockDevice;
+use crate::locks::Mutex;
+use crate::traits::{BlockDevice, LockProvider};
 
 /// A cached sector entry.
 struct CacheEntry {
@@ -27,7 +27,7 @@ struct CacheEntry {
 /// dirty sectors are flushed to the device on [`flush()`](Self::flush), eviction,
 /// or [`Drop`].
 pub(crate) struct CachedPartition {
-    device: SpinMutex<Box<dyn BlockDevice + Send>>,
+    device: Mutex<Box<dyn BlockDevice + Send>>,
     pub(crate) sector_size: usize,
     pub(crate) fat_start_sector: SectorId,
     /// How many sectors are mapped to a single cluster
@@ -39,7 +39,7 @@ pub(crate) struct CachedPartition {
     /// Number of sectors per FAT table
     pub(crate) sectors_per_fat: u32,
     /// Sector cache. Protected by its own lock to avoid holding the device lock.
-    cache: SpinMutex<SectorCache>,
+    cache: Mutex<SectorCache>,
 }
 
 struct SectorCache {
@@ -102,6 +102,7 @@ impl CachedPartition {
             fat_amount,
             sectors_per_fat,
             0, // default: no caching (preserves existing behavior)
+            &*crate::locks::default_provider(),
         )
     }
 
@@ -114,6 +115,7 @@ impl CachedPartition {
         fat_amount: u8,
         sectors_per_fat: u32,
         cache_capacity: usize,
+        locks: &dyn LockProvider,
     ) -> Self
     where
         T: BlockDevice + Send + 'static,
@@ -123,14 +125,14 @@ impl CachedPartition {
             cache_capacity
         );
         Self {
-            device: SpinMutex::new(Box::new(device)),
+            device: Mutex::new(Box::new(device), locks),
             sector_size,
             fat_start_sector,
             sectors_per_cluster,
             data_start_sector,
             fat_amount,
             sectors_per_fat,
-            cache: SpinMutex::new(SectorCache::new(cache_capacity)),
+            cache: Mutex::new(SectorCache::new(cache_capacity), locks),
         }
     }
 
@@ -262,7 +264,6 @@ impl CachedPartition {
         Ok(len)
     }
 
-    #[allow(unused)]
     fn write_sector(self: Arc<Self>, sector: SectorId, buf: &[u8]) -> Result<usize> {
         let mut cache = self.cache.lock();
         if cache.capacity == 0 {
@@ -363,6 +364,58 @@ impl CachedPartition {
         Ok(len)
     }
 
+    /// Discard `count` sectors starting at `start` on the device. Cached copies
+    /// are dropped, after writing back pending writes so that a wipe queued
+    /// before the discard still reaches the device.
+    pub(crate) fn discard(&self, start: SectorId, count: u32) -> Result<()> {
+        let mut cache = self.cache.lock();
+        let mut device = self.device.lock();
+        Self::flush_range(&mut cache, &mut device, start, count)?;
+        cache
+            .entries
+            .retain(|entry| !Self::in_range(entry.sector, start, count));
+        device.discard(start, count)
+    }
+
+    /// Write back the pending writes to `count` sectors starting at `start`.
+    fn flush_range(
+        cache: &mut SectorCache,
+        device: &mut Box<dyn BlockDevice + Send>,
+        start: SectorId,
+        count: u32,
+    ) -> Result<()> {
+        for entry in cache.entries.iter_mut() {
+            if Self::in_range(entry.sector, start, count) {
+                Self::flush_entry(device, entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn in_range(sector: SectorId, start: SectorId, count: u32) -> bool {
+        sector.0 >= start.0 && sector.0 - start.0 < count
+    }
+
+    /// Overwrite every sector of `cluster` with `byte`. The wipe is written
+    /// through to the device, as the cluster is usually freed right after and
+    /// a dirty cached copy could be lost in a crash or replaced once the
+    /// cluster is reused, leaving the old data on disk.
+    pub(crate) fn fill_cluster(self: Arc<Self>, cluster: ClusterId, byte: u8) -> Result<()> {
+        let first_sector = self.cluster_to_sector(cluster);
+        let buf = vec![byte; self.sector_size];
+        for i in 0..self.sectors_per_cluster {
+            self.clone().write_sector(first_sector + i, &buf)?;
+        }
+        let mut cache = self.cache.lock();
+        let mut device = self.device.lock();
+        Self::flush_range(
+            &mut cache,
+            &mut device,
+            first_sector,
+            self.sectors_per_cluster,
+        )
+    }
+
     /// Converts a cluster (a FAT concept) to a sector (a BlockDevice concept).
     ///
     /// To do so, it uses some useful info from the BPB section.
@@ -491,6 +544,7 @@ mod tests {
             1,
             1,
             cache_capacity,
+            &*crate::locks::default_provider(),
         ))
     }
 
@@ -22,6 +22,11 @@ impl ClusterChainReader {
             device,
         }
     }
+    /// The cluster holding the last byte read, or the one seeked to.
+    pub(crate) fn current_cluster(&self) -> Option<ClusterId> {
+        self.current_cluster
+    }
+
     fn next_cluster(&self) -> Result<Option<ClusterId>> {
         if self.current_cluster.is_none() {
             return Ok(None);
@@ -101,7 +106,7 @@ impl ClusterChainReader {
             let amount = self.device.clone().read_sector_offset(
                 self.current_sector,
                 self.offset_byte_in_current_sector,
-                &mut buf[total..core::cmp::min(buf_len, space_left_in_current_sector)],
+                &mut buf[total..core::cmp::min(total + space_left_in_current_sector, buf_len)],
             )?;
             total += amount;
             self.offset_byte_in_current_sector += amount;
@@ -0,0 +1,206 @@
+//! Online defragmentation.
+//!
+//! Files are relocated one at a time into a single run of free clusters. The
+//! order of the on-disk updates is chosen so that an interruption (power loss,
+//! budget exhausted, I/O error) never leaves a file pointing at clusters that
+//! were already released:
+//!
+//! 1. the destination run is linked as a new chain in the FAT,
+//! 2. the data is copied from the old chain into the new one,
+//! 3. the directory entry is switched to the new start cluster,
+//! 4. the old chain is freed.
+//!
+//! A crash between 1 and 3 leaks the new chain, a crash after 3 leaks the old
+//! one; in both cases the file itself stays intact and a filesystem check can
+//! reclaim the lost clusters.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use log::{debug, info};
+use snafu::ensure;
+
+use crate::api::{Directory, Metadata};
+use crate::{ClusterId, PathBuf, Result, VfatFS, VfatMetadataTrait, error, fat_table};
+
+/// Limits how much work a single [`VfatFS::defragment`] call may do, so the
+/// defragmenter can run incrementally, e.g. whenever the device is idle.
+///
+/// The budget is checked between files: a file is always moved as a whole.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefragBudget {
+    /// Stop once at least this many clusters have been relocated.
+    pub max_clusters: Option<u32>,
+    /// Stop once this many seconds have elapsed, according to the
+    /// filesystem's [`TimeManagerTrait`](crate::TimeManagerTrait).
+    pub max_seconds: Option<u64>,
+}
+
+/// Options for [`VfatFS::defragment`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefragOptions {
+    /// Also move contiguous files toward the start of the volume whenever a
+    /// lower free run can hold them, consolidating the free space at the end.
+    pub compact: bool,
+    /// How much work this call is allowed to do.
+    pub budget: DefragBudget,
+}
+
+/// Outcome of a [`VfatFS::defragment`] call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefragReport {
+    /// Number of files that were inspected.
+    pub files_scanned: u32,
+    /// Number of files that were moved to a new run.
+    pub files_relocated: u32,
+    /// Number of clusters copied.
+    pub clusters_moved: u32,
+    /// `true` if the whole volume was visited, `false` if the budget ran out
+    /// first. Calling [`VfatFS::defragment`] again continues the work.
+    pub complete: bool,
+}
+
+impl VfatFS {
+    /// Relocate the file at `path` into a single contiguous run of clusters.
+    ///
+    /// Returns `true` if the file was moved. Empty files, files that are already
+    /// contiguous, directories, and files for which no free run is large enough
+    /// are left where they are.
+    ///
+    /// Open [`File`](crate::File) handles to the same file keep the old start
+    /// cluster: drop them before defragmenting.
+    pub fn defragment_file(&mut self, path: PathBuf) -> Result<bool> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        let entry = self.get_from_absolute_path_unlocked(path)?;
+        if entry.is_dir() {
+            return Ok(false);
+        }
+        let parent = self
+            .get_from_absolute_path_unlocked(entry.metadata.parent().clone())?
+            .into_directory_unchecked();
+        Ok(self.relocate_file(&parent, entry.metadata, false)? > 0)
+    }
+
+    /// Defragment every file on the volume, within the given budget.
+    ///
+    /// Directories are visited depth-first starting from the root. Each
+    /// fragmented file is moved to the lowest free run large enough to hold it;
+    /// with [`DefragOptions::compact`] contiguous files are moved down as well.
+    /// Directories themselves are never relocated.
+    pub fn defragment(&mut self, options: DefragOptions) -> Result<DefragReport> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+
+        let started_at = self.time_manager.get_current_timestamp();
+        let budget = options.budget;
+        let mut report = DefragReport::default();
+        let mut pending: Vec<Directory> = vec![self.get_root_unlocked()?];
+
+        while let Some(directory) = pending.pop() {
+            for entry in directory.iter_unlocked() {
+                let entry = entry?;
+                if entry.is_dir() {
+                    if entry.name() != "." && entry.name() != ".." {
+                        pending.push(entry.into_directory_unchecked());
+                    }
+                    continue;
+                }
+                if entry.metadata.attributes.is_volume_id() {
+                    continue;
+                }
+                let out_of_clusters = budget
+                    .max_clusters
+                    .is_some_and(|max| report.clusters_moved >= max);
+                let out_of_time = budget.max_seconds.is_some_and(|max| {
+                    self.time_manager
+                        .get_current_timestamp()
+                        .saturating_sub(started_at)
+                        >= max
+                });
+                if out_of_clusters || out_of_time {
+                    info!("Defragmentation budget exhausted: {:?}", report);
+                    return Ok(report);
+                }
+
+                report.files_scanned += 1;
+                let moved = self.relocate_file(&directory, entry.metadata, options.compact)?;
+                if moved > 0 {
+                    report.files_relocated += 1;
+                    report.clusters_moved += moved;
+                }
+            }
+        }
+        report.complete = true;
+        Ok(report)
+    }
+
+    /// Move a file's clusters into one free run, returning how many clusters
+    /// were moved (0 if the file was left in place).
+    fn relocate_file(
+        &self,
+        parent: &Directory,
+        mut metadata: Metadata,
+        compact: bool,
+    ) -> Result<u32> {
+        if metadata.has_no_cluster_allocated() {
+            return Ok(0);
+        }
+        let chain = fat_table::collect_chain(metadata.cluster, self.device.clone())?;
+        let len = chain.len() as u32;
+        let head = u32::from(chain[0]);
+        let contiguous = chain
+            .windows(2)
+            .all(|pair| u32::from(pair[1]) == u32::from(pair[0]) + 1);
+        if contiguous && !compact {
+            return Ok(0);
+        }
+        let target = match self.find_free_run(len)? {
+            Some(target) => target,
+            None => {
+                debug!("No free run of {} clusters for {:?}", len, metadata.name());
+                return Ok(0);
+            }
+        };
+        if contiguous && u32::from(target) >= head {
+            return Ok(0);
+        }
+        info!(
+            "Relocating {:?}: {} clusters from {} to {}",
+            metadata.full_path(),
+            len,
+            head,
+            target
+        );
+
+        self.allocate_run(target, len)?;
+        self.copy_clusters(chain[0], target, len)?;
+        self.device.flush()?;
+
+        metadata.cluster = target;
+        parent.update_entry(metadata)?;
+        self.device.flush()?;
+
+        self.delete_fat_cluster_chain(chain[0], self.options.secure_delete)?;
+        Ok(len)
+    }
+
+    /// Copy `len` clusters from the chain starting at `from` into the chain
+    /// starting at `to`. Both chains must be at least `len` clusters long.
+    pub(crate) fn copy_clusters(&self, from: ClusterId, to: ClusterId, len: u32) -> Result<()> {
+        let cluster_size = self.bytes_per_cluster() as usize;
+        let mut buf = vec![0u8; cluster_size];
+        let mut reader = self.cluster_chain_reader(from);
+        let mut writer = self.cluster_chain_writer(to);
+        for _ in 0..len {
+            let read = reader.read(&mut buf)?;
+            ensure!(
+                read == cluster_size,
+                error::FilesystemCorruptedSnafu {
+                    reason: "Cluster chain ended before its expected length"
+                }
+            );
+            writer.write(&buf)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+//! Directory entry lookup cache.
+//!
+//! Maps a (directory cluster, name) pair to the location of the entry in the
+//! directory and its short entry, so that path resolution doesn't rescan every
+//! directory along the path. Misses are cached too (negative entries), which
+//! makes `contains` checks before a create cheap.
+//!
+//! Any write to the slots of a directory drops all the cached names of that
+//! directory, and freeing the chain of a directory drops them as well, since
+//! its clusters may later hold another directory.
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+
+use crate::ClusterId;
+use crate::api::raw_directory_entry::RegularDirectoryEntry;
+
+/// Where an entry lives in its directory.
+#[derive(Clone)]
+pub(crate) struct EntryLocation {
+    /// The entry's name, as stored on disk.
+    pub(crate) name: String,
+    /// Slot of the short entry.
+    pub(crate) index: usize,
+    /// Number of LFN slots right before the short entry.
+    pub(crate) lfn_count: usize,
+    pub(crate) regular: RegularDirectoryEntry,
+}
+
+struct CachedLookup {
+    /// `None` if the name is known not to exist.
+    location: Option<EntryLocation>,
+    last_used: u64,
+}
+
+/// A bounded, least-recently-used cache of directory lookups.
+pub(crate) struct DentryCache {
+    capacity: usize,
+    tick: u64,
+    directories: BTreeMap<u32, BTreeMap<String, CachedLookup>>,
+    /// Last use tick → key, to find the least recently used lookup.
+    lru: BTreeMap<u64, (u32, String)>,
+}
+
+impl DentryCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            directories: BTreeMap::new(),
+            lru: BTreeMap::new(),
+        }
+    }
+
+    /// `Some(None)` for a cached miss, `None` if the name isn't cached.
+    pub(crate) fn get(&mut self, dir: ClusterId, name: &str) -> Option<Option<EntryLocation>> {
+        self.tick += 1;
+        let lookup = self.directories.get_mut(&u32::from(dir))?.get_mut(name)?;
+        let key = self
+            .lru
+            .remove(&lookup.last_used)
+            .expect("cached lookup missing from the LRU index");
+        lookup.last_used = self.tick;
+        self.lru.insert(self.tick, key);
+        Some(lookup.location.clone())
+    }
+
+    pub(crate) fn insert(&mut self, dir: ClusterId, name: &str, location: Option<EntryLocation>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        let dir = u32::from(dir);
+        let lookup = CachedLookup {
+            location,
+            last_used: self.tick,
+        };
+        let previous = self
+            .directories
+            .entry(dir)
+            .or_default()
+            .insert(name.into(), lookup);
+        if let Some(previous) = previous {
+            self.lru.remove(&previous.last_used);
+        }
+        self.lru.insert(self.tick, (dir, name.into()));
+        while self.lru.len() > self.capacity {
+            let (_, (dir, name)) = self.lru.pop_first().expect("non-empty LRU index");
+            self.remove(dir, &name);
+        }
+    }
+
+    /// Drop every cached name of the directory starting at `dir`.
+    pub(crate) fn invalidate_dir(&mut self, dir: ClusterId) {
+        if let Some(names) = self.directories.remove(&u32::from(dir)) {
+            for lookup in names.values() {
+                self.lru.remove(&lookup.last_used);
+            }
+        }
+    }
+
+    fn remove(&mut self, dir: u32, name: &str) {
+        if let Some(names) = self.directories.get_mut(&dir) {
+            names.remove(name);
+            if names.is_empty() {
+                self.directories.remove(&dir);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::raw_directory_entry::UnknownDirectoryEntry;
+
+    fn location(name: &str, index: usize) -> Option<EntryLocation> {
+        Some(EntryLocation {
+            name: name.into(),
+            index,
+            lfn_count: 0,
+            regular: UnknownDirectoryEntry::from([0u8; 32]).into(),
+        })
+    }
+
+    fn cached_index(cache: &mut DentryCache, dir: u32, name: &str) -> Option<Option<usize>> {
+        cache
+            .get(ClusterId::new(dir), name)
+            .map(|location| location.map(|location| location.index))
+    }
+
+    #[test]
+    fn test_hits_and_negative_entries() {
+        let mut cache = DentryCache::new(4);
+        cache.insert(ClusterId::new(2), "a", location("a", 3));
+        cache.insert(ClusterId::new(2), "missing", None);
+        assert_eq!(cached_index(&mut cache, 2, "a"), Some(Some(3)));
+        assert_eq!(cached_index(&mut cache, 2, "missing"), Some(None));
+        assert_eq!(cached_index(&mut cache, 2, "other"), None);
+        assert_eq!(cached_index(&mut cache, 5, "a"), None);
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut cache = DentryCache::new(2);
+        cache.insert(ClusterId::new(2), "a", location("a", 1));
+        cache.insert(ClusterId::new(2), "b", location("b", 2));
+        // Touch "a": "b" becomes the least recently used.
+        assert!(cached_index(&mut cache, 2, "a").is_some());
+        cache.insert(ClusterId::new(3), "c", location("c", 3));
+        assert_eq!(cached_index(&mut cache, 2, "b"), None);
+        assert_eq!(cached_index(&mut cache, 2, "a"), Some(Some(1)));
+        assert_eq!(cached_index(&mut cache, 3, "c"), Some(Some(3)));
+        assert_eq!(cache.lru.len(), 2);
+    }
+
+    #[test]
+    fn test_invalidate_dir_only_drops_that_directory() {
+        let mut cache = DentryCache::new(8);
+        cache.insert(ClusterId::new(2), "a", location("a", 1));
+        cache.insert(ClusterId::new(2), "b", None);
+        cache.insert(ClusterId::new(3), "a", location("a", 7));
+        cache.invalidate_dir(ClusterId::new(2));
+        assert_eq!(cached_index(&mut cache, 2, "a"), None);
+        assert_eq!(cached_index(&mut cache, 2, "b"), None);
+        assert_eq!(cached_index(&mut cache, 3, "a"), Some(Some(7)));
+        assert_eq!(cache.lru.len(), 1);
+    }
+
+    #[test]
+    fn test_zero_capacity_caches_nothing() {
+        let mut cache = DentryCache::new(0);
+        cache.insert(ClusterId::new(2), "a", location("a", 1));
+        assert_eq!(cached_index(&mut cache, 2, "a"), None);
+    }
+}
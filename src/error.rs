@@ -4,6 +4,7 @@ use snafu::prelude::*;
 /// VfatRS result type
 pub type Result<T> = core::result::Result<T, VfatRsError>;
 use crate::io::Error as IoError;
+use crate::io::ErrorKind;
 
 /// Errors that can occur during VFAT filesystem operations.
 #[derive(Debug, Snafu)]
@@ -93,6 +94,60 @@ pub enum VfatRsError {
         /// Description of the corruption.
         reason: &'static str,
     },
+    /// A contiguous allocation could not be satisfied: there is no free run long
+    /// enough, or the file being extended is already fragmented.
+    #[snafu(display("No contiguous run of {} free clusters available", clusters))]
+    ContiguousSpaceNotFound {
+        /// Length of the run that was requested, in clusters.
+        clusters: u32,
+    },
+    /// A deleted entry can't be restored.
+    #[snafu(display("Cannot undelete '{}': {}", target, reason))]
+    UndeleteFailed {
+        /// Reconstructed name of the deleted entry.
+        target: String,
+        /// Why it can't be restored.
+        reason: &'static str,
+    },
+    /// The requested volume size is not usable, e.g. it leaves no room for the
+    /// data region or the device does not reach that far.
+    #[snafu(display("Cannot resize volume: {}", reason))]
+    InvalidResize {
+        /// Why the new size was rejected.
+        reason: &'static str,
+    },
+    /// Shrinking would leave fewer clusters than are currently in use.
+    #[snafu(display(
+        "Volume too small: {} clusters in use, only {} available after resizing",
+        used,
+        available
+    ))]
+    VolumeTooSmall {
+        /// Clusters currently in use.
+        used: u32,
+        /// Data clusters the resized volume would have.
+        available: u32,
+    },
+    /// A file was expected, but the path names a directory.
+    #[snafu(display("Is a directory: '{}'", target))]
+    IsADirectory {
+        /// Path of the directory.
+        target: String,
+    },
+    /// A directory was expected, but the path names a file.
+    #[snafu(display("Not a directory: '{}'", target))]
+    NotADirectory {
+        /// Path of the file.
+        target: String,
+    },
+    /// The path can't be used for this operation, e.g. removing the root.
+    #[snafu(display("Invalid path '{}': {}", target, reason))]
+    InvalidPath {
+        /// The offending path.
+        target: String,
+        /// Why it was rejected.
+        reason: &'static str,
+    },
     /// The file or directory name exceeds the maximum length (255 characters).
     #[snafu(display("Name too long ({} chars, max 255): '{}'", length, name))]
     NameTooLong {
@@ -101,6 +156,54 @@ pub enum VfatRsError {
         /// Actual length.
         length: usize,
     },
+    /// The volume label can't be stored, see
+    /// [`VfatFS::set_label`](crate::VfatFS::set_label).
+    #[snafu(display("Invalid volume label '{}': {}", label, reason))]
+    InvalidLabel {
+        /// The offending label.
+        label: String,
+        /// Why it was rejected.
+        reason: &'static str,
+    },
+    /// The operation isn't allowed on this handle or entry, e.g. writing
+    /// through a [`File`](crate::File) opened read-only.
+    #[snafu(display("Access denied on '{}': {}", target, reason))]
+    AccessDenied {
+        /// Path of the file.
+        target: String,
+        /// Why the operation was refused.
+        reason: &'static str,
+    },
+}
+
+impl VfatRsError {
+    /// The [`ErrorKind`](crate::io::ErrorKind) `std::fs` would report for
+    /// this error, e.g. `NotFound` for a missing entry or `AlreadyExists` for
+    /// a name in use.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            VfatRsError::FileNotFound { .. } | VfatRsError::EntryNotFound { .. } => {
+                ErrorKind::NotFound
+            }
+            VfatRsError::NameAlreadyInUse { .. } => ErrorKind::AlreadyExists,
+            VfatRsError::NonEmptyDirectory { .. } => ErrorKind::DirectoryNotEmpty,
+            VfatRsError::AccessDenied { .. } => ErrorKind::PermissionDenied,
+            VfatRsError::IsADirectory { .. } => ErrorKind::IsADirectory,
+            VfatRsError::NotADirectory { .. } => ErrorKind::NotADirectory,
+            VfatRsError::FreeClusterNotFound | VfatRsError::ContiguousSpaceNotFound { .. } => {
+                ErrorKind::StorageFull
+            }
+            VfatRsError::CannotDeletePseudoDir { .. }
+            | VfatRsError::CircularMove { .. }
+            | VfatRsError::PathNotAbsolute { .. }
+            | VfatRsError::InvalidPath { .. }
+            | VfatRsError::InvalidLabel { .. }
+            | VfatRsError::NameTooLong { .. } => ErrorKind::InvalidInput,
+            VfatRsError::FilesystemCorrupted { .. } => ErrorKind::InvalidData,
+            VfatRsError::IoError { source } => source.kind(),
+            _ => ErrorKind::Other,
+        }
+    }
 }
 
 impl From<IoError> for VfatRsError {
@@ -128,9 +231,17 @@ pub enum MbrError {
 
 // Used for Impl Write/Read
 impl From<VfatRsError> for binrw::io::Error {
-    fn from(_err: VfatRsError) -> Self {
+    fn from(err: VfatRsError) -> Self {
+        #[cfg(feature = "std")]
+        {
+            std::io::Error::new(err.kind(), err)
+        }
         // TODO: provide useful output
-        binrw::io::ErrorKind::Other.into()
+        #[cfg(not(feature = "std"))]
+        {
+            let _ = err;
+            binrw::io::ErrorKind::Other.into()
+        }
     }
 }
 
@@ -1,9 +1,16 @@
+use alloc::vec::Vec;
+
+use snafu::ensure;
+
 use crate::ArcMutex;
-use crate::error::Result;
+use crate::error::{self, Result};
 use crate::fat_table::fat_entry::FAT_ENTRY_SIZE;
 use crate::fat_table::{FatEntry, get_params};
 use crate::{CachedPartition, ClusterId};
 
+/// Maximum cluster chain length to prevent infinite loops in corrupted filesystems.
+pub(crate) const MAX_CLUSTER_CHAIN_LENGTH: u32 = 1_048_576;
+
 /// Returns the next clusterid in the chain after the provided cluster_id, if any.
 /// To do that, query the fat table for this cluster id, and see if it is a Data Cluster (e.g. a
 /// node in the chain) return the next element, otherwise it's a dead end and return null.
@@ -28,3 +35,67 @@ pub(crate) fn read_fat_entry(
         .read_sector_offset(sector, offset, &mut buf)
         .map(|_| FatEntry::from(buf))
 }
+
+/// Collect every cluster of the chain starting at `start`, in chain order.
+///
+/// The walk is bounded by [`MAX_CLUSTER_CHAIN_LENGTH`], so a circular chain on a
+/// corrupted filesystem is reported as an error instead of looping forever.
+pub(crate) fn collect_chain(
+    start: ClusterId,
+    device: ArcMutex<CachedPartition>,
+) -> Result<Vec<ClusterId>> {
+    let mut chain = Vec::new();
+    let mut current = start;
+    loop {
+        ensure!(
+            chain.len() < MAX_CLUSTER_CHAIN_LENGTH as usize,
+            error::FilesystemCorruptedSnafu {
+                reason: "Cluster chain exceeds maximum length (possible circular reference)"
+            }
+        );
+        chain.push(current);
+        match next_cluster(current, device.clone())? {
+            Some(next) => current = next,
+            None => break,
+        }
+    }
+    Ok(chain)
+}
+
+/// Call `f(first_cluster, length)` for every run of physically consecutive
+/// clusters in the chain starting at `start`, in chain order.
+///
+/// Nothing but the current run is kept in memory. Like [`collect_chain`], the
+/// walk is bounded by [`MAX_CLUSTER_CHAIN_LENGTH`].
+pub(crate) fn for_each_run<F>(
+    start: ClusterId,
+    device: ArcMutex<CachedPartition>,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(ClusterId, u32),
+{
+    let mut run_start = start;
+    let mut run_len = 1u32;
+    let mut current = start;
+    let mut walked = 1u32;
+    while let Some(next) = next_cluster(current, device.clone())? {
+        ensure!(
+            walked < MAX_CLUSTER_CHAIN_LENGTH,
+            error::FilesystemCorruptedSnafu {
+                reason: "Cluster chain exceeds maximum length (possible circular reference)"
+            }
+        );
+        walked += 1;
+        if u32::from(next) == u32::from(current) + 1 {
+            run_len += 1;
+        } else {
+            f(run_start, run_len);
+            run_start = next;
+            run_len = 1;
+        }
+        current = next;
+    }
+    f(run_start, run_len);
+    Ok(())
+}
@@ -2,13 +2,11 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use crate::error::{self, Result};
-use crate::fat_table::{FatEntry, get_params};
-use crate::{ArcMutex, CachedPartition, ClusterId, fat_table};
+use crate::fat_table::{FatEntry, MAX_CLUSTER_CHAIN_LENGTH, get_params};
+use crate::{ArcMutex, CachedPartition, ClusterId, WipePattern, fat_table};
+use log::warn;
 use snafu::ensure;
 
-/// Maximum cluster chain length to prevent infinite loops in corrupted filesystems.
-const MAX_CLUSTER_CHAIN_LENGTH: u32 = 1_048_576;
-
 /// Delete a cluster chain starting from `start`.
 ///
 /// For crash safety, the chain is collected first and then deleted in reverse
@@ -16,9 +14,14 @@ const MAX_CLUSTER_CHAIN_LENGTH: u32 = 1_048_576;
 /// the head of the chain still points to valid (not-yet-freed) clusters,
 /// avoiding orphaned cluster chains. A filesystem check tool can reclaim the
 /// partially-freed tail.
+///
+/// With `wipe`, the clusters are overwritten before any of them is freed. With
+/// `discard`, they are discarded on the device once freed.
 pub(crate) fn delete_cluster_chain(
     start: ClusterId,
     device: ArcMutex<CachedPartition>,
+    wipe: Option<WipePattern>,
+    discard: bool,
 ) -> Result<()> {
     // Clusters 0 and 1 are reserved (FAT[0] is the media descriptor, FAT[1] is
     // the end-of-chain marker). A cluster id of 0 also means "no cluster
@@ -31,27 +34,17 @@ pub(crate) fn delete_cluster_chain(
     }
 
     // Phase 1: collect the full chain.
-    let mut chain = Vec::new();
-    let mut current = start;
-    loop {
-        ensure!(
-            chain.len() < MAX_CLUSTER_CHAIN_LENGTH as usize,
-            error::FilesystemCorruptedSnafu {
-                reason: "Cluster chain exceeds maximum length (possible circular reference)"
-            }
-        );
-        chain.push(current);
-        match fat_table::next_cluster(current, device.clone())? {
-            Some(next) => current = next,
-            None => break,
-        }
-    }
+    let chain = fat_table::collect_chain(start, device.clone())?;
+    wipe_clusters(&device, &chain, wipe)?;
 
     // Phase 2: delete from last to first for crash safety.
     const DELETED_ENTRY: FatEntry = FatEntry::Unused;
     for &cluster in chain.iter().rev() {
         set_fat_entry(device.clone(), cluster, DELETED_ENTRY)?;
     }
+    if discard {
+        discard_clusters(&device, &chain);
+    }
 
     Ok(())
 }
@@ -60,14 +53,17 @@ pub(crate) fn delete_cluster_chain(
 ///
 /// If `keep_count` is 0, the entire chain is freed (equivalent to `delete_cluster_chain`).
 /// Otherwise, the `keep_count`-th cluster is marked as `LastCluster` and all
-/// subsequent clusters are freed in reverse order for crash safety.
+/// subsequent clusters are freed in reverse order for crash safety. `wipe` and
+/// `discard` apply to the freed clusters as in [`delete_cluster_chain`].
 pub(crate) fn truncate_cluster_chain(
     start: ClusterId,
     keep_count: u32,
     device: ArcMutex<CachedPartition>,
+    wipe: Option<WipePattern>,
+    discard: bool,
 ) -> Result<()> {
     if keep_count == 0 {
-        return delete_cluster_chain(start, device);
+        return delete_cluster_chain(start, device, wipe, discard);
     }
 
     // Reserved clusters (0/1) are never a valid chain start; cluster 0 also
@@ -106,6 +102,8 @@ pub(crate) fn truncate_cluster_chain(
         }
     }
 
+    wipe_clusters(&device, &tail, wipe)?;
+
     // Mark the last kept cluster as end-of-chain
     set_fat_entry(device.clone(), last_keep, FatEntry::LastCluster(0x0FFFFFFF))?;
 
@@ -113,10 +111,46 @@ pub(crate) fn truncate_cluster_chain(
     for &cluster in tail.iter().rev() {
         set_fat_entry(device.clone(), cluster, FatEntry::Unused)?;
     }
+    if discard {
+        discard_clusters(&device, &tail);
+    }
 
     Ok(())
 }
 
+/// Overwrite the content of `clusters` with `wipe`'s pattern, if any.
+fn wipe_clusters(
+    device: &ArcMutex<CachedPartition>,
+    clusters: &[ClusterId],
+    wipe: Option<WipePattern>,
+) -> Result<()> {
+    let Some(pattern) = wipe else {
+        return Ok(());
+    };
+    for &cluster in clusters {
+        device.clone().fill_cluster(cluster, pattern.byte())?;
+    }
+    Ok(())
+}
+
+/// Discard the sectors of the freed `clusters`, coalescing adjacent clusters
+/// into a single range. Failures are only logged: the clusters are already
+/// free, and discarding is advisory.
+fn discard_clusters(device: &ArcMutex<CachedPartition>, clusters: &[ClusterId]) {
+    let mut sorted: Vec<u32> = clusters.iter().map(|&cluster| u32::from(cluster)).collect();
+    sorted.sort_unstable();
+    for run in sorted.chunk_by(|a, b| *b == a + 1) {
+        let start = device.cluster_to_sector(ClusterId::new(run[0]));
+        let count = run.len() as u32 * device.sectors_per_cluster;
+        if let Err(err) = device.discard(start, count) {
+            warn!(
+                "Discarding {} sectors at {} failed: {:?}",
+                count, start, err
+            );
+        }
+    }
+}
+
 pub(crate) fn set_fat_entry(
     device: Arc<CachedPartition>,
     cluster_id: ClusterId,
@@ -145,12 +179,14 @@ mod tests {
     use alloc::vec::Vec;
     use spin::mutex::SpinMutex;
 
+    type WriteLog = Arc<SpinMutex<Vec<(SectorId, usize, Vec<u8>)>>>;
+
     struct WriteTrackingDevice {
-        writes: Arc<SpinMutex<Vec<(SectorId, usize, Vec<u8>)>>>,
+        writes: WriteLog,
     }
 
     impl WriteTrackingDevice {
-        fn new(writes: Arc<SpinMutex<Vec<(SectorId, usize, Vec<u8>)>>>) -> Self {
+        fn new(writes: WriteLog) -> Self {
             Self { writes }
         }
     }
@@ -380,7 +416,7 @@ mod tests {
         *writes_before_crash.lock() = Some(crash_after);
 
         // Deletion will partially succeed then fail
-        let _ = delete_cluster_chain(ClusterId::new(2), cached);
+        let _ = delete_cluster_chain(ClusterId::new(2), cached, None, false);
 
         // Verify: walk from cluster 2, every reachable entry must be valid
         let mut current = 2u32;
@@ -390,7 +426,7 @@ mod tests {
             match entry {
                 FatEntry::DataCluster(next) => {
                     assert!(
-                        next >= 2 && next <= 6,
+                        (2..=6).contains(&next),
                         "Cluster {} points to invalid cluster {}",
                         current,
                         next
@@ -449,7 +485,7 @@ mod tests {
             1,
         ));
 
-        truncate_cluster_chain(ClusterId::new(2), 2, cached).unwrap();
+        truncate_cluster_chain(ClusterId::new(2), 2, cached, None, false).unwrap();
 
         // Cluster 2 → 3(last)
         assert!(matches!(
@@ -495,7 +531,7 @@ mod tests {
             1,
         ));
 
-        truncate_cluster_chain(ClusterId::new(2), 0, cached).unwrap();
+        truncate_cluster_chain(ClusterId::new(2), 0, cached, None, false).unwrap();
 
         assert!(matches!(
             CrashSimDevice::get_entry(&fat_sector, 2),
@@ -530,7 +566,7 @@ mod tests {
             1,
         ));
 
-        truncate_cluster_chain(ClusterId::new(2), 5, cached).unwrap();
+        truncate_cluster_chain(ClusterId::new(2), 5, cached, None, false).unwrap();
 
         // Chain should be unchanged
         assert!(matches!(
@@ -581,8 +617,8 @@ mod tests {
         ));
 
         // Deleting the chain of an empty file (cluster 0) must be a no-op.
-        delete_cluster_chain(ClusterId::new(0), cached.clone()).unwrap();
-        delete_cluster_chain(ClusterId::new(1), cached).unwrap();
+        delete_cluster_chain(ClusterId::new(0), cached.clone(), None, false).unwrap();
+        delete_cluster_chain(ClusterId::new(1), cached, None, false).unwrap();
 
         assert!(
             matches!(
@@ -69,6 +69,36 @@ impl BlockDevice for FilebackedBlockDevice {
         Ok(buf.len())
     }
 
+    /// Punch a hole in the image file, so the discarded sectors read back as
+    /// zeros and no longer take up space on the host filesystem.
+    #[cfg(target_os = "linux")]
+    fn discard(&mut self, start: SectorId, count: u32) -> crate::Result<()> {
+        use std::os::fd::AsRawFd;
+        let offset = start.0 as i64 * self.sector_size() as i64;
+        let len = count as i64 * self.sector_size() as i64;
+        debug!("Punching a hole at {}, length {}", offset, len);
+        // SAFETY: fallocate only reads its integer arguments, and the file
+        // descriptor stays open for the whole call.
+        let ret = unsafe {
+            libc::fallocate(
+                self.image.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                offset,
+                len,
+            )
+        };
+        if ret != 0 {
+            // Keep the errno, so e.g. EINVAL is reported as `InvalidInput` and
+            // EBADF on a read-only image isn't just `Other`.
+            let err = std::io::Error::last_os_error();
+            // Discarding is advisory: a host filesystem without hole support is fine.
+            if err.raw_os_error() != Some(libc::EOPNOTSUPP) {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
     fn get_canonical_name() -> &'static str
     where
         Self: Sized,
@@ -1,6 +1,6 @@
 use core::fmt;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub(crate) struct ClusterId(u32);
 impl From<ClusterId> for u32 {
     fn from(cid: ClusterId) -> Self {
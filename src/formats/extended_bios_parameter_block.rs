@@ -13,7 +13,7 @@ pub struct BiosParameterBlock {
     /// however it does recommend the value "MSWIN4.1" as some 3rd party drivers supposedly check it and expect it to have that value.
     /// Older versions of dos also report MSDOS5.1, linux-formatted floppy will likely to carry "mkdosfs" here, and FreeDOS formatted disks have been observed to have "FRDOS5.1" here.
     /// If the string is less than 8 bytes, it is padded with spaces.
-    oem_identifier: u64,
+    pub(crate) oem_identifier: u64,
     /// Number of bytes per sector, in little-endian format
     pub bytes_per_sector: u16,
     /// Numbr of sectors per cluster:
@@ -44,13 +44,13 @@ pub struct ExtendedBiosParameterBlock {
     /// Cluster pointing to the root (`/`) directory
     pub root_cluster: u32,
     pub(crate) fsinfo_sector: u16,
-    _backup_boot_sector: u16,
+    pub(crate) backup_boot_sector: u16,
     _reserved: [u8; 12],
     _drive_number: u8,
     _reserved2: u8,
     /// 0x28 or 0x29 for VFat / fat 32.
     pub signature: u8,
-    _volumeid_serial_number: u32,
+    pub(crate) volumeid_serial_number: u32,
     /// Padded with spaces.
     pub volume_label_string: [u8; 11],
     /// System identifier string. This field is a string representation of the FAT file system type.
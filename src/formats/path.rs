@@ -1,97 +1,681 @@
+//! A `no_std` implementation of `std::path` for `/`-separated paths.
+//!
+//! Vfat uses utf8/utf16 for encoding: https://wiki.gentoo.org/wiki/FAT/en#UTF-8.2FUTF-16_character_hardware_bugs
+//! therefore it's ok to use a String as a baking data structure. Paths are
+//! parsed like `std::path` does on Unix, so that code behaves the same with
+//! and without the `std` feature: see the tests at the end of this file.
+use alloc::borrow::{Borrow, ToOwned};
 use alloc::string::String;
-use core::iter;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
 
-/// A simple implementation of PathBuf. Vfat uses utf8/utf16 for encoding: https://wiki.gentoo.org/wiki/FAT/en#UTF-8.2FUTF-16_character_hardware_bugs
-/// therefore it's ok to use a String as a baking data structure.
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
-pub struct PathBuf(pub String);
+use crate::{Component as CrateComponent, Path as CratePath, PathBuf as CratePathBuf};
 
-impl PathBuf {
-    /// Create a new `PathBuf` from a string-like value.
-    pub fn new<S: AsRef<str>>(path: S) -> Self {
-        Self(String::from(path.as_ref()))
+const SEPARATOR: char = '/';
+
+/// A single component of a path, see [`Path::components`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Component<'a> {
+    /// The root directory, `/`.
+    RootDir,
+    /// A `.` at the start of a relative path. Other `.` are dropped.
+    CurDir,
+    /// `..`.
+    ParentDir,
+    /// A file or directory name.
+    Normal(&'a str),
+}
+
+impl<'a> Component<'a> {
+    /// Returns the component as a string slice.
+    pub fn as_str(self) -> &'a str {
+        match self {
+            Component::RootDir => "/",
+            Component::CurDir => ".",
+            Component::ParentDir => "..",
+            Component::Normal(name) => name,
+        }
+    }
+}
+
+impl AsRef<str> for Component<'_> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<Path> for Component<'_> {
+    fn as_ref(&self) -> &Path {
+        Path::new(self.as_str())
+    }
+}
+
+/// Iterator over the [`Component`]s of a [`Path`].
+///
+/// Repeated separators and `.` components are skipped, except for a `.` at
+/// the start of a relative path.
+#[derive(Clone)]
+pub struct Components<'a> {
+    path: &'a str,
+    /// `RootDir` or `CurDir` if the path starts with one and it wasn't
+    /// returned yet.
+    prefix: Option<Component<'a>>,
+    /// The part of `path` after the prefix that wasn't returned yet.
+    body: &'a str,
+}
+
+impl<'a> Components<'a> {
+    fn new(path: &'a str) -> Self {
+        let (prefix, body) = if path.starts_with(SEPARATOR) {
+            (Some(Component::RootDir), path.trim_start_matches(SEPARATOR))
+        } else if path == "." || path.starts_with("./") {
+            (Some(Component::CurDir), &path[1..])
+        } else {
+            (None, path)
+        };
+        Self { path, prefix, body }
+    }
+
+    fn classify(name: &'a str) -> Option<Component<'a>> {
+        match name {
+            "" | "." => None,
+            ".." => Some(Component::ParentDir),
+            name => Some(Component::Normal(name)),
+        }
+    }
+
+    /// The rest of the path, as a [`Path`].
+    pub fn as_path(&self) -> &'a Path {
+        let mut rest = self.clone();
+        // Drop the trailing separators and `.`s, like std does.
+        loop {
+            let trimmed = rest.body.trim_end_matches(SEPARATOR);
+            let last = trimmed.rsplit(SEPARATOR).next().unwrap_or("");
+            if last == "." {
+                rest.body = &trimmed[..trimmed.len() - 1];
+            } else {
+                rest.body = trimmed;
+                break;
+            }
+        }
+        let start = if rest.prefix.is_some() || rest.body.is_empty() {
+            0
+        } else {
+            self.offset_of(rest.body.trim_start_matches(SEPARATOR))
+        };
+        let end = match (rest.prefix, rest.body.is_empty()) {
+            (Some(_), true) => 1,
+            (None, true) => return Path::new(""),
+            (_, false) => self.offset_of(rest.body) + rest.body.len(),
+        };
+        Path::new(&self.path[start..end])
+    }
+
+    fn offset_of(&self, part: &str) -> usize {
+        part.as_ptr() as usize - self.path.as_ptr() as usize
+    }
+}
+
+impl<'a> Iterator for Components<'a> {
+    type Item = Component<'a>;
+
+    fn next(&mut self) -> Option<Component<'a>> {
+        if let Some(prefix) = self.prefix.take() {
+            return Some(prefix);
+        }
+        loop {
+            if self.body.is_empty() {
+                return None;
+            }
+            let (name, rest) = self.body.split_once(SEPARATOR).unwrap_or((self.body, ""));
+            self.body = rest;
+            if let Some(component) = Self::classify(name) {
+                return Some(component);
+            }
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for Components<'a> {
+    fn next_back(&mut self) -> Option<Component<'a>> {
+        loop {
+            if self.body.is_empty() {
+                return self.prefix.take();
+            }
+            let (rest, name) = self.body.rsplit_once(SEPARATOR).unwrap_or(("", self.body));
+            self.body = rest;
+            if let Some(component) = Self::classify(name) {
+                return Some(component);
+            }
+        }
+    }
+}
+
+/// A slice of a path, like `std::path::Path`.
+#[repr(transparent)]
+pub struct Path {
+    inner: str,
+}
+
+impl Path {
+    /// Wrap a string slice as a `Path`.
+    pub fn new<S: AsRef<str> + ?Sized>(path: &S) -> &Path {
+        // SAFETY: Path is a repr(transparent) wrapper around str.
+        unsafe { &*(path.as_ref() as *const str as *const Path) }
     }
     /// Returns an iterator over the path components.
-    pub fn iter(&self) -> impl Iterator<Item = &str> {
-        iter::once("/").chain(self.0[1..].split_terminator('/'))
+    pub fn components(&self) -> Components<'_> {
+        Components::new(&self.inner)
+    }
+    /// Returns an iterator over the path components, as string slices.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.components().map(Component::as_str)
     }
     /// Returns the path as a string slice.
     pub fn to_str(&self) -> &str {
-        self.0.as_str()
+        &self.inner
     }
     /// Returns a displayable string slice.
     pub fn display(&self) -> &str {
         self.to_str()
     }
+    /// Copy this path into a [`PathBuf`].
+    pub fn to_path_buf(&self) -> PathBuf {
+        PathBuf(String::from(&self.inner))
+    }
     /// Returns `true` if the path starts with `/`.
     pub fn is_absolute(&self) -> bool {
-        self.0.starts_with('/')
+        self.has_root()
     }
-    /// Returns the parent directory path, or None if this is the root.
-    pub fn parent(&self) -> Option<PathBuf> {
-        if self.0 == "/" {
-            return None;
-        }
-        let trimmed = self.0.trim_end_matches('/');
-        match trimmed.rfind('/') {
-            Some(0) => Some(PathBuf::from("/")),
-            Some(pos) => Some(PathBuf::from(&trimmed[..pos])),
-            None => None,
+    /// Returns `true` if the path doesn't start with `/`.
+    pub fn is_relative(&self) -> bool {
+        !self.is_absolute()
+    }
+    /// Returns `true` if the path starts with `/`.
+    pub fn has_root(&self) -> bool {
+        self.inner.starts_with(SEPARATOR)
+    }
+    /// Returns the path without its final component, or `None` if it ends in
+    /// the root or is empty.
+    pub fn parent(&self) -> Option<&Path> {
+        let mut components = self.components();
+        match components.next_back()? {
+            Component::Normal(_) | Component::CurDir | Component::ParentDir => {
+                Some(components.as_path())
+            }
+            Component::RootDir => None,
         }
     }
-    /// Returns the final component of the path.
+    /// Returns the final component of the path, if it's a file or directory
+    /// name.
     pub fn file_name(&self) -> Option<&str> {
-        let trimmed = self.0.trim_end_matches('/');
-        if trimmed.is_empty() {
-            return None;
-        }
-        match trimmed.rfind('/') {
-            Some(pos) => {
-                let name = &trimmed[pos + 1..];
-                if name.is_empty() { None } else { Some(name) }
+        match self.components().next_back()? {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        }
+    }
+    /// Returns the file name without its extension.
+    pub fn file_stem(&self) -> Option<&str> {
+        let (stem, extension) = split_extension(self.file_name()?);
+        stem.or(extension)
+    }
+    /// Returns the extension of the file name: what follows its last `.`,
+    /// unless that `.` starts the name.
+    pub fn extension(&self) -> Option<&str> {
+        let (stem, extension) = split_extension(self.file_name()?);
+        stem.and(extension)
+    }
+    /// Returns `true` if `base` is a prefix of this path, component-wise.
+    pub fn starts_with<P: AsRef<Path>>(&self, base: P) -> bool {
+        let mut components = self.components();
+        for base_component in base.as_ref().components() {
+            if components.next() != Some(base_component) {
+                return false;
+            }
+        }
+        true
+    }
+    /// Returns `true` if `child` is a suffix of this path, component-wise.
+    pub fn ends_with<P: AsRef<Path>>(&self, child: P) -> bool {
+        let mut components = self.components().rev();
+        for child_component in child.as_ref().components().rev() {
+            if components.next() != Some(child_component) {
+                return false;
+            }
+        }
+        true
+    }
+    /// Returns this path with `path` appended, see [`PathBuf::push`].
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let mut joined = self.to_path_buf();
+        joined.push(path);
+        joined
+    }
+    /// Returns this path with its file name replaced, see
+    /// [`PathBuf::set_file_name`].
+    pub fn with_file_name<S: AsRef<str>>(&self, file_name: S) -> PathBuf {
+        let mut path = self.to_path_buf();
+        path.set_file_name(file_name);
+        path
+    }
+    /// Returns this path with its extension replaced, see
+    /// [`PathBuf::set_extension`].
+    pub fn with_extension<S: AsRef<str>>(&self, extension: S) -> PathBuf {
+        let mut path = self.to_path_buf();
+        path.set_extension(extension);
+        path
+    }
+}
+
+/// Split a file name into stem and extension, like std does: `..` and
+/// names starting with their only `.` have no extension.
+fn split_extension(file_name: &str) -> (Option<&str>, Option<&str>) {
+    if file_name == ".." {
+        return (Some(file_name), None);
+    }
+    match file_name.rsplit_once('.') {
+        Some(("", _)) | None => (None, Some(file_name)),
+        Some((stem, extension)) => (Some(stem), Some(extension)),
+    }
+}
+
+/// An owned path, like `std::path::PathBuf`.
+#[derive(Clone, Default)]
+pub struct PathBuf(pub String);
+
+impl PathBuf {
+    /// Create an empty `PathBuf`.
+    pub fn new() -> Self {
+        Self(String::new())
+    }
+    /// Borrow as a [`Path`].
+    pub fn as_path(&self) -> &Path {
+        Path::new(self.0.as_str())
+    }
+    /// Append `path`. An absolute `path` replaces the current one.
+    pub fn push<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            self.0.clear();
+        } else if !self.0.is_empty() && !self.0.ends_with(SEPARATOR) {
+            self.0.push(SEPARATOR);
+        }
+        self.0.push_str(&path.inner);
+    }
+    /// Truncate to [`Path::parent`]. Returns `false` if there is no parent.
+    pub fn pop(&mut self) -> bool {
+        match self.parent().map(|parent| parent.inner.len()) {
+            Some(len) => {
+                self.0.truncate(len);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Replace the file name, or append `file_name` if there is none.
+    pub fn set_file_name<S: AsRef<str>>(&mut self, file_name: S) {
+        if self.file_name().is_some() {
+            self.pop();
+        }
+        self.push(file_name.as_ref());
+    }
+    /// Replace the extension, or remove it if `extension` is empty. Returns
+    /// `false` and does nothing if there is no file name.
+    pub fn set_extension<S: AsRef<str>>(&mut self, extension: S) -> bool {
+        let Some(stem) = self.file_stem() else {
+            return false;
+        };
+        let end_of_stem = stem.as_ptr() as usize + stem.len() - self.0.as_ptr() as usize;
+        self.0.truncate(end_of_stem);
+        let extension = extension.as_ref();
+        if !extension.is_empty() {
+            self.0.push('.');
+            self.0.push_str(extension);
+        }
+        true
+    }
+}
+
+/// Resolve `.` and `..` in `path` and drop repeated separators, without
+/// looking at the filesystem: there are no links on FAT, so this is the path
+/// the volume would resolve too. `..` at the root stays at the root, leading
+/// `..` in a relative path are kept.
+pub fn normalize_path<P: AsRef<CratePath>>(path: P) -> CratePathBuf {
+    let mut normalized = CratePathBuf::new();
+    // Number of names in `normalized` that a `..` can remove.
+    let mut names = 0;
+    for component in path.as_ref().components() {
+        match component {
+            CrateComponent::CurDir => {}
+            CrateComponent::ParentDir if names > 0 => {
+                normalized.pop();
+                names -= 1;
             }
-            None => Some(trimmed),
+            CrateComponent::ParentDir if normalized.has_root() => {}
+            CrateComponent::Normal(_) => {
+                normalized.push(component);
+                names += 1;
+            }
+            _ => normalized.push(component),
         }
     }
-    /// Returns true if `self` starts with the given base path.
-    pub fn starts_with(&self, base: &PathBuf) -> bool {
-        let self_trimmed = self.0.trim_end_matches('/');
-        let base_trimmed = base.0.trim_end_matches('/');
-        if base_trimmed == "/" {
-            return self_trimmed.starts_with('/');
+    if normalized.components().next().is_none() {
+        normalized.push(".");
+    }
+    normalized
+}
+
+impl Deref for PathBuf {
+    type Target = Path;
+    fn deref(&self) -> &Path {
+        self.as_path()
+    }
+}
+impl Borrow<Path> for PathBuf {
+    fn borrow(&self) -> &Path {
+        self.as_path()
+    }
+}
+impl ToOwned for Path {
+    type Owned = PathBuf;
+    fn to_owned(&self) -> PathBuf {
+        self.to_path_buf()
+    }
+}
+impl AsRef<Path> for Path {
+    fn as_ref(&self) -> &Path {
+        self
+    }
+}
+impl AsRef<Path> for PathBuf {
+    fn as_ref(&self) -> &Path {
+        self.as_path()
+    }
+}
+impl AsRef<Path> for str {
+    fn as_ref(&self) -> &Path {
+        Path::new(self)
+    }
+}
+impl AsRef<Path> for String {
+    fn as_ref(&self) -> &Path {
+        Path::new(self.as_str())
+    }
+}
+impl AsRef<str> for Path {
+    fn as_ref(&self) -> &str {
+        &self.inner
+    }
+}
+
+// Paths compare component-wise, like in std: `/a//b/` equals `/a/b`.
+impl PartialEq for Path {
+    fn eq(&self, other: &Path) -> bool {
+        self.components().eq(other.components())
+    }
+}
+impl Eq for Path {}
+impl PartialOrd for Path {
+    fn partial_cmp(&self, other: &Path) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Path {
+    fn cmp(&self, other: &Path) -> Ordering {
+        self.components().cmp(other.components())
+    }
+}
+impl Hash for Path {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for component in self.components() {
+            component.hash(state);
         }
-        self_trimmed == base_trimmed
-            || self_trimmed.starts_with(&alloc::format!("{}/", base_trimmed))
     }
 }
-impl core::fmt::Display for PathBuf {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+impl PartialEq for PathBuf {
+    fn eq(&self, other: &PathBuf) -> bool {
+        self.as_path() == other.as_path()
+    }
+}
+impl Eq for PathBuf {}
+impl PartialOrd for PathBuf {
+    fn partial_cmp(&self, other: &PathBuf) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for PathBuf {
+    fn cmp(&self, other: &PathBuf) -> Ordering {
+        self.as_path().cmp(other.as_path())
+    }
+}
+impl Hash for PathBuf {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_path().hash(state);
+    }
+}
+
+impl fmt::Debug for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+impl fmt::Debug for PathBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_path(), f)
+    }
+}
+impl fmt::Display for PathBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 impl PartialEq<String> for &PathBuf {
     fn eq(&self, other: &String) -> bool {
-        other.as_str() == self.0.as_str()
+        self.as_path() == Path::new(other)
     }
 }
 impl PartialEq<&str> for &PathBuf {
     fn eq(&self, other: &&str) -> bool {
-        *other == self.0.as_str()
+        self.as_path() == Path::new(other)
     }
 }
 impl PartialEq<&str> for PathBuf {
     fn eq(&self, other: &&str) -> bool {
-        *other == self.0.as_str()
+        self.as_path() == Path::new(other)
     }
 }
 
 impl From<&str> for PathBuf {
     fn from(s: &str) -> Self {
-        Self::new(s)
+        Self(String::from(s))
     }
 }
 impl From<String> for PathBuf {
     fn from(s: String) -> Self {
-        Self::new(s)
+        Self(s)
+    }
+}
+impl From<&String> for PathBuf {
+    fn from(s: &String) -> Self {
+        Self(s.clone())
+    }
+}
+impl From<&Path> for PathBuf {
+    fn from(path: &Path) -> Self {
+        path.to_path_buf()
+    }
+}
+impl From<&PathBuf> for PathBuf {
+    fn from(path: &PathBuf) -> Self {
+        path.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec::Vec;
+    use std::path::Path as StdPath;
+    use std::path::PathBuf as StdPathBuf;
+
+    const SAMPLES: &[&str] = &[
+        "",
+        "/",
+        "//",
+        ".",
+        "..",
+        "./",
+        "/.",
+        "/..",
+        "a",
+        "a/",
+        "a//b",
+        "a/./b",
+        "a/.",
+        "a/..",
+        "./a/b",
+        "../a",
+        "/a",
+        "/a/",
+        "/a/b",
+        "/a/b/",
+        "/a//b/./c/",
+        "/a/../b",
+        "/dir/file.txt",
+        "/dir/archive.tar.gz",
+        "/dir/.hidden",
+        "/dir/trailing.",
+        "/dir/.hidden.txt",
+        "/dir.d/file",
+        "ünïcödé/ファイル.txt",
+    ];
+
+    fn std_str(path: &StdPath) -> &str {
+        path.to_str().unwrap()
+    }
+
+    #[test]
+    fn test_components_match_std() {
+        for sample in SAMPLES {
+            let ours: Vec<&str> = Path::new(sample).iter().collect();
+            let std: Vec<&str> = StdPath::new(sample)
+                .iter()
+                .map(|c| c.to_str().unwrap())
+                .collect();
+            assert_eq!(ours, std, "components of {sample:?}");
+            let ours: Vec<&str> = Path::new(sample).iter().rev().collect();
+            let std: Vec<&str> = StdPath::new(sample)
+                .iter()
+                .rev()
+                .map(|c| c.to_str().unwrap())
+                .collect();
+            assert_eq!(ours, std, "reversed components of {sample:?}");
+        }
+    }
+
+    #[test]
+    fn test_queries_match_std() {
+        for sample in SAMPLES {
+            let (ours, std) = (Path::new(sample), StdPath::new(sample));
+            assert_eq!(
+                ours.parent().map(Path::to_str),
+                std.parent().map(std_str),
+                "parent of {sample:?}"
+            );
+            assert_eq!(
+                ours.file_name(),
+                std.file_name().map(|n| n.to_str().unwrap()),
+                "file_name of {sample:?}"
+            );
+            assert_eq!(
+                ours.file_stem(),
+                std.file_stem().map(|n| n.to_str().unwrap()),
+                "file_stem of {sample:?}"
+            );
+            assert_eq!(
+                ours.extension(),
+                std.extension().map(|n| n.to_str().unwrap()),
+                "extension of {sample:?}"
+            );
+            assert_eq!(ours.is_absolute(), std.is_absolute(), "{sample:?}");
+        }
+    }
+
+    #[test]
+    fn test_comparisons_match_std() {
+        for a in SAMPLES {
+            for b in SAMPLES {
+                let (ours_a, ours_b) = (Path::new(a), Path::new(b));
+                let (std_a, std_b) = (StdPath::new(a), StdPath::new(b));
+                assert_eq!(ours_a == ours_b, std_a == std_b, "{a:?} == {b:?}");
+                assert_eq!(ours_a.cmp(ours_b), std_a.cmp(std_b), "{a:?} cmp {b:?}");
+                assert_eq!(
+                    ours_a.starts_with(ours_b),
+                    std_a.starts_with(std_b),
+                    "{a:?} starts_with {b:?}"
+                );
+                assert_eq!(
+                    ours_a.ends_with(ours_b),
+                    std_a.ends_with(std_b),
+                    "{a:?} ends_with {b:?}"
+                );
+                assert_eq!(
+                    ours_a.join(b).to_str(),
+                    std_str(&std_a.join(b)),
+                    "{a:?} join {b:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_mutations_match_std() {
+        for sample in SAMPLES {
+            let (mut ours, mut std) = (PathBuf::from(*sample), StdPathBuf::from(sample));
+            assert_eq!(ours.pop(), std.pop(), "pop {sample:?}");
+            assert_eq!(ours.to_str(), std_str(&std), "pop {sample:?}");
+
+            for extension in ["", "rs", "tar.gz"] {
+                let (mut ours, mut std) = (PathBuf::from(*sample), StdPathBuf::from(sample));
+                assert_eq!(
+                    ours.set_extension(extension),
+                    std.set_extension(extension),
+                    "{sample:?} set_extension {extension:?}"
+                );
+                assert_eq!(ours.to_str(), std_str(&std), "{sample:?} {extension:?}");
+            }
+
+            let (mut ours, mut std) = (PathBuf::from(*sample), StdPathBuf::from(sample));
+            ours.set_file_name("name.txt");
+            std.set_file_name("name.txt");
+            assert_eq!(ours.to_str(), std_str(&std), "set_file_name {sample:?}");
+        }
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        let cases = [
+            ("/", "/"),
+            ("/a//b/./c/", "/a/b/c"),
+            ("/a/b/../c", "/a/c"),
+            ("/a/../../..", "/"),
+            ("/..", "/"),
+            ("a/../..", ".."),
+            ("../a/./b/..", "../a"),
+            ("./a", "a"),
+            ("a/..", "."),
+            ("", "."),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(
+                normalize_path(path).display().to_string(),
+                expected,
+                "{path:?}"
+            );
+        }
     }
 }
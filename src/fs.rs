@@ -0,0 +1,447 @@
+//! Path-based API modelled on `std::fs`.
+//!
+//! Every function takes an absolute path and runs under a single acquisition
+//! of the volume lock. Functions working on a single file or directory take
+//! it shared, together with the locks of that file and directory; the other
+//! ones take it exclusively, so e.g. [`VfatFS::write`] creates and fills the
+//! file atomically with respect to other callers. Errors follow `std::fs`:
+//! [`VfatRsError::kind`] returns the [`ErrorKind`](crate::io::ErrorKind) the
+//! corresponding `std::fs` call would fail with.
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::api::{Directory, DirectoryEntry, EntryType, File, Metadata, OpenOptions};
+use crate::{Path, PathBuf, Result, VfatFS, VfatRsError};
+
+const PSEUDO_FOLDERS: [&str; 2] = [".", ".."];
+
+/// Split `path` into its parent directory and final component.
+pub(crate) fn split_parent(path: PathBuf) -> Result<(PathBuf, String)> {
+    #[cfg(feature = "std")]
+    let name = path.file_name().and_then(|name| name.to_str());
+    #[cfg(not(feature = "std"))]
+    let name = path.file_name();
+    match (path.parent().map(PathBuf::from), name) {
+        (Some(parent), Some(name)) if !PSEUDO_FOLDERS.contains(&name) => {
+            Ok((parent, name.to_string()))
+        }
+        _ => Err(VfatRsError::InvalidPath {
+            target: path.display().to_string(),
+            reason: "the path has no final component",
+        }),
+    }
+}
+
+fn is_not_found(err: &VfatRsError) -> bool {
+    matches!(
+        err,
+        VfatRsError::EntryNotFound { .. } | VfatRsError::FileNotFound { .. }
+    )
+}
+
+impl VfatFS {
+    /// Open the file at `path` for reading, like `std::fs::File::open`. To
+    /// write to it, use [`Self::open_with`] with [`OpenOptions::write`].
+    ///
+    /// Fails with [`VfatRsError::IsADirectory`] if `path` is a directory.
+    pub fn open<P: Into<PathBuf>>(&mut self, path: P) -> Result<File> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        let mut file = self.file_at(path.into())?;
+        file.mode = *OpenOptions::new().read(true);
+        Ok(file)
+    }
+
+    /// Open the file at `path` with `options`, like
+    /// `std::fs::OpenOptions::open`. Fails with
+    /// [`ErrorKind::InvalidInput`](crate::io::ErrorKind::InvalidInput) on
+    /// combinations `std` rejects too, like `truncate` without `write`.
+    pub fn open_with<P: Into<PathBuf>>(&mut self, path: P, options: &OpenOptions) -> Result<File> {
+        options.validate()?;
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        self.open_with_unlocked(path.into(), options)
+    }
+
+    /// Create the file at `path`, truncating it if it already exists, like
+    /// `std::fs::File::create`. The parent directory must exist.
+    pub fn create<P: Into<PathBuf>>(&mut self, path: P) -> Result<File> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        self.create_unlocked(path.into())
+    }
+
+    /// Create a directory at `path`. The parent directory must exist, and
+    /// `path` must not.
+    pub fn create_dir<P: Into<PathBuf>>(&mut self, path: P) -> Result<Directory> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        let path = path.into();
+        let (parent, name) = split_parent(path.clone())?;
+        let mut parent = self.directory_at(parent)?;
+        let dir_lock = parent.lock();
+        let _dir_guard = dir_lock.write();
+        Ok(parent
+            .create(name, EntryType::Directory)?
+            .into_directory_unchecked())
+    }
+
+    /// Create the directory at `path` and any missing ancestor, like
+    /// `std::fs::create_dir_all`. Succeeds if `path` is already a directory.
+    pub fn create_dir_all<P: Into<PathBuf>>(&mut self, path: P) -> Result<Directory> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        let path = path.into();
+        // Walk up to the deepest existing ancestor, then create downwards.
+        let mut missing = Vec::new();
+        let mut current = path.clone();
+        let mut directory = loop {
+            match self.get_from_absolute_path_unlocked(current.clone()) {
+                Ok(entry) if entry.is_dir() => break entry.into_directory_unchecked(),
+                Ok(_) if current == path => {
+                    return Err(VfatRsError::NameAlreadyInUse {
+                        target: path.display().to_string(),
+                    });
+                }
+                Ok(_) => {
+                    return Err(VfatRsError::NotADirectory {
+                        target: current.display().to_string(),
+                    });
+                }
+                Err(err) if is_not_found(&err) => {
+                    let (parent, name) = split_parent(current)?;
+                    missing.push(name);
+                    current = parent;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        for name in missing.into_iter().rev() {
+            directory = directory
+                .create(name, EntryType::Directory)?
+                .into_directory_unchecked();
+        }
+        Ok(directory)
+    }
+
+    /// Remove the file at `path`, like `std::fs::remove_file`.
+    ///
+    /// Fails with [`VfatRsError::IsADirectory`] if `path` is a directory.
+    pub fn remove_file<P: Into<PathBuf>>(&mut self, path: P) -> Result<()> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        let path = path.into();
+        let (parent, name) = split_parent(path.clone())?;
+        let mut parent = self.directory_at(parent)?;
+        let dir_lock = parent.lock();
+        let _dir_guard = dir_lock.write();
+        let entry = Self::entry_in(&parent, &name, &path)?;
+        if entry.is_dir() {
+            return Err(VfatRsError::IsADirectory {
+                target: path.display().to_string(),
+            });
+        }
+        let wipe = self.options.secure_delete;
+        parent.delete_unlocked(name, wipe)
+    }
+
+    /// Remove the empty directory at `path`, like `std::fs::remove_dir`.
+    ///
+    /// Fails with [`VfatRsError::NonEmptyDirectory`] if it has entries, and
+    /// with [`VfatRsError::NotADirectory`] if `path` is a file.
+    pub fn remove_dir<P: Into<PathBuf>>(&mut self, path: P) -> Result<()> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        let path = path.into();
+        let (mut parent, name, entry) = self.entry_with_parent(path.clone())?;
+        if !entry.is_dir() {
+            return Err(VfatRsError::NotADirectory {
+                target: path.display().to_string(),
+            });
+        }
+        let wipe = self.options.secure_delete;
+        parent.delete_unlocked(name, wipe)
+    }
+
+    /// Remove the directory at `path` with everything it contains, like
+    /// `std::fs::remove_dir_all`.
+    pub fn remove_dir_all<P: Into<PathBuf>>(&mut self, path: P) -> Result<()> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        let path = path.into();
+        let (mut parent, name, entry) = self.entry_with_parent(path.clone())?;
+        if !entry.is_dir() {
+            return Err(VfatRsError::NotADirectory {
+                target: path.display().to_string(),
+            });
+        }
+        Self::empty_directory(entry.into_directory_unchecked())?;
+        let wipe = self.options.secure_delete;
+        parent.delete_unlocked(name, wipe)
+    }
+
+    /// Rename `from` to `to`, moving it to another directory if needed, like
+    /// `std::fs::rename`. An existing file at `to` is replaced, as is an
+    /// existing empty directory if `from` is a directory.
+    pub fn rename<P: Into<PathBuf>, Q: Into<PathBuf>>(&mut self, from: P, to: Q) -> Result<()> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        let (from, to) = (from.into(), to.into());
+        let (mut parent, name, entry) = self.entry_with_parent(from)?;
+        match self.get_from_absolute_path_unlocked(to.clone()) {
+            Ok(existing) if existing.is_dir() && !entry.is_dir() => {
+                return Err(VfatRsError::IsADirectory {
+                    target: to.display().to_string(),
+                });
+            }
+            Ok(existing) if !existing.is_dir() && entry.is_dir() => {
+                return Err(VfatRsError::NotADirectory {
+                    target: to.display().to_string(),
+                });
+            }
+            Err(err) if !is_not_found(&err) => return Err(err),
+            _ => {}
+        }
+        parent.rename_unlocked(name, to)
+    }
+
+    /// Returns the metadata of the entry at `path`, like `std::fs::metadata`.
+    pub fn metadata<P: Into<PathBuf>>(&mut self, path: P) -> Result<Metadata> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        Ok(self.get_from_absolute_path_unlocked(path.into())?.metadata)
+    }
+
+    /// Read the whole content of the file at `path`, like `std::fs::read`.
+    pub fn read<P: Into<PathBuf>>(&mut self, path: P) -> Result<Vec<u8>> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        let mut file = self.file_at(path.into())?;
+        let io_lock = file.io_lock.clone();
+        let _io_guard = io_lock.read();
+        let mut content = vec![0; file.metadata.size()];
+        let mut filled = 0;
+        while filled < content.len() {
+            match file.read_unlocked(&mut content[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        content.truncate(filled);
+        Ok(content)
+    }
+
+    /// Write `contents` as the whole content of the file at `path`, creating
+    /// or truncating it, like `std::fs::write`.
+    pub fn write<P: Into<PathBuf>, C: AsRef<[u8]>>(&mut self, path: P, contents: C) -> Result<()> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        let mut file = self.create_unlocked(path.into())?;
+        Self::write_all(&mut file, contents.as_ref())
+    }
+
+    /// Returns the entries of the directory at `path`, like
+    /// `std::fs::read_dir`. The `.` and `..` pseudo entries and the volume
+    /// label are left out.
+    pub fn read_dir<P: Into<PathBuf>>(&mut self, path: P) -> Result<Vec<DirectoryEntry>> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        let directory = self.directory_at(path.into())?;
+        let dir_lock = directory.lock();
+        let _dir_guard = dir_lock.read();
+        let mut entries = Vec::new();
+        for entry in directory.iter_unlocked() {
+            let entry = entry?;
+            if !PSEUDO_FOLDERS.contains(&entry.metadata.name())
+                && !entry.metadata.attributes.is_volume_id()
+            {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Returns `true` if `path` names an existing file or directory, like
+    /// `std::fs::exists`.
+    pub fn exists<P: Into<PathBuf>>(&mut self, path: P) -> Result<bool> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        match self.get_from_absolute_path_unlocked(path.into()) {
+            Ok(_) => Ok(true),
+            Err(err) if is_not_found(&err) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Copy the content of the file `from` to the file `to`, creating or
+    /// truncating it, like `std::fs::copy`. Returns the number of bytes
+    /// copied.
+    pub fn copy<P: Into<PathBuf>, Q: Into<PathBuf>>(&mut self, from: P, to: Q) -> Result<u64> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        let mut source = self.file_at(from.into())?;
+        let mut destination = self.create_unlocked(to.into())?;
+        let mut buf = vec![0; self.bytes_per_cluster() as usize];
+        let mut copied = 0;
+        loop {
+            let read = source.read_unlocked(&mut buf)?;
+            if read == 0 {
+                return Ok(copied);
+            }
+            Self::write_all(&mut destination, &buf[..read])?;
+            copied += read as u64;
+        }
+    }
+
+    fn create_unlocked(&mut self, path: PathBuf) -> Result<File> {
+        let mut options = OpenOptions::read_write();
+        options.create(true).truncate(true);
+        self.open_with_unlocked(path, &options)
+    }
+
+    /// Open or create the file at `path`. Its directory stays locked until
+    /// the handle is registered as open, so that a concurrent delete leaves
+    /// the clusters to the handle.
+    fn open_with_unlocked(&mut self, path: PathBuf, options: &OpenOptions) -> Result<File> {
+        let (parent, name) = split_parent(path.clone())?;
+        let mut parent = self.directory_at(parent)?;
+        let find_or_create = |parent: &mut Directory| match parent.find_unlocked(&name)? {
+            Some(entry) if entry.is_dir() => Err(VfatRsError::IsADirectory {
+                target: path.display().to_string(),
+            }),
+            Some(_) if options.create_new => Err(VfatRsError::NameAlreadyInUse {
+                target: path.display().to_string(),
+            }),
+            Some(entry) => Ok(entry.into_file_unchecked()),
+            None if options.create || options.create_new => Ok(parent
+                .create(name.clone(), EntryType::File)?
+                .into_file_unchecked()),
+            None => Err(VfatRsError::EntryNotFound {
+                target: path.display().to_string(),
+            }),
+        };
+        let dir_lock = parent.lock();
+        let mut file = if options.create || options.create_new {
+            let _dir_guard = dir_lock.write();
+            find_or_create(&mut parent)?
+        } else {
+            let _dir_guard = dir_lock.read();
+            find_or_create(&mut parent)?
+        };
+        if options.writable() {
+            self.ensure_modifiable(file.metadata())?;
+        }
+        if options.truncate {
+            // Truncating updates the entry, under the directory lock: the
+            // file lock comes first.
+            let io_lock = file.io_lock.clone();
+            let _io_guard = io_lock.write();
+            let wipe = self.options.secure_delete;
+            file.truncate_unlocked(0, wipe)?;
+        }
+        file.mode = *options;
+        Ok(file)
+    }
+
+    /// Open the file at `path`, like [`Self::open_with_unlocked`] without
+    /// creating it.
+    fn file_at(&mut self, path: PathBuf) -> Result<File> {
+        if !path.is_absolute() {
+            return Err(VfatRsError::PathNotAbsolute {
+                target: path.display().to_string(),
+            });
+        }
+        let path = crate::normalize_path(&path);
+        // Once normalized, only the root has no final component.
+        let (parent, name) = split_parent(path.clone()).map_err(|_| VfatRsError::IsADirectory {
+            target: path.display().to_string(),
+        })?;
+        let parent = self.directory_at(parent)?;
+        let dir_lock = parent.lock();
+        let _dir_guard = dir_lock.read();
+        match Self::entry_in(&parent, &name, &path)? {
+            entry if entry.is_dir() => Err(VfatRsError::IsADirectory {
+                target: path.display().to_string(),
+            }),
+            entry => Ok(entry.into_file_unchecked()),
+        }
+    }
+
+    pub(crate) fn directory_at(&mut self, path: PathBuf) -> Result<Directory> {
+        let entry = self.get_from_absolute_path_unlocked(path.clone())?;
+        if !entry.is_dir() {
+            return Err(VfatRsError::NotADirectory {
+                target: path.display().to_string(),
+            });
+        }
+        Ok(entry.into_directory_unchecked())
+    }
+
+    /// Resolve `path`, returning its parent directory and name too.
+    fn entry_with_parent(&mut self, path: PathBuf) -> Result<(Directory, String, DirectoryEntry)> {
+        let (parent, name) = split_parent(path.clone())?;
+        let parent = self.directory_at(parent)?;
+        let entry = Self::entry_in(&parent, &name, &path)?;
+        Ok((parent, name, entry))
+    }
+
+    /// The entry called `name` in `parent`, whose path is `path`.
+    fn entry_in(parent: &Directory, name: &str, path: &Path) -> Result<DirectoryEntry> {
+        parent
+            .find_unlocked(name)?
+            .ok_or_else(|| VfatRsError::EntryNotFound {
+                target: path.display().to_string(),
+            })
+    }
+
+    /// Delete everything inside `directory`.
+    ///
+    /// The directories being emptied are kept on a stack on the heap: the top
+    /// one loses its files, then either its first subdirectory is pushed or,
+    /// if it has none left, it is popped and deleted from the one below.
+    fn empty_directory(directory: Directory) -> Result<()> {
+        let wipe = directory.vfat_filesystem.options.secure_delete;
+        let mut stack = vec![(directory, String::new())];
+        while let Some((directory, _)) = stack.last_mut() {
+            let mut subdirectory = None;
+            // Deleting only marks the slots of the entry just listed.
+            let listing = Directory::new(
+                directory.vfat_filesystem.clone(),
+                directory.metadata.clone(),
+            );
+            for entry in listing.iter_unlocked() {
+                let entry = entry?;
+                let name = entry.metadata.name().to_string();
+                if PSEUDO_FOLDERS.contains(&name.as_str()) {
+                    continue;
+                }
+                if !entry.is_dir() {
+                    directory.delete_unlocked(name, wipe)?;
+                } else if subdirectory.is_none() {
+                    subdirectory = Some((entry.into_directory_unchecked(), name));
+                }
+            }
+            match subdirectory {
+                Some(subdirectory) => stack.push(subdirectory),
+                None => {
+                    let (_, name) = stack.pop().expect("non-empty stack");
+                    if let Some((parent, _)) = stack.last_mut() {
+                        parent.delete_unlocked(name, wipe)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_all(file: &mut File, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match file.write_unlocked(buf)? {
+                0 => return Err(crate::io::ErrorKind::WriteZero.into()),
+                written => buf = &buf[written..],
+            }
+        }
+        Ok(())
+    }
+}
@@ -240,6 +240,15 @@ mod nonstd_io {
         /// An error returned when an operation could not be completed because an
         /// "end of file" was reached prematurely.
         UnexpectedEof,
+        /// A directory was specified where a non-directory was expected.
+        IsADirectory,
+        /// A non-directory was specified where a directory was expected.
+        NotADirectory,
+        /// A non-empty directory was specified where an empty directory was
+        /// expected.
+        DirectoryNotEmpty,
+        /// The underlying storage is full.
+        StorageFull,
     }
     impl Error {
         /// Creates a new I/O error from a known kind of error as well as an
@@ -274,7 +283,7 @@ mod nonstd_io {
     }
     impl From<VfatRsError> for Error {
         fn from(err: VfatRsError) -> Self {
-            Self::new(ErrorKind::Other, err)
+            Self::new(err.kind(), err)
         }
     }
 }
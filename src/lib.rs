@@ -14,43 +14,68 @@ extern crate core;
 
 use alloc::sync::Arc;
 
+pub use analysis::{DiskUsage, FREE_RUN_BUCKETS, FileUsage, FreeSpaceStats, VolumeUsage};
 pub use api::EntryType;
-use api::raw_directory_entry::{
-    Attributes, RegularDirectoryEntry, UnknownDirectoryEntry, VfatDirectoryEntry,
+pub use api::raw_directory_entry::{Attributes, attribute};
+use api::raw_directory_entry::{RegularDirectoryEntry, UnknownDirectoryEntry, VfatDirectoryEntry};
+pub use api::timestamp::{FileTimes, VfatTimestamp};
+pub use api::{
+    AllocateMode, DeletedEntry, Directory, DirectoryEntry, DirectoryIter, Extent, File, Metadata,
+    OpenOptions, VfatMetadataTrait,
 };
-pub use api::timestamp::VfatTimestamp;
-pub use api::{Directory, DirectoryEntry, File, Metadata, VfatMetadataTrait};
 pub(crate) use cache::CachedPartition;
+pub use defrag::{DefragBudget, DefragOptions, DefragReport};
 pub use error::{Result, VfatRsError};
 pub(crate) use formats::cluster_id::ClusterId;
+pub use formats::path::normalize_path;
 #[cfg(not(feature = "std"))]
-pub use formats::path::PathBuf;
+pub use formats::path::{Component, Components, Path, PathBuf};
 #[cfg(feature = "std")]
-pub use std::path::PathBuf;
+pub use std::path::{Component, Components, Path, PathBuf};
 
 pub use formats::sector_id::SectorId;
+pub use locks::SpinLocks;
+#[cfg(feature = "std")]
+pub use locks::StdLocks;
+pub use options::{
+    AccessTimePolicy, DEFAULT_DENTRY_CACHE_CAPACITY, MountOptions, UtcOffset, WipePattern,
+};
+pub use search::FindCriteria;
+pub use tree::{Walk, WalkEntry, WalkOrder};
 pub use vfat::VfatFS;
+pub use volume::{FatType, VolumeInfo};
 
+mod analysis;
 mod api;
 mod cache;
 mod cluster;
+mod defrag;
+mod dentry_cache;
 /// VfatRs error definitions
 mod error;
 mod fat_table;
 #[cfg(feature = "std")]
 mod fileblockdevice;
 mod formats;
+mod fs;
 /// I/O traits and error types.
 pub mod io;
 #[cfg(kani)]
 mod kani_proofs;
+mod locks;
 mod macros;
 /// Master Boot Record parsing.
 pub mod mbr;
+mod open_files;
+mod options;
+mod resize;
+mod search;
 mod time;
 /// OS-integration traits (`BlockDevice`, `TimeManagerTrait`).
 pub mod traits;
+mod tree;
 mod vfat;
+mod volume;
 
 const EBPF_VFAT_MAGIC: u8 = 0x28;
 const EBPF_VFAT_MAGIC_ALT: u8 = 0x29;
@@ -69,4 +94,4 @@ pub use time::TimeManagerNoop;
 #[cfg(feature = "std")]
 pub use fileblockdevice::FilebackedBlockDevice;
 
-pub use traits::{BlockDevice, TimeManagerTrait};
+pub use traits::{BlockDevice, LockProvider, RawLock, TimeManagerTrait};
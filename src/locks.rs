@@ -0,0 +1,470 @@
+//! Locks of a volume, and the order they are taken in.
+//!
+//! 1. The volume lock, `VfatFS::fs_lock`. Operations confined to a file or
+//!    a directory take it shared. The other ones take it exclusively and
+//!    need no other lock: renames, deleting directories, recursive copies
+//!    and removals, whole volume reports, defragmentation, resizing,
+//!    undeleting and free space wiping or trimming.
+//! 2. The I/O lock of a file, shared by all its handles: exclusive to change
+//!    the file, shared to read it.
+//! 3. Directory locks, from the [`LockTable`]: exclusive to change the slots
+//!    of a directory, shared to read them. A parent is locked before its
+//!    child, and at most one directory is locked otherwise.
+//! 4. The allocator lock, held while looking for free clusters and changing
+//!    the FAT.
+//! 5. Leaf locks, never held while taking another lock but the ones below
+//!    them in the list: the open file table, then a file's shared state, the
+//!    dentry cache, and the sector cache and device.
+//!
+//! No lock is ever taken twice by the same thread. The public entry points
+//! take the volume lock and the locks of what they work on, and the
+//! `*_unlocked` functions they call expect them to be held. Path lookups
+//! lock each directory while searching it, so they are never made while
+//! holding a directory lock.
+//!
+//! The locks come from the [`LockProvider`] of the volume, see
+//! [`MountOptions::locks`](crate::MountOptions::locks).
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hint;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ClusterId;
+use crate::traits::{LockProvider, RawLock};
+
+/// Spinning locks, the default without the `std` feature. They work
+/// anywhere, but keep the CPU busy while waiting.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpinLocks;
+
+impl LockProvider for SpinLocks {
+    fn new_lock(&self) -> Box<dyn RawLock> {
+        Box::new(SpinLock(AtomicUsize::new(0)))
+    }
+}
+
+/// A spinning reader-writer lock: the number of shared holders, or
+/// [`SpinLock::EXCLUSIVE`].
+struct SpinLock(AtomicUsize);
+
+impl SpinLock {
+    const EXCLUSIVE: usize = usize::MAX;
+}
+
+impl RawLock for SpinLock {
+    fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            hint::spin_loop();
+        }
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let mut holders = self.0.load(Ordering::Relaxed);
+        while holders != Self::EXCLUSIVE {
+            match self.0.compare_exchange_weak(
+                holders,
+                holders + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => holders = current,
+            }
+        }
+        false
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.0.fetch_sub(1, Ordering::Release);
+    }
+
+    fn lock_exclusive(&self) {
+        while self
+            .0
+            .compare_exchange_weak(0, Self::EXCLUSIVE, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.0.store(0, Ordering::Release);
+    }
+}
+
+/// Sleeping locks from the standard library, the default with the `std`
+/// feature. Waiting threads are parked, and once a thread waits for a lock
+/// exclusively no new shared holder gets it before that thread.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdLocks;
+
+#[cfg(feature = "std")]
+impl LockProvider for StdLocks {
+    fn new_lock(&self) -> Box<dyn RawLock> {
+        Box::<StdLock>::default()
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct StdLock {
+    state: std::sync::Mutex<StdLockState>,
+    /// Notified whenever the lock is released.
+    released: std::sync::Condvar,
+}
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct StdLockState {
+    readers: usize,
+    writer: bool,
+    waiting_writers: usize,
+}
+
+#[cfg(feature = "std")]
+impl StdLockState {
+    fn can_read(&self) -> bool {
+        !self.writer && self.waiting_writers == 0
+    }
+}
+
+#[cfg(feature = "std")]
+impl StdLock {
+    // The state is never left inconsistent, so a poisoned mutex is still good.
+    fn state(&self) -> std::sync::MutexGuard<'_, StdLockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn wait<'a>(
+        &self,
+        state: std::sync::MutexGuard<'a, StdLockState>,
+    ) -> std::sync::MutexGuard<'a, StdLockState> {
+        self.released
+            .wait(state)
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(feature = "std")]
+impl RawLock for StdLock {
+    fn lock_shared(&self) {
+        let mut state = self.state();
+        while !state.can_read() {
+            state = self.wait(state);
+        }
+        state.readers += 1;
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let mut state = self.state();
+        let available = state.can_read();
+        if available {
+            state.readers += 1;
+        }
+        available
+    }
+
+    unsafe fn unlock_shared(&self) {
+        let mut state = self.state();
+        state.readers -= 1;
+        if state.readers == 0 {
+            self.released.notify_all();
+        }
+    }
+
+    fn lock_exclusive(&self) {
+        let mut state = self.state();
+        state.waiting_writers += 1;
+        while state.writer || state.readers > 0 {
+            state = self.wait(state);
+        }
+        state.waiting_writers -= 1;
+        state.writer = true;
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.state().writer = false;
+        self.released.notify_all();
+    }
+}
+
+/// The default lock provider: [`StdLocks`] with the `std` feature, else
+/// [`SpinLocks`].
+pub(crate) fn default_provider() -> Arc<dyn LockProvider> {
+    #[cfg(feature = "std")]
+    return Arc::new(StdLocks);
+    #[cfg(not(feature = "std"))]
+    return Arc::new(SpinLocks);
+}
+
+/// A mutex on a lock from a [`LockProvider`].
+pub(crate) struct Mutex<T> {
+    raw: Box<dyn RawLock>,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: the data is only reached through the lock, or with `&mut self`.
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub(crate) fn new(data: T, locks: &dyn LockProvider) -> Self {
+        Self {
+            raw: locks.new_lock(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        self.raw.lock_exclusive();
+        MutexGuard { mutex: self }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Mutex")
+    }
+}
+
+pub(crate) struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock exclusively.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock exclusively.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard holds the lock exclusively.
+        unsafe { self.mutex.raw.unlock_exclusive() }
+    }
+}
+
+/// A reader-writer lock on a lock from a [`LockProvider`].
+pub(crate) struct RwLock<T> {
+    raw: Box<dyn RawLock>,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: shared guards only hand out `&T`, the exclusive one `&mut T`.
+unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub(crate) fn new(data: T, locks: &dyn LockProvider) -> Self {
+        Self {
+            raw: locks.new_lock(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.raw.lock_shared();
+        RwLockReadGuard { lock: self }
+    }
+
+    pub(crate) fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        if self.raw.try_lock_shared() {
+            Some(RwLockReadGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.raw.lock_exclusive();
+        RwLockWriteGuard { lock: self }
+    }
+}
+
+impl<T> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RwLock")
+    }
+}
+
+pub(crate) struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock shared.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard holds the lock shared.
+        unsafe { self.lock.raw.unlock_shared() }
+    }
+}
+
+pub(crate) struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock exclusively.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock exclusively.
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard holds the lock exclusively.
+        unsafe { self.lock.raw.unlock_exclusive() }
+    }
+}
+
+/// A lock guarding no data by itself, shared by everything it protects.
+pub(crate) type SharedLock = Arc<RwLock<()>>;
+
+/// A new [`SharedLock`] from `locks`.
+pub(crate) fn shared_lock(locks: &dyn LockProvider) -> SharedLock {
+    Arc::new(RwLock::new((), locks))
+}
+
+/// Locks of the directories in use, by first cluster.
+///
+/// Entries only live as long as someone holds the lock: dead ones are
+/// dropped as the table grows.
+pub(crate) struct LockTable {
+    locks: BTreeMap<ClusterId, Weak<RwLock<()>>>,
+    /// Table size at which dead entries are dropped next.
+    prune_at: usize,
+    provider: Arc<dyn LockProvider>,
+}
+
+impl LockTable {
+    pub(crate) fn new(provider: Arc<dyn LockProvider>) -> Self {
+        Self {
+            locks: BTreeMap::new(),
+            prune_at: 0,
+            provider,
+        }
+    }
+
+    /// The lock of the directory starting at `directory`.
+    pub(crate) fn get(&mut self, directory: ClusterId) -> SharedLock {
+        if let Some(lock) = self.locks.get(&directory).and_then(Weak::upgrade) {
+            return lock;
+        }
+        if self.locks.len() >= self.prune_at {
+            self.locks.retain(|_, lock| lock.strong_count() > 0);
+            self.prune_at = 2 * self.locks.len() + 16;
+        }
+        let lock = shared_lock(&*self.provider);
+        self.locks.insert(directory, Arc::downgrade(&lock));
+        lock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_directory_has_one_lock_while_in_use() {
+        let mut table = LockTable::new(Arc::new(SpinLocks));
+        let first = table.get(ClusterId::new(2));
+        let second = table.get(ClusterId::new(2));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &table.get(ClusterId::new(3))));
+
+        let _guard = first.write();
+        assert!(second.try_read().is_none());
+    }
+
+    #[test]
+    fn unused_locks_are_dropped() {
+        let mut table = LockTable::new(Arc::new(SpinLocks));
+        let kept = table.get(ClusterId::new(2));
+        for cluster in 3..1000 {
+            table.get(ClusterId::new(cluster));
+        }
+        assert!(table.locks.len() < 100);
+        assert!(Arc::ptr_eq(&kept, &table.get(ClusterId::new(2))));
+    }
+
+    #[test]
+    fn guards_release_their_lock() {
+        let mutex = Mutex::new(1, &SpinLocks);
+        *mutex.lock() += 1;
+        assert_eq!(*mutex.lock(), 2);
+
+        let lock = RwLock::new((), &SpinLocks);
+        let first = lock.read();
+        let second = lock.try_read();
+        assert!(second.is_some());
+        drop((first, second));
+        drop(lock.write());
+        assert!(lock.try_read().is_some());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_locks_make_readers_wait_for_a_waiting_writer() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::thread;
+        use std::time::Duration;
+
+        let lock = Arc::new(RwLock::new((), &StdLocks));
+        let written = Arc::new(AtomicBool::new(false));
+        let reader = lock.read();
+        let writer = {
+            let (lock, written) = (lock.clone(), written.clone());
+            thread::spawn(move || {
+                let _guard = lock.write();
+                written.store(true, Ordering::SeqCst);
+            })
+        };
+        while lock.try_read().is_some() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!written.load(Ordering::SeqCst));
+        drop(reader);
+        writer.join().unwrap();
+        assert!(written.load(Ordering::SeqCst));
+        assert!(lock.try_read().is_some());
+    }
+}
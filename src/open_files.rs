@@ -0,0 +1,352 @@
+//! Table of the files open on a volume.
+//!
+//! Every [`File`](crate::File) handle of the same directory entry shares one
+//! [`SharedFile`], so a size change or a first cluster allocated through one
+//! handle is seen by all the others. Entries are keyed by where their short
+//! entry lives: the first cluster of the parent directory and the slot index
+//! in it. Renames, moves and relocations of the parent directory re-key them.
+//!
+//! Deleting an entry that is still open only removes its directory slots: the
+//! cluster chain is freed when the last handle is closed, like unlinking an
+//! open file on POSIX.
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use log::error;
+
+use crate::api::Metadata;
+use crate::locks::{Mutex, SharedLock, shared_lock};
+use crate::traits::LockProvider;
+use crate::{ArcMutex, CachedPartition, ClusterId, PathBuf, WipePattern, fat_table};
+
+/// Location of a short directory entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct EntrySlot {
+    /// First cluster of the directory holding the entry.
+    pub(crate) directory: ClusterId,
+    /// Index of the short entry's slot in the directory.
+    pub(crate) index: usize,
+}
+
+/// State shared by the handles of one file.
+#[derive(Debug)]
+pub(crate) struct SharedFile {
+    pub(crate) metadata: Metadata,
+    /// Bumped whenever the cluster chain may shrink or change head, so the
+    /// handles drop their cached chain positions.
+    pub(crate) generation: u64,
+    /// The directory entry doesn't match `metadata` yet.
+    pub(crate) dirty: bool,
+    /// The directory entry was deleted while the file was open.
+    pub(crate) unlinked: bool,
+    /// How to wipe the clusters once an unlinked file is closed.
+    pub(crate) wipe: Option<WipePattern>,
+    /// The I/O lock of the file.
+    pub(crate) io_lock: SharedLock,
+    /// Number of open handles, counted under the table lock: the handles'
+    /// `Arc`s are only released after they are closed, so their count can't
+    /// tell which close is the last.
+    handles: usize,
+}
+
+impl SharedFile {
+    pub(crate) fn new(metadata: Metadata, locks: &dyn LockProvider) -> Self {
+        Self {
+            metadata,
+            generation: 0,
+            dirty: false,
+            unlinked: false,
+            wipe: None,
+            io_lock: shared_lock(locks),
+            handles: 1,
+        }
+    }
+}
+
+pub(crate) type SharedFileRef = Arc<Mutex<SharedFile>>;
+
+/// The open files of a volume.
+///
+/// Lock order: the table is locked before any [`SharedFile`], and neither is
+/// held while doing I/O. See [`crate::locks`].
+pub(crate) struct OpenFileTable {
+    files: BTreeMap<EntrySlot, Weak<Mutex<SharedFile>>>,
+    /// Chains of unlinked files closed while the filesystem was busy, to be
+    /// freed by the next delete, close of an unlinked file or
+    /// [`VfatFS::sync`](crate::VfatFS::sync), or else by the [`OrphanReaper`].
+    orphans: Vec<(ClusterId, Option<WipePattern>)>,
+    locks: Arc<dyn LockProvider>,
+}
+
+impl OpenFileTable {
+    pub(crate) fn new(locks: Arc<dyn LockProvider>) -> Self {
+        Self {
+            files: BTreeMap::new(),
+            orphans: Vec::new(),
+            locks,
+        }
+    }
+
+    /// The state of the file at `slot`, registered from `metadata` if the
+    /// file isn't open yet.
+    pub(crate) fn open(&mut self, slot: EntrySlot, metadata: &Metadata) -> SharedFileRef {
+        if let Some(shared) = self.get(slot) {
+            shared.lock().handles += 1;
+            return shared;
+        }
+        let shared = SharedFile::new(metadata.clone(), &*self.locks);
+        let shared = Arc::new(Mutex::new(shared, &*self.locks));
+        self.files.insert(slot, Arc::downgrade(&shared));
+        shared
+    }
+
+    pub(crate) fn get(&self, slot: EntrySlot) -> Option<SharedFileRef> {
+        self.files.get(&slot).and_then(Weak::upgrade)
+    }
+
+    /// The entry at `slot` was deleted. Returns `true` if the file is open:
+    /// freeing its chain is then up to the last handle.
+    pub(crate) fn unlink(&mut self, slot: EntrySlot, wipe: Option<WipePattern>) -> bool {
+        let Some(shared) = self.files.remove(&slot).and_then(|file| file.upgrade()) else {
+            return false;
+        };
+        let mut shared = shared.lock();
+        shared.unlinked = true;
+        shared.wipe = wipe;
+        true
+    }
+
+    /// The entry at `from` was renamed or moved to `to`, in the directory
+    /// whose path is `parent`.
+    pub(crate) fn rename(&mut self, from: EntrySlot, to: EntrySlot, name: String, parent: PathBuf) {
+        let Some(file) = self.files.remove(&from) else {
+            return;
+        };
+        if let Some(shared) = file.upgrade() {
+            let mut shared = shared.lock();
+            shared.metadata.slot = Some(to);
+            shared.metadata.relocate(name, parent);
+            self.files.insert(to, file);
+        }
+    }
+
+    /// The directory whose chain started at `from` now starts at `to`.
+    pub(crate) fn directory_moved(&mut self, from: ClusterId, to: ClusterId) {
+        let moved: Vec<EntrySlot> = self
+            .files
+            .keys()
+            .filter(|slot| slot.directory == from)
+            .copied()
+            .collect();
+        for slot in moved {
+            let file = self.files.remove(&slot).expect("slot listed above");
+            let to = EntrySlot {
+                directory: to,
+                index: slot.index,
+            };
+            if let Some(shared) = file.upgrade() {
+                shared.lock().metadata.slot = Some(to);
+                self.files.insert(to, file);
+            }
+        }
+    }
+
+    /// The directory entry at `slot` was rewritten with `metadata`, maybe by
+    /// something other than a handle of the file, such as a defragmentation.
+    pub(crate) fn entry_updated(&self, slot: EntrySlot, metadata: &Metadata) {
+        let Some(shared) = self.get(slot) else {
+            return;
+        };
+        let mut shared = shared.lock();
+        let current = &shared.metadata;
+        if current.cluster != metadata.cluster || current.size > metadata.size {
+            shared.generation += 1;
+        }
+        shared.metadata.cluster = metadata.cluster;
+        shared.metadata.size = metadata.size;
+        shared.metadata.attributes = metadata.attributes;
+        shared.metadata.set_times(&metadata.times());
+        shared.dirty = false;
+    }
+
+    /// A handle of `shared` is being closed. If it was the last handle of an
+    /// unlinked file, returns the chain to free and how to wipe it.
+    pub(crate) fn close(
+        &mut self,
+        shared_ref: &SharedFileRef,
+    ) -> Option<(ClusterId, Option<WipePattern>)> {
+        let mut shared = shared_ref.lock();
+        shared.handles -= 1;
+        if shared.handles > 0 {
+            return None;
+        }
+        if let Some(slot) = shared.metadata.slot
+            && !shared.unlinked
+            && self
+                .files
+                .get(&slot)
+                .is_some_and(|file| file.as_ptr() == Arc::as_ptr(shared_ref))
+        {
+            self.files.remove(&slot);
+        }
+        (shared.unlinked && !shared.metadata.has_no_cluster_allocated())
+            .then_some((shared.metadata.cluster, shared.wipe))
+    }
+
+    pub(crate) fn push_orphan(&mut self, cluster: ClusterId, wipe: Option<WipePattern>) {
+        self.orphans.push((cluster, wipe));
+    }
+
+    pub(crate) fn take_orphans(&mut self) -> Vec<(ClusterId, Option<WipePattern>)> {
+        core::mem::take(&mut self.orphans)
+    }
+}
+
+/// Frees the chains left in the orphan list of a volume when its last handle
+/// is dropped, as no later operation will.
+pub(crate) struct OrphanReaper {
+    open_files: Arc<Mutex<OpenFileTable>>,
+    device: ArcMutex<CachedPartition>,
+    discard: bool,
+}
+
+impl OrphanReaper {
+    pub(crate) fn new(
+        open_files: Arc<Mutex<OpenFileTable>>,
+        device: ArcMutex<CachedPartition>,
+        discard: bool,
+    ) -> Self {
+        Self {
+            open_files,
+            device,
+            discard,
+        }
+    }
+}
+
+impl Drop for OrphanReaper {
+    fn drop(&mut self) {
+        // Every handle of the volume is gone: nothing else can use the FAT.
+        for (cluster, wipe) in self.open_files.lock().take_orphans() {
+            let freed =
+                fat_table::delete_cluster_chain(cluster, self.device.clone(), wipe, self.discard);
+            if let Err(err) = freed {
+                error!("Freeing the clusters of a deleted file failed: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::raw_directory_entry::Attributes;
+    use crate::api::timestamp::VfatTimestamp;
+    use crate::locks::SpinLocks;
+
+    fn slot(directory: u32, index: usize) -> EntrySlot {
+        EntrySlot {
+            directory: ClusterId::new(directory),
+            index,
+        }
+    }
+
+    fn metadata(slot: EntrySlot, cluster: u32) -> Metadata {
+        let mut metadata = Metadata::new(
+            VfatTimestamp::new(0),
+            VfatTimestamp::new(0),
+            "file.txt",
+            10,
+            PathBuf::from("/file.txt"),
+            ClusterId::new(cluster),
+            PathBuf::from("/"),
+            Attributes(0),
+        );
+        metadata.slot = Some(slot);
+        metadata
+    }
+
+    #[test]
+    fn handles_of_a_slot_share_their_state() {
+        let mut table = OpenFileTable::new(Arc::new(SpinLocks));
+        let first = table.open(slot(2, 3), &metadata(slot(2, 3), 10));
+        let second = table.open(slot(2, 3), &metadata(slot(2, 3), 99));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(second.lock().metadata.cluster, ClusterId::new(10));
+
+        assert_eq!(table.close(&first), None);
+        drop(first);
+        assert_eq!(table.close(&second), None);
+        drop(second);
+        assert!(table.get(slot(2, 3)).is_none());
+    }
+
+    #[test]
+    fn last_close_of_an_unlinked_file_frees_it() {
+        let mut table = OpenFileTable::new(Arc::new(SpinLocks));
+        let first = table.open(slot(2, 3), &metadata(slot(2, 3), 10));
+        let second = table.open(slot(2, 3), &metadata(slot(2, 3), 10));
+        assert!(table.unlink(slot(2, 3), Some(WipePattern::Zeros)));
+        assert!(!table.unlink(slot(2, 4), None));
+
+        // The slot can be reused by another file right away.
+        let other = table.open(slot(2, 3), &metadata(slot(2, 3), 20));
+        assert!(!Arc::ptr_eq(&first, &other));
+
+        assert_eq!(table.close(&first), None);
+        drop(first);
+        assert_eq!(
+            table.close(&second),
+            Some((ClusterId::new(10), Some(WipePattern::Zeros)))
+        );
+        assert!(table.get(slot(2, 3)).is_some());
+    }
+
+    #[test]
+    fn closing_both_handles_before_releasing_them_frees_once() {
+        let mut table = OpenFileTable::new(Arc::new(SpinLocks));
+        let first = table.open(slot(2, 3), &metadata(slot(2, 3), 10));
+        let second = table.open(slot(2, 3), &metadata(slot(2, 3), 10));
+        assert!(table.unlink(slot(2, 3), None));
+
+        // Handles are dropped after being closed: two threads closing the
+        // last two handles may both still hold their `Arc`.
+        assert_eq!(table.close(&first), None);
+        assert_eq!(table.close(&second), Some((ClusterId::new(10), None)));
+    }
+
+    #[test]
+    fn renames_and_directory_moves_rekey_entries() {
+        let mut table = OpenFileTable::new(Arc::new(SpinLocks));
+        let file = table.open(slot(2, 3), &metadata(slot(2, 3), 10));
+        table.rename(
+            slot(2, 3),
+            slot(5, 7),
+            "moved.txt".into(),
+            PathBuf::from("/dir/"),
+        );
+        assert!(table.get(slot(2, 3)).is_none());
+        assert!(table.get(slot(5, 7)).is_some());
+        assert_eq!(
+            file.lock().metadata.full_path(),
+            &PathBuf::from("/dir/moved.txt")
+        );
+
+        table.directory_moved(ClusterId::new(5), ClusterId::new(9));
+        assert!(table.get(slot(9, 7)).is_some());
+        assert_eq!(file.lock().metadata.slot, Some(slot(9, 7)));
+    }
+
+    #[test]
+    fn outside_updates_bump_the_generation_when_the_chain_moves() {
+        let mut table = OpenFileTable::new(Arc::new(SpinLocks));
+        let file = table.open(slot(2, 3), &metadata(slot(2, 3), 10));
+        table.entry_updated(slot(2, 3), &metadata(slot(2, 3), 10));
+        assert_eq!(file.lock().generation, 0);
+        table.entry_updated(slot(2, 3), &metadata(slot(2, 3), 40));
+        assert_eq!(file.lock().generation, 1);
+        assert_eq!(file.lock().metadata.cluster, ClusterId::new(40));
+    }
+}
@@ -0,0 +1,220 @@
+//! Mount-time options.
+
+use alloc::sync::Arc;
+
+use crate::locks;
+use crate::traits::LockProvider;
+use crate::{Metadata, VfatTimestamp};
+
+/// Content written over data before it is released, see
+/// [`MountOptions::secure_delete`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WipePattern {
+    /// Overwrite with zeros.
+    Zeros,
+    /// Overwrite every byte with the given value.
+    Byte(u8),
+}
+
+impl WipePattern {
+    pub(crate) fn byte(self) -> u8 {
+        match self {
+            WipePattern::Zeros => 0,
+            WipePattern::Byte(byte) => byte,
+        }
+    }
+}
+
+/// When reading a file updates its last access date, see
+/// [`MountOptions::access_time`]. The date is written at most once a day
+/// per file, as FAT doesn't keep the time of day.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccessTimePolicy {
+    /// Never, like the `noatime` mount option: reading never writes to the
+    /// volume.
+    #[default]
+    Never,
+    /// Like `relatime`: only if the access date is before the modification
+    /// date, or more than a day old.
+    Relative,
+    /// Like `strictatime`: whenever the access date isn't today.
+    Strict,
+}
+
+impl AccessTimePolicy {
+    /// Whether reading the entry with `metadata` on `today`, the start of
+    /// the local day in `utc_offset`, updates its access date.
+    pub(crate) fn should_update(
+        self,
+        metadata: &Metadata,
+        today: VfatTimestamp,
+        utc_offset: UtcOffset,
+    ) -> bool {
+        const SECONDS_IN_DAY: u64 = 24 * 60 * 60;
+        let accessed = metadata.accessed().to_unix_timestamp();
+        match self {
+            AccessTimePolicy::Never => false,
+            AccessTimePolicy::Relative => {
+                let modified = utc_offset.local_date(metadata.modified());
+                accessed < modified.to_unix_timestamp()
+                    || accessed + SECONDS_IN_DAY < today.to_unix_timestamp()
+            }
+            AccessTimePolicy::Strict => accessed != today.to_unix_timestamp(),
+        }
+    }
+}
+
+/// The time zone of the timestamps on the volume, see
+/// [`MountOptions::utc_offset`]. FAT stores local times, without saying
+/// which zone they are in: the offset only comes from the mount options,
+/// and nothing about it is read from or written to the volume.
+#[derive(Debug, Clone, Copy)]
+pub enum UtcOffset {
+    /// A fixed offset in minutes east of UTC, e.g. 60 for CET and -300 for
+    /// EST. `Fixed(0)`, the default, stores UTC.
+    Fixed(i16),
+    /// The offset in minutes east of UTC in effect at the given Unix time,
+    /// for zones with daylight saving time.
+    Dynamic(fn(u64) -> i16),
+}
+
+impl Default for UtcOffset {
+    fn default() -> Self {
+        UtcOffset::Fixed(0)
+    }
+}
+
+impl UtcOffset {
+    /// The time zone of this machine, as the kernel's vfat driver uses.
+    #[cfg(feature = "std")]
+    pub const LOCAL: UtcOffset = UtcOffset::Dynamic(local_offset_minutes);
+
+    /// The offset in minutes east of UTC at `unix` seconds since the epoch.
+    pub fn minutes_at(self, unix: u64) -> i16 {
+        match self {
+            UtcOffset::Fixed(minutes) => minutes,
+            UtcOffset::Dynamic(offset) => offset(unix),
+        }
+    }
+
+    /// Decode an exFAT-style offset byte, as exFAT keeps next to each
+    /// timestamp and some FAT tools reuse: the top bit tells the offset is
+    /// valid, the other 7 are a signed count of 15 minute steps. Returns
+    /// `None` if the valid bit is clear.
+    ///
+    /// FAT32 entries have no room for the byte, so it is never read from the
+    /// volume: this is for mounting with an offset a tool recorded elsewhere.
+    pub fn from_exfat(offset: u8) -> Option<UtcOffset> {
+        if offset & 0x80 == 0 {
+            return None;
+        }
+        // Sign-extend the 7 bit value.
+        let steps = ((offset << 1) as i8) >> 1;
+        Some(UtcOffset::Fixed(steps as i16 * 15))
+    }
+
+    /// Encode the offset at `unix` as an exFAT-style offset byte, see
+    /// [`UtcOffset::from_exfat`], for tools that record the offset of the
+    /// volume next to it. Offsets are rounded towards zero to 15 minutes,
+    /// and clamped to the -16:00 to +15:45 the byte can hold.
+    pub fn to_exfat(self, unix: u64) -> u8 {
+        let steps = (self.minutes_at(unix) / 15).clamp(-64, 63) as i8;
+        0x80 | (steps as u8 & 0x7F)
+    }
+
+    /// `utc` in the local time it is stored in on the volume.
+    pub(crate) fn to_local(self, utc: VfatTimestamp) -> VfatTimestamp {
+        let unix = utc.to_unix_timestamp();
+        utc.shifted(self.minutes_at(unix) as i64 * 60)
+    }
+
+    /// The UTC time of `local`, as stored on the volume. Around a daylight
+    /// saving change the offset is the one in effect an offset earlier.
+    pub(crate) fn to_utc(self, local: VfatTimestamp) -> VfatTimestamp {
+        let unix = local.to_unix_timestamp();
+        let guess = unix.saturating_add_signed(-(self.minutes_at(unix) as i64 * 60));
+        local.shifted(-(self.minutes_at(guess) as i64 * 60))
+    }
+
+    /// The start of the local day `utc` falls on, in UTC: the precision of
+    /// the last access date.
+    pub(crate) fn local_date(self, utc: VfatTimestamp) -> VfatTimestamp {
+        self.to_utc(self.to_local(utc).date())
+    }
+}
+
+#[cfg(feature = "std")]
+fn local_offset_minutes(unix: u64) -> i16 {
+    use chrono::{Local, Offset, TimeZone};
+    Local
+        .timestamp_opt(unix as i64, 0)
+        .single()
+        .map_or(0, |time| {
+            (time.offset().fix().local_minus_utc() / 60) as i16
+        })
+}
+
+/// Default for [`MountOptions::dentry_cache_capacity`].
+pub const DEFAULT_DENTRY_CACHE_CAPACITY: usize = 256;
+
+/// Options for [`VfatFS::new_with_options`](crate::VfatFS::new_with_options).
+#[derive(Debug, Clone)]
+pub struct MountOptions {
+    /// Maximum number of sectors to cache in memory. 0 disables caching.
+    pub cache_capacity: usize,
+    /// Maximum number of directory lookups (hits and misses) to cache. 0
+    /// disables the cache. Defaults to [`DEFAULT_DENTRY_CACHE_CAPACITY`].
+    ///
+    /// The cache assumes this `VfatFS` is the only writer of the volume.
+    pub dentry_cache_capacity: usize,
+    /// Securely delete on this volume: clusters released by a delete or a
+    /// truncate are overwritten with this pattern before being freed, and
+    /// the directory slots of removed entries are scrubbed. `None` (the
+    /// default) only updates the FAT and marks the slots as deleted.
+    pub secure_delete: Option<WipePattern>,
+    /// Discard clusters on the device as soon as they are freed, like the
+    /// `discard` mount option of other filesystems. See
+    /// [`BlockDevice::discard`](crate::BlockDevice::discard) and
+    /// [`VfatFS::fstrim`](crate::VfatFS::fstrim) for the batch alternative.
+    pub discard: bool,
+    /// Match names exactly. By default lookups and collision checks ignore
+    /// case like Windows does, while names are stored as given: `README.TXT`
+    /// opens `readme.txt`, and `foo` can't be created next to `Foo`. With
+    /// strict matching both can coexist, which other FAT implementations
+    /// will see as duplicates.
+    pub case_sensitive: bool,
+    /// Force changes to read-only entries. By default writing to, deleting
+    /// or renaming an entry with the read-only attribute fails with
+    /// [`VfatRsError::AccessDenied`](crate::VfatRsError::AccessDenied), and
+    /// only its attributes can be changed.
+    pub ignore_read_only: bool,
+    /// When reads update the last access date of files. Defaults to
+    /// [`AccessTimePolicy::Never`].
+    pub access_time: AccessTimePolicy,
+    /// The time zone timestamps are stored in. Timestamps in the API are in
+    /// UTC and converted when entries are read and written. Defaults to
+    /// UTC; Windows and most other systems store local time, see
+    /// `UtcOffset::LOCAL` with the `std` feature.
+    pub utc_offset: UtcOffset,
+    /// Where the locks of the volume come from. Defaults to
+    /// [`StdLocks`](crate::StdLocks) with the `std` feature, and to
+    /// [`SpinLocks`](crate::SpinLocks) otherwise. The provider lives as
+    /// long as the volume or any of its handles.
+    pub locks: Arc<dyn LockProvider>,
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        Self {
+            cache_capacity: 0,
+            dentry_cache_capacity: DEFAULT_DENTRY_CACHE_CAPACITY,
+            secure_delete: None,
+            discard: false,
+            case_sensitive: false,
+            ignore_read_only: false,
+            access_time: AccessTimePolicy::default(),
+            utc_offset: UtcOffset::default(),
+            locks: locks::default_provider(),
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! Offline resizing of a FAT32 volume.
+//!
+//! Growing only has to extend the cluster count, unless the FAT becomes too
+//! small to address the new clusters: then every FAT copy grows as well and the
+//! whole data region is moved toward the end of the device to make room.
+//! Shrinking first moves every cluster past the new end into free space below
+//! it, fixing up the FAT links, the directory entries (including the `.` and
+//! `..` pseudo entries) and the root cluster; the FAT keeps its size.
+//!
+//! Either way the boot sector, its backup and the FSInfo sector are rewritten
+//! last. A resize is not crash safe: an interruption can leave the volume
+//! inconsistent, so callers should keep a backup of anything valuable.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use binrw::BinReaderExt;
+use binrw::io::Cursor;
+use log::info;
+use snafu::ensure;
+
+use crate::api::Directory;
+use crate::fat_table::{FAT_ENTRY_SIZE, FatEntry};
+use crate::formats::extended_bios_parameter_block::FullExtendedBIOSParameterBlock;
+use crate::time::TimeManagerNoop;
+use crate::{
+    BlockDevice, ClusterId, Result, SECTOR_SIZE, SectorId, VfatFS, VfatMetadataTrait, error,
+    fat_table,
+};
+
+const ENTRIES_PER_SECTOR: u32 = (SECTOR_SIZE / FAT_ENTRY_SIZE) as u32;
+/// Volumes with fewer clusters are FAT12/16 by definition, whatever the BPB says.
+pub(crate) const MIN_FAT32_CLUSTERS: u32 = 65_525;
+/// Highest cluster count a FAT32 volume can have (ids `2..0x0FFF_FFF7`).
+const MAX_FAT32_CLUSTERS: u32 = 0x0FFF_FFF5;
+
+// Byte offsets of the fields rewritten in the boot sector and FSInfo sector.
+const BPB_TOTAL_SECTORS_16: usize = 0x13;
+const BPB_TOTAL_SECTORS_32: usize = 0x20;
+const BPB_SECTORS_PER_FAT_32: usize = 0x24;
+const BPB_ROOT_CLUSTER: usize = 0x2C;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+
+/// Layout of the volume, in sectors relative to the partition start.
+struct Geometry {
+    start: u32,
+    reserved: u32,
+    fat_amount: u32,
+    sectors_per_fat: u32,
+    sectors_per_cluster: u32,
+    fsinfo_sector: Option<u32>,
+    backup_boot_sector: Option<u32>,
+}
+
+impl Geometry {
+    fn new(start: u32, ebpb: &FullExtendedBIOSParameterBlock) -> Self {
+        let optional_sector =
+            |sector: u16| (sector != 0 && sector != 0xFFFF).then_some(sector as u32);
+        Geometry {
+            start,
+            reserved: ebpb.bpb.reserved_sectors as u32,
+            fat_amount: ebpb.bpb.fat_amount as u32,
+            sectors_per_fat: ebpb.extended.sectors_per_fat,
+            sectors_per_cluster: ebpb.bpb.sectors_per_cluster as u32,
+            fsinfo_sector: optional_sector(ebpb.extended.fsinfo_sector),
+            backup_boot_sector: optional_sector(ebpb.extended.backup_boot_sector),
+        }
+    }
+
+    fn fat_start(&self) -> u32 {
+        self.start + self.reserved
+    }
+
+    fn data_start(&self, sectors_per_fat: u32) -> u32 {
+        self.fat_start() + self.fat_amount * sectors_per_fat
+    }
+
+    fn clusters(&self, total_sectors: u32, sectors_per_fat: u32) -> u32 {
+        let data_sectors =
+            total_sectors.saturating_sub(self.reserved + self.fat_amount * sectors_per_fat);
+        data_sectors / self.sectors_per_cluster
+    }
+
+    /// Smallest FAT size, never below the current one, able to address every
+    /// cluster of a volume of `total_sectors`.
+    fn sectors_per_fat_for(&self, total_sectors: u32) -> u32 {
+        let mut sectors_per_fat = self.sectors_per_fat;
+        loop {
+            let entries = self.clusters(total_sectors, sectors_per_fat) + 2;
+            let needed = entries.div_ceil(ENTRIES_PER_SECTOR);
+            if needed <= sectors_per_fat {
+                return sectors_per_fat;
+            }
+            sectors_per_fat = needed;
+        }
+    }
+}
+
+impl VfatFS {
+    /// Grow or shrink the unmounted FAT32 volume starting at
+    /// `partition_start_sector` so that it spans `new_sector_count` sectors.
+    ///
+    /// When growing, the device (or partition) must already be large enough.
+    /// When shrinking, data stored past the new end is moved down first; if the
+    /// clusters in use can't fit, [`VfatRsError::VolumeTooSmall`] is returned
+    /// and the volume is left untouched.
+    ///
+    /// The volume must not be mounted while it is being resized.
+    ///
+    /// [`VfatRsError::VolumeTooSmall`]: crate::VfatRsError::VolumeTooSmall
+    pub fn resize<B: BlockDevice + Send + 'static>(
+        device: B,
+        partition_start_sector: u32,
+        new_sector_count: u32,
+    ) -> Result<()> {
+        let mut fs =
+            Self::new_with_cache(device, partition_start_sector, TimeManagerNoop::new(), 0)?;
+        let mut boot_sector = [0u8; SECTOR_SIZE];
+        fs.device
+            .read_sector(partition_start_sector.into(), &mut boot_sector)?;
+        let ebpb: FullExtendedBIOSParameterBlock = Cursor::new(&boot_sector).read_le()?;
+        ensure!(
+            ebpb.bpb.sectors_per_fat == 0 && ebpb.bpb.bytes_per_sector as usize == SECTOR_SIZE,
+            error::InvalidResizeSnafu {
+                reason: "only FAT32 volumes with 512-byte sectors can be resized"
+            }
+        );
+        let geometry = Geometry::new(partition_start_sector, &ebpb);
+
+        let old_clusters = fs.total_clusters;
+        let sectors_per_fat = geometry.sectors_per_fat_for(new_sector_count);
+        let new_clusters = geometry.clusters(new_sector_count, sectors_per_fat);
+        ensure!(
+            new_clusters >= MIN_FAT32_CLUSTERS,
+            error::InvalidResizeSnafu {
+                reason: "too few clusters for FAT32"
+            }
+        );
+        ensure!(
+            new_clusters <= MAX_FAT32_CLUSTERS,
+            error::InvalidResizeSnafu {
+                reason: "too many clusters for FAT32"
+            }
+        );
+        let used = old_clusters - fs.count_free_clusters()?;
+        ensure!(
+            used <= new_clusters,
+            error::VolumeTooSmallSnafu {
+                used,
+                available: new_clusters
+            }
+        );
+        info!(
+            "Resizing volume: {} -> {} sectors, {} -> {} clusters, FAT: {} -> {} sectors",
+            ebpb.total_logical_sectors(),
+            new_sector_count,
+            old_clusters,
+            new_clusters,
+            geometry.sectors_per_fat,
+            sectors_per_fat
+        );
+
+        if new_clusters < old_clusters {
+            // From now on the allocator only hands out clusters below the new end.
+            fs.total_clusters = new_clusters;
+            *fs.allocator.lock() = 2;
+            fs.move_clusters_below(2 + new_clusters)?;
+            fs.clear_fat_entries(&geometry, sectors_per_fat, new_clusters, old_clusters)?;
+        } else {
+            let mut last_sector = [0u8; SECTOR_SIZE];
+            let read = fs.device.read_sector(
+                SectorId(partition_start_sector + new_sector_count - 1),
+                &mut last_sector,
+            )?;
+            ensure!(
+                read == SECTOR_SIZE,
+                error::InvalidResizeSnafu {
+                    reason: "the device is smaller than the requested size"
+                }
+            );
+            if sectors_per_fat > geometry.sectors_per_fat {
+                fs.grow_fat(&geometry, sectors_per_fat, old_clusters)?;
+            }
+            fs.clear_fat_entries(&geometry, sectors_per_fat, old_clusters, new_clusters)?;
+        }
+        fs.device.flush()?;
+
+        fs.write_resized_boot_sectors(
+            &geometry,
+            new_sector_count,
+            sectors_per_fat,
+            new_clusters - used,
+        )?;
+        fs.device.flush()
+    }
+
+    /// Move every cluster at or past `limit` into free space below it.
+    fn move_clusters_below(&mut self, limit: u32) -> Result<()> {
+        let root = self.move_chain_below(self.root_cluster, limit)?;
+        if root != self.root_cluster {
+            self.open_files
+                .lock()
+                .directory_moved(self.root_cluster, root);
+            self.root_cluster = root;
+        }
+
+        // Each pending directory carries whether it was moved, in which case
+        // the ".." entries of its subdirectories must follow it.
+        let mut pending = vec![(self.get_root_unlocked()?, false)];
+        while let Some((directory, moved)) = pending.pop() {
+            for entry in directory.iter_unlocked() {
+                let entry = entry?;
+                if entry.metadata.attributes.is_volume_id()
+                    || entry.name() == "."
+                    || entry.name() == ".."
+                {
+                    continue;
+                }
+                let is_dir = entry.is_dir();
+                let mut metadata = entry.metadata;
+                let mut entry_moved = false;
+                if !metadata.has_no_cluster_allocated() {
+                    let head = self.move_chain_below(metadata.cluster, limit)?;
+                    if head != metadata.cluster {
+                        metadata.cluster = head;
+                        directory.update_entry(metadata.clone())?;
+                        entry_moved = true;
+                    }
+                }
+                if is_dir {
+                    if entry_moved {
+                        Directory::update_dot_cluster(self, metadata.cluster)?;
+                    }
+                    if moved {
+                        Directory::update_dotdot_cluster(
+                            self,
+                            metadata.cluster,
+                            directory.metadata.cluster,
+                        )?;
+                    }
+                    pending.push((Directory::new(self.clone(), metadata), entry_moved));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the clusters of the chain starting at `head` that lie at or past
+    /// `limit` into free clusters, relink the chain and return its head.
+    fn move_chain_below(&self, head: ClusterId, limit: u32) -> Result<ClusterId> {
+        let chain = fat_table::collect_chain(head, self.device.clone())?;
+        if chain.iter().all(|cluster| u32::from(*cluster) < limit) {
+            return Ok(head);
+        }
+        let mut moved = Vec::with_capacity(chain.len());
+        for &cluster in &chain {
+            if u32::from(cluster) < limit {
+                moved.push(cluster);
+            } else {
+                let target = self.allocate_cluster_new_entry()?;
+                self.copy_clusters(cluster, target, 1)?;
+                moved.push(target);
+            }
+        }
+        for (i, &cluster) in moved.iter().enumerate() {
+            let next = moved.get(i + 1);
+            if cluster == chain[i] && next == chain.get(i + 1) {
+                continue;
+            }
+            let entry = match next {
+                Some(next) => FatEntry::from_chain(*next),
+                None => self.new_last_cluster_fat_entry(),
+            };
+            self.write_entry_in_vfat_table(cluster, entry)?;
+        }
+        Ok(moved[0])
+    }
+
+    /// Grow every FAT copy to `sectors_per_fat` sectors, shifting the used
+    /// clusters of the data region toward the end of the device first.
+    fn grow_fat(&self, geometry: &Geometry, sectors_per_fat: u32, clusters: u32) -> Result<()> {
+        let old_data = geometry.data_start(geometry.sectors_per_fat);
+        let shift = geometry.data_start(sectors_per_fat) - old_data;
+        let fat_start = geometry.fat_start();
+        let mut buf = [0u8; SECTOR_SIZE];
+
+        // Walk the clusters backwards: every destination is either free or a
+        // source that was already copied.
+        let last_fat_sector = (clusters + 1) / ENTRIES_PER_SECTOR;
+        for fat_sector in (0..=last_fat_sector).rev() {
+            let mut entries = [0u8; SECTOR_SIZE];
+            self.device
+                .read_sector(SectorId(fat_start + fat_sector), &mut entries)?;
+            for (index, bytes) in entries.chunks(FAT_ENTRY_SIZE).enumerate().rev() {
+                let cid = fat_sector * ENTRIES_PER_SECTOR + index as u32;
+                if cid < 2 || cid >= 2 + clusters || FatEntry::new_ref(bytes) == FatEntry::Unused {
+                    continue;
+                }
+                let first = old_data + (cid - 2) * geometry.sectors_per_cluster;
+                for sector in (first..first + geometry.sectors_per_cluster).rev() {
+                    self.device.read_sector(SectorId(sector), &mut buf)?;
+                    self.device
+                        .clone()
+                        .write_sector_offset(SectorId(sector + shift), 0, &buf)?;
+                }
+            }
+        }
+
+        // The new copies never overlap the first FAT, which is the source.
+        let zeros = [0u8; SECTOR_SIZE];
+        for copy in (1..geometry.fat_amount).rev() {
+            for sector in 0..sectors_per_fat {
+                let data = if sector < geometry.sectors_per_fat {
+                    self.device
+                        .read_sector(SectorId(fat_start + sector), &mut buf)?;
+                    &buf
+                } else {
+                    &zeros
+                };
+                let target = SectorId(fat_start + copy * sectors_per_fat + sector);
+                self.device.clone().write_sector_offset(target, 0, data)?;
+            }
+        }
+        for sector in geometry.sectors_per_fat..sectors_per_fat {
+            self.device
+                .clone()
+                .write_sector_offset(SectorId(fat_start + sector), 0, &zeros)?;
+        }
+        Ok(())
+    }
+
+    /// Mark the entries of clusters `2 + from..2 + to` as unused in every FAT
+    /// copy laid out with `sectors_per_fat` sectors.
+    fn clear_fat_entries(
+        &self,
+        geometry: &Geometry,
+        sectors_per_fat: u32,
+        from: u32,
+        to: u32,
+    ) -> Result<()> {
+        let (first, end) = (2 + from, 2 + to);
+        if first >= end {
+            return Ok(());
+        }
+        let mut buf = [0u8; SECTOR_SIZE];
+        for copy in 0..geometry.fat_amount {
+            let fat_start = geometry.fat_start() + copy * sectors_per_fat;
+            for fat_sector in first / ENTRIES_PER_SECTOR..=(end - 1) / ENTRIES_PER_SECTOR {
+                let sector = SectorId(fat_start + fat_sector);
+                self.device.read_sector(sector, &mut buf)?;
+                for (index, bytes) in buf.chunks_mut(FAT_ENTRY_SIZE).enumerate() {
+                    let cid = fat_sector * ENTRIES_PER_SECTOR + index as u32;
+                    if (first..end).contains(&cid) {
+                        bytes.fill(0);
+                    }
+                }
+                self.device.clone().write_sector_offset(sector, 0, &buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the new geometry in the FSInfo sector and in the boot sector,
+    /// backups first.
+    fn write_resized_boot_sectors(
+        &self,
+        geometry: &Geometry,
+        total_sectors: u32,
+        sectors_per_fat: u32,
+        free_clusters: u32,
+    ) -> Result<()> {
+        let backup = geometry.backup_boot_sector;
+        let fsinfo_sectors = geometry
+            .fsinfo_sector
+            .into_iter()
+            .flat_map(|fsinfo| [backup.map(|backup| backup + fsinfo), Some(fsinfo)])
+            .flatten();
+        for fsinfo in fsinfo_sectors {
+            let sector = SectorId(geometry.start + fsinfo);
+            self.device.clone().write_sector_offset(
+                sector,
+                FSINFO_FREE_COUNT,
+                &free_clusters.to_le_bytes(),
+            )?;
+            self.device.clone().write_sector_offset(
+                sector,
+                FSINFO_NEXT_FREE,
+                &2u32.to_le_bytes(),
+            )?;
+        }
+
+        let mut buf = [0u8; SECTOR_SIZE];
+        for boot in backup.into_iter().chain([0]) {
+            let sector = SectorId(geometry.start + boot);
+            self.device.read_sector(sector, &mut buf)?;
+            buf[BPB_TOTAL_SECTORS_16..BPB_TOTAL_SECTORS_16 + 2].fill(0);
+            buf[BPB_TOTAL_SECTORS_32..BPB_TOTAL_SECTORS_32 + 4]
+                .copy_from_slice(&total_sectors.to_le_bytes());
+            buf[BPB_SECTORS_PER_FAT_32..BPB_SECTORS_PER_FAT_32 + 4]
+                .copy_from_slice(&sectors_per_fat.to_le_bytes());
+            buf[BPB_ROOT_CLUSTER..BPB_ROOT_CLUSTER + 4]
+                .copy_from_slice(&u32::from(self.root_cluster).to_le_bytes());
+            self.device.clone().write_sector_offset(sector, 0, &buf)?;
+        }
+        Ok(())
+    }
+}
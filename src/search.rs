@@ -0,0 +1,431 @@
+//! Searching a tree by name pattern or by metadata.
+//!
+//! Both [`VfatFS::glob`] and [`VfatFS::find`] run on top of a [`Walk`], so
+//! only the directory being listed is held in memory, and a glob doesn't even
+//! list the directories its pattern can't match below.
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Bound, RangeBounds};
+
+use crate::api::case_folding::fold;
+use crate::{PathBuf, Result, VfatFS, VfatRsError, VfatTimestamp, Walk, WalkEntry, normalize_path};
+
+/// Returns `true` if `name` matches the single-component `pattern`.
+///
+/// `*` matches any run of characters, `?` any one character, and `[...]` one
+/// character of a set like `[abc]` or `[a-z]`, negated by a leading `!`.
+fn matches_name(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Where to resume after the last `*`: pattern index, name index.
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, len)) = match_class(&pattern[p..], name[n]) {
+                    if matched {
+                        p += len;
+                        n += 1;
+                        continue;
+                    }
+                } else if name[n] == '[' {
+                    // An unclosed `[` is a literal.
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+            }
+            Some(&ch) if ch == name[n] => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+        // Mismatch: let the last `*` swallow one more character.
+        match backtrack {
+            Some((star, start)) => {
+                backtrack = Some((star, start + 1));
+                p = star + 1;
+                n = start + 1;
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&ch| ch == '*')
+}
+
+/// Match `ch` against the class at the start of `pattern`. Returns whether
+/// it matched and the length of the class, or `None` if it isn't closed.
+fn match_class(pattern: &[char], ch: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(i)?;
+        if start == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            matched |= (start..=pattern[i + 2]).contains(&ch);
+            i += 3;
+        } else {
+            matched |= start == ch;
+            i += 1;
+        }
+    }
+}
+
+fn has_wildcards(component: &str) -> bool {
+    component.contains(['*', '?', '['])
+}
+
+/// A path pattern split into components, matched one path component at a
+/// time. `**` matches any number of components, including none.
+#[derive(Clone)]
+struct PathPattern {
+    components: Vec<String>,
+}
+
+impl PathPattern {
+    /// The pattern positions reachable after matching `names`, skipping over
+    /// `**`s that match no component.
+    fn states<'a>(&self, names: impl Iterator<Item = &'a str>) -> Vec<bool> {
+        let mut states = vec![false; self.components.len() + 1];
+        states[0] = true;
+        self.close(&mut states);
+        for name in names {
+            let mut next = vec![false; states.len()];
+            for (i, component) in self.components.iter().enumerate() {
+                if !states[i] {
+                    continue;
+                }
+                if component == "**" {
+                    next[i] = true;
+                } else if matches_name(component, name) {
+                    next[i + 1] = true;
+                }
+            }
+            self.close(&mut next);
+            states = next;
+        }
+        states
+    }
+
+    fn close(&self, states: &mut [bool]) {
+        for (i, component) in self.components.iter().enumerate() {
+            if states[i] && component == "**" {
+                states[i + 1] = true;
+            }
+        }
+    }
+}
+
+/// Conditions on the entries returned by [`VfatFS::find`]. Every condition
+/// that is set must hold; a new `FindCriteria` matches everything. For
+/// instance the files not modified since `cutoff` are found with
+/// `fs.find(root, move |entry| criteria.matches(entry))`, where `criteria` is
+/// `FindCriteria::new().files().modified_before(cutoff)`.
+#[derive(Debug, Clone)]
+pub struct FindCriteria {
+    name: Option<String>,
+    kind: Option<bool>,
+    min_size: Bound<u64>,
+    max_size: Bound<u64>,
+    modified_after: Option<u64>,
+    modified_before: Option<u64>,
+    created_after: Option<u64>,
+    created_before: Option<u64>,
+    hidden: Option<bool>,
+    system: Option<bool>,
+    read_only: Option<bool>,
+    archive: Option<bool>,
+}
+
+impl Default for FindCriteria {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FindCriteria {
+    /// Criteria matching every entry.
+    pub fn new() -> Self {
+        Self {
+            name: None,
+            kind: None,
+            min_size: Bound::Unbounded,
+            max_size: Bound::Unbounded,
+            modified_after: None,
+            modified_before: None,
+            created_after: None,
+            created_before: None,
+            hidden: None,
+            system: None,
+            read_only: None,
+            archive: None,
+        }
+    }
+
+    /// The name must match `pattern`, ignoring case. `*`, `?` and `[...]`
+    /// work like in [`VfatFS::glob`].
+    pub fn name(mut self, pattern: &str) -> Self {
+        self.name = Some(fold(pattern));
+        self
+    }
+
+    /// Only match files.
+    pub fn files(mut self) -> Self {
+        self.kind = Some(false);
+        self
+    }
+
+    /// Only match directories.
+    pub fn directories(mut self) -> Self {
+        self.kind = Some(true);
+        self
+    }
+
+    /// The size in bytes must be within `range`, e.g. `1024..` or `..=4096`.
+    pub fn size<R: RangeBounds<u64>>(mut self, range: R) -> Self {
+        self.min_size = range.start_bound().cloned();
+        self.max_size = range.end_bound().cloned();
+        self
+    }
+
+    /// The last modification must be strictly later than `timestamp`.
+    pub fn modified_after(mut self, timestamp: VfatTimestamp) -> Self {
+        self.modified_after = Some(timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// The last modification must be strictly earlier than `timestamp`.
+    pub fn modified_before(mut self, timestamp: VfatTimestamp) -> Self {
+        self.modified_before = Some(timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// The creation must be strictly later than `timestamp`.
+    pub fn created_after(mut self, timestamp: VfatTimestamp) -> Self {
+        self.created_after = Some(timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// The creation must be strictly earlier than `timestamp`.
+    pub fn created_before(mut self, timestamp: VfatTimestamp) -> Self {
+        self.created_before = Some(timestamp.to_unix_timestamp());
+        self
+    }
+
+    /// The hidden attribute must be `set`, or clear.
+    pub fn hidden(mut self, set: bool) -> Self {
+        self.hidden = Some(set);
+        self
+    }
+
+    /// The system attribute must be `set`, or clear.
+    pub fn system(mut self, set: bool) -> Self {
+        self.system = Some(set);
+        self
+    }
+
+    /// The read-only attribute must be `set`, or clear.
+    pub fn read_only(mut self, set: bool) -> Self {
+        self.read_only = Some(set);
+        self
+    }
+
+    /// The archive attribute must be `set`, or clear.
+    pub fn archive(mut self, set: bool) -> Self {
+        self.archive = Some(set);
+        self
+    }
+
+    /// Returns `true` if `entry` meets every condition.
+    pub fn matches(&self, entry: &WalkEntry) -> bool {
+        let metadata = &entry.entry.metadata;
+        let attributes = metadata.attributes;
+        let size = metadata.size as u64;
+        let modified = metadata.modified().to_unix_timestamp();
+        let created = metadata.created().to_unix_timestamp();
+        let flag = |wanted: Option<bool>, actual: bool| wanted.is_none_or(|set| set == actual);
+        self.name
+            .as_ref()
+            .is_none_or(|pattern| matches_name(pattern, &fold(metadata.name())))
+            && self.kind.is_none_or(|is_dir| is_dir == entry.is_dir())
+            && (self.min_size, self.max_size).contains(&size)
+            && self.modified_after.is_none_or(|after| modified > after)
+            && self.modified_before.is_none_or(|before| modified < before)
+            && self.created_after.is_none_or(|after| created > after)
+            && self.created_before.is_none_or(|before| created < before)
+            && flag(self.hidden, attributes.is_hidden())
+            && flag(self.system, attributes.is_system())
+            && flag(self.read_only, attributes.is_read_only())
+            && flag(self.archive, attributes.is_archive())
+    }
+}
+
+impl VfatFS {
+    /// Returns the entries whose absolute path matches `pattern`, e.g.
+    /// `/logs/**/*.csv`.
+    ///
+    /// Each component of the pattern may use `*`, `?` and `[...]` (see
+    /// [`FindCriteria::name`]), and a `**` component matches any number of
+    /// directories, including none. Names are compared ignoring case unless
+    /// the volume is mounted with
+    /// [`MountOptions::case_sensitive`](crate::MountOptions::case_sensitive).
+    /// The search starts from the longest prefix of the pattern without
+    /// wildcards, and doesn't descend into directories the pattern can't
+    /// match below. A missing prefix matches nothing.
+    ///
+    /// Fails with [`VfatRsError::PathNotAbsolute`] if `pattern` isn't
+    /// absolute.
+    pub fn glob(
+        &mut self,
+        pattern: &str,
+    ) -> Result<impl Iterator<Item = Result<WalkEntry>> + use<>> {
+        if !pattern.starts_with('/') {
+            return Err(VfatRsError::PathNotAbsolute {
+                target: pattern.to_string(),
+            });
+        }
+        let pattern = normalize_path(pattern).display().to_string();
+        let case_sensitive = self.options.case_sensitive;
+        let normalize = move |name: &str| {
+            if case_sensitive {
+                name.to_string()
+            } else {
+                fold(name)
+            }
+        };
+        let mut root = PathBuf::from("/");
+        let mut components = Vec::new();
+        for component in pattern.split('/').filter(|c| !c.is_empty()) {
+            if components.is_empty() && !has_wildcards(component) {
+                root.push(component);
+            } else {
+                components.push(normalize(component));
+            }
+        }
+        let walk = self.exists(root.clone())?.then(|| self.walk(root.clone()));
+        let pattern = PathPattern { components };
+        let prefix_len = root.components().count();
+        let states = move |entry: &WalkEntry| {
+            let names = entry.path().components().skip(prefix_len);
+            #[cfg(feature = "std")]
+            let names: Vec<String> = names
+                .map(|name| normalize(name.as_os_str().to_str().unwrap_or_default()))
+                .collect();
+            #[cfg(not(feature = "std"))]
+            let names: Vec<String> = names.map(|name| normalize(name.as_str())).collect();
+            pattern.states(names.iter().map(String::as_str))
+        };
+        Ok(glob_walk(walk, states))
+    }
+
+    /// Returns the entries below `root`, `root` included, for which
+    /// `predicate` returns `true`, in the order of [`VfatFS::walk`].
+    /// [`FindCriteria`] covers the usual conditions.
+    pub fn find<P, F>(
+        &self,
+        root: P,
+        mut predicate: F,
+    ) -> impl Iterator<Item = Result<WalkEntry>> + use<P, F>
+    where
+        P: Into<PathBuf>,
+        F: FnMut(&WalkEntry) -> bool,
+    {
+        self.walk(root).filter(move |entry| match entry {
+            Ok(entry) => predicate(entry),
+            Err(_) => true,
+        })
+    }
+}
+
+/// Walk the entries that may lead to a match, returning those that match.
+fn glob_walk<S>(walk: Option<Walk>, states: S) -> impl Iterator<Item = Result<WalkEntry>>
+where
+    S: Fn(&WalkEntry) -> Vec<bool> + Clone + 'static,
+{
+    let descend = states.clone();
+    walk.map(|walk| walk.filter_entry(move |entry| descend(entry).contains(&true)))
+        .into_iter()
+        .flatten()
+        .filter(move |entry| match entry {
+            Ok(entry) => *states(entry).last().expect("at least one state"),
+            Err(_) => true,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_wildcards() {
+        assert!(matches_name("*.csv", "data.csv"));
+        assert!(matches_name("*.csv", ".csv"));
+        assert!(!matches_name("*.csv", "data.csv.bak"));
+        assert!(matches_name("d?ta*", "data.csv"));
+        assert!(matches_name("*a*b*", "xxaxxbxx"));
+        assert!(!matches_name("*a*b", "xxaxxbxx"));
+        assert!(matches_name("**", ""));
+        assert!(!matches_name("?", ""));
+        assert!(matches_name("exact", "exact"));
+        assert!(!matches_name("exact", "exactly"));
+    }
+
+    #[test]
+    fn test_name_classes() {
+        assert!(matches_name("log[0-9].txt", "log7.txt"));
+        assert!(!matches_name("log[0-9].txt", "logx.txt"));
+        assert!(matches_name("log[!0-9].txt", "logx.txt"));
+        assert!(matches_name("[]]", "]"));
+        assert!(matches_name("[a-]", "-"));
+        assert!(matches_name("a[b", "a[b"));
+    }
+
+    fn pattern(components: &[&str]) -> PathPattern {
+        PathPattern {
+            components: components.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_double_star_matches_any_depth() {
+        let pattern = pattern(&["**", "*.csv"]);
+        let states = |path: &[&str]| pattern.states(path.iter().copied());
+        assert!(*states(&["a.csv"]).last().unwrap());
+        assert!(*states(&["x", "y", "a.csv"]).last().unwrap());
+        assert!(!*states(&["x", "y"]).last().unwrap());
+        // Any directory may still lead to a match.
+        assert!(states(&["x", "y"]).contains(&true));
+    }
+
+    #[test]
+    fn test_prefix_states_prune_directories() {
+        let pattern = pattern(&["2024*", "*.csv"]);
+        let states = |path: &[&str]| pattern.states(path.iter().copied());
+        assert!(states(&["2024-01"]).contains(&true));
+        assert!(!states(&["2023-12"]).contains(&true));
+        assert!(*states(&["2024-01", "a.csv"]).last().unwrap());
+        assert!(!states(&["2024-01", "a.csv", "deeper"]).contains(&true));
+    }
+}
@@ -3,11 +3,14 @@
 //! The OS should provide:
 //! * An implementation for the `TimeManagerTrait`. This is used for timestamping file creation and update.
 //! * An implementation for the `BlockDevice` trait. This is used to interact with the disk.
+//! * Optionally, a [`LockProvider`] for the locks of the filesystem: spinning locks are used by default.
 //! * Alloc support. It is mostly used to allocate some vec and strings, it might be put behind a feature flag later on.
 //!
+use alloc::boxed::Box;
+use core::fmt::Debug;
+
 use crate::api::timestamp::VfatTimestamp;
 use crate::{SectorId, error};
-use core::fmt::Debug;
 
 /// An interface to the OS-owned timer. Needed for timestamping file creations and update.
 pub trait TimeManagerTrait: Debug + Send + Sync {
@@ -61,6 +64,16 @@ pub trait BlockDevice {
         buf: &[u8],
     ) -> error::Result<usize>;
 
+    /// Tell the device that `count` sectors starting at `start` no longer hold
+    /// useful data (TRIM/discard), so flash storage can reclaim them and image
+    /// files can release the space. Their content is undefined afterwards.
+    ///
+    /// This is advisory: the default implementation does nothing.
+    fn discard(&mut self, start: SectorId, count: u32) -> error::Result<()> {
+        let _ = (start, count);
+        Ok(())
+    }
+
     /// A human readable name for this device
     fn get_canonical_name() -> &'static str
     where
@@ -69,3 +82,38 @@ pub trait BlockDevice {
         "Block Device"
     }
 }
+
+/// A reader-writer lock guarding no data, used by the filesystem for all its
+/// locks (see [`LockProvider`]). Mutual exclusion only takes it exclusively.
+///
+/// Locks are never taken recursively: a thread holding a lock, shared or
+/// exclusive, doesn't take it again before releasing it.
+pub trait RawLock: Send + Sync {
+    /// Take the lock shared, waiting until there is no exclusive holder.
+    fn lock_shared(&self);
+    /// Take the lock shared if it has no exclusive holder right now.
+    fn try_lock_shared(&self) -> bool;
+    /// Release a shared hold.
+    ///
+    /// # Safety
+    /// The calling thread must hold the lock shared.
+    unsafe fn unlock_shared(&self);
+    /// Take the lock exclusively, waiting until it has no other holder.
+    fn lock_exclusive(&self);
+    /// Release the exclusive hold.
+    ///
+    /// # Safety
+    /// The calling thread must hold the lock exclusively.
+    unsafe fn unlock_exclusive(&self);
+}
+
+/// Creates the locks of a volume. Spinning locks by default, which work
+/// anywhere, or sleeping ones with the `std` feature.
+///
+/// A kernel can provide locks disabling interrupts, or sleeping ones
+/// integrated with its scheduler, through
+/// [`MountOptions::locks`](crate::MountOptions::locks).
+pub trait LockProvider: Debug + Send + Sync {
+    /// A new, unlocked lock.
+    fn new_lock(&self) -> Box<dyn RawLock>;
+}
@@ -0,0 +1,290 @@
+//! Recursive operations on directory trees.
+//!
+//! Trees are traversed with an explicit work list on the heap rather than by
+//! recursion, so the stack use stays the same however deep the tree is.
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::analysis::is_real_entry;
+use crate::api::{Directory, DirectoryEntry, EntryType, Metadata};
+use crate::fs::split_parent;
+use crate::{PathBuf, Result, VfatFS, VfatRsError};
+
+/// Order in which a [`Walk`] visits the entries of a tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// Every directory is followed by its whole subtree, before its next
+    /// sibling.
+    #[default]
+    DepthFirst,
+    /// Level by level: all the entries at depth `n` come before the entries
+    /// at depth `n + 1`.
+    BreadthFirst,
+}
+
+/// An entry found by a [`Walk`].
+#[derive(Debug)]
+pub struct WalkEntry {
+    /// The file or directory.
+    pub entry: DirectoryEntry,
+    /// Distance from the root of the walk, which has depth 0.
+    pub depth: usize,
+}
+
+impl WalkEntry {
+    /// Returns the absolute path of the entry.
+    pub fn path(&self) -> &PathBuf {
+        self.entry.metadata.full_path()
+    }
+
+    /// Returns `true` if the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.entry.is_dir()
+    }
+}
+
+type EntryFilter = Box<dyn FnMut(&WalkEntry) -> bool>;
+
+/// Iterator over a directory tree, returned by [`VfatFS::walk`].
+///
+/// The root comes first, at depth 0. The `.` and `..` pseudo entries and the
+/// volume label are never returned. Each directory is listed right before
+/// its first child is returned, and the locks are only held while listing
+/// it: like [`Directory::iter`], changes made during the walk may or
+/// may not be seen. A read error is returned as an item, and the walk goes on
+/// with the next pending entry.
+pub struct Walk {
+    vfat_filesystem: VfatFS,
+    /// Path of the root, until the first call to `next`.
+    root: Option<PathBuf>,
+    order: WalkOrder,
+    max_depth: Option<usize>,
+    filters: Vec<EntryFilter>,
+    /// Entries found but not returned yet.
+    pending: VecDeque<WalkEntry>,
+    /// The directory returned last, to be listed on the next call.
+    to_expand: Option<(Directory, usize)>,
+}
+
+impl Walk {
+    /// Visit the tree in `order`. Defaults to [`WalkOrder::DepthFirst`].
+    pub fn order(mut self, order: WalkOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Don't descend below `depth`: with 0 only the root is returned, with 1
+    /// the root and its direct children, and so on.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Only return the entries for which `filter` returns `true`. A rejected
+    /// directory is pruned: nothing below it is visited. When called more
+    /// than once, an entry must pass every filter.
+    pub fn filter_entry<F>(mut self, filter: F) -> Self
+    where
+        F: FnMut(&WalkEntry) -> bool + 'static,
+    {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    fn accept(&mut self, entry: &WalkEntry) -> bool {
+        self.filters.iter_mut().all(|filter| filter(entry))
+    }
+
+    fn open_root(&mut self, root: PathBuf) -> Result<()> {
+        let lock = self.vfat_filesystem.fs_lock.clone();
+        let entry = {
+            let _guard = lock.read();
+            self.vfat_filesystem.get_from_absolute_path_unlocked(root)?
+        };
+        let entry = WalkEntry { entry, depth: 0 };
+        if self.accept(&entry) {
+            self.pending.push_back(entry);
+        }
+        Ok(())
+    }
+
+    fn expand(&mut self, directory: Directory, depth: usize) -> Result<()> {
+        let mut children = Vec::new();
+        for entry in directory.iter() {
+            let child = WalkEntry {
+                entry: entry?,
+                depth: depth + 1,
+            };
+            if is_real_entry(&child.entry) && self.accept(&child) {
+                children.push(child);
+            }
+        }
+        match self.order {
+            WalkOrder::DepthFirst => self.pending.extend(children.into_iter().rev()),
+            WalkOrder::BreadthFirst => self.pending.extend(children),
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take()
+            && let Err(err) = self.open_root(root)
+        {
+            return Some(Err(err));
+        }
+        if let Some((directory, depth)) = self.to_expand.take()
+            && let Err(err) = self.expand(directory, depth)
+        {
+            return Some(Err(err));
+        }
+        let next = match self.order {
+            WalkOrder::DepthFirst => self.pending.pop_back(),
+            WalkOrder::BreadthFirst => self.pending.pop_front(),
+        }?;
+        if next.is_dir() && self.max_depth.is_none_or(|max| next.depth < max) {
+            let directory =
+                Directory::new(self.vfat_filesystem.clone(), next.entry.metadata.clone());
+            self.to_expand = Some((directory, next.depth));
+        }
+        Some(Ok(next))
+    }
+}
+
+impl VfatFS {
+    /// Walk the tree rooted at the absolute path `root`, which may also be a
+    /// file. See [`Walk`] for the order, depth and filter options.
+    pub fn walk<P: Into<PathBuf>>(&self, root: P) -> Walk {
+        Walk {
+            vfat_filesystem: self.clone(),
+            root: Some(root.into()),
+            order: WalkOrder::default(),
+            max_depth: None,
+            filters: Vec::new(),
+            pending: VecDeque::new(),
+            to_expand: None,
+        }
+    }
+
+    /// Copy the directory `from` with everything it contains to `to`, which
+    /// must not exist yet, like `cp -a`. Timestamps and attributes are
+    /// preserved. Returns the number of bytes copied.
+    ///
+    /// Fails with [`VfatRsError::InvalidPath`] if `to` is inside `from`.
+    pub fn copy_dir<P: Into<PathBuf>, Q: Into<PathBuf>>(&mut self, from: P, to: Q) -> Result<u64> {
+        let mut destination = self.clone();
+        self.copy_dir_to(from, &mut destination, to)
+    }
+
+    /// Like [`VfatFS::copy_dir`], with `to` on the `destination` volume.
+    ///
+    /// Both volumes stay locked for the whole copy: the source for reading,
+    /// the destination for writing.
+    pub fn copy_dir_to<P: Into<PathBuf>, Q: Into<PathBuf>>(
+        &mut self,
+        from: P,
+        destination: &mut VfatFS,
+        to: Q,
+    ) -> Result<u64> {
+        let same_volume = Arc::ptr_eq(&self.fs_lock, &destination.fs_lock);
+        let source_lock = self.fs_lock.clone();
+        let destination_lock = destination.fs_lock.clone();
+        // Two volumes are always locked in the same (address) order, so
+        // copies running in opposite directions can't deadlock.
+        let _source_guard;
+        let _destination_guard;
+        if same_volume {
+            _source_guard = None;
+            _destination_guard = destination_lock.write();
+        } else if Arc::as_ptr(&source_lock) < Arc::as_ptr(&destination_lock) {
+            _source_guard = Some(source_lock.read());
+            _destination_guard = destination_lock.write();
+        } else {
+            _destination_guard = destination_lock.write();
+            _source_guard = Some(source_lock.read());
+        }
+        self.copy_dir_unlocked(from.into(), destination, to.into(), same_volume)
+    }
+
+    fn copy_dir_unlocked(
+        &mut self,
+        from: PathBuf,
+        destination: &mut VfatFS,
+        to: PathBuf,
+        same_volume: bool,
+    ) -> Result<u64> {
+        let source = self.directory_at(from)?;
+        let (parent_path, name) = split_parent(to.clone())?;
+        let mut parent = destination.directory_at(parent_path.clone())?;
+        if same_volume {
+            // Going up from the destination's parent must not meet the source.
+            let mut ancestor = Some(parent_path);
+            while let Some(path) = ancestor {
+                let entry = self.get_from_absolute_path_unlocked(path.clone())?;
+                if entry.metadata.cluster == source.metadata.cluster {
+                    return Err(VfatRsError::InvalidPath {
+                        target: to.display().to_string(),
+                        reason: "cannot copy a directory into itself",
+                    });
+                }
+                ancestor = path.parent().map(PathBuf::from);
+            }
+        }
+
+        let mut target = parent
+            .create(name, EntryType::Directory)?
+            .into_directory_unchecked();
+        Self::preserve_metadata(&mut parent, &mut target.metadata, &source.metadata)?;
+        let mut pending = vec![(source, target)];
+        let mut buf = vec![0; self.bytes_per_cluster() as usize];
+        let mut copied = 0;
+        while let Some((source, mut target)) = pending.pop() {
+            for entry in source.iter_unlocked() {
+                let entry = entry?;
+                if !is_real_entry(&entry) {
+                    continue;
+                }
+                let name = entry.metadata.name().to_string();
+                if entry.is_dir() {
+                    let mut directory = target
+                        .create(name, EntryType::Directory)?
+                        .into_directory_unchecked();
+                    Self::preserve_metadata(&mut target, &mut directory.metadata, &entry.metadata)?;
+                    pending.push((entry.into_directory_unchecked(), directory));
+                    continue;
+                }
+                let mut copy = target.create(name, EntryType::File)?.into_file_unchecked();
+                let mut file = entry.into_file_unchecked();
+                loop {
+                    let read = file.read_unlocked(&mut buf)?;
+                    if read == 0 {
+                        break;
+                    }
+                    Self::write_all(&mut copy, &buf[..read])?;
+                    copied += read as u64;
+                }
+                Self::preserve_metadata(&mut target, &mut copy.metadata, &file.metadata)?;
+            }
+        }
+        Ok(copied)
+    }
+
+    /// Give the copy `metadata`, an entry of `parent`, the timestamps and
+    /// attributes of `original`.
+    fn preserve_metadata(
+        parent: &mut Directory,
+        metadata: &mut Metadata,
+        original: &Metadata,
+    ) -> Result<()> {
+        metadata.attributes = original.attributes;
+        metadata.set_times(&original.times());
+        parent.update_entry(metadata.clone())
+    }
+}
@@ -1,32 +1,30 @@
 use alloc::sync::Arc;
 use core::fmt;
+use core::ops::ControlFlow;
 
 use binrw::BinReaderExt;
 use binrw::io::Cursor;
 use log::{debug, info, trace};
 use snafu::ensure;
-use spin::mutex::SpinMutex;
-use spin::rwlock::RwLock;
 
 use crate::alloc::string::ToString;
 use crate::cluster::{cluster_reader, cluster_writer};
-use crate::fat_table::FAT_ENTRY_SIZE;
+use crate::dentry_cache::DentryCache;
 use crate::fat_table::FatEntry;
+use crate::fat_table::{FAT_ENTRY_SIZE, MAX_CLUSTER_CHAIN_LENGTH};
 use crate::formats::extended_bios_parameter_block::{
     BiosParameterBlock, ExtendedBiosParameterBlock, FullExtendedBIOSParameterBlock,
 };
 use crate::formats::fsinfo::FSInfoSector;
+use crate::locks::{LockTable, Mutex, SharedLock, shared_lock};
+use crate::open_files::{OpenFileTable, OrphanReaper};
 use crate::{
     ArcMutex, Attributes, BlockDevice, CachedPartition, ClusterId, Directory, DirectoryEntry,
-    EBPF_VFAT_MAGIC, EBPF_VFAT_MAGIC_ALT, Metadata, RegularDirectoryEntry, SectorId,
-    UnknownDirectoryEntry, VfatDirectoryEntry, VfatRsError, fat_table,
+    EBPF_VFAT_MAGIC, EBPF_VFAT_MAGIC_ALT, Metadata, MountOptions, RegularDirectoryEntry, SectorId,
+    UnknownDirectoryEntry, VfatDirectoryEntry, VfatRsError, WipePattern, fat_table,
 };
-use crate::{PathBuf, SECTOR_SIZE, TimeManagerTrait};
-use crate::{Result, VfatMetadataTrait, error};
-
-/// Maximum cluster chain length to prevent infinite loops in corrupted filesystems.
-/// 2^20 = 1,048,576 iterations supports files up to 512GB with 512KB clusters.
-const MAX_CLUSTER_CHAIN_LENGTH: u32 = 1_048_576;
+use crate::{PathBuf, SECTOR_SIZE, TimeManagerTrait, VfatTimestamp};
+use crate::{Result, error};
 
 /// Main entry point for your VFAT filesystem.
 ///
@@ -34,9 +32,14 @@ const MAX_CLUSTER_CHAIN_LENGTH: u32 = 1_048_576;
 ///
 /// ## Thread Safety
 ///
-/// `VfatFS` uses an internal `RwLock` to synchronize access. Read operations
-/// (listing directories, reading files) can proceed concurrently. Write
-/// operations (creating/deleting files, writing data) are serialized.
+/// Operations on different files, or on the entries of different
+/// directories, run concurrently: each file and each directory has its own
+/// lock, and the FAT is guarded by an allocator lock held only while
+/// clusters are allocated or freed. Reads of the same file or directory run
+/// concurrently too. Operations spanning several directories, like renames,
+/// recursive copies and removals or defragmentation, lock the whole volume.
+/// The locks come from [`MountOptions::locks`]: spinning ones by default,
+/// sleeping ones with the `std` feature.
 ///
 /// Individual [`File`](crate::api::File) objects are **not** `Sync` — do not
 /// share a single `File` across threads. Instead, open the file independently
@@ -56,17 +59,33 @@ pub struct VfatFS {
     pub(crate) eoc_marker: FatEntry,
     // heap allocated to mostly to ease api
     pub(crate) time_manager: Arc<dyn TimeManagerTrait>,
-    /// Filesystem-wide lock: read operations take a shared (read) lock,
-    /// write/mutating operations take an exclusive (write) lock.
-    pub(crate) fs_lock: Arc<RwLock<()>>,
-    /// Hint for the next free cluster search start position (from FSInfo sector).
-    /// Updated after each successful allocation to avoid re-scanning used clusters.
-    last_alloc_hint: Arc<SpinMutex<u32>>,
+    /// Volume lock: taken shared by operations confined to a file or a
+    /// directory, which take their own locks too, and exclusively by the ones
+    /// spanning several directories. See [`crate::locks`] for the lock order.
+    pub(crate) fs_lock: SharedLock,
+    /// Locks of the directories in use.
+    pub(crate) directory_locks: Arc<Mutex<LockTable>>,
+    /// Allocator lock, held while looking for free clusters and changing the
+    /// FAT. Guards the hint for the next free cluster search start position
+    /// (from FSInfo sector), updated after each successful allocation to
+    /// avoid re-scanning used clusters.
+    pub(crate) allocator: Arc<Mutex<u32>>,
     /// Sector number of the FSInfo sector (absolute), or `None` if not present.
     fsinfo_sector: Option<SectorId>,
+    /// The boot sector, holding the BPB: the first sector of the partition.
+    pub(crate) boot_sector: SectorId,
     /// Total number of addressable data clusters in the volume (cluster ids
     /// `2..2 + total_clusters`). Used for free-space reporting (`statfs`).
-    total_clusters: u32,
+    pub(crate) total_clusters: u32,
+    /// Options this volume was mounted with.
+    pub(crate) options: MountOptions,
+    /// Directory lookups, shared by every handle of this volume.
+    pub(crate) dentry_cache: Arc<Mutex<DentryCache>>,
+    /// Files open on this volume, shared by every handle of this volume.
+    pub(crate) open_files: Arc<Mutex<OpenFileTable>>,
+    /// Frees the orphaned chains of `open_files` once the last handle of
+    /// this volume is dropped.
+    _orphan_reaper: Arc<OrphanReaper>,
 }
 
 impl fmt::Debug for VfatFS {
@@ -102,10 +121,24 @@ impl VfatFS {
     /// `cache_capacity` is the maximum number of sectors to cache in memory.
     /// Use 0 to disable caching (all I/O goes directly to the device).
     pub fn new_with_cache<B: BlockDevice + Send + 'static>(
-        mut device: B,
+        device: B,
         partition_start_sector: u32,
         time_manager: impl TimeManagerTrait + 'static,
         cache_capacity: usize,
+    ) -> Result<Self> {
+        let options = MountOptions {
+            cache_capacity,
+            ..MountOptions::default()
+        };
+        Self::new_with_options(device, partition_start_sector, time_manager, options)
+    }
+
+    /// Create a new VFat filesystem with a custom time manager and mount options.
+    pub fn new_with_options<B: BlockDevice + Send + 'static>(
+        mut device: B,
+        partition_start_sector: u32,
+        time_manager: impl TimeManagerTrait + 'static,
+        options: MountOptions,
     ) -> Result<Self> {
         let time_manager = Arc::new(time_manager);
         let full_ebpb = Self::read_fullebpb(&mut device, partition_start_sector)?;
@@ -114,7 +147,7 @@ impl VfatFS {
             partition_start_sector,
             full_ebpb,
             time_manager,
-            cache_capacity,
+            options,
         )
     }
 
@@ -183,7 +216,7 @@ impl VfatFS {
         partition_start_sector: u32,
         full_ebpb: FullExtendedBIOSParameterBlock,
         time_manager: Arc<dyn TimeManagerTrait>,
-        cache_capacity: usize,
+        options: MountOptions,
     ) -> Result<Self> {
         Self::validate_bpb(&full_ebpb.bpb, &full_ebpb.extended)?;
         let fat_start_sector =
@@ -225,19 +258,42 @@ impl VfatFS {
             data_start_sector,
             fat_amount,
             sectors_per_fat,
-            cache_capacity,
+            options.cache_capacity,
+            &*options.locks,
         );
+        let device = Arc::new(cached_partition);
+        let open_files = Arc::new(Mutex::new(
+            OpenFileTable::new(options.locks.clone()),
+            &*options.locks,
+        ));
+        let orphan_reaper = Arc::new(OrphanReaper::new(
+            open_files.clone(),
+            device.clone(),
+            options.discard,
+        ));
         Ok(VfatFS {
-            device: Arc::new(cached_partition),
+            device,
             fat_start_sector,
             root_cluster,
             eoc_marker,
             sectors_per_fat,
             time_manager,
-            fs_lock: Arc::new(RwLock::new(())),
-            last_alloc_hint: Arc::new(SpinMutex::new(alloc_hint)),
+            fs_lock: shared_lock(&*options.locks),
+            directory_locks: Arc::new(Mutex::new(
+                LockTable::new(options.locks.clone()),
+                &*options.locks,
+            )),
+            allocator: Arc::new(Mutex::new(alloc_hint, &*options.locks)),
             fsinfo_sector: fsinfo_abs_sector,
+            boot_sector: SectorId::from(partition_start_sector),
             total_clusters,
+            open_files,
+            _orphan_reaper: orphan_reaper,
+            dentry_cache: Arc::new(Mutex::new(
+                DentryCache::new(options.dentry_cache_capacity),
+                &*options.locks,
+            )),
+            options,
         })
     }
 
@@ -266,7 +322,7 @@ impl VfatFS {
         Ok(raw_entry)
     }
 
-    fn new_last_cluster_fat_entry(&self) -> FatEntry {
+    pub(crate) fn new_last_cluster_fat_entry(&self) -> FatEntry {
         // Last cluster is initialized with the eoc_marker
         FatEntry::LastCluster(self.eoc_marker.into())
     }
@@ -276,12 +332,11 @@ impl VfatFS {
     /// Scans from the hint to the end of the FAT, then wraps around from
     /// sector 0 to the hint. This avoids re-scanning already-allocated
     /// clusters at the beginning of the FAT.
-    pub(crate) fn find_free_cluster(&self) -> Result<Option<ClusterId>> {
+    pub(crate) fn find_free_cluster(&self, hint: u32) -> Result<Option<ClusterId>> {
         info!("Starting find free cluster routine");
         const ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / FAT_ENTRY_SIZE;
         const BUF_SIZE: usize = FAT_ENTRY_SIZE * ENTRIES_PER_SECTOR;
 
-        let hint = *self.last_alloc_hint.lock();
         let hint_sector = hint / ENTRIES_PER_SECTOR as u32;
         let hint_offset = hint as usize % ENTRIES_PER_SECTOR;
 
@@ -338,23 +393,187 @@ impl VfatFS {
         Ok(None)
     }
 
+    /// Call `f(start, len)` for every maximal run of consecutive free clusters,
+    /// in ascending cluster order, until it returns [`ControlFlow::Break`].
+    ///
+    /// Only one FAT sector is held in memory at a time, so this is usable on
+    /// devices with little RAM regardless of the volume size.
+    pub(crate) fn for_each_free_run<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(ClusterId, u32) -> ControlFlow<()>,
+    {
+        const ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / FAT_ENTRY_SIZE;
+        const BUF_SIZE: usize = FAT_ENTRY_SIZE * ENTRIES_PER_SECTOR;
+
+        let last_valid_cid = 2u32.saturating_add(self.total_clusters);
+        let mut run: Option<(u32, u32)> = None;
+        'scan: for i in 0..self.sectors_per_fat {
+            let mut buf = [0u8; BUF_SIZE];
+            self.device
+                .read_sector(self.fat_start_sector + i, &mut buf)?;
+            for (id, bytes) in buf.chunks(FAT_ENTRY_SIZE).enumerate() {
+                let cid = ENTRIES_PER_SECTOR as u32 * i + id as u32;
+                if cid < 2 {
+                    continue;
+                }
+                if cid >= last_valid_cid {
+                    break 'scan;
+                }
+                if let FatEntry::Unused = FatEntry::new_ref(bytes) {
+                    run = Some(run.map_or((cid, 1), |(start, len)| (start, len + 1)));
+                } else if let Some((start, len)) = run.take()
+                    && f(ClusterId::new(start), len).is_break()
+                {
+                    return Ok(());
+                }
+            }
+        }
+        if let Some((start, len)) = run {
+            let _ = f(ClusterId::new(start), len);
+        }
+        Ok(())
+    }
+
+    /// Find the lowest run of at least `len` consecutive free clusters.
+    ///
+    /// Unlike [`Self::find_free_cluster`] this ignores the allocation hint and
+    /// always scans from the start of the data area (first fit), which is what
+    /// contiguous allocation and compaction want.
+    pub(crate) fn find_free_run(&self, len: u32) -> Result<Option<ClusterId>> {
+        let mut found = None;
+        self.for_each_free_run(|start, run_len| {
+            if run_len >= len {
+                found = Some(start);
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        })?;
+        Ok(found)
+    }
+
+    /// Mark `len` clusters starting at `start` as a single chain in the FAT.
+    ///
+    /// The run must already be known to be free, and the caller must hold the
+    /// allocator lock or the volume lock exclusively since then. The chain is linked from its
+    /// tail to its head, so the head only becomes reachable once the rest of the
+    /// run is in place.
+    pub(crate) fn allocate_run(&self, start: ClusterId, len: u32) -> Result<()> {
+        let first = u32::from(start);
+        for cid in (first..first + len).rev() {
+            let entry = if cid + 1 == first + len {
+                self.new_last_cluster_fat_entry()
+            } else {
+                FatEntry::from_chain(ClusterId::new(cid + 1))
+            };
+            self.write_entry_in_vfat_table(ClusterId::new(cid), entry)?;
+        }
+        Ok(())
+    }
+
     /// Allocate a cluster for a new file.
+    pub(crate) fn allocate_cluster_new_entry(&self) -> Result<ClusterId> {
+        let mut hint = self.allocator.lock();
+        self.allocate_cluster(&mut hint)
+    }
+
     /// First find an empty cluster. Then set this cluster id as LastCluster.
     /// Updates the allocation hint so the next search starts after this cluster.
-    pub(crate) fn allocate_cluster_new_entry(&self) -> Result<ClusterId> {
+    /// `hint` is the content of the allocator lock, held by the caller.
+    fn allocate_cluster(&self, hint: &mut u32) -> Result<ClusterId> {
         let free_cluster_id = self
-            .find_free_cluster()?
+            .find_free_cluster(*hint)?
             .ok_or(VfatRsError::FreeClusterNotFound)?;
         let entry = self.new_last_cluster_fat_entry();
         info!("Found free cluster: {}", free_cluster_id);
         self.write_entry_in_vfat_table(free_cluster_id, entry)?;
 
         // Advance the hint past the just-allocated cluster.
-        *self.last_alloc_hint.lock() = u32::from(free_cluster_id) + 1;
+        *hint = u32::from(free_cluster_id) + 1;
 
         Ok(free_cluster_id)
     }
 
+    /// Make sure the chain starting at `head` (or a new chain, if `None`) is at
+    /// least `count` clusters long, and return its head.
+    ///
+    /// With `contiguous`, the result is a single run: a new chain is placed in
+    /// the lowest free run that fits, and an existing chain is only extended in
+    /// place. Otherwise clusters are taken one by one from the allocator.
+    /// Either way the reservation is all-or-nothing: if it cannot be completed,
+    /// the clusters taken so far are released again.
+    pub(crate) fn reserve_clusters(
+        &self,
+        head: Option<ClusterId>,
+        count: u32,
+        contiguous: bool,
+    ) -> Result<ClusterId> {
+        let no_run = || VfatRsError::ContiguousSpaceNotFound { clusters: count };
+        let mut hint = self.allocator.lock();
+        let (head, tail, existing) = match head {
+            None if contiguous => {
+                let start = self.find_free_run(count)?.ok_or_else(no_run)?;
+                self.allocate_run(start, count)?;
+                return Ok(start);
+            }
+            None => {
+                let first = self.allocate_cluster(&mut hint)?;
+                if let Err(err) = self.extend_chain(&mut hint, first, count - 1) {
+                    self.free_cluster_chain(first, None)?;
+                    return Err(err);
+                }
+                return Ok(first);
+            }
+            Some(head) => {
+                let (mut runs, mut tail, mut existing) = (0u32, head, 0u32);
+                fat_table::for_each_run(head, self.device.clone(), |start, len| {
+                    runs += 1;
+                    tail = ClusterId::new(u32::from(start) + len - 1);
+                    existing += len;
+                })?;
+                if contiguous && runs > 1 {
+                    return Err(no_run());
+                }
+                (head, tail, existing)
+            }
+        };
+        if existing >= count {
+            return Ok(head);
+        }
+        let extra = count - existing;
+        if contiguous {
+            let first_new = u32::from(tail) + 1;
+            let last_valid_cid = 2u32.saturating_add(self.total_clusters);
+            ensure!(
+                first_new.saturating_add(extra) <= last_valid_cid,
+                error::ContiguousSpaceNotFoundSnafu { clusters: count }
+            );
+            for cid in first_new..first_new + extra {
+                let entry = fat_table::read_fat_entry(ClusterId::new(cid), self.device.clone())?;
+                ensure!(
+                    entry == FatEntry::Unused,
+                    error::ContiguousSpaceNotFoundSnafu { clusters: count }
+                );
+            }
+            self.allocate_run(ClusterId::new(first_new), extra)?;
+            self.write_entry_in_vfat_table(tail, FatEntry::from_chain(ClusterId::new(first_new)))?;
+        } else if let Err(err) = self.extend_chain(&mut hint, tail, extra) {
+            self.shrink_cluster_chain(head, existing, None)?;
+            return Err(err);
+        }
+        Ok(head)
+    }
+
+    /// Append `count` clusters after `tail`, the last cluster of a chain.
+    fn extend_chain(&self, hint: &mut u32, tail: ClusterId, count: u32) -> Result<()> {
+        let mut last = tail;
+        for _ in 0..count {
+            let next = self.allocate_cluster(hint)?;
+            self.write_entry_in_vfat_table(last, FatEntry::from_chain(next))?;
+            last = next;
+        }
+        Ok(())
+    }
+
     /// Finds a free clusters and updates the chain:
     ///  * previous cluster in the chain to point to the newly allocated one,
     /// * new clusterId added as final entry
@@ -368,17 +587,22 @@ impl VfatFS {
                 reason: "Attempted to extend a chain starting at a reserved cluster (id < 2)"
             }
         );
+        let mut hint = self.allocator.lock();
         let tail_cluster_id = self.get_last_cluster_in_chain(head)?;
         debug!("Tail cluster: {}", tail_cluster_id);
 
-        let free_cluster_id = self.allocate_cluster_new_entry()?;
+        let free_cluster_id = self.allocate_cluster(&mut hint)?;
 
         let updated_entry = FatEntry::from_chain(free_cluster_id);
         self.write_entry_in_vfat_table(tail_cluster_id, updated_entry)?;
         info!("Updated the entry");
         Ok(free_cluster_id)
     }
-    fn write_entry_in_vfat_table(&self, cluster_id: ClusterId, entry: FatEntry) -> Result<()> {
+    pub(crate) fn write_entry_in_vfat_table(
+        &self,
+        cluster_id: ClusterId,
+        entry: FatEntry,
+    ) -> Result<()> {
         fat_table::set_fat_entry(self.device.clone(), cluster_id, entry)
     }
 
@@ -416,14 +640,60 @@ impl VfatFS {
         cluster_reader::ClusterChainReader::new(self.device.clone(), cluster_id)
     }
 
-    /// This will delete all the cluster chain starting from cluster_id.
-    pub(crate) fn delete_fat_cluster_chain(&self, cluster_id: ClusterId) -> Result<()> {
-        fat_table::delete_cluster_chain(cluster_id, self.device.clone())
+    /// This will delete all the cluster chain starting from cluster_id,
+    /// overwriting it first with `wipe`'s pattern if any.
+    pub(crate) fn delete_fat_cluster_chain(
+        &self,
+        cluster_id: ClusterId,
+        wipe: Option<WipePattern>,
+    ) -> Result<()> {
+        let _guard = self.allocator.lock();
+        self.free_cluster_chain(cluster_id, wipe)
+    }
+
+    /// [`Self::delete_fat_cluster_chain`], for callers holding the allocator lock.
+    fn free_cluster_chain(&self, cluster_id: ClusterId, wipe: Option<WipePattern>) -> Result<()> {
+        // The chain may be a directory's: its names must not outlive it.
+        self.dentry_cache.lock().invalidate_dir(cluster_id);
+        fat_table::delete_cluster_chain(cluster_id, self.device.clone(), wipe, self.options.discard)
+    }
+
+    /// Free the chains of the unlinked files whose last handle was closed
+    /// while the volume lock was taken exclusively. The caller holds the
+    /// volume lock.
+    pub(crate) fn release_orphans(&self) -> Result<()> {
+        let orphans = self.open_files.lock().take_orphans();
+        for (cluster, wipe) in orphans {
+            self.delete_fat_cluster_chain(cluster, wipe)?;
+        }
+        Ok(())
     }
 
     /// Truncate a cluster chain, keeping `keep_count` clusters and freeing the rest.
-    pub(crate) fn truncate_cluster_chain(&self, start: ClusterId, keep_count: u32) -> Result<()> {
-        fat_table::truncate_cluster_chain(start, keep_count, self.device.clone())
+    pub(crate) fn truncate_cluster_chain(
+        &self,
+        start: ClusterId,
+        keep_count: u32,
+        wipe: Option<WipePattern>,
+    ) -> Result<()> {
+        let _guard = self.allocator.lock();
+        self.shrink_cluster_chain(start, keep_count, wipe)
+    }
+
+    /// [`Self::truncate_cluster_chain`], for callers holding the allocator lock.
+    fn shrink_cluster_chain(
+        &self,
+        start: ClusterId,
+        keep_count: u32,
+        wipe: Option<WipePattern>,
+    ) -> Result<()> {
+        fat_table::truncate_cluster_chain(
+            start,
+            keep_count,
+            self.device.clone(),
+            wipe,
+            self.options.discard,
+        )
     }
 
     /// Returns the number of bytes per cluster.
@@ -464,6 +734,80 @@ impl VfatFS {
         Ok(free)
     }
 
+    /// Overwrite every free cluster of the volume with `pattern`, so data
+    /// released before secure deletion was enabled can't be recovered.
+    /// Returns the number of clusters wiped.
+    ///
+    /// Slack space after the end of a file in its last cluster and the slots of
+    /// deleted directory entries are not touched.
+    pub fn wipe_free_space(&self, pattern: WipePattern) -> Result<u32> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        let mut wiped = 0;
+        let mut result = Ok(());
+        self.for_each_free_run(|start, len| {
+            let first = u32::from(start);
+            for cid in first..first + len {
+                result = self
+                    .device
+                    .clone()
+                    .fill_cluster(ClusterId::new(cid), pattern.byte());
+                if result.is_err() {
+                    return ControlFlow::Break(());
+                }
+                wiped += 1;
+            }
+            ControlFlow::Continue(())
+        })?;
+        result?;
+        self.device.flush()?;
+        Ok(wiped)
+    }
+
+    /// Discard every free cluster of the volume on the device, like `fstrim`.
+    /// Adjacent free clusters are discarded as a single range. Returns the
+    /// number of clusters discarded.
+    ///
+    /// Useful on volumes mounted without [`MountOptions::discard`], or to catch
+    /// up on space freed by another implementation.
+    pub fn fstrim(&self) -> Result<u32> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.write();
+        // Pending FAT updates must not be lost if they share a range with
+        // discarded sectors: flush them first.
+        self.device.flush()?;
+        let sectors_per_cluster = self.device.sectors_per_cluster;
+        let mut trimmed = 0;
+        let mut result = Ok(());
+        self.for_each_free_run(|start, len| {
+            result = self.device.discard(
+                self.device.cluster_to_sector(start),
+                len * sectors_per_cluster,
+            );
+            if result.is_err() {
+                return ControlFlow::Break(());
+            }
+            trimmed += len;
+            ControlFlow::Continue(())
+        })?;
+        result?;
+        Ok(trimmed)
+    }
+
+    /// Bring the volume on the device up to date, like `sync`: free the
+    /// clusters of deleted files whose last handle was closed while the volume
+    /// was busy, write the allocation hint to the FSInfo sector and flush the
+    /// sector cache.
+    ///
+    /// Dropping the last handle of the volume frees those clusters too.
+    pub fn sync(&self) -> Result<()> {
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        self.release_orphans()?;
+        self.flush_fsinfo();
+        self.device.flush()
+    }
+
     /// Write the current allocation hint back to the FSInfo sector on disk.
     ///
     /// This is advisory — the hint speeds up the next mount but correctness
@@ -473,7 +817,7 @@ impl VfatFS {
             Some(s) => s,
             None => return,
         };
-        let hint = *self.last_alloc_hint.lock();
+        let hint = *self.allocator.lock();
         // Write nxt_free (offset 492 = 4 + 480 + 4 + 4 = 492 bytes into the sector).
         let hint_bytes = hint.to_le_bytes();
         let _ = self
@@ -492,8 +836,29 @@ impl VfatFS {
         self.get_from_absolute_path_unlocked(absolute_path)
     }
 
-    /// Internal path resolution without acquiring the lock (for use by callers
-    /// that already hold the lock).
+    /// Refuse to change or remove a read-only entry, unless the volume was
+    /// mounted with [`MountOptions::ignore_read_only`].
+    ///
+    /// Attribute changes don't go through here, so clearing the read-only
+    /// bit first is how a single entry is forced.
+    pub(crate) fn ensure_modifiable(&self, metadata: &Metadata) -> Result<()> {
+        if !metadata.attributes.is_read_only() || self.options.ignore_read_only {
+            return Ok(());
+        }
+        Err(VfatRsError::AccessDenied {
+            target: metadata.full_path().display().to_string(),
+            reason: "the entry is read-only",
+        })
+    }
+
+    /// The lock of the directory starting at `cluster`.
+    pub(crate) fn directory_lock(&self, cluster: ClusterId) -> SharedLock {
+        self.directory_locks.lock().get(cluster)
+    }
+
+    /// Internal path resolution without acquiring the volume lock (for use by
+    /// callers that already hold it). Each directory along the path is locked
+    /// while it is searched, so the caller must not hold any directory lock.
     pub(crate) fn get_from_absolute_path_unlocked(
         &mut self,
         absolute_path: PathBuf,
@@ -504,6 +869,8 @@ impl VfatFS {
                 target: absolute_path.display().to_string()
             }
         );
+        // `.`, `..` and repeated separators are resolved lexically.
+        let absolute_path = crate::normalize_path(&absolute_path);
         if absolute_path.iter().count() == 1 {
             return self.get_root_unlocked().map(From::from);
         }
@@ -512,11 +879,15 @@ impl VfatFS {
         path_iter.next();
         for sub_path in path_iter {
             let directory = current_entry.into_directory_or_not_found()?;
-            let matches: Option<DirectoryEntry> = directory
-                .contents_unlocked()?
-                .into_iter()
-                .filter(|entry| entry.metadata().name() == sub_path)
-                .last();
+            #[cfg(feature = "std")]
+            let name = sub_path.to_str().unwrap_or_default();
+            #[cfg(not(feature = "std"))]
+            let name = sub_path;
+            let lock = self.directory_lock(directory.metadata.cluster);
+            let matches = {
+                let _guard = lock.read();
+                directory.find_unlocked(name)?
+            };
             current_entry = matches.ok_or_else(|| VfatRsError::EntryNotFound {
                 #[cfg(feature = "std")]
                 target: sub_path.to_str().unwrap().into(),
@@ -553,16 +924,20 @@ impl VfatFS {
         let _ = cluster_reader.read(&mut buf)?;
         let unknown_entries: UnknownDirectoryEntry = buf.into();
         debug!("Unknown entries: {:?}", unknown_entries);
-        let volume_id = VfatDirectoryEntry::from(unknown_entries)
+        // The root has no entry of its own: its timestamps are the volume
+        // label's, if it has one first.
+        let (created, modified) = VfatDirectoryEntry::from(unknown_entries)
             .into_regular()
             .filter(|regular| regular.is_volume_id())
-            .ok_or_else(|| {
-                crate::io::Error::new(crate::io::ErrorKind::NotFound, "Volume id not found?!")
-            })?;
+            .map_or(
+                (VfatTimestamp::new(0), VfatTimestamp::new(0)),
+                |volume_id| (volume_id.creation_time, volume_id.last_modification_time),
+            );
 
+        let utc_offset = self.options.utc_offset;
         let metadata = Metadata::new(
-            volume_id.creation_time,
-            volume_id.last_modification_time,
+            utc_offset.to_utc(created),
+            utc_offset.to_utc(modified),
             "/",
             size_of::<RegularDirectoryEntry>() as u32,
             PathBuf::from("/"),
@@ -579,13 +954,15 @@ mod test {
     use alloc::format;
     use alloc::sync::Arc;
     use alloc::vec::Vec;
-    use spin::mutex::SpinMutex;
-    use spin::rwlock::RwLock;
 
+    use crate::dentry_cache::DentryCache;
     use crate::fat_table::FAT_ENTRY_SIZE;
     use crate::io::Write;
+    use crate::locks::{LockTable, Mutex, SpinLocks, shared_lock};
+    use crate::open_files::{OpenFileTable, OrphanReaper};
     use crate::{
-        BlockDevice, CachedPartition, ClusterId, Result, SectorId, TimeManagerNoop, VfatFS,
+        BlockDevice, CachedPartition, ClusterId, MountOptions, Result, SectorId, TimeManagerNoop,
+        VfatFS,
     };
 
     pub struct ArrayBackedBlockDevice {
@@ -668,25 +1045,37 @@ mod test {
     #[test]
     fn test_circular_cluster_chain_detection() {
         let dev = CircularChainDevice;
+        let device = Arc::new(CachedPartition::new(
+            dev,
+            512,
+            SectorId(1),
+            1,
+            SectorId(100),
+            2,
+            50,
+        ));
+        let open_files = Arc::new(Mutex::new(
+            OpenFileTable::new(Arc::new(SpinLocks)),
+            &SpinLocks,
+        ));
+        let orphan_reaper = Arc::new(OrphanReaper::new(open_files.clone(), device.clone(), false));
         let vfat = VfatFS {
-            device: Arc::new(CachedPartition::new(
-                dev,
-                512,
-                SectorId(1),
-                1,
-                SectorId(100),
-                2,
-                50,
-            )),
+            device,
             fat_start_sector: SectorId(1),
             sectors_per_fat: 50,
             root_cluster: ClusterId::new(2),
             eoc_marker: Default::default(),
             time_manager: TimeManagerNoop::new_arc(),
-            fs_lock: Arc::new(RwLock::new(())),
-            last_alloc_hint: Arc::new(SpinMutex::new(2)),
+            fs_lock: shared_lock(&SpinLocks),
+            directory_locks: Arc::new(Mutex::new(LockTable::new(Arc::new(SpinLocks)), &SpinLocks)),
+            allocator: Arc::new(Mutex::new(2, &SpinLocks)),
             fsinfo_sector: None,
+            boot_sector: SectorId(0),
             total_clusters: 0,
+            options: MountOptions::default(),
+            dentry_cache: Arc::new(Mutex::new(DentryCache::new(0), &SpinLocks)),
+            open_files,
+            _orphan_reaper: orphan_reaper,
         };
 
         // Attempt to traverse the circular chain - should return error, not hang
@@ -725,30 +1114,42 @@ mod test {
         let data_start_sector = SectorId(2);
         let fat_amount = 2;
         let sectors_per_fat = 1;
+        let device = Arc::new(CachedPartition::new(
+            dev,
+            sector_size,
+            fat_start_sector,
+            sectors_per_cluster,
+            data_start_sector,
+            fat_amount,
+            sectors_per_fat,
+        ));
+        let open_files = Arc::new(Mutex::new(
+            OpenFileTable::new(Arc::new(SpinLocks)),
+            &SpinLocks,
+        ));
+        let orphan_reaper = Arc::new(OrphanReaper::new(open_files.clone(), device.clone(), false));
         let vfat = VfatFS {
-            device: Arc::new(CachedPartition::new(
-                dev,
-                sector_size,
-                fat_start_sector,
-                sectors_per_cluster,
-                data_start_sector,
-                fat_amount,
-                sectors_per_fat,
-            )),
+            device,
             fat_start_sector,
             sectors_per_fat,
             root_cluster: ClusterId::new(0),
             eoc_marker: Default::default(),
             time_manager: TimeManagerNoop::new_arc(),
-            fs_lock: Arc::new(RwLock::new(())),
-            last_alloc_hint: Arc::new(SpinMutex::new(0)),
+            fs_lock: shared_lock(&SpinLocks),
+            directory_locks: Arc::new(Mutex::new(LockTable::new(Arc::new(SpinLocks)), &SpinLocks)),
+            allocator: Arc::new(Mutex::new(0, &SpinLocks)),
             fsinfo_sector: None,
+            boot_sector: SectorId(0),
             total_clusters: 0,
+            options: MountOptions::default(),
+            dentry_cache: Arc::new(Mutex::new(DentryCache::new(0), &SpinLocks)),
+            open_files,
+            _orphan_reaper: orphan_reaper,
         };
         // Reserved clusters 0 and 1 must never be returned, even though their
         // FAT entries read as unused; the first allocatable cluster is 3.
         assert_eq!(
-            vfat.find_free_cluster().unwrap().unwrap(),
+            vfat.find_free_cluster(0).unwrap().unwrap(),
             ClusterId::new(3)
         );
     }
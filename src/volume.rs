@@ -0,0 +1,180 @@
+//! Identity of a volume: its label, serial number and layout.
+//!
+//! The label is kept twice: in a volume label entry of the root directory,
+//! which Windows and most other systems read, and in the boot sector, which
+//! some tools read instead. [`VfatFS::set_label`] updates both, and the
+//! boot sector's backup.
+use alloc::string::String;
+
+use binrw::BinReaderExt;
+use binrw::io::Cursor;
+
+use crate::formats::extended_bios_parameter_block::FullExtendedBIOSParameterBlock;
+use crate::resize::MIN_FAT32_CLUSTERS;
+use crate::{EBPF_VFAT_MAGIC_ALT, Result, SECTOR_SIZE, VfatFS, VfatRsError};
+
+/// Volumes with fewer clusters are FAT12.
+const MIN_FAT16_CLUSTERS: u32 = 4_085;
+/// Byte offset of the label in a FAT32 boot sector.
+const BPB_VOLUME_LABEL: usize = 0x47;
+/// The boot sector label of volumes without one.
+const NO_NAME: [u8; 11] = *b"NO NAME    ";
+/// Characters Windows doesn't allow in labels.
+const FORBIDDEN: &[u8] = b"\"*+,./:;<=>?[\\]|";
+
+/// The FAT variant of a volume, decided by its cluster count as the
+/// specification says, whatever the boot sector claims.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    /// Fewer than 4085 clusters.
+    Fat12,
+    /// Fewer than 65525 clusters.
+    Fat16,
+    /// 65525 clusters or more.
+    Fat32,
+}
+
+/// Identity and size of a volume, returned by [`VfatFS::info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeInfo {
+    /// The volume label, without the trailing padding. `None` if the volume
+    /// has none.
+    pub label: Option<String>,
+    /// The serial number set when the volume was formatted, usually shown
+    /// as two groups of 4 hex digits (`1234-ABCD`).
+    pub serial_number: u32,
+    /// The name of the system that formatted the volume, e.g. `MSWIN4.1`
+    /// or `mkfs.fat`.
+    pub oem_name: String,
+    /// The FAT variant.
+    pub fat_type: FatType,
+    /// Size of a cluster in bytes.
+    pub cluster_size: u32,
+    /// Size of the data region in bytes.
+    pub total_bytes: u64,
+    /// Free space in bytes.
+    pub free_bytes: u64,
+    /// Number of copies of the FAT.
+    pub fat_count: u8,
+}
+
+impl VfatFS {
+    /// Report the label, serial number, layout and free space of the volume.
+    /// The free space is counted by scanning the FAT.
+    pub fn info(&self) -> Result<VolumeInfo> {
+        let free_clusters = self.count_free_clusters()?;
+        let _guard = self.fs_lock.read();
+        let ebpb = self.read_boot_sector()?;
+        let root = self.clone().get_root_unlocked()?;
+        let dir_lock = root.lock();
+        let _dir_guard = dir_lock.read();
+
+        let label = match root.volume_label_entry()? {
+            Some((_, regular)) => {
+                let mut name = [0u8; 11];
+                name[..8].copy_from_slice(&{ regular.file_name });
+                name[8..].copy_from_slice(&{ regular.file_ext });
+                decode(&name)
+            }
+            // Without an entry, trust the boot sector unless it has the
+            // placeholder.
+            None if ebpb.extended.signature == EBPF_VFAT_MAGIC_ALT
+                && ebpb.extended.volume_label_string != NO_NAME =>
+            {
+                decode(&ebpb.extended.volume_label_string)
+            }
+            None => None,
+        };
+        let clusters = self.cluster_count();
+        let fat_type = if clusters < MIN_FAT16_CLUSTERS {
+            FatType::Fat12
+        } else if clusters < MIN_FAT32_CLUSTERS {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+        let cluster_size = self.bytes_per_cluster();
+        Ok(VolumeInfo {
+            label,
+            serial_number: ebpb.extended.volumeid_serial_number,
+            oem_name: decode(&{ ebpb.bpb.oem_identifier }.to_le_bytes()).unwrap_or_default(),
+            fat_type,
+            cluster_size,
+            total_bytes: clusters as u64 * cluster_size as u64,
+            free_bytes: free_clusters as u64 * cluster_size as u64,
+            fat_count: ebpb.bpb.fat_amount,
+        })
+    }
+
+    /// Set the volume label, or remove it if `label` is empty. Labels are up
+    /// to 11 ASCII characters, stored in uppercase like Windows does, and
+    /// can't contain `"*+,./:;<=>?[\]|` or control characters.
+    ///
+    /// The label entry of the root directory is updated, or added, along
+    /// with the boot sector and its backup.
+    pub fn set_label(&mut self, label: &str) -> Result<()> {
+        let encoded = encode(label)?;
+        let lock = self.fs_lock.clone();
+        let _guard = lock.read();
+        let mut root = self.get_root_unlocked()?;
+        let dir_lock = root.lock();
+        let _dir_guard = dir_lock.write();
+        root.write_volume_label(encoded)?;
+
+        let ebpb = self.read_boot_sector()?;
+        // Older boot sectors have no label field.
+        if ebpb.extended.signature != EBPF_VFAT_MAGIC_ALT {
+            return Ok(());
+        }
+        let backup = ebpb.extended.backup_boot_sector;
+        let backup = (backup != 0 && backup != 0xFFFF).then(|| self.boot_sector + backup as u32);
+        for sector in backup.into_iter().chain([self.boot_sector]) {
+            self.device.clone().write_sector_offset(
+                sector,
+                BPB_VOLUME_LABEL,
+                &encoded.unwrap_or(NO_NAME),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn read_boot_sector(&self) -> Result<FullExtendedBIOSParameterBlock> {
+        let mut buf = [0u8; SECTOR_SIZE];
+        self.device.read_sector(self.boot_sector, &mut buf)?;
+        Ok(Cursor::new(&buf).read_le()?)
+    }
+}
+
+/// `label` padded with spaces as stored on disk, `None` to remove it.
+fn encode(label: &str) -> Result<Option<[u8; 11]>> {
+    let invalid = |reason| VfatRsError::InvalidLabel {
+        label: String::from(label),
+        reason,
+    };
+    let trimmed = label.trim_end_matches(' ');
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    if trimmed.len() > 11 {
+        return Err(invalid("longer than 11 characters"));
+    }
+    if !trimmed
+        .bytes()
+        .all(|byte| byte.is_ascii() && !byte.is_ascii_control())
+    {
+        return Err(invalid("only ASCII characters are allowed"));
+    }
+    if trimmed.bytes().any(|byte| FORBIDDEN.contains(&byte)) {
+        return Err(invalid("contains a character FAT doesn't allow in labels"));
+    }
+    let mut encoded = [b' '; 11];
+    encoded[..trimmed.len()].copy_from_slice(trimmed.to_ascii_uppercase().as_bytes());
+    Ok(Some(encoded))
+}
+
+/// A label or name padded with spaces, `None` if it's blank.
+fn decode(padded: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(padded);
+    let text = text.trim_end_matches([' ', '\0']);
+    (!text.is_empty()).then(|| String::from(text))
+}
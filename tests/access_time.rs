@@ -0,0 +1,232 @@
+//! Hermetic tests for last access dates, their update policies, and
+//! creation times to the hundredth of a second.
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{
+    AccessTimePolicy, BlockDevice, FileTimes, MountOptions, SectorId, TimeManagerTrait, VfatFS,
+    VfatTimestamp,
+};
+
+/// 2024-03-10 12:00:00 UTC.
+const NOON: u64 = 1_710_072_000;
+const DAY: u64 = 24 * 60 * 60;
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+/// A clock the tests move forward.
+#[derive(Debug, Clone, Default)]
+struct Clock(Arc<AtomicU64>);
+
+impl Clock {
+    fn set(&self, seconds: u64) {
+        self.0.store(seconds, Ordering::SeqCst);
+    }
+}
+
+impl TimeManagerTrait for Clock {
+    fn get_current_timestamp(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+struct Volume {
+    image: Arc<Mutex<Vec<u8>>>,
+    clock: Clock,
+}
+
+impl Volume {
+    fn new() -> Self {
+        let mut image = vec![0u8; 48 * 1024 * 1024];
+        {
+            let cursor = Cursor::new(&mut image[..]);
+            let options = fatfs::FormatVolumeOptions::new()
+                .fat_type(fatfs::FatType::Fat32)
+                .volume_label(*b"VFATRSTEST ");
+            fatfs::format_volume(cursor, options).expect("format FAT32 image");
+        }
+        let clock = Clock::default();
+        clock.set(NOON);
+        Self {
+            image: Arc::new(Mutex::new(image)),
+            clock,
+        }
+    }
+
+    fn mount(&self, access_time: AccessTimePolicy) -> VfatFS {
+        let device = MemoryBlockDevice(self.image.clone());
+        let options = MountOptions {
+            access_time,
+            ..MountOptions::default()
+        };
+        VfatFS::new_with_options(device, 0, self.clock.clone(), options).expect("open VfatFS")
+    }
+}
+
+fn date(seconds: u64) -> VfatTimestamp {
+    VfatTimestamp::from(seconds).date()
+}
+
+fn accessed(fs: &mut VfatFS, path: &str) -> VfatTimestamp {
+    fs.metadata(path).unwrap().accessed()
+}
+
+#[test]
+fn reads_never_update_the_access_date_by_default() {
+    let volume = Volume::new();
+    let mut fs = volume.mount(AccessTimePolicy::default());
+    fs.write("/file.txt", b"content").unwrap();
+    assert_eq!(accessed(&mut fs, "/file.txt"), date(NOON));
+
+    volume.clock.set(NOON + 10 * DAY);
+    let before = volume.image.lock().unwrap().clone();
+    fs.read("/file.txt").unwrap();
+    fs.open("/file.txt")
+        .unwrap()
+        .read_at(0, &mut [0; 4])
+        .unwrap();
+    assert_eq!(accessed(&mut fs, "/file.txt"), date(NOON));
+    assert!(*volume.image.lock().unwrap() == before, "a read wrote");
+}
+
+#[test]
+fn strict_updates_on_every_new_day() {
+    let volume = Volume::new();
+    let mut fs = volume.mount(AccessTimePolicy::Strict);
+    fs.write("/file.txt", b"content").unwrap();
+
+    volume.clock.set(NOON + DAY);
+    let mut file = fs.open("/file.txt").unwrap();
+    file.read(&mut [0; 4]).unwrap();
+    assert_eq!(file.metadata().accessed(), date(NOON + DAY));
+
+    volume.clock.set(NOON + 2 * DAY);
+    fs.open("/file.txt")
+        .unwrap()
+        .read_at(0, &mut [0; 4])
+        .unwrap();
+    drop((fs, file));
+    let mut fs = volume.mount(AccessTimePolicy::Never);
+    assert_eq!(accessed(&mut fs, "/file.txt"), date(NOON + 2 * DAY));
+
+    let image = volume.image.lock().unwrap().clone();
+    let fatfs = fatfs::FileSystem::new(Cursor::new(image), fatfs::FsOptions::new()).unwrap();
+    let entry = fatfs
+        .root_dir()
+        .iter()
+        .map(Result::unwrap)
+        .find(|entry| entry.file_name() == "file.txt")
+        .unwrap();
+    let accessed = entry.accessed();
+    assert_eq!((accessed.year, accessed.month, accessed.day), (2024, 3, 12));
+}
+
+#[test]
+fn relative_updates_after_a_change_or_a_day() {
+    let volume = Volume::new();
+    let mut fs = volume.mount(AccessTimePolicy::Relative);
+    fs.write("/file.txt", b"content").unwrap();
+    let mut file = fs.open("/file.txt").unwrap();
+    file.set_times(FileTimes {
+        accessed: Some(VfatTimestamp::from(NOON - 5 * DAY)),
+        modified: Some(VfatTimestamp::from(NOON - 3 * DAY)),
+        ..FileTimes::default()
+    })
+    .unwrap();
+
+    // Accessed before the last change.
+    fs.read("/file.txt").unwrap();
+    assert_eq!(accessed(&mut fs, "/file.txt"), date(NOON));
+
+    // Accessed after the last change, and at most a day ago.
+    volume.clock.set(NOON + DAY);
+    fs.read("/file.txt").unwrap();
+    assert_eq!(accessed(&mut fs, "/file.txt"), date(NOON));
+
+    volume.clock.set(NOON + 2 * DAY);
+    fs.read("/file.txt").unwrap();
+    assert_eq!(accessed(&mut fs, "/file.txt"), date(NOON + 2 * DAY));
+}
+
+#[test]
+fn times_round_trip_with_creation_hundredths() {
+    let volume = Volume::new();
+    // An odd second: the second FAT's 2 second resolution loses is kept in
+    // the hundredths.
+    volume.clock.set(NOON + 1);
+    let mut fs = volume.mount(AccessTimePolicy::Never);
+    let file = fs.create("/file.txt").unwrap();
+    assert_eq!(file.metadata().created_unix_millis(), (NOON + 1) * 1000);
+    drop(file);
+
+    let created_millis = (NOON - 7 * DAY) * 1000 + 1_370;
+    let (created, created_centiseconds) = VfatTimestamp::from_unix_millis(created_millis);
+    fs.open("/file.txt")
+        .unwrap()
+        .set_times(FileTimes {
+            created: Some(created),
+            created_centiseconds,
+            accessed: Some(VfatTimestamp::from(NOON - 3 * DAY + 4000)),
+            ..FileTimes::default()
+        })
+        .unwrap();
+    drop(fs);
+
+    let mut fs = volume.mount(AccessTimePolicy::Never);
+    let metadata = fs.metadata("/file.txt").unwrap();
+    assert_eq!(metadata.created(), created);
+    assert_eq!(metadata.creation_centiseconds(), 137);
+    assert_eq!(metadata.created_unix_millis(), created_millis);
+    assert_eq!(metadata.accessed(), date(NOON - 3 * DAY));
+    assert_eq!(metadata.modified(), VfatTimestamp::from(NOON + 1));
+
+    let image = volume.image.lock().unwrap().clone();
+    let fatfs = fatfs::FileSystem::new(Cursor::new(image), fatfs::FsOptions::new()).unwrap();
+    let entry = fatfs
+        .root_dir()
+        .iter()
+        .map(Result::unwrap)
+        .find(|entry| entry.file_name() == "file.txt")
+        .unwrap();
+    let time = entry.created().time;
+    assert_eq!(
+        (time.hour, time.min, time.sec, time.millis),
+        (12, 0, 1, 370)
+    );
+}
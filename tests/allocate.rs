@@ -0,0 +1,225 @@
+//! Hermetic tests for preallocation ([`vfat_rs::File::allocate`]) and
+//! contiguous file creation ([`vfat_rs::Directory::create_file_contiguous`]).
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{AllocateMode, BlockDevice, FileUsage, PathBuf, SectorId, VfatFS, VfatRsError};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(size_mib: usize) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; size_mib * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    (VfatFS::new(device, 0).expect("open VfatFS"), image)
+}
+
+fn usage_of(fs: &mut VfatFS, path: &str) -> FileUsage {
+    let path = PathBuf::from(path);
+    let mut found = None;
+    fs.analyze(|usage| {
+        if usage.path == path {
+            found = Some(usage.clone());
+        }
+    })
+    .unwrap();
+    found.unwrap_or_else(|| panic!("{} not reported", path.display()))
+}
+
+/// Leave the free space fragmented into single-cluster holes at the start of
+/// the data area, followed by one large free run.
+fn fragment_free_space(fs: &mut VfatFS, holes: usize) {
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut root = fs.get_root().unwrap();
+    for i in 0..holes * 2 {
+        root.create_file(format!("filler{i}"))
+            .unwrap()
+            .write(&vec![0xEE; cluster_size])
+            .unwrap();
+    }
+    for i in (0..holes * 2).step_by(2) {
+        root.delete(format!("filler{i}")).unwrap();
+    }
+}
+
+const KEEP_SIZE: AllocateMode = AllocateMode {
+    contiguous: false,
+    keep_size: true,
+};
+
+#[test]
+fn keep_size_reserves_clusters_without_growing_the_file() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster();
+    let free_before = fs.count_free_clusters().unwrap();
+
+    let mut file = fs
+        .get_root()
+        .unwrap()
+        .create_file("rec.bin".to_string())
+        .unwrap();
+    file.allocate(cluster_size * 10, KEEP_SIZE).unwrap();
+    assert_eq!(file.metadata().size(), 0);
+    assert_eq!(fs.count_free_clusters().unwrap(), free_before - 10);
+
+    // Writing into the reservation must not take any more clusters.
+    file.write(&vec![7u8; cluster_size as usize * 10]).unwrap();
+    assert_eq!(fs.count_free_clusters().unwrap(), free_before - 10);
+    assert_eq!(usage_of(&mut fs, "/rec.bin").clusters, 10);
+
+    // Allocation never shrinks.
+    file.allocate(cluster_size, KEEP_SIZE).unwrap();
+    assert_eq!(usage_of(&mut fs, "/rec.bin").clusters, 10);
+}
+
+#[test]
+fn allocate_grows_the_file_with_zeros() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let mut file = fs
+        .get_root()
+        .unwrap()
+        .create_file("grow.bin".to_string())
+        .unwrap();
+    file.write(b"hello").unwrap();
+    file.allocate(10_000, AllocateMode::default()).unwrap();
+    assert_eq!(file.metadata().size(), 10_000);
+
+    let mut file = fs
+        .get_from_absolute_path("/grow.bin".into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    let mut buf = vec![0xFF; 10_000];
+    let mut read = 0;
+    while read < buf.len() {
+        read += file.read(&mut buf[read..]).unwrap();
+    }
+    assert_eq!(&buf[..5], b"hello");
+    assert!(buf[5..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn contiguous_file_skips_small_holes() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster();
+    fragment_free_space(&mut fs, 4);
+
+    let mut root = fs.get_root().unwrap();
+    let file = root
+        .create_file_contiguous("boot.img".to_string(), cluster_size * 3 + 1)
+        .unwrap();
+    assert_eq!(file.metadata().size(), cluster_size as usize * 3 + 1);
+    let usage = usage_of(&mut fs, "/boot.img");
+    assert_eq!((usage.clusters, usage.fragments), (4, 1));
+}
+
+#[test]
+fn contiguous_allocation_extends_in_place() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster();
+    let contiguous = AllocateMode {
+        contiguous: true,
+        keep_size: true,
+    };
+    let mut file = fs
+        .get_root()
+        .unwrap()
+        .create_file_contiguous("video.raw".to_string(), cluster_size * 2)
+        .unwrap();
+    file.allocate(cluster_size * 6, contiguous).unwrap();
+    let usage = usage_of(&mut fs, "/video.raw");
+    assert_eq!((usage.clusters, usage.fragments), (6, 1));
+
+    // Something now sits right after the file: it can't grow in place.
+    fs.get_root()
+        .unwrap()
+        .create_file("blocker".to_string())
+        .unwrap()
+        .write(b"x")
+        .unwrap();
+    let free_before = fs.count_free_clusters().unwrap();
+    let err = file.allocate(cluster_size * 7, contiguous).unwrap_err();
+    assert!(matches!(
+        err,
+        VfatRsError::ContiguousSpaceNotFound { clusters: 7 }
+    ));
+    assert_eq!(fs.count_free_clusters().unwrap(), free_before);
+}
+
+#[test]
+fn failed_contiguous_create_leaves_nothing_behind() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster();
+    let largest = fs.free_space_stats().unwrap().largest_free_run;
+    let free_before = fs.count_free_clusters().unwrap();
+
+    let mut root = fs.get_root().unwrap();
+    let err = root
+        .create_file_contiguous("huge.bin".to_string(), cluster_size * (largest + 1))
+        .unwrap_err();
+    assert!(matches!(err, VfatRsError::ContiguousSpaceNotFound { .. }));
+    assert!(!root.contains("huge.bin").unwrap());
+    assert_eq!(fs.count_free_clusters().unwrap(), free_before);
+}
+
+#[test]
+fn failed_reservation_is_rolled_back() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster();
+    let mut file = fs
+        .get_root()
+        .unwrap()
+        .create_file("big.bin".to_string())
+        .unwrap();
+    file.write(b"data").unwrap();
+    let free_before = fs.count_free_clusters().unwrap();
+
+    let err = file
+        .allocate(cluster_size * (free_before + 2), KEEP_SIZE)
+        .unwrap_err();
+    assert!(matches!(err, VfatRsError::FreeClusterNotFound));
+    assert_eq!(fs.count_free_clusters().unwrap(), free_before);
+    assert_eq!(usage_of(&mut fs, "/big.bin").clusters, 1);
+}
@@ -0,0 +1,231 @@
+//! Hermetic tests for reading and setting FAT attributes, the archive bit
+//! and the protection of read-only entries.
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::io::ErrorKind;
+use vfat_rs::{
+    Attributes, BlockDevice, MountOptions, OpenOptions, SectorId, TimeManagerNoop, VfatFS,
+    attribute,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(options: MountOptions) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    let fs =
+        VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS");
+    (fs, image)
+}
+
+fn remount(image: &Arc<Mutex<Vec<u8>>>) -> VfatFS {
+    let device = MemoryBlockDevice(image.clone());
+    VfatFS::new(device, 0).expect("remount VfatFS")
+}
+
+fn kind<T: std::fmt::Debug>(result: vfat_rs::Result<T>) -> ErrorKind {
+    result.unwrap_err().kind()
+}
+
+fn attributes(fs: &mut VfatFS, path: &str) -> Attributes {
+    fs.metadata(path).unwrap().attributes()
+}
+
+fn set_attributes(fs: &mut VfatFS, path: &str, bits: u8) {
+    fs.open_with(path, OpenOptions::new().read(true))
+        .unwrap()
+        .set_attributes(Attributes(bits))
+        .unwrap();
+}
+
+#[test]
+fn attributes_persist_and_other_implementations_see_them() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    fs.write("/system.bin", b"firmware").unwrap();
+    fs.create_dir("/config").unwrap();
+    assert!(attributes(&mut fs, "/system.bin").is_archive());
+
+    set_attributes(
+        &mut fs,
+        "/system.bin",
+        attribute::HIDDEN | attribute::SYSTEM | attribute::ARCHIVE,
+    );
+    let mut config = fs
+        .get_from_absolute_path("/config".into())
+        .unwrap()
+        .into_directory()
+        .unwrap();
+    // The directory bit can't be cleared.
+    config
+        .set_attributes(Attributes(attribute::HIDDEN))
+        .unwrap();
+    drop(config);
+    drop(fs);
+
+    let mut fs = remount(&image);
+    let file = attributes(&mut fs, "/system.bin");
+    assert!(file.is_hidden() && file.is_system() && file.is_archive());
+    assert!(!file.is_read_only());
+    let directory = attributes(&mut fs, "/config");
+    assert!(directory.is_hidden() && directory.is_directory());
+
+    let image = image.lock().unwrap().clone();
+    let fatfs = fatfs::FileSystem::new(Cursor::new(image), fatfs::FsOptions::new()).unwrap();
+    for entry in fatfs.root_dir().iter().map(Result::unwrap) {
+        let expected = match entry.file_name().as_str() {
+            "system.bin" => {
+                fatfs::FileAttributes::HIDDEN
+                    | fatfs::FileAttributes::SYSTEM
+                    | fatfs::FileAttributes::ARCHIVE
+            }
+            "config" => fatfs::FileAttributes::HIDDEN | fatfs::FileAttributes::DIRECTORY,
+            _ => continue,
+        };
+        assert_eq!(entry.attributes(), expected, "{}", entry.file_name());
+    }
+}
+
+#[test]
+fn changing_the_content_sets_the_archive_bit() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    fs.write("/backup.txt", b"first").unwrap();
+    set_attributes(&mut fs, "/backup.txt", 0);
+    assert!(!attributes(&mut fs, "/backup.txt").is_archive());
+
+    // Reading doesn't count as a change.
+    fs.read("/backup.txt").unwrap();
+    assert!(!attributes(&mut fs, "/backup.txt").is_archive());
+
+    // Overwriting in place leaves the size, and nothing else, unchanged.
+    let mut file = fs
+        .open_with("/backup.txt", OpenOptions::new().read(true).write(true))
+        .unwrap();
+    file.write_at(0, b"F").unwrap();
+    assert!(attributes(&mut fs, "/backup.txt").is_archive());
+    drop(file);
+
+    set_attributes(&mut fs, "/backup.txt", 0);
+    fs.open_with("/backup.txt", OpenOptions::new().read(true).write(true))
+        .unwrap()
+        .truncate(1)
+        .unwrap();
+    drop(fs);
+    assert!(attributes(&mut remount(&image), "/backup.txt").is_archive());
+}
+
+#[test]
+fn read_only_entries_refuse_changes() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.write("/locked.txt", b"keep").unwrap();
+    fs.create_dir("/locked").unwrap();
+    set_attributes(&mut fs, "/locked.txt", attribute::READ_ONLY);
+    fs.get_from_absolute_path("/locked".into())
+        .unwrap()
+        .into_directory()
+        .unwrap()
+        .set_attributes(Attributes(attribute::READ_ONLY))
+        .unwrap();
+
+    let denied = ErrorKind::PermissionDenied;
+    assert_eq!(kind(fs.write("/locked.txt", b"x")), denied);
+    assert_eq!(
+        kind(fs.open_with("/locked.txt", OpenOptions::new().append(true))),
+        denied
+    );
+    assert_eq!(kind(fs.create("/locked.txt")), denied);
+    assert_eq!(kind(fs.remove_file("/locked.txt")), denied);
+    assert_eq!(kind(fs.rename("/locked.txt", "/moved.txt")), denied);
+    fs.write("/other.txt", b"other").unwrap();
+    assert_eq!(kind(fs.rename("/other.txt", "/locked.txt")), denied);
+    assert_eq!(kind(fs.remove_dir("/locked")), denied);
+    assert_eq!(fs.read("/locked.txt").unwrap(), b"keep");
+
+    // Entries can still be created in a read-only directory.
+    fs.write("/locked/inner.txt", b"inner").unwrap();
+    fs.remove_file("/locked/inner.txt").unwrap();
+
+    // Handles opened before the attribute was set can't write either.
+    let mut file = fs
+        .open_with("/other.txt", OpenOptions::new().read(true).write(true))
+        .unwrap();
+    set_attributes(&mut fs, "/other.txt", attribute::READ_ONLY);
+    assert_eq!(kind(file.write(b"x")), denied);
+    drop(file);
+
+    set_attributes(&mut fs, "/locked.txt", attribute::ARCHIVE);
+    fs.write("/locked.txt", b"changed").unwrap();
+    fs.remove_file("/locked.txt").unwrap();
+}
+
+#[test]
+fn read_only_entries_can_be_forced() {
+    let options = MountOptions {
+        ignore_read_only: true,
+        ..MountOptions::default()
+    };
+    let (mut fs, _) = fresh_fat32_fs(options);
+    fs.write("/locked.txt", b"keep").unwrap();
+    set_attributes(&mut fs, "/locked.txt", attribute::READ_ONLY);
+
+    fs.write("/locked.txt", b"changed").unwrap();
+    assert!(attributes(&mut fs, "/locked.txt").is_read_only());
+    fs.rename("/locked.txt", "/moved.txt").unwrap();
+    fs.remove_file("/moved.txt").unwrap();
+}
+
+#[test]
+fn the_root_has_no_attributes() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    assert!(root.metadata.attributes().is_directory());
+    assert_eq!(
+        root.set_attributes(Attributes(attribute::HIDDEN))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::PermissionDenied
+    );
+}
@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 mod array_blockdev;
 mod file_blockdev;
 
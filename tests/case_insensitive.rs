@@ -0,0 +1,274 @@
+//! Hermetic tests for case-insensitive, case-preserving name lookups, and for
+//! exact matching with [`vfat_rs::MountOptions::case_sensitive`].
+
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{
+    BlockDevice, MountOptions, SectorId, TimeManagerNoop, VfatFS, VfatMetadataTrait, VfatRsError,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(options: MountOptions) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    let fs =
+        VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS");
+    (fs, image)
+}
+
+fn strict() -> MountOptions {
+    MountOptions {
+        case_sensitive: true,
+        ..MountOptions::default()
+    }
+}
+
+fn root_names(fs: &mut VfatFS) -> Vec<String> {
+    let mut names: Vec<String> = fs
+        .get_root()
+        .unwrap()
+        .contents()
+        .unwrap()
+        .iter()
+        .map(|entry| entry.name().to_string())
+        // The volume label entry.
+        .filter(|name| name != "VFATRSTE.ST")
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn lookup_ignores_case_and_returns_the_stored_name() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("readme.txt".into()).unwrap();
+    root.create_directory("Docs".into()).unwrap();
+
+    let entry = fs.get_from_absolute_path("/README.TXT".into()).unwrap();
+    assert_eq!(entry.name(), "readme.txt");
+    let dir = fs.get_from_absolute_path("/dOCS/".into()).unwrap();
+    assert_eq!(dir.name(), "Docs");
+    assert!(root.contains("ReadMe.Txt").unwrap());
+}
+
+#[test]
+fn lookup_folds_non_ascii_letters() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("Ärger.txt".into()).unwrap();
+    root.create_file("σοφία".into()).unwrap();
+
+    let entry = fs.get_from_absolute_path("/äRGER.TXT".into()).unwrap();
+    assert_eq!(entry.name(), "Ärger.txt");
+    assert!(root.contains("ΣΟΦΊΑ").unwrap());
+}
+
+/// Characters outside the BMP take two UTF-16 units, which may be split
+/// between two name fields of a slot or between two slots.
+#[test]
+fn surrogate_pairs_across_lfn_fields_and_slots_round_trip() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    // Units 12/13 straddle the first two slots, units 4/5 the first two fields.
+    let names = ["abcdefghijkl\u{1F600}.txt", "abcd\u{1F600}.txt"];
+    for name in names {
+        root.create_file(name.to_string()).unwrap();
+    }
+
+    assert_eq!(root_names(&mut fs), names);
+    for name in names {
+        let entry = fs
+            .get_from_absolute_path(format!("/{name}").into())
+            .unwrap();
+        assert_eq!(entry.name(), name);
+    }
+    let image = image.lock().unwrap().clone();
+    let fatfs = fatfs::FileSystem::new(Cursor::new(image), fatfs::FsOptions::new()).unwrap();
+    for name in names {
+        fatfs.root_dir().open_file(name).unwrap();
+    }
+
+    root.delete(names[0].to_string()).unwrap();
+    let deleted = root.deleted_entries().unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].name(), names[0]);
+}
+
+#[test]
+fn creating_a_case_variant_is_a_collision() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("Foo".into()).unwrap();
+
+    let err = root.create_file("foo".into()).unwrap_err();
+    assert!(
+        matches!(err, VfatRsError::NameAlreadyInUse { .. }),
+        "{err:?}"
+    );
+    let err = root.create_directory("FOO".into()).unwrap_err();
+    assert!(
+        matches!(err, VfatRsError::NameAlreadyInUse { .. }),
+        "{err:?}"
+    );
+    assert_eq!(root_names(&mut fs), ["Foo"]);
+}
+
+#[test]
+fn rename_can_change_only_the_case() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("foo".into())
+        .unwrap()
+        .write(b"content")
+        .unwrap();
+
+    root.rename("foo".into(), "/FOO".into()).unwrap();
+
+    assert_eq!(root_names(&mut fs), ["FOO"]);
+    let mut file = fs
+        .get_from_absolute_path("/foo".into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    let mut buf = [0u8; 7];
+    file.read(&mut buf).unwrap();
+    assert_eq!(&buf, b"content");
+}
+
+#[test]
+fn rename_replaces_a_case_variant() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("a".into()).unwrap();
+    root.create_file("Target".into()).unwrap();
+    root.create_directory("dir".into()).unwrap();
+    let mut dir = fs
+        .get_from_absolute_path("/dir/".into())
+        .unwrap()
+        .into_directory()
+        .unwrap();
+    dir.create_file("Moved".into()).unwrap();
+
+    // Same directory.
+    root.rename("a".into(), "/TARGET".into()).unwrap();
+    assert_eq!(root_names(&mut fs), ["TARGET", "dir"]);
+
+    // Across directories.
+    dir.rename("Moved".into(), "/target".into()).unwrap();
+    assert_eq!(root_names(&mut fs), ["dir", "target"]);
+}
+
+#[test]
+fn moving_a_directory_below_a_case_variant_of_itself_is_refused() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.create_dir_all("/Outer/inner").unwrap();
+
+    let err = fs.rename("/Outer", "/outer/inside").unwrap_err();
+    assert!(matches!(err, VfatRsError::CircularMove { .. }), "{err:?}");
+    let err = fs.rename("/Outer", "/OUTER/INNER/inside").unwrap_err();
+    assert!(matches!(err, VfatRsError::CircularMove { .. }), "{err:?}");
+
+    assert_eq!(root_names(&mut fs), ["Outer"]);
+    assert!(
+        fs.metadata("/Outer/inner")
+            .unwrap()
+            .attributes()
+            .is_directory()
+    );
+}
+
+#[test]
+fn strict_mode_matches_names_exactly() {
+    let (mut fs, _) = fresh_fat32_fs(strict());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("Foo".into()).unwrap();
+    root.create_file("foo".into()).unwrap();
+
+    assert_eq!(root_names(&mut fs), ["Foo", "foo"]);
+    assert!(!root.contains("FOO").unwrap());
+    let err = fs.get_from_absolute_path("/FOO".into()).unwrap_err();
+    assert!(matches!(err, VfatRsError::EntryNotFound { .. }), "{err:?}");
+    assert_eq!(
+        fs.get_from_absolute_path("/foo".into()).unwrap().name(),
+        "foo"
+    );
+}
+
+#[test]
+fn stored_case_is_visible_to_other_implementations() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("MixedCase.Txt".into()).unwrap();
+    root.rename("MixedCase.Txt".into(), "/mixedcase.TXT".into())
+        .unwrap();
+    root.create_file("Ärger.txt".into()).unwrap();
+
+    let image = image.lock().unwrap().clone();
+    let fatfs = fatfs::FileSystem::new(Cursor::new(image), fatfs::FsOptions::new()).unwrap();
+    let mut names: Vec<String> = fatfs
+        .root_dir()
+        .iter()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| {
+            !entry
+                .attributes()
+                .contains(fatfs::FileAttributes::VOLUME_ID)
+        })
+        .map(|entry| entry.file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["mixedcase.TXT", "Ärger.txt"]);
+    let mut content = String::new();
+    fatfs
+        .root_dir()
+        .open_file("MIXEDCASE.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert!(content.is_empty());
+}
@@ -0,0 +1,267 @@
+//! Hermetic stress tests for the per-file, per-directory and allocator
+//! locks: threads working on different files and directories run at the
+//! same time, never share a cluster, and never deadlock with the operations
+//! locking the whole volume.
+
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use vfat_rs::{BlockDevice, File, OpenOptions, SectorId, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+const THREADS: usize = 6;
+/// Generous bound on how long a stress run may take: past it, the threads
+/// are considered deadlocked.
+const DEADLOCK_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs() -> VfatFS {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let device = MemoryBlockDevice(Arc::new(Mutex::new(image)));
+    VfatFS::new(device, 0).expect("open VfatFS")
+}
+
+/// Run `body` on `THREADS` threads, each with its own handle of `fs` and its
+/// index, and fail instead of hanging if they don't all finish in time.
+fn run_threads<F>(fs: &VfatFS, body: F)
+where
+    F: Fn(VfatFS, usize) + Send + Sync + 'static,
+{
+    let body = Arc::new(body);
+    let (done, finished) = mpsc::channel();
+    let handles: Vec<_> = (0..THREADS)
+        .map(|index| {
+            let (fs, body, done) = (fs.clone(), body.clone(), done.clone());
+            thread::spawn(move || {
+                body(fs, index);
+                done.send(()).unwrap();
+            })
+        })
+        .collect();
+    for _ in 0..THREADS {
+        finished
+            .recv_timeout(DEADLOCK_TIMEOUT)
+            .expect("a thread panicked or deadlocked");
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+fn pattern(index: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + index * 31) as u8).collect()
+}
+
+fn read_all(fs: &mut VfatFS, path: &str) -> Vec<u8> {
+    fs.read(path).unwrap()
+}
+
+/// The clusters of `file`, by starting sector of each cluster.
+fn clusters_of(fs: &VfatFS, file: &File) -> Vec<u32> {
+    let sectors_per_cluster = fs.bytes_per_cluster() / SECTOR_SIZE as u32;
+    let mut clusters = Vec::new();
+    for extent in file.extents().unwrap() {
+        let count = extent.length / fs.bytes_per_cluster() as u64;
+        for i in 0..count as u32 {
+            clusters.push(extent.start_sector.0 + i * sectors_per_cluster);
+        }
+    }
+    clusters
+}
+
+#[test]
+fn parallel_writers_never_share_a_cluster() {
+    let mut fs = fresh_fat32_fs();
+    let free_before = fs.count_free_clusters().unwrap();
+    run_threads(&fs, |mut fs, index| {
+        let mut file = fs.create(format!("/writer{index}.bin")).unwrap();
+        let data = pattern(index, 40 * 1024);
+        // Mix sequential writes and positional writes past the end.
+        for chunk in data[..20 * 1024].chunks(700) {
+            file.write(chunk).unwrap();
+        }
+        for (i, chunk) in data[20 * 1024..].chunks(1500).enumerate() {
+            file.write_at((20 * 1024 + i * 1500) as u64, chunk).unwrap();
+        }
+    });
+
+    let bytes_per_cluster = fs.bytes_per_cluster() as usize;
+    let mut seen = HashSet::new();
+    let mut used = 0;
+    for index in 0..THREADS {
+        let path = format!("/writer{index}.bin");
+        assert_eq!(read_all(&mut fs, &path), pattern(index, 40 * 1024));
+        let file = fs.open(path).unwrap();
+        let clusters = clusters_of(&fs, &file);
+        assert_eq!(clusters.len(), (40 * 1024usize).div_ceil(bytes_per_cluster));
+        for cluster in clusters {
+            assert!(seen.insert(cluster), "cluster allocated twice");
+            used += 1;
+        }
+    }
+    // The root directory may have grown by a few clusters.
+    let free_after = fs.count_free_clusters().unwrap();
+    assert!(free_before - free_after >= used);
+    assert!(free_before - free_after <= used + 2);
+}
+
+#[test]
+fn parallel_creates_and_deletes_in_separate_directories() {
+    let mut fs = fresh_fat32_fs();
+    for index in 0..THREADS {
+        fs.create_dir(format!("/dir{index}")).unwrap();
+    }
+    run_threads(&fs, |mut fs, index| {
+        let mut directory = fs
+            .get_from_absolute_path(format!("/dir{index}").into())
+            .unwrap()
+            .into_directory()
+            .unwrap();
+        for i in 0..40 {
+            let mut file = directory
+                .create_file(format!("a rather long file name {i}.txt"))
+                .unwrap();
+            file.write(&pattern(i, 600)).unwrap();
+        }
+        for i in (0..40).step_by(2) {
+            directory
+                .delete(format!("a rather long file name {i}.txt"))
+                .unwrap();
+        }
+        // Every thread also works in a directory shared by all of them.
+        fs.write(format!("/shared {index}.txt"), pattern(index, 100))
+            .unwrap();
+        assert!(!directory.contents().unwrap().is_empty());
+    });
+
+    for index in 0..THREADS {
+        let mut names: Vec<String> = fs
+            .read_dir(format!("/dir{index}"))
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.metadata.name().to_string())
+            .collect();
+        names.sort();
+        let mut expected: Vec<String> = (1..40)
+            .step_by(2)
+            .map(|i| format!("a rather long file name {i}.txt"))
+            .collect();
+        expected.sort();
+        assert_eq!(names, expected);
+        for i in (1..40).step_by(2) {
+            let path = format!("/dir{index}/a rather long file name {i}.txt");
+            assert_eq!(read_all(&mut fs, &path), pattern(i, 600));
+        }
+        let path = format!("/shared {index}.txt");
+        assert_eq!(read_all(&mut fs, &path), pattern(index, 100));
+    }
+}
+
+#[test]
+fn mixed_operations_do_not_deadlock() {
+    let mut fs = fresh_fat32_fs();
+    fs.create_dir("/work").unwrap();
+    fs.write("/work/common.bin", pattern(0, 8 * 1024)).unwrap();
+    run_threads(&fs, |mut fs, index| {
+        for round in 0..15 {
+            match (index + round) % 6 {
+                // Handles of the same file, writing and reading.
+                0 => {
+                    let mut file = fs
+                        .open_with(
+                            "/work/common.bin",
+                            OpenOptions::new().read(true).write(true),
+                        )
+                        .unwrap();
+                    file.write_at((round * 100) as u64, &pattern(round, 300))
+                        .unwrap();
+                    let mut buf = [0u8; 512];
+                    file.read_at(0, &mut buf).unwrap();
+                }
+                1 => {
+                    let file = fs.open("/work/common.bin").unwrap();
+                    let mut buf = vec![0u8; 2048];
+                    file.read_at(1024, &mut buf).unwrap();
+                    file.extents().unwrap();
+                }
+                // Operations locking the whole volume.
+                2 => {
+                    let from = format!("/work/renamed {index}.txt");
+                    let to = format!("/moved {index}.txt");
+                    fs.write(&from, pattern(index, 50)).unwrap();
+                    fs.rename(&from, &to).unwrap();
+                    fs.remove_file(&to).unwrap();
+                }
+                3 => {
+                    fs.analyze(|_| {}).unwrap();
+                    fs.walk("/").count();
+                }
+                // Directory operations.
+                4 => {
+                    let path = format!("/work/dir {index} {round}");
+                    fs.create_dir(&path).unwrap();
+                    fs.create(format!("{path}/file.txt")).unwrap();
+                    fs.remove_file(format!("{path}/file.txt")).unwrap();
+                    fs.remove_dir(&path).unwrap();
+                }
+                _ => {
+                    let path = format!("/work/temp {index}.txt");
+                    let mut file = fs.create(&path).unwrap();
+                    file.write(&pattern(round, 3000)).unwrap();
+                    // Deleted while open: the handle keeps the clusters.
+                    fs.remove_file(&path).unwrap();
+                    file.write(&pattern(round, 600)).unwrap();
+                    fs.read_dir("/work").unwrap();
+                }
+            }
+        }
+    });
+
+    let entries = fs.read_dir("/work").unwrap();
+    assert_eq!(entries.len(), 1, "{entries:?}");
+    assert_eq!(read_all(&mut fs, "/work/common.bin").len(), 8 * 1024);
+}
@@ -0,0 +1,232 @@
+//! Hermetic tests for the online defragmenter ([`VfatFS::defragment`] and
+//! [`VfatFS::defragment_file`]).
+//!
+//! Fragmented files are produced by interleaving cluster-sized writes to two
+//! files and then deleting one of them, which leaves holes in the other file's
+//! chain. The image is re-opened with the `fatfs` crate afterwards to make sure
+//! the result is still a consistent FAT32 volume.
+
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, DefragBudget, DefragOptions, SectorId, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(size_mib: usize) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; size_mib * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    (VfatFS::new(device, 0).expect("open VfatFS"), image)
+}
+
+/// Deterministic per-file content, different for every cluster.
+fn pattern(seed: u8, cluster: usize, cluster_size: usize) -> Vec<u8> {
+    (0..cluster_size)
+        .map(|i| {
+            seed.wrapping_add(cluster as u8)
+                .wrapping_add((i % 251) as u8)
+        })
+        .collect()
+}
+
+/// Write `clusters` clusters to each of `names` in round-robin order, so every
+/// file ends up with one fragment per cluster.
+fn write_interleaved(fs: &mut VfatFS, names: &[&str], clusters: usize) {
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut root = fs.get_root().unwrap();
+    let mut files: Vec<_> = names
+        .iter()
+        .map(|name| root.create_file(name.to_string()).unwrap())
+        .collect();
+    for cluster in 0..clusters {
+        for (seed, file) in files.iter_mut().enumerate() {
+            file.write(&pattern(seed as u8, cluster, cluster_size))
+                .unwrap();
+        }
+    }
+}
+
+fn read_file(fs: &mut VfatFS, path: &str) -> Vec<u8> {
+    let mut file = fs
+        .get_from_absolute_path(path.into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    let mut out = vec![0u8; file.metadata().size()];
+    let mut read = 0;
+    while read < out.len() {
+        let n = file.read(&mut out[read..]).unwrap();
+        assert!(n > 0, "unexpected EOF reading {path}");
+        read += n;
+    }
+    out
+}
+
+fn expected(seed: u8, clusters: usize, cluster_size: usize) -> Vec<u8> {
+    (0..clusters)
+        .flat_map(|c| pattern(seed, c, cluster_size))
+        .collect()
+}
+
+/// The image must still be readable by an independent FAT implementation.
+fn fatfs_read(image: &Arc<Mutex<Vec<u8>>>, name: &str) -> Vec<u8> {
+    let bytes = image.lock().unwrap().clone();
+    let fs = fatfs::FileSystem::new(Cursor::new(bytes), fatfs::FsOptions::new()).unwrap();
+    let mut out = Vec::new();
+    fs.root_dir()
+        .open_file(name)
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap();
+    out
+}
+
+#[test]
+fn defragment_file_makes_a_fragmented_file_contiguous() {
+    let (mut fs, image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    write_interleaved(&mut fs, &["a.bin", "b.bin"], 8);
+    fs.get_root().unwrap().delete("b.bin".to_string()).unwrap();
+
+    assert!(fs.defragment_file("/a.bin".into()).unwrap());
+    // Already contiguous now: a second pass has nothing to do.
+    assert!(!fs.defragment_file("/a.bin".into()).unwrap());
+
+    let want = expected(0, 8, cluster_size);
+    assert_eq!(read_file(&mut fs, "/a.bin"), want);
+    drop(fs);
+    assert_eq!(fatfs_read(&image, "a.bin"), want);
+}
+
+#[test]
+fn defragment_does_not_leak_clusters() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    write_interleaved(&mut fs, &["a.bin", "b.bin", "c.bin"], 6);
+    fs.get_root().unwrap().delete("b.bin".to_string()).unwrap();
+    let free_before = fs.count_free_clusters().unwrap();
+
+    let report = fs.defragment(DefragOptions::default()).unwrap();
+    assert!(report.complete);
+    assert_eq!(report.files_relocated, 2);
+    assert_eq!(report.clusters_moved, 12);
+    assert_eq!(fs.count_free_clusters().unwrap(), free_before);
+
+    let again = fs.defragment(DefragOptions::default()).unwrap();
+    assert!(again.complete);
+    assert_eq!(again.files_relocated, 0);
+}
+
+#[test]
+fn defragment_respects_the_cluster_budget() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    write_interleaved(&mut fs, &["a.bin", "b.bin", "c.bin"], 4);
+    fs.get_root().unwrap().delete("b.bin".to_string()).unwrap();
+
+    let options = DefragOptions {
+        budget: DefragBudget {
+            max_clusters: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let first = fs.defragment(options).unwrap();
+    assert!(!first.complete);
+    assert_eq!(first.files_relocated, 1);
+
+    let second = fs.defragment(options).unwrap();
+    assert_eq!(second.files_relocated, 1);
+    let third = fs.defragment(options).unwrap();
+    assert!(third.complete);
+    assert_eq!(third.files_relocated, 0);
+
+    assert_eq!(read_file(&mut fs, "/a.bin"), expected(0, 4, cluster_size));
+    assert_eq!(read_file(&mut fs, "/c.bin"), expected(2, 4, cluster_size));
+}
+
+#[test]
+fn compaction_moves_files_toward_the_start() {
+    let (mut fs, image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut root = fs.get_root().unwrap();
+    let mut first = root.create_file("first.bin".to_string()).unwrap();
+    first.write(&vec![1u8; cluster_size * 4]).unwrap();
+    let mut nested = root.create_directory("nested".to_string()).unwrap();
+    let mut second = nested.create_file("second.bin".to_string()).unwrap();
+    second.write(&expected(7, 3, cluster_size)).unwrap();
+    // An open file keeps its clusters until it is closed.
+    drop(first);
+    root.delete("first.bin".to_string()).unwrap();
+
+    // Nothing is fragmented, so a plain pass leaves everything alone.
+    let plain = fs.defragment(DefragOptions::default()).unwrap();
+    assert_eq!(plain.files_relocated, 0);
+
+    let compact = fs
+        .defragment(DefragOptions {
+            compact: true,
+            ..Default::default()
+        })
+        .unwrap();
+    assert!(compact.complete);
+    assert_eq!(compact.files_relocated, 1);
+    assert_eq!(compact.clusters_moved, 3);
+
+    assert_eq!(
+        read_file(&mut fs, "/nested/second.bin"),
+        expected(7, 3, cluster_size)
+    );
+    drop(fs);
+    let bytes = image.lock().unwrap().clone();
+    let check = fatfs::FileSystem::new(Cursor::new(bytes), fatfs::FsOptions::new()).unwrap();
+    let mut out = Vec::new();
+    check
+        .root_dir()
+        .open_file("nested/second.bin")
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, expected(7, 3, cluster_size));
+}
@@ -0,0 +1,230 @@
+//! Hermetic tests for the directory entry lookup cache
+//! ([`vfat_rs::MountOptions::dentry_cache_capacity`]).
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, MountOptions, SectorId, TimeManagerNoop, VfatFS, VfatMetadataTrait};
+
+const SECTOR_SIZE: usize = 512;
+
+/// In-memory device counting reads, which can be told to fail every read
+/// past a given count.
+#[derive(Clone)]
+struct MemoryBlockDevice {
+    data: Arc<Mutex<Vec<u8>>>,
+    reads: Arc<AtomicUsize>,
+    fail_after: Arc<AtomicUsize>,
+}
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        if self.reads.fetch_add(1, Ordering::SeqCst) >= self.fail_after.load(Ordering::SeqCst) {
+            return Err(vfat_rs::io::ErrorKind::Other.into());
+        }
+        let data = self.data.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.data.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_image() -> MemoryBlockDevice {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    MemoryBlockDevice {
+        data: Arc::new(Mutex::new(image)),
+        reads: Arc::new(AtomicUsize::new(0)),
+        fail_after: Arc::new(AtomicUsize::new(usize::MAX)),
+    }
+}
+
+fn mount(device: &MemoryBlockDevice, dentry_cache_capacity: usize) -> VfatFS {
+    let options = MountOptions {
+        dentry_cache_capacity,
+        ..MountOptions::default()
+    };
+    VfatFS::new_with_options(device.clone(), 0, TimeManagerNoop::new(), options)
+        .expect("open VfatFS")
+}
+
+/// Resolve `path` and describe what was found, for comparisons.
+fn describe(fs: &mut VfatFS, path: &str) -> Option<(String, bool, usize)> {
+    match fs.get_from_absolute_path(path.into()) {
+        Ok(entry) => {
+            let name = entry.name().to_string();
+            let size = entry.metadata.size();
+            Some((name, entry.into_directory().is_some(), size))
+        }
+        Err(vfat_rs::VfatRsError::EntryNotFound { .. }) => None,
+        Err(err) => panic!("lookup of {path} failed: {err:?}"),
+    }
+}
+
+#[test]
+fn repeated_deep_lookups_skip_the_directory_scans() {
+    let device = fresh_image();
+    let mut fs = mount(&device, 256);
+    let mut path = String::new();
+    for depth in 0..6 {
+        let mut dir = if path.is_empty() {
+            fs.get_root().unwrap()
+        } else {
+            fs.get_from_absolute_path(path.as_str().into())
+                .unwrap()
+                .into_directory()
+                .unwrap()
+        };
+        for sibling in 0..100 {
+            dir.create_file(format!("sibling with a long name {sibling:03}"))
+                .unwrap();
+        }
+        dir.create_directory(format!("level {depth}")).unwrap();
+        path = format!("{path}/level {depth}");
+    }
+    let target = format!("{path}/");
+
+    let uncached = mount(&device, 0);
+    device.reads.store(0, Ordering::SeqCst);
+    uncached
+        .clone()
+        .get_from_absolute_path(target.as_str().into())
+        .unwrap();
+    let scanning = device.reads.load(Ordering::SeqCst);
+
+    fs.get_from_absolute_path(target.as_str().into()).unwrap();
+    device.reads.store(0, Ordering::SeqCst);
+    let entry = fs.get_from_absolute_path(target.as_str().into()).unwrap();
+    let cached = device.reads.load(Ordering::SeqCst);
+    assert_eq!(entry.name(), "level 5");
+    assert!(
+        cached * 20 < scanning,
+        "{cached} reads with the cache, {scanning} without"
+    );
+}
+
+#[test]
+fn misses_are_cached_until_the_name_is_created() {
+    let device = fresh_image();
+    let mut fs = mount(&device, 256);
+    let mut root = fs.get_root().unwrap();
+    for i in 0..100 {
+        root.create_file(format!("file {i}")).unwrap();
+    }
+    assert!(!fs.path_exists("/missing".into()).unwrap());
+    device.reads.store(0, Ordering::SeqCst);
+    assert!(!fs.path_exists("/missing".into()).unwrap());
+    assert!(device.reads.load(Ordering::SeqCst) < 5);
+
+    root.create_file("missing".to_string()).unwrap();
+    assert!(fs.path_exists("/missing".into()).unwrap());
+}
+
+#[test]
+fn stale_entries_are_never_returned() {
+    let device = fresh_image();
+    let mut fs = mount(&device, 8);
+    let mut root = fs.get_root().unwrap();
+    root.create_directory("a".to_string()).unwrap();
+    root.create_directory("b".to_string()).unwrap();
+
+    let paths = [
+        "/a/x", "/a/y", "/b/x", "/b/y", "/a/d/x", "/b/d/x", "/a/d", "/b/d",
+    ];
+    // Deterministic pseudo-random sequence of operations.
+    let mut seed = 0x2545_F491_4F6C_DD1Du64;
+    let mut next = |bound: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % bound as u64) as usize
+    };
+    for step in 0..400 {
+        let dir_name = ["a", "b"][next(2)];
+        let mut dir = fs
+            .get_from_absolute_path(format!("/{dir_name}").as_str().into())
+            .unwrap()
+            .into_directory()
+            .unwrap();
+        let name = ["x", "y", "d"][next(3)];
+        match next(4) {
+            0 if !dir.contains(name).unwrap() => {
+                if name == "d" {
+                    let mut sub = dir.create_directory(name.to_string()).unwrap();
+                    sub.create_file("x".to_string()).unwrap();
+                } else {
+                    let mut file = dir.create_file(name.to_string()).unwrap();
+                    file.write(&vec![1; step + 1]).unwrap();
+                    file.flush().unwrap();
+                }
+            }
+            1 if dir.contains(name).unwrap() => {
+                if name == "d" {
+                    let mut sub = fs
+                        .get_from_absolute_path(format!("/{dir_name}/d").as_str().into())
+                        .unwrap()
+                        .into_directory()
+                        .unwrap();
+                    sub.delete("x".to_string()).unwrap();
+                }
+                dir.delete(name.to_string()).unwrap();
+            }
+            2 if dir.contains(name).unwrap() => {
+                let other = if dir_name == "a" { "b" } else { "a" };
+                let destination = format!("/{other}/{name}");
+                if describe(&mut fs, &destination).is_none() {
+                    dir.rename(name.to_string(), destination.as_str().into())
+                        .unwrap();
+                }
+            }
+            3 if dir.contains(name).unwrap() && name != "d" => {
+                let mut file = fs
+                    .get_from_absolute_path(format!("/{dir_name}/{name}").as_str().into())
+                    .unwrap()
+                    .into_file()
+                    .unwrap();
+                file.write(&[2; 3]).unwrap();
+                file.flush().unwrap();
+            }
+            _ => {}
+        }
+
+        let mut uncached = mount(&device, 0);
+        for path in paths {
+            assert_eq!(
+                describe(&mut fs, path),
+                describe(&mut uncached, path),
+                "step {step}: {path}"
+            );
+        }
+    }
+}
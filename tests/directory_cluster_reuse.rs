@@ -88,7 +88,9 @@ fn new_directory_on_recycled_cluster_is_empty() {
     }
 
     // Free the clusters again so the next allocation wraps around and reuses
-    // them (now carrying stale 0x5A data).
+    // them (now carrying stale 0x5A data). An open file keeps its clusters
+    // until it is closed.
+    drop(filler);
     root.delete("filler.bin".to_string()).unwrap();
 
     // Create a fresh directory; it should land on a recycled, dirty cluster.
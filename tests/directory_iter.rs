@@ -0,0 +1,178 @@
+//! Hermetic tests for [`vfat_rs::Directory::iter`].
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, SectorId, VfatFS, VfatMetadataTrait};
+
+const SECTOR_SIZE: usize = 512;
+
+/// In-memory device counting reads, which can be told to fail every read
+/// past a given count.
+#[derive(Clone)]
+struct MemoryBlockDevice {
+    data: Arc<Mutex<Vec<u8>>>,
+    reads: Arc<AtomicUsize>,
+    fail_after: Arc<AtomicUsize>,
+}
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        if self.reads.fetch_add(1, Ordering::SeqCst) >= self.fail_after.load(Ordering::SeqCst) {
+            return Err(vfat_rs::io::ErrorKind::Other.into());
+        }
+        let data = self.data.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.data.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs() -> (VfatFS, MemoryBlockDevice) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let device = MemoryBlockDevice {
+        data: Arc::new(Mutex::new(image)),
+        reads: Arc::new(AtomicUsize::new(0)),
+        fail_after: Arc::new(AtomicUsize::new(usize::MAX)),
+    };
+    (VfatFS::new(device.clone(), 0).expect("open VfatFS"), device)
+}
+
+fn file_name(i: usize) -> String {
+    format!("a rather long file name number {i:04}.txt")
+}
+
+/// Creates `/big` holding `count` files with long names, spanning several
+/// clusters.
+fn populate(fs: &mut VfatFS, count: usize) {
+    let mut root = fs.get_root().unwrap();
+    let mut dir = root.create_directory("big".to_string()).unwrap();
+    for i in 0..count {
+        dir.create_file(file_name(i)).unwrap();
+    }
+}
+
+fn big_dir(fs: &mut VfatFS) -> vfat_rs::Directory {
+    fs.get_from_absolute_path("/big".into())
+        .unwrap()
+        .into_directory()
+        .unwrap()
+}
+
+#[test]
+fn iter_matches_contents() {
+    let (mut fs, _) = fresh_fat32_fs();
+    populate(&mut fs, 300);
+    let mut dir = big_dir(&mut fs);
+    dir.delete(file_name(7)).unwrap();
+
+    let iterated: Vec<String> = dir
+        .iter()
+        .map(|entry| entry.unwrap().name().to_string())
+        .collect();
+    let listed: Vec<String> = dir
+        .contents()
+        .unwrap()
+        .iter()
+        .map(|entry| entry.name().to_string())
+        .collect();
+    assert_eq!(iterated, listed);
+    assert_eq!(iterated.len(), 2 + 299);
+    assert_eq!(&iterated[..3], &[".", "..", &file_name(0)]);
+    assert!(!iterated.contains(&file_name(7)));
+
+    let via_into_iter = (&dir).into_iter().filter(|entry| entry.is_ok()).count();
+    assert_eq!(via_into_iter, iterated.len());
+}
+
+#[test]
+fn lookups_stop_at_the_first_match() {
+    let (mut fs, device) = fresh_fat32_fs();
+    populate(&mut fs, 300);
+    let dir = big_dir(&mut fs);
+
+    device.reads.store(0, Ordering::SeqCst);
+    dir.contents().unwrap();
+    let full_listing = device.reads.load(Ordering::SeqCst);
+
+    device.reads.store(0, Ordering::SeqCst);
+    assert!(dir.contains(&file_name(1)).unwrap());
+    let early_match = device.reads.load(Ordering::SeqCst);
+    assert!(
+        early_match * 10 < full_listing,
+        "{early_match} reads for an early match, {full_listing} for the listing"
+    );
+
+    device.reads.store(0, Ordering::SeqCst);
+    let first = dir.iter().next().unwrap().unwrap();
+    assert_eq!(first.name(), ".");
+    assert!(device.reads.load(Ordering::SeqCst) * 10 < full_listing);
+}
+
+#[test]
+fn read_errors_are_reported_per_item() {
+    let (mut fs, device) = fresh_fat32_fs();
+    populate(&mut fs, 300);
+    let dir = big_dir(&mut fs);
+
+    device.reads.store(0, Ordering::SeqCst);
+    device.fail_after.store(10, Ordering::SeqCst);
+    let items: Vec<_> = dir.iter().collect();
+    device.fail_after.store(usize::MAX, Ordering::SeqCst);
+
+    let last = items.last().unwrap();
+    assert!(last.is_err());
+    let ok = items.iter().filter(|item| item.is_ok()).count();
+    assert!(ok > 0);
+    assert_eq!(ok, items.len() - 1);
+    assert!(dir.contents().is_ok());
+}
+
+#[test]
+fn directory_can_change_while_iterating() {
+    let (mut fs, _) = fresh_fat32_fs();
+    populate(&mut fs, 40);
+    let dir = big_dir(&mut fs);
+    let mut other_handle = big_dir(&mut fs);
+
+    let mut seen = 0;
+    for entry in dir.iter() {
+        let entry = entry.unwrap();
+        if entry.name().starts_with("a rather") {
+            other_handle.delete(entry.name().to_string()).unwrap();
+        }
+        seen += 1;
+    }
+    assert_eq!(seen, 42);
+    assert_eq!(dir.contents().unwrap().len(), 2);
+}
@@ -0,0 +1,112 @@
+//! Hermetic tests for the paths of entries created through directory handles.
+//!
+//! A directory's path ends with a separator, and the path of an entry is its
+//! directory's path followed by its name. The handle returned by
+//! `create_directory` must follow the same rule as the ones returned by
+//! `contents`, or the entries created through it get paths like
+//! `/outerinner.txt` that can't be looked up again.
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, SectorId, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(size_mib: usize) -> VfatFS {
+    let mut image = vec![0u8; size_mib * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let device = MemoryBlockDevice(Arc::new(Mutex::new(image)));
+    VfatFS::new(device, 0).expect("open VfatFS")
+}
+
+/// A file created through the handle returned by `create_directory` is found
+/// at its directory's path, and writes through it update its entry.
+#[test]
+fn file_in_new_directory_has_well_formed_path() {
+    let mut fs = fresh_fat32_fs(34);
+    let mut root = fs.get_root().unwrap();
+    let mut outer = root.create_directory("outer".to_string()).unwrap();
+    let mut file = outer.create_file("inner.txt".to_string()).unwrap();
+    assert_eq!(
+        file.metadata().full_path().display().to_string(),
+        "/outer/inner.txt"
+    );
+    file.write(b"hello").unwrap();
+    file.flush().unwrap();
+
+    let mut file = fs
+        .get_from_absolute_path("/outer/inner.txt".into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    let mut content = [0u8; 5];
+    assert_eq!(file.read(&mut content).unwrap(), 5);
+    assert_eq!(&content, b"hello");
+}
+
+/// Directories nested through freshly created handles keep well-formed paths
+/// at every level.
+#[test]
+fn nested_new_directories_have_well_formed_paths() {
+    let mut fs = fresh_fat32_fs(34);
+    let mut root = fs.get_root().unwrap();
+    let mut outer = root.create_directory("outer".to_string()).unwrap();
+    let mut inner = outer.create_directory("inner".to_string()).unwrap();
+    inner.create_file("leaf.txt".to_string()).unwrap();
+
+    let listed: Vec<String> = fs
+        .get_from_absolute_path("/outer/inner".into())
+        .unwrap()
+        .into_directory()
+        .unwrap()
+        .contents()
+        .unwrap()
+        .iter()
+        .map(|entry| entry.metadata.full_path().display().to_string())
+        .collect();
+    assert!(
+        listed.contains(&"/outer/inner/leaf.txt".to_string()),
+        "{listed:?}"
+    );
+}
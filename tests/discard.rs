@@ -0,0 +1,259 @@
+//! Hermetic tests for [`vfat_rs::BlockDevice::discard`] propagation:
+//! [`vfat_rs::MountOptions::discard`] and [`vfat_rs::VfatFS::fstrim`].
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, Directory, MountOptions, SectorId, TimeManagerNoop, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+
+type Discards = Arc<Mutex<Vec<(u32, u32)>>>;
+
+/// In-memory device recording every discarded range.
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>, Discards);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn discard(&mut self, start: SectorId, count: u32) -> vfat_rs::Result<()> {
+        self.1.lock().unwrap().push((start.0, count));
+        Ok(())
+    }
+}
+
+fn fresh_fat32_fs(discard: bool) -> (VfatFS, Discards) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let discards = Discards::default();
+    let device = MemoryBlockDevice(Arc::new(Mutex::new(image)), discards.clone());
+    let options = MountOptions {
+        discard,
+        ..MountOptions::default()
+    };
+    let fs =
+        VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS");
+    (fs, discards)
+}
+
+/// Sector ranges `(start, count)` holding the data of `path`, one per extent.
+fn sector_ranges(fs: &mut VfatFS, path: &str) -> Vec<(u32, u32)> {
+    let cluster_size = fs.bytes_per_cluster() as u64;
+    let sectors_per_cluster = (cluster_size / SECTOR_SIZE as u64) as u32;
+    let file = fs
+        .get_from_absolute_path(path.into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    file.extents()
+        .unwrap()
+        .into_iter()
+        .map(|extent| {
+            let clusters = extent.length.div_ceil(cluster_size) as u32;
+            (extent.start_sector.0, clusters * sectors_per_cluster)
+        })
+        .collect()
+}
+
+/// Write `a` and `b` one cluster at a time, alternating, so both end up
+/// fragmented and interleaved.
+fn create_interleaved(fs: &VfatFS, root: &mut Directory, clusters: usize) {
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut a = root.create_file("a.bin".to_string()).unwrap();
+    let mut b = root.create_file("b.bin".to_string()).unwrap();
+    for _ in 0..clusters {
+        a.write(&vec![0xAA; cluster_size]).unwrap();
+        b.write(&vec![0xBB; cluster_size]).unwrap();
+    }
+    a.flush().unwrap();
+    b.flush().unwrap();
+}
+
+#[test]
+fn delete_discards_the_freed_clusters() {
+    let (mut fs, discards) = fresh_fat32_fs(true);
+    let mut root = fs.get_root().unwrap();
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut file = root.create_file("big.bin".to_string()).unwrap();
+    file.write(&vec![1; cluster_size * 8]).unwrap();
+    file.flush().unwrap();
+    let expected = sector_ranges(&mut fs, "/big.bin");
+    assert_eq!(expected.len(), 1);
+    discards.lock().unwrap().clear();
+
+    drop(file);
+    root.delete("big.bin".to_string()).unwrap();
+    // A contiguous chain is discarded with a single call.
+    assert_eq!(*discards.lock().unwrap(), expected);
+}
+
+#[test]
+fn fragmented_delete_discards_one_range_per_run() {
+    let (mut fs, discards) = fresh_fat32_fs(true);
+    let mut root = fs.get_root().unwrap();
+    create_interleaved(&fs, &mut root, 4);
+    let mut expected = sector_ranges(&mut fs, "/a.bin");
+    assert_eq!(expected.len(), 4);
+    discards.lock().unwrap().clear();
+
+    root.delete("a.bin".to_string()).unwrap();
+    expected.sort();
+    assert_eq!(*discards.lock().unwrap(), expected);
+}
+
+#[test]
+fn truncate_discards_only_the_tail() {
+    let (mut fs, discards) = fresh_fat32_fs(true);
+    let mut root = fs.get_root().unwrap();
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut file = root.create_file("big.bin".to_string()).unwrap();
+    file.write(&vec![1; cluster_size * 6]).unwrap();
+    file.flush().unwrap();
+    let (start, _) = sector_ranges(&mut fs, "/big.bin")[0];
+    let sectors_per_cluster = (cluster_size / SECTOR_SIZE) as u32;
+    discards.lock().unwrap().clear();
+
+    file.truncate(cluster_size as u32 * 2).unwrap();
+    assert_eq!(
+        *discards.lock().unwrap(),
+        vec![(start + 2 * sectors_per_cluster, 4 * sectors_per_cluster)]
+    );
+}
+
+#[test]
+fn no_discard_without_the_mount_option() {
+    let (mut fs, discards) = fresh_fat32_fs(false);
+    let mut root = fs.get_root().unwrap();
+    create_interleaved(&fs, &mut root, 2);
+    root.delete("a.bin".to_string()).unwrap();
+    assert!(discards.lock().unwrap().is_empty());
+}
+
+#[test]
+fn fstrim_discards_all_the_free_space() {
+    let (mut fs, discards) = fresh_fat32_fs(false);
+    let mut root = fs.get_root().unwrap();
+    create_interleaved(&fs, &mut root, 3);
+    root.delete("a.bin".to_string()).unwrap();
+    let kept = sector_ranges(&mut fs, "/b.bin");
+
+    let trimmed = fs.fstrim().unwrap();
+    assert_eq!(trimmed, fs.count_free_clusters().unwrap());
+
+    let discards = discards.lock().unwrap();
+    let sectors_per_cluster = fs.bytes_per_cluster() / SECTOR_SIZE as u32;
+    let total: u32 = discards.iter().map(|&(_, count)| count).sum();
+    assert_eq!(total, trimmed * sectors_per_cluster);
+    // The holes left by a.bin are discarded, the clusters of b.bin never are.
+    assert!(discards.len() >= 3);
+    let overlaps = |(a, a_len): (u32, u32), (b, b_len): (u32, u32)| a < b + b_len && b < a + a_len;
+    assert!(
+        discards
+            .iter()
+            .all(|&range| kept.iter().all(|&used| !overlaps(range, used)))
+    );
+}
+
+#[cfg(all(feature = "std", target_os = "linux"))]
+#[test]
+fn filebacked_device_punches_holes() {
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::unix::fs::MetadataExt;
+
+    let path = std::env::temp_dir().join(format!("vfat-rs-discard-{}.img", std::process::id()));
+    let mut image = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    image.write_all(&vec![0x5A; 1024 * 1024]).unwrap();
+    image.sync_all().unwrap();
+    let blocks_before = image.metadata().unwrap().blocks();
+
+    let mut device = vfat_rs::FilebackedBlockDevice {
+        image: image.try_clone().unwrap(),
+    };
+    device.discard(SectorId(8), 1024).unwrap();
+
+    let metadata = image.metadata().unwrap();
+    assert_eq!(metadata.len(), 1024 * 1024);
+    let mut content = Vec::new();
+    image.seek(SeekFrom::Start(0)).unwrap();
+    image.read_to_end(&mut content).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(content[..8 * SECTOR_SIZE].iter().all(|&b| b == 0x5A));
+    assert!(content[1032 * SECTOR_SIZE..].iter().all(|&b| b == 0x5A));
+    let hole = &content[8 * SECTOR_SIZE..1032 * SECTOR_SIZE];
+    // Host filesystems without hole support leave the data in place.
+    if metadata.blocks() < blocks_before {
+        assert!(hole.iter().all(|&b| b == 0));
+    }
+}
+
+#[cfg(all(feature = "std", target_os = "linux"))]
+#[test]
+fn filebacked_device_reports_discard_errors_by_kind() {
+    use std::io::Write;
+
+    let path = std::env::temp_dir().join(format!(
+        "vfat-rs-discard-readonly-{}.img",
+        std::process::id()
+    ));
+    std::fs::File::create(&path)
+        .unwrap()
+        .write_all(&vec![0x5A; 64 * SECTOR_SIZE])
+        .unwrap();
+    // Punching a hole needs a writable descriptor: fallocate fails with EBADF.
+    let mut device = vfat_rs::FilebackedBlockDevice {
+        image: std::fs::File::open(&path).unwrap(),
+    };
+    let err = device.discard(SectorId(8), 8).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+
+    const EBADF: i32 = 9;
+    let vfat_rs::VfatRsError::IoError { source } = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(source.raw_os_error(), Some(EBADF));
+    assert_eq!(
+        source.kind(),
+        std::io::Error::from_raw_os_error(EBADF).kind()
+    );
+}
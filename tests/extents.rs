@@ -0,0 +1,139 @@
+//! Hermetic tests for the extent map ([`vfat_rs::File::extents`]) and
+//! [`vfat_rs::File::bmap`], checked against the raw bytes of the image.
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, Extent, SectorId, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(size_mib: usize) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; size_mib * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    (VfatFS::new(device, 0).expect("open VfatFS"), image)
+}
+
+fn read_raw(image: &Arc<Mutex<Vec<u8>>>, sector: SectorId, len: usize) -> Vec<u8> {
+    let data = image.lock().unwrap();
+    let start = sector.0 as usize * SECTOR_SIZE;
+    data[start..start + len].to_vec()
+}
+
+#[test]
+fn extents_follow_a_fragmented_file() {
+    let (mut fs, image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut root = fs.get_root().unwrap();
+    let content: Vec<u8> = (0..cluster_size * 4).map(|i| (i / 7) as u8).collect();
+
+    // Interleave another file so `data.bin` ends up in two runs.
+    let mut data = root.create_file("data.bin".to_string()).unwrap();
+    data.write(&content[..cluster_size * 2]).unwrap();
+    root.create_file("gap".to_string())
+        .unwrap()
+        .write(b"gap")
+        .unwrap();
+    data.write(&content[cluster_size * 2..]).unwrap();
+    data.flush().unwrap();
+
+    let extents = data.extents().unwrap();
+    assert_eq!(extents.len(), 2);
+    assert_eq!(
+        extents
+            .iter()
+            .map(|e| (e.file_offset, e.length))
+            .collect::<Vec<_>>(),
+        vec![
+            (0, cluster_size as u64 * 2),
+            (cluster_size as u64 * 2, cluster_size as u64 * 2)
+        ]
+    );
+    for Extent {
+        file_offset,
+        start_sector,
+        length,
+    } in extents
+    {
+        let start = file_offset as usize;
+        assert_eq!(
+            read_raw(&image, start_sector, length as usize),
+            content[start..start + length as usize]
+        );
+    }
+}
+
+#[test]
+fn bmap_maps_offsets_to_sectors() {
+    let (mut fs, image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster() as u64;
+    let mut file = fs
+        .get_root()
+        .unwrap()
+        .create_file("map.bin".to_string())
+        .unwrap();
+    let content: Vec<u8> = (0..cluster_size * 3).map(|i| (i % 251) as u8).collect();
+    file.write(&content).unwrap();
+    file.flush().unwrap();
+
+    for offset in [0, 1, 511, 512, cluster_size, cluster_size * 3 - 1] {
+        let sector = file.bmap(offset).unwrap().expect("offset is allocated");
+        let raw = read_raw(&image, sector, SECTOR_SIZE);
+        assert_eq!(raw[offset as usize % SECTOR_SIZE], content[offset as usize]);
+    }
+    assert_eq!(file.bmap(cluster_size * 3).unwrap(), None);
+}
+
+#[test]
+fn empty_file_has_no_extents() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let file = fs
+        .get_root()
+        .unwrap()
+        .create_file("empty".to_string())
+        .unwrap();
+    assert!(file.extents().unwrap().is_empty());
+    assert_eq!(file.bmap(0).unwrap(), None);
+}
@@ -0,0 +1,224 @@
+//! Hermetic tests for the `std::fs`-style path API on [`vfat_rs::VfatFS`].
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::io::ErrorKind;
+use vfat_rs::{
+    BlockDevice, MountOptions, OpenOptions, SectorId, TimeManagerNoop, VfatFS, VfatMetadataTrait,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(options: MountOptions) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    let fs =
+        VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS");
+    (fs, image)
+}
+
+fn kind<T: std::fmt::Debug>(result: vfat_rs::Result<T>) -> ErrorKind {
+    result.unwrap_err().kind()
+}
+
+#[test]
+fn write_read_and_create_truncates() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let content: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+    fs.write("/data.bin", &content).unwrap();
+    assert_eq!(fs.read("/data.bin").unwrap(), content);
+
+    fs.write("/data.bin", b"short").unwrap();
+    assert_eq!(fs.read("/data.bin").unwrap(), b"short");
+
+    let file = fs.create("/data.bin").unwrap();
+    assert_eq!(file.metadata().size(), 0);
+    assert!(fs.read("/data.bin").unwrap().is_empty());
+
+    let mut file = fs
+        .open_with("/DATA.BIN", OpenOptions::new().read(true).write(true))
+        .unwrap();
+    file.write(b"abc").unwrap();
+    assert_eq!(fs.metadata("/data.bin").unwrap().size(), 3);
+}
+
+#[test]
+fn errors_follow_std_fs_kinds() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.create_dir("/dir").unwrap();
+    fs.write("/dir/file", b"x").unwrap();
+
+    assert_eq!(kind(fs.open("/missing")), ErrorKind::NotFound);
+    assert_eq!(kind(fs.open("/dir")), ErrorKind::IsADirectory);
+    assert_eq!(kind(fs.create("/missing/file")), ErrorKind::NotFound);
+    assert_eq!(kind(fs.create("/dir/file/x")), ErrorKind::NotADirectory);
+    assert_eq!(kind(fs.create("/dir")), ErrorKind::IsADirectory);
+    assert_eq!(kind(fs.create_dir("/dir")), ErrorKind::AlreadyExists);
+    assert_eq!(kind(fs.remove_file("/dir")), ErrorKind::IsADirectory);
+    assert_eq!(kind(fs.remove_dir("/dir/file")), ErrorKind::NotADirectory);
+    assert_eq!(kind(fs.remove_dir("/dir")), ErrorKind::DirectoryNotEmpty);
+    assert_eq!(kind(fs.remove_dir("/")), ErrorKind::InvalidInput);
+    assert_eq!(kind(fs.read_dir("/dir/file")), ErrorKind::NotADirectory);
+    assert_eq!(kind(fs.metadata("relative")), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn create_dir_all_creates_missing_ancestors() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.create_dir("/a").unwrap();
+    let dir = fs.create_dir_all("/a/b/c/d").unwrap();
+    assert_eq!(dir.metadata().name(), "d");
+    assert!(fs.exists("/a/b/c/d").unwrap());
+    // Already there: nothing to do.
+    fs.create_dir_all("/a/b/c/d/").unwrap();
+
+    fs.write("/a/file", b"").unwrap();
+    assert_eq!(kind(fs.create_dir_all("/a/file")), ErrorKind::AlreadyExists);
+    assert_eq!(
+        kind(fs.create_dir_all("/a/file/x/y")),
+        ErrorKind::NotADirectory
+    );
+}
+
+#[test]
+fn remove_file_remove_dir_and_remove_dir_all() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let free_before = fs.count_free_clusters().unwrap();
+    fs.create_dir_all("/tree/left/deep").unwrap();
+    fs.create_dir_all("/tree/right").unwrap();
+    fs.write("/tree/left/deep/file", vec![7u8; 9000]).unwrap();
+    fs.write("/tree/right/file", b"x").unwrap();
+    fs.write("/tree/top", b"y").unwrap();
+    fs.create_dir("/empty").unwrap();
+
+    fs.remove_file("/tree/top").unwrap();
+    assert!(!fs.exists("/tree/top").unwrap());
+    fs.remove_dir("/empty").unwrap();
+    assert!(!fs.exists("/empty").unwrap());
+
+    fs.remove_dir_all("/tree").unwrap();
+    assert!(!fs.exists("/tree").unwrap());
+    assert_eq!(fs.count_free_clusters().unwrap(), free_before);
+    assert_eq!(kind(fs.remove_dir_all("/tree")), ErrorKind::NotFound);
+}
+
+#[test]
+fn rename_moves_and_replaces() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.create_dir_all("/src/sub").unwrap();
+    fs.create_dir("/dst").unwrap();
+    fs.write("/src/a", b"aaa").unwrap();
+    fs.write("/dst/b", b"bbb").unwrap();
+
+    // Within a subdirectory.
+    fs.rename("/src/a", "/src/renamed").unwrap();
+    assert_eq!(fs.read("/src/renamed").unwrap(), b"aaa");
+    assert!(!fs.exists("/src/a").unwrap());
+
+    // Across directories, replacing the destination.
+    fs.rename("/src/renamed", "/dst/b").unwrap();
+    assert_eq!(fs.read("/dst/b").unwrap(), b"aaa");
+    assert!(!fs.exists("/src/renamed").unwrap());
+
+    assert_eq!(
+        kind(fs.rename("/dst/b", "/src/sub")),
+        ErrorKind::IsADirectory
+    );
+    assert_eq!(
+        kind(fs.rename("/src/sub", "/dst/b")),
+        ErrorKind::NotADirectory
+    );
+    assert_eq!(
+        kind(fs.rename("/src", "/src/sub/x")),
+        ErrorKind::InvalidInput
+    );
+
+    fs.rename("/src/sub", "/moved").unwrap();
+    assert!(fs.metadata("/moved").unwrap().full_path() == "/moved/");
+}
+
+#[test]
+fn read_dir_lists_entries_without_pseudo_entries() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.create_dir("/dir").unwrap();
+    fs.write("/dir/one", b"1").unwrap();
+    fs.create_dir("/dir/two").unwrap();
+
+    let mut names: Vec<String> = fs
+        .read_dir("/dir")
+        .unwrap()
+        .iter()
+        .map(|entry| entry.name().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["one", "two"]);
+
+    let root: Vec<String> = fs
+        .read_dir("/")
+        .unwrap()
+        .iter()
+        .map(|entry| entry.name().to_string())
+        .collect();
+    assert_eq!(root, ["dir"]);
+}
+
+#[test]
+fn copy_duplicates_the_content() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let content: Vec<u8> = (0..20_000u32).map(|i| (i * 7) as u8).collect();
+    fs.write("/original", &content).unwrap();
+    fs.create_dir("/backup").unwrap();
+    fs.write("/backup/copy", b"old content, longer than nothing")
+        .unwrap();
+
+    assert_eq!(
+        fs.copy("/original", "/backup/copy").unwrap(),
+        content.len() as u64
+    );
+    assert_eq!(fs.read("/backup/copy").unwrap(), content);
+    assert_eq!(fs.read("/original").unwrap(), content);
+    assert_eq!(kind(fs.copy("/backup", "/x")), ErrorKind::IsADirectory);
+}
@@ -0,0 +1,196 @@
+//! Hermetic tests for mounting with the locks of a custom
+//! [`vfat_rs::LockProvider`].
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use vfat_rs::{
+    BlockDevice, LockProvider, MountOptions, RawLock, SectorId, SpinLocks, TimeManagerNoop, VfatFS,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(locks: Arc<dyn LockProvider>) -> VfatFS {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let device = MemoryBlockDevice(Arc::new(Mutex::new(image)));
+    let options = MountOptions {
+        locks,
+        ..MountOptions::default()
+    };
+    VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS")
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    created: AtomicUsize,
+    taken: AtomicUsize,
+    /// Holds taken and not released yet.
+    held: AtomicUsize,
+}
+
+/// Spinning locks keeping count of what they are asked.
+#[derive(Debug, Default)]
+struct CountingLocks(Arc<Counters>);
+
+struct CountingLock {
+    inner: Box<dyn RawLock>,
+    counters: Arc<Counters>,
+}
+
+impl CountingLock {
+    fn taken(&self) {
+        self.counters.taken.fetch_add(1, Ordering::SeqCst);
+        self.counters.held.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn released(&self) {
+        self.counters.held.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl LockProvider for CountingLocks {
+    fn new_lock(&self) -> Box<dyn RawLock> {
+        self.0.created.fetch_add(1, Ordering::SeqCst);
+        Box::new(CountingLock {
+            inner: SpinLocks.new_lock(),
+            counters: self.0.clone(),
+        })
+    }
+}
+
+impl RawLock for CountingLock {
+    fn lock_shared(&self) {
+        self.inner.lock_shared();
+        self.taken();
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let locked = self.inner.try_lock_shared();
+        if locked {
+            self.taken();
+        }
+        locked
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.released();
+        unsafe { self.inner.unlock_shared() }
+    }
+
+    fn lock_exclusive(&self) {
+        self.inner.lock_exclusive();
+        self.taken();
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.released();
+        unsafe { self.inner.unlock_exclusive() }
+    }
+}
+
+/// A volume mounted with counting locks, and their counters.
+fn counted_fs() -> (VfatFS, Arc<Counters>) {
+    let locks = CountingLocks::default();
+    let counters = locks.0.clone();
+    (fresh_fat32_fs(Arc::new(locks)), counters)
+}
+
+#[test]
+fn every_lock_comes_from_the_provider() {
+    let (mut fs, counters) = counted_fs();
+    let created_at_mount = counters.created.load(Ordering::SeqCst);
+    assert!(created_at_mount > 0);
+
+    fs.create_dir("/dir").unwrap();
+    let mut file = fs.create("/dir/file.txt").unwrap();
+    file.write(b"locked").unwrap();
+    drop(file);
+    assert_eq!(fs.read("/dir/file.txt").unwrap(), b"locked");
+    fs.rename("/dir/file.txt", "/file.txt").unwrap();
+    fs.remove_dir("/dir").unwrap();
+
+    assert!(counters.created.load(Ordering::SeqCst) > created_at_mount);
+    assert!(counters.taken.load(Ordering::SeqCst) > 0);
+    assert_eq!(counters.held.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn custom_locks_are_used_across_threads() {
+    let (fs, counters) = counted_fs();
+    let handles: Vec<_> = (0..4)
+        .map(|index| {
+            let mut fs = fs.clone();
+            thread::spawn(move || {
+                let path = format!("/file{index}.bin");
+                let data = vec![index as u8; 10 * 1024];
+                fs.write(&path, &data).unwrap();
+                assert_eq!(fs.read(&path).unwrap(), data);
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(counters.held.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn spinning_locks_can_be_chosen_explicitly() {
+    let mut fs = fresh_fat32_fs(Arc::new(SpinLocks));
+    fs.write("/spin.txt", b"spin").unwrap();
+    assert_eq!(fs.read("/spin.txt").unwrap(), b"spin");
+}
+
+#[test]
+fn the_provider_is_dropped_with_the_volume() {
+    let (mut fs, counters) = counted_fs();
+    let mut file = fs.create("/kept.txt").unwrap();
+    file.write(b"open").unwrap();
+    drop(fs);
+    assert!(Arc::strong_count(&counters) > 1, "the open file keeps it");
+    drop(file);
+    assert_eq!(Arc::strong_count(&counters), 1);
+}
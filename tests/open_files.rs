@@ -0,0 +1,282 @@
+//! Hermetic tests for the open file table: handles of the same file share
+//! their size and first cluster, and a file deleted while open keeps its
+//! clusters until its last handle is closed.
+
+use std::io::Cursor;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+
+use vfat_rs::{BlockDevice, File, OpenOptions, SectorId, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fat32_image() -> Arc<Mutex<Vec<u8>>> {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    Arc::new(Mutex::new(image))
+}
+
+fn mount(image: &Arc<Mutex<Vec<u8>>>) -> VfatFS {
+    VfatFS::new(MemoryBlockDevice(image.clone()), 0).expect("open VfatFS")
+}
+
+fn fresh_fat32_fs() -> VfatFS {
+    mount(&fat32_image())
+}
+
+/// Open `path` for reading and writing.
+fn open_rw(fs: &mut VfatFS, path: &str) -> File {
+    fs.open_with(path, OpenOptions::new().read(true).write(true))
+        .unwrap()
+}
+
+/// Delete the open `path` and close its last handle while the volume is
+/// locked exclusively, so its clusters can't be freed right away.
+fn orphan(fs: &mut VfatFS, path: &str) {
+    let mut file = Some(fs.open(path).unwrap());
+    fs.remove_file(path).unwrap();
+    // Reports hold the volume lock exclusively while visiting the entries.
+    fs.analyze(|_| drop(file.take())).unwrap();
+    assert!(file.is_none());
+}
+
+#[test]
+fn handles_share_the_size() {
+    let mut fs = fresh_fat32_fs();
+    fs.write("/shared.txt", b"hello").unwrap();
+    let mut writer = open_rw(&mut fs, "/shared.txt");
+    let mut reader = fs.open("/shared.txt").unwrap();
+
+    writer.write_at(5, b" world").unwrap();
+    assert_eq!(reader.size(), 11);
+    let mut buf = [0u8; 32];
+    assert_eq!(reader.read(&mut buf).unwrap(), 11);
+    assert_eq!(&buf[..11], b"hello world");
+    assert_eq!(reader.metadata().size(), 11);
+
+    // A handle opened later starts from the shared state too.
+    let late = fs.open("/shared.txt").unwrap();
+    assert_eq!(late.metadata().size(), 11);
+}
+
+#[test]
+fn first_cluster_is_allocated_once() {
+    let mut fs = fresh_fat32_fs();
+    fs.write("/log.txt", b"").unwrap();
+    let free = fs.count_free_clusters().unwrap();
+    let mut options = OpenOptions::new();
+    options.append(true);
+    let mut first = fs.open_with("/log.txt", &options).unwrap();
+    let mut second = fs.open_with("/log.txt", &options).unwrap();
+
+    first.write(b"one ").unwrap();
+    second.write(b"two ").unwrap();
+    first.write(b"three").unwrap();
+    assert_eq!(fs.read("/log.txt").unwrap(), b"one two three");
+    assert_eq!(fs.count_free_clusters().unwrap(), free - 1);
+}
+
+#[test]
+fn truncate_through_one_handle_is_seen_by_the_others() {
+    let mut fs = fresh_fat32_fs();
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    fs.write("/data.bin", vec![1u8; cluster_size * 4]).unwrap();
+    let mut a = open_rw(&mut fs, "/data.bin");
+    let mut b = open_rw(&mut fs, "/data.bin");
+
+    // Leave b's writer and position hints deep in the chain.
+    b.seek(vfat_rs::io::SeekFrom::Start(cluster_size as u64 * 3))
+        .unwrap();
+    b.write(&[2u8; 10]).unwrap();
+    b.write_at(cluster_size as u64 * 2, &[3u8; 10]).unwrap();
+
+    a.truncate(0).unwrap();
+    assert_eq!(b.size(), 0);
+    b.write_at(0, b"fresh").unwrap();
+    b.write(b"!").unwrap();
+
+    // b's sequential write still goes to its own offset, past the new end.
+    let content = fs.read("/data.bin").unwrap();
+    assert_eq!(content.len(), cluster_size * 3 + 11);
+    assert_eq!(&content[..5], b"fresh");
+    assert_eq!(content[cluster_size * 3 + 10], b'!');
+}
+
+#[test]
+fn deleted_file_stays_usable_until_closed() {
+    let mut fs = fresh_fat32_fs();
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let free = fs.count_free_clusters().unwrap();
+    fs.write("/tmp.bin", vec![7u8; cluster_size * 3]).unwrap();
+    let mut file = open_rw(&mut fs, "/tmp.bin");
+    let other = fs.open("/tmp.bin").unwrap();
+
+    fs.remove_file("/tmp.bin").unwrap();
+    assert!(!fs.exists("/tmp.bin").unwrap());
+    assert_eq!(fs.count_free_clusters().unwrap(), free - 3);
+
+    // Both handles keep working on the unlinked file.
+    file.write_at(cluster_size as u64 * 3, &[8u8; 100]).unwrap();
+    let mut buf = vec![0u8; 101];
+    assert_eq!(
+        other
+            .read_at(cluster_size as u64 * 3 - 1, &mut buf)
+            .unwrap(),
+        101
+    );
+    assert_eq!(buf[0], 7);
+    assert!(buf[1..].iter().all(|&byte| byte == 8));
+    assert_eq!(fs.count_free_clusters().unwrap(), free - 4);
+
+    // A new file can take the name, and the slot, meanwhile.
+    fs.write("/tmp.bin", b"new").unwrap();
+    assert_eq!(fs.read("/tmp.bin").unwrap(), b"new");
+    assert_eq!(other.size(), cluster_size as u64 * 3 + 100);
+
+    drop(file);
+    assert_eq!(fs.count_free_clusters().unwrap(), free - 5);
+    drop(other);
+    assert_eq!(fs.count_free_clusters().unwrap(), free - 1);
+    assert_eq!(fs.read("/tmp.bin").unwrap(), b"new");
+}
+
+#[test]
+fn handles_follow_a_rename() {
+    let mut fs = fresh_fat32_fs();
+    fs.create_dir_all("/archive").unwrap();
+    fs.write("/current.log", b"first").unwrap();
+    let mut file = open_rw(&mut fs, "/current.log");
+
+    fs.rename("/current.log", "/renamed.log").unwrap();
+    file.write_at(5, b" second").unwrap();
+    assert_eq!(fs.read("/renamed.log").unwrap(), b"first second");
+
+    fs.rename("/renamed.log", "/archive/old.log").unwrap();
+    file.write_at(12, b" third").unwrap();
+    assert_eq!(fs.read("/archive/old.log").unwrap(), b"first second third");
+    assert_eq!(fs.open("/archive/old.log").unwrap().metadata().size(), 18);
+}
+
+#[test]
+fn handles_under_a_renamed_directory_write_their_own_entry() {
+    let mut fs = fresh_fat32_fs();
+    fs.create_dir_all("/dir").unwrap();
+    fs.write("/dir/a.txt", b"original").unwrap();
+    let mut file = open_rw(&mut fs, "/dir/a.txt");
+
+    fs.rename("/dir", "/moved").unwrap();
+    fs.create_dir_all("/dir").unwrap();
+    fs.write("/dir/a.txt", b"other").unwrap();
+
+    file.seek(vfat_rs::io::SeekFrom::End(0)).unwrap();
+    let appended = b" appended through old handle";
+    assert_eq!(file.write(appended).unwrap(), appended.len());
+    drop(file);
+
+    assert_eq!(
+        fs.read("/moved/a.txt").unwrap(),
+        b"original appended through old handle"
+    );
+    assert_eq!(fs.read("/dir/a.txt").unwrap(), b"other");
+}
+
+#[test]
+fn concurrent_last_closes_free_an_unlinked_file() {
+    let mut fs = fresh_fat32_fs();
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let free = fs.count_free_clusters().unwrap();
+    for _ in 0..200 {
+        fs.write("/tmp.bin", vec![7u8; cluster_size * 2]).unwrap();
+        let handles = [fs.open("/tmp.bin").unwrap(), fs.open("/tmp.bin").unwrap()];
+        fs.remove_file("/tmp.bin").unwrap();
+
+        // Both handles are closed at once: exactly one of them is the last.
+        let barrier = Arc::new(Barrier::new(handles.len()));
+        let threads: Vec<_> = handles
+            .into_iter()
+            .map(|file| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    drop(file);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(fs.count_free_clusters().unwrap(), free);
+    }
+}
+
+#[test]
+fn sync_frees_the_chains_of_files_closed_while_the_volume_was_locked() {
+    let mut fs = fresh_fat32_fs();
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    fs.write("/keep.txt", b"keep").unwrap();
+    let free = fs.count_free_clusters().unwrap();
+    fs.write("/tmp.bin", vec![7u8; cluster_size * 3]).unwrap();
+
+    orphan(&mut fs, "/tmp.bin");
+    assert_eq!(fs.count_free_clusters().unwrap(), free - 3);
+    fs.sync().unwrap();
+    assert_eq!(fs.count_free_clusters().unwrap(), free);
+}
+
+#[test]
+fn dropping_the_volume_frees_the_chains_of_files_closed_while_it_was_locked() {
+    let image = fat32_image();
+    let mut fs = mount(&image);
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    fs.write("/keep.txt", b"keep").unwrap();
+    let free = fs.count_free_clusters().unwrap();
+    fs.write("/tmp.bin", vec![7u8; cluster_size * 3]).unwrap();
+
+    orphan(&mut fs, "/tmp.bin");
+    let root = fs.get_root().unwrap();
+    drop(fs);
+    // A directory handle keeps the volume alive.
+    assert_eq!(mount(&image).count_free_clusters().unwrap(), free - 3);
+    drop(root);
+    assert_eq!(mount(&image).count_free_clusters().unwrap(), free);
+}
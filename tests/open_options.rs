@@ -0,0 +1,210 @@
+//! Hermetic tests for [`vfat_rs::OpenOptions`] and the access modes it gives to
+//! [`vfat_rs::File`] handles.
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::io::ErrorKind;
+use vfat_rs::io::SeekFrom;
+use vfat_rs::{BlockDevice, MountOptions, OpenOptions, SectorId, TimeManagerNoop, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(options: MountOptions) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    let fs =
+        VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS");
+    (fs, image)
+}
+
+fn kind<T: std::fmt::Debug>(result: vfat_rs::Result<T>) -> ErrorKind {
+    result.unwrap_err().kind()
+}
+
+fn fs_with_file(content: &[u8]) -> VfatFS {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.write("/file.txt", content).unwrap();
+    fs
+}
+
+fn read_all(file: &mut vfat_rs::File) -> Vec<u8> {
+    let mut content = vec![0; file.metadata().size()];
+    let read = file.read(&mut content).unwrap();
+    content.truncate(read);
+    content
+}
+
+#[test]
+fn read_only_handles_refuse_writes() {
+    let mut fs = fs_with_file(b"hello");
+    let mut file = fs
+        .open_with("/file.txt", OpenOptions::new().read(true))
+        .unwrap();
+    assert_eq!(read_all(&mut file), b"hello");
+    assert_eq!(kind(file.write(b"x")), ErrorKind::PermissionDenied);
+    assert_eq!(kind(file.truncate(0)), ErrorKind::PermissionDenied);
+    assert_eq!(fs.read("/file.txt").unwrap(), b"hello");
+
+    let mut file = fs
+        .open_with("/file.txt", OpenOptions::new().write(true))
+        .unwrap();
+    assert_eq!(kind(file.read(&mut [0; 4])), ErrorKind::PermissionDenied);
+    file.write(b"J").unwrap();
+    assert_eq!(fs.read("/file.txt").unwrap(), b"Jello");
+}
+
+#[test]
+fn open_is_read_only_like_std() {
+    let mut fs = fs_with_file(b"hello");
+    let mut file = fs.open("/file.txt").unwrap();
+    assert_eq!(read_all(&mut file), b"hello");
+    assert_eq!(kind(file.write(b"x")), ErrorKind::PermissionDenied);
+    assert_eq!(kind(file.truncate(0)), ErrorKind::PermissionDenied);
+    assert_eq!(fs.read("/file.txt").unwrap(), b"hello");
+}
+
+#[test]
+fn create_truncate_and_create_new() {
+    let mut fs = fs_with_file(b"hello");
+    assert_eq!(
+        kind(fs.open_with("/new.txt", OpenOptions::new().write(true))),
+        ErrorKind::NotFound
+    );
+    fs.open_with("/new.txt", OpenOptions::new().write(true).create(true))
+        .unwrap()
+        .write(b"new")
+        .unwrap();
+    assert_eq!(fs.read("/new.txt").unwrap(), b"new");
+
+    let create_new = OpenOptions::new().write(true).create_new(true).to_owned();
+    assert_eq!(
+        kind(fs.open_with("/file.txt", &create_new)),
+        ErrorKind::AlreadyExists
+    );
+    fs.open_with("/other.txt", &create_new).unwrap();
+    assert!(fs.exists("/other.txt").unwrap());
+
+    fs.open_with("/file.txt", OpenOptions::new().write(true).truncate(true))
+        .unwrap();
+    assert_eq!(fs.read("/file.txt").unwrap(), b"");
+
+    fs.create_dir("/dir").unwrap();
+    assert_eq!(
+        kind(fs.open_with("/dir", OpenOptions::new().read(true))),
+        ErrorKind::IsADirectory
+    );
+}
+
+#[test]
+fn append_always_writes_at_the_end() {
+    let mut fs = fs_with_file(b"one");
+    let mut appender = fs
+        .open_with("/file.txt", OpenOptions::new().append(true))
+        .unwrap();
+    appender.write(b",two").unwrap();
+
+    // Another handle grows the file behind the appender's back.
+    let mut other = fs
+        .open_with("/file.txt", OpenOptions::new().read(true).write(true))
+        .unwrap();
+    other.seek(SeekFrom::End(0)).unwrap();
+    other.write(b",three").unwrap();
+
+    appender.seek(SeekFrom::Start(0)).unwrap();
+    appender.write(b",four").unwrap();
+    assert_eq!(fs.read("/file.txt").unwrap(), b"one,two,three,four");
+    assert_eq!(appender.metadata().size(), 18);
+}
+
+#[test]
+fn append_to_an_empty_file_allocates_its_first_cluster() {
+    let mut fs = fs_with_file(b"");
+    let mut appender = fs
+        .open_with("/file.txt", OpenOptions::new().append(true).create(true))
+        .unwrap();
+    let mut other = fs
+        .open_with("/file.txt", OpenOptions::new().read(true).write(true))
+        .unwrap();
+    other.write(b"first").unwrap();
+    appender.write(b" second").unwrap();
+    assert_eq!(fs.read("/file.txt").unwrap(), b"first second");
+}
+
+#[test]
+fn invalid_combinations_are_rejected() {
+    let mut fs = fs_with_file(b"hello");
+    for options in [
+        OpenOptions::new(),
+        OpenOptions::new().read(true).truncate(true).to_owned(),
+        OpenOptions::new().read(true).create(true).to_owned(),
+        OpenOptions::new().append(true).truncate(true).to_owned(),
+    ] {
+        assert_eq!(
+            kind(fs.open_with("/file.txt", &options)),
+            ErrorKind::InvalidInput,
+            "{options:?}"
+        );
+    }
+    assert_eq!(fs.read("/file.txt").unwrap(), b"hello");
+}
+
+#[test]
+fn directory_open_with_resolves_relative_paths() {
+    let mut fs = fs_with_file(b"hello");
+    let dir = fs.create_dir_all("/a/b").unwrap();
+    let mut file = dir
+        .open_with(
+            "c.txt",
+            OpenOptions::new().read(true).write(true).create(true),
+        )
+        .unwrap();
+    file.write(b"relative").unwrap();
+    assert_eq!(fs.read("/a/b/c.txt").unwrap(), b"relative");
+    let mut file = dir
+        .open_with("../../file.txt", OpenOptions::new().read(true))
+        .unwrap();
+    assert_eq!(read_all(&mut file), b"hello");
+}
@@ -0,0 +1,242 @@
+//! Hermetic tests for positional I/O: [`vfat_rs::File::read_at`] and
+//! [`vfat_rs::File::write_at`].
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::io::SeekFrom;
+use vfat_rs::{BlockDevice, File, OpenOptions, SectorId, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(bytes_per_cluster: u32) -> VfatFS {
+    // FAT32 needs at least 65525 clusters.
+    let mut image = vec![0u8; (70_000 * bytes_per_cluster as usize).max(48 * 1024 * 1024)];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .bytes_per_cluster(bytes_per_cluster)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let device = MemoryBlockDevice(Arc::new(Mutex::new(image)));
+    VfatFS::new(device, 0).expect("open VfatFS")
+}
+
+/// A position-dependent byte pattern, coprime with the sector and cluster sizes.
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn read_exact_at(file: &File, offset: u64, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    let mut total = 0;
+    while total < len {
+        let read = file
+            .read_at(offset + total as u64, &mut buf[total..])
+            .unwrap();
+        assert!(
+            read > 0,
+            "unexpected EOF at offset {}",
+            offset + total as u64
+        );
+        total += read;
+    }
+    buf
+}
+
+#[test]
+fn read_at_random_offsets_leaves_offset_alone() {
+    for bytes_per_cluster in [512, 1024] {
+        let mut fs = fresh_fat32_fs(bytes_per_cluster);
+        let data = pattern(100_000);
+        fs.write("/random.bin", &data).unwrap();
+        let file = fs.open("/random.bin").unwrap();
+
+        for &(offset, len) in &[
+            (70_001, 5000),
+            (3, 1),
+            (4095, 2),
+            (511, 4098),
+            (99_000, 1000),
+            (40_000, 33_333),
+            (0, 100_000),
+        ] {
+            assert_eq!(
+                read_exact_at(&file, offset as u64, len),
+                data[offset..offset + len],
+                "{bytes_per_cluster} bytes per cluster, offset {offset}"
+            );
+        }
+        assert_eq!(file.offset, 0);
+
+        // Reads stop at the end of the file.
+        let mut buf = [0u8; 64];
+        assert_eq!(file.read_at(99_990, &mut buf).unwrap(), 10);
+        assert_eq!(file.read_at(100_000, &mut buf).unwrap(), 0);
+        assert_eq!(file.read_at(1 << 40, &mut buf).unwrap(), 0);
+    }
+}
+
+#[test]
+fn read_at_is_shared_between_threads() {
+    let mut fs = fresh_fat32_fs(1024);
+    let data = pattern(256 * 1024);
+    fs.write("/shared.bin", &data).unwrap();
+    let file = fs.open("/shared.bin").unwrap();
+
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let (file, data) = (&file, &data);
+            scope.spawn(move || {
+                // Each thread reads its own stripes, interleaved with the others.
+                for stripe in (thread..64).step_by(4) {
+                    let offset = stripe * 4096 + 100;
+                    let len = 3000.min(data.len() - offset);
+                    assert_eq!(
+                        read_exact_at(file, offset as u64, len),
+                        data[offset..offset + len]
+                    );
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn write_at_overwrites_and_extends() {
+    for bytes_per_cluster in [512, 1024] {
+        let mut fs = fresh_fat32_fs(bytes_per_cluster);
+        let mut expected = pattern(20_000);
+        fs.write("/write.bin", &expected).unwrap();
+        let mut file = fs
+            .open_with("/write.bin", OpenOptions::new().read(true).write(true))
+            .unwrap();
+
+        // Overwrite across cluster boundaries, out of order.
+        for &(offset, len, byte) in &[(9000, 5000, 0xAA), (1, 700, 0xBB), (4090, 12, 0xCC)] {
+            assert_eq!(file.write_at(offset, &vec![byte; len]).unwrap(), len);
+            expected[offset as usize..offset as usize + len].fill(byte);
+        }
+        // Extend from the last byte.
+        assert_eq!(file.write_at(19_999, &[0xDD; 9000]).unwrap(), 9000);
+        expected.truncate(19_999);
+        expected.extend_from_slice(&[0xDD; 9000]);
+        assert_eq!(file.offset, 0);
+        assert_eq!(file.metadata().size(), expected.len());
+
+        assert_eq!(read_exact_at(&file, 0, expected.len()), expected);
+        assert_eq!(fs.read("/write.bin").unwrap(), expected);
+    }
+}
+
+#[test]
+fn write_at_past_the_end_zero_fills_the_gap() {
+    let mut fs = fresh_fat32_fs(1024);
+    let mut root = fs.get_root().unwrap();
+    let mut file = root.create_file("gap.bin".to_string()).unwrap();
+
+    file.write_at(10_000, b"tail").unwrap();
+    assert_eq!(file.metadata().size(), 10_004);
+    let mut expected = vec![0u8; 10_000];
+    expected.extend_from_slice(b"tail");
+    assert_eq!(fs.read("/gap.bin").unwrap(), expected);
+
+    // A second gap, starting inside the last cluster.
+    file.write_at(20_000, b"more").unwrap();
+    expected.resize(20_000, 0);
+    expected.extend_from_slice(b"more");
+    assert_eq!(fs.read("/gap.bin").unwrap(), expected);
+}
+
+#[test]
+fn write_at_after_truncate_uses_the_new_chain() {
+    let mut fs = fresh_fat32_fs(512);
+    fs.write("/trunc.bin", pattern(8192)).unwrap();
+    let mut file = fs
+        .open_with("/trunc.bin", OpenOptions::new().read(true).write(true))
+        .unwrap();
+
+    // Leave position hints deep in the chain, then cut it short.
+    file.write_at(8000, &[1; 100]).unwrap();
+    file.truncate(600).unwrap();
+    file.write_at(5000, &[2; 10]).unwrap();
+
+    let mut expected = pattern(600);
+    expected.resize(5000, 0);
+    expected.extend_from_slice(&[2; 10]);
+    assert_eq!(fs.read("/trunc.bin").unwrap(), expected);
+}
+
+#[test]
+fn write_at_in_append_mode_ignores_the_offset() {
+    let mut fs = fresh_fat32_fs(512);
+    fs.write("/log.txt", b"first").unwrap();
+    let mut file = fs
+        .open_with("/log.txt", OpenOptions::new().append(true))
+        .unwrap();
+
+    file.write_at(0, b" second").unwrap();
+    assert_eq!(fs.read("/log.txt").unwrap(), b"first second");
+
+    let mut buf = [0u8; 5];
+    assert!(file.read_at(0, &mut buf).is_err());
+}
+
+#[test]
+fn sequential_write_and_read_mix_with_positional_io() {
+    let mut fs = fresh_fat32_fs(1024);
+    let mut root = fs.get_root().unwrap();
+    let mut file = root.create_file("mixed.bin".to_string()).unwrap();
+
+    file.write(&[7; 5000]).unwrap();
+    file.write_at(100, &[8; 10]).unwrap();
+    file.write(&[9; 10]).unwrap();
+    assert_eq!(file.offset, 5010);
+
+    file.seek(SeekFrom::Start(95)).unwrap();
+    let mut buf = [0u8; 20];
+    file.read(&mut buf).unwrap();
+    let mut expected = [7u8; 20];
+    expected[5..15].fill(8);
+    assert_eq!(buf, expected);
+    assert_eq!(
+        read_exact_at(&file, 4998, 12),
+        [7, 7, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9]
+    );
+}
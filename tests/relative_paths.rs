@@ -0,0 +1,132 @@
+//! Hermetic tests for relative path resolution ([`vfat_rs::Directory::open`]) and
+//! normalisation of the paths given to [`vfat_rs::VfatFS`].
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{
+    BlockDevice, MountOptions, PathBuf, SectorId, TimeManagerNoop, VfatFS, VfatMetadataTrait,
+    normalize_path,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(options: MountOptions) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    let fs =
+        VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS");
+    (fs, image)
+}
+
+fn setup() -> VfatFS {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.create_dir_all("/a/b/c").unwrap();
+    fs.write("/a/x", b"x").unwrap();
+    fs.write("/a/b/c/deep.txt", b"deep").unwrap();
+    fs
+}
+
+fn dir(fs: &mut VfatFS, path: &str) -> vfat_rs::Directory {
+    fs.get_from_absolute_path(path.into())
+        .unwrap()
+        .into_directory()
+        .unwrap()
+}
+
+#[test]
+fn directory_open_resolves_relative_paths() {
+    let mut fs = setup();
+    let c = dir(&mut fs, "/a/b/c");
+
+    let x = c.open("../../x").unwrap();
+    assert_eq!(x.name(), "x");
+    assert!(x.metadata.full_path() == "/a/x");
+    assert_eq!(c.open("./deep.txt").unwrap().name(), "deep.txt");
+    assert_eq!(c.open("deep.txt").unwrap().name(), "deep.txt");
+    assert_eq!(c.open("..").unwrap().name(), "b");
+    assert_eq!(c.open(".").unwrap().name(), "c");
+    // Absolute paths ignore the directory.
+    assert_eq!(c.open("/a/x").unwrap().name(), "x");
+
+    let root = fs.get_root().unwrap();
+    // `..` of the root is the root.
+    assert_eq!(root.open("../a/./b/../x").unwrap().name(), "x");
+    assert_eq!(root.open("..").unwrap().name(), "/");
+
+    let err = c.open("../missing").unwrap_err();
+    assert_eq!(err.kind(), vfat_rs::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn absolute_paths_are_normalised() {
+    let mut fs = setup();
+    for path in ["/a//x", "/a/./x", "/a/b/../x", "/a/b/c/../../x", "/../a/x"] {
+        let entry = fs
+            .get_from_absolute_path(path.into())
+            .unwrap_or_else(|err| panic!("{path}: {err:?}"));
+        assert_eq!(entry.name(), "x", "{path}");
+    }
+    assert_eq!(fs.read("/a/b/c/./../c//deep.txt").unwrap(), b"deep");
+    assert!(fs.exists("/a/b/..").unwrap());
+}
+
+#[test]
+fn path_helpers_compose_with_the_filesystem() {
+    let mut fs = setup();
+    let base = PathBuf::from("/a/b");
+    let file = base.join("c").join("deep.txt");
+    assert_eq!(file.with_extension(""), PathBuf::from("/a/b/c/deep"));
+    assert_eq!(fs.read(file.clone()).unwrap(), b"deep");
+
+    let copy = file.with_extension("bak");
+    fs.copy(file.clone(), copy.clone()).unwrap();
+    assert_eq!(fs.read(copy.clone()).unwrap(), b"deep");
+    assert!(copy.starts_with(&base));
+    assert_eq!(
+        normalize_path(copy.join("../../x")),
+        PathBuf::from("/a/b/x")
+    );
+}
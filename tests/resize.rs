@@ -0,0 +1,320 @@
+//! Hermetic tests for [`vfat_rs::VfatFS::resize`]: the resized images are
+//! checked with the `fatfs` crate and by remounting them.
+
+use std::io::{Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, SectorId, VfatFS, VfatRsError};
+
+const MIB: usize = 1024 * 1024;
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let start = start.min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+/// A `device_mib` image holding a FAT32 volume of `volume_mib`.
+fn fat32_image(device_mib: usize, volume_mib: usize) -> Arc<Mutex<Vec<u8>>> {
+    let mut image = vec![0u8; device_mib * MIB];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .total_sectors((volume_mib * MIB / SECTOR_SIZE) as u32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    Arc::new(Mutex::new(image))
+}
+
+fn mount(image: &Arc<Mutex<Vec<u8>>>) -> VfatFS {
+    VfatFS::new(MemoryBlockDevice(image.clone()), 0).expect("open VfatFS")
+}
+
+fn sectors(mib: usize) -> u32 {
+    (mib * MIB / SECTOR_SIZE) as u32
+}
+
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i as u8).wrapping_mul(31) ^ seed)
+        .collect()
+}
+
+fn write_file(fs: &mut VfatFS, dir: &str, name: &str, content: &[u8]) {
+    let mut dir = fs
+        .get_from_absolute_path(dir.into())
+        .unwrap()
+        .into_directory()
+        .unwrap();
+    let mut file = dir.create_file(name.to_string()).unwrap();
+    file.write(content).unwrap();
+    file.flush().unwrap();
+}
+
+fn open_fatfs(image: &[u8]) -> fatfs::FileSystem<Cursor<Vec<u8>>> {
+    fatfs::FileSystem::new(Cursor::new(image.to_vec()), fatfs::FsOptions::new()).unwrap()
+}
+
+fn read_with_fatfs(image: &[u8], path: &str) -> Vec<u8> {
+    let fs = open_fatfs(image);
+    let mut content = Vec::new();
+    fs.root_dir()
+        .open_file(path)
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    content
+}
+
+fn fat_sectors(image: &[u8], boot_sector: usize) -> u32 {
+    let offset = boot_sector * SECTOR_SIZE + 0x24;
+    u32::from_le_bytes(image[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn grow_extends_the_fat_and_moves_the_data() {
+    let image = fat32_image(64, 48);
+    let small = pattern(5000, 1);
+    let nested = pattern(70_000, 2);
+    {
+        let mut fs = mount(&image);
+        fs.get_root()
+            .unwrap()
+            .create_directory("docs".to_string())
+            .unwrap()
+            .create_directory("deep".to_string())
+            .unwrap();
+        write_file(&mut fs, "/", "small.txt", &small);
+        write_file(&mut fs, "/docs/deep", "nested.bin", &nested);
+    }
+    let old_fat = fat_sectors(&image.lock().unwrap(), 0);
+
+    VfatFS::resize(MemoryBlockDevice(image.clone()), 0, sectors(64)).unwrap();
+
+    let data = image.lock().unwrap().clone();
+    assert!(fat_sectors(&data, 0) > old_fat);
+    assert_eq!(fat_sectors(&data, 6), fat_sectors(&data, 0));
+    assert_eq!(read_with_fatfs(&data, "small.txt"), small);
+    assert_eq!(read_with_fatfs(&data, "docs/deep/nested.bin"), nested);
+
+    let stats = open_fatfs(&data).stats().unwrap();
+    let mut fs = mount(&image);
+    assert_eq!(stats.total_clusters(), fs.cluster_count());
+    assert_eq!(stats.free_clusters(), fs.count_free_clusters().unwrap());
+    let free = stats.free_clusters() as usize * stats.cluster_size() as usize;
+    assert!(free > 60 * MIB);
+
+    let more = pattern(2 * MIB, 3);
+    write_file(&mut fs, "/docs", "more.bin", &more);
+    let data = image.lock().unwrap().clone();
+    assert_eq!(read_with_fatfs(&data, "docs/more.bin"), more);
+}
+
+#[test]
+fn shrink_moves_data_below_the_new_end() {
+    let image = fat32_image(64, 64);
+    let top = pattern(MIB, 4);
+    let inner = pattern(20_000, 5);
+    {
+        let mut fs = mount(&image);
+        // Push the interesting data past 44 MiB, then free the space below.
+        write_file(&mut fs, "/", "filler", &vec![0xEE; 44 * MIB]);
+        write_file(&mut fs, "/", "top.bin", &top);
+        let mut root = fs.get_root().unwrap();
+        let mut high = root.create_directory("high".to_string()).unwrap();
+        high.create_directory("inner".to_string()).unwrap();
+        high.create_directory("sibling".to_string()).unwrap();
+        write_file(&mut fs, "/high/inner", "data.bin", &inner);
+        fs.get_root().unwrap().delete("filler".to_string()).unwrap();
+    }
+
+    VfatFS::resize(MemoryBlockDevice(image.clone()), 0, sectors(40)).unwrap();
+
+    // Nothing may be left past the new end of the volume.
+    image.lock().unwrap().truncate(40 * MIB);
+    let data = image.lock().unwrap().clone();
+    assert_eq!(read_with_fatfs(&data, "top.bin"), top);
+    assert_eq!(read_with_fatfs(&data, "high/inner/data.bin"), inner);
+    // ".." of the moved subdirectory follows its parent.
+    let fatfs = open_fatfs(&data);
+    fatfs.root_dir().open_dir("high/inner/../sibling").unwrap();
+    let stats = fatfs.stats().unwrap();
+    drop(fatfs);
+
+    let mut fs = mount(&image);
+    assert_eq!(fs.cluster_count(), stats.total_clusters());
+    assert_eq!(fs.count_free_clusters().unwrap(), stats.free_clusters());
+    let mut file = fs
+        .get_from_absolute_path("/high/inner/data.bin".into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    let mut buf = vec![0u8; inner.len()];
+    let mut read = 0;
+    while read < buf.len() {
+        read += file.read(&mut buf[read..]).unwrap();
+    }
+    assert_eq!(buf, inner);
+}
+
+#[test]
+fn shrink_refuses_when_the_data_does_not_fit() {
+    let image = fat32_image(64, 64);
+    write_file(&mut mount(&image), "/", "large", &vec![1; 42 * MIB]);
+    let before = image.lock().unwrap().clone();
+
+    let err = VfatFS::resize(MemoryBlockDevice(image.clone()), 0, sectors(40)).unwrap_err();
+    assert!(matches!(err, VfatRsError::VolumeTooSmall { .. }));
+    assert!(*image.lock().unwrap() == before);
+}
+
+#[test]
+fn resize_refuses_unusable_sizes() {
+    let image = fat32_image(48, 48);
+    let before = image.lock().unwrap().clone();
+
+    let err = VfatFS::resize(MemoryBlockDevice(image.clone()), 0, sectors(56)).unwrap_err();
+    assert!(matches!(err, VfatRsError::InvalidResize { .. }));
+    // Below the FAT32 minimum of 65525 clusters.
+    let err = VfatFS::resize(MemoryBlockDevice(image.clone()), 0, sectors(24)).unwrap_err();
+    assert!(matches!(err, VfatRsError::InvalidResize { .. }));
+    assert!(*image.lock().unwrap() == before);
+}
+
+fn read_u16(image: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(image[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(image: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(image[offset..offset + 4].try_into().unwrap())
+}
+
+/// Byte offset of `cluster` in `image`, and the size of a cluster.
+fn cluster_offset(image: &[u8], cluster: u32) -> (usize, usize) {
+    let bytes_per_sector = read_u16(image, 0x0B) as usize;
+    let cluster_size = image[0x0D] as usize * bytes_per_sector;
+    let reserved = read_u16(image, 0x0E) as usize;
+    let fats = image[0x10] as usize;
+    let data_start = (reserved + fats * fat_sectors(image, 0) as usize) * bytes_per_sector;
+    (
+        data_start + (cluster as usize - 2) * cluster_size,
+        cluster_size,
+    )
+}
+
+/// The first cluster of the entry named `short_name` in the directory
+/// starting at `dir_cluster`.
+fn entry_cluster(image: &[u8], dir_cluster: u32, short_name: &[u8; 11]) -> u32 {
+    let (start, cluster_size) = cluster_offset(image, dir_cluster);
+    let entry = image[start..start + cluster_size]
+        .chunks(32)
+        .find(|entry| &entry[..11] == short_name)
+        .expect("entry not found");
+    (read_u16(entry, 20) as u32) << 16 | read_u16(entry, 26) as u32
+}
+
+/// The cluster stored in the `..` entry of the directory at `dir_cluster`.
+fn dotdot_cluster(image: &[u8], dir_cluster: u32) -> u32 {
+    let (start, _) = cluster_offset(image, dir_cluster);
+    let dotdot = &image[start + 32..start + 64];
+    assert_eq!(&dotdot[..11], b"..         ");
+    (read_u16(dotdot, 20) as u32) << 16 | read_u16(dotdot, 26) as u32
+}
+
+/// A volume whose root directory doesn't start at cluster 2, as left by a
+/// resize that moved the root. Cluster 2, the old root, is made free again
+/// and is the next one allocated.
+fn image_with_moved_root() -> (Arc<Mutex<Vec<u8>>>, u32) {
+    let image = fat32_image(48, 48);
+    let mut data = image.lock().unwrap();
+    {
+        let fatfs =
+            fatfs::FileSystem::new(Cursor::new(&mut data[..]), fatfs::FsOptions::new()).unwrap();
+        let cluster_size = fatfs.stats().unwrap().cluster_size() as usize;
+        let mut file = fatfs.root_dir().create_file("NEWROOT.BIN").unwrap();
+        file.write_all(&vec![0u8; cluster_size]).unwrap();
+    }
+    let root = {
+        let inner = &mut data[..];
+        let root = entry_cluster(inner, 2, b"NEWROOT BIN");
+        for boot_sector in [0, 6] {
+            let offset = boot_sector * SECTOR_SIZE + 0x2C;
+            inner[offset..offset + 4].copy_from_slice(&root.to_le_bytes());
+        }
+        // Free the old root: zero its cluster and its FAT entries.
+        let (start, cluster_size) = cluster_offset(inner, 2);
+        inner[start..start + cluster_size].fill(0);
+        let reserved = read_u16(inner, 0x0E) as usize;
+        for fat in 0..inner[0x10] as usize {
+            let offset = (reserved + fat * fat_sectors(inner, 0) as usize) * SECTOR_SIZE + 2 * 4;
+            inner[offset..offset + 4].copy_from_slice(&0u32.to_le_bytes());
+        }
+        // Hand it out first, through the FSInfo next free cluster hint.
+        let fsinfo = read_u16(inner, 0x30) as usize * SECTOR_SIZE + 0x1EC;
+        inner[fsinfo..fsinfo + 4].copy_from_slice(&2u32.to_le_bytes());
+        root
+    };
+    assert_ne!(root, 2);
+    assert_eq!(read_u32(&data, 0x2C), root);
+    drop(data);
+    (image, root)
+}
+
+#[test]
+fn dotdot_entries_follow_a_root_not_at_cluster_2() {
+    let (image, root) = image_with_moved_root();
+    {
+        let mut fs = mount(&image);
+        let mut top = fs
+            .get_root()
+            .unwrap()
+            .create_directory("TOP".to_string())
+            .unwrap();
+        top.create_directory("CHILD".to_string()).unwrap();
+    }
+
+    let data = image.lock().unwrap().clone();
+    let top = entry_cluster(&data, root, b"TOP~1      ");
+    let child = entry_cluster(&data, top, b"CHILD~1    ");
+    // The freed old root is reused for TOP, so CHILD's parent sits at
+    // cluster 2 without being the root.
+    assert_eq!(top, 2);
+    // A subdirectory of the root stores 0 in "..", whatever the root cluster.
+    assert_eq!(dotdot_cluster(&data, top), 0);
+    assert_eq!(dotdot_cluster(&data, child), top);
+    open_fatfs(&data)
+        .root_dir()
+        .open_dir("TOP/CHILD/../../TOP")
+        .unwrap();
+}
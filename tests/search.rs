@@ -0,0 +1,182 @@
+//! Hermetic tests for [`vfat_rs::VfatFS::glob`] and [`vfat_rs::VfatFS::find`].
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::io::ErrorKind;
+use vfat_rs::{
+    BlockDevice, FindCriteria, MountOptions, SectorId, TimeManagerNoop, VfatFS, VfatTimestamp,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn kind<T: std::fmt::Debug>(result: vfat_rs::Result<T>) -> ErrorKind {
+    result.unwrap_err().kind()
+}
+
+fn format_image() -> Vec<u8> {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    let cursor = Cursor::new(&mut image[..]);
+    let options = fatfs::FormatVolumeOptions::new()
+        .fat_type(fatfs::FatType::Fat32)
+        .volume_label(*b"VFATRSTEST ");
+    fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    image
+}
+
+fn fresh_fat32_fs(options: MountOptions) -> VfatFS {
+    let device = MemoryBlockDevice(Arc::new(Mutex::new(format_image())));
+    VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS")
+}
+
+/// /logs/{x.csv, 2024-01/{a.csv, b.txt}, 2024-02/deep/c.csv}, /other/d.csv
+fn sample_tree(options: MountOptions) -> VfatFS {
+    let mut fs = fresh_fat32_fs(options);
+    fs.create_dir_all("/logs/2024-01").unwrap();
+    fs.create_dir_all("/logs/2024-02/deep").unwrap();
+    fs.create_dir_all("/other").unwrap();
+    fs.write("/logs/x.csv", vec![0; 10]).unwrap();
+    fs.write("/logs/2024-01/a.csv", vec![0; 1000]).unwrap();
+    fs.write("/logs/2024-01/b.txt", vec![0; 100]).unwrap();
+    fs.write("/logs/2024-02/deep/c.csv", vec![0; 5000]).unwrap();
+    fs.write("/other/d.csv", b"").unwrap();
+    fs
+}
+
+fn names<I: Iterator<Item = vfat_rs::Result<vfat_rs::WalkEntry>>>(entries: I) -> Vec<String> {
+    let mut names: Vec<String> = entries
+        .map(|entry| entry.unwrap().entry.metadata.name().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn glob_double_star_matches_any_depth() {
+    let mut fs = sample_tree(MountOptions::default());
+    let found = names(fs.glob("/logs/**/*.csv").unwrap());
+    assert_eq!(found, ["a.csv", "c.csv", "x.csv"]);
+    let found = names(fs.glob("/**/deep").unwrap());
+    assert_eq!(found, ["deep"]);
+    let found = names(fs.glob("/*/*.csv").unwrap());
+    assert_eq!(found, ["d.csv", "x.csv"]);
+}
+
+#[test]
+fn glob_wildcards_and_classes() {
+    let mut fs = sample_tree(MountOptions::default());
+    assert_eq!(
+        names(fs.glob("/logs/2024-0[1]/*").unwrap()),
+        ["a.csv", "b.txt"]
+    );
+    assert_eq!(
+        names(fs.glob("/logs/2024-??").unwrap()),
+        ["2024-01", "2024-02"]
+    );
+    assert_eq!(names(fs.glob("/logs/*/?.txt").unwrap()), ["b.txt"]);
+    assert_eq!(names(fs.glob("/logs/x.csv").unwrap()), ["x.csv"]);
+    assert_eq!(
+        names(fs.glob("/logs/./2024-01/../x.csv").unwrap()),
+        ["x.csv"]
+    );
+    assert!(names(fs.glob("/missing/**/*.csv").unwrap()).is_empty());
+    assert_eq!(kind(fs.glob("logs/*").map(|_| ())), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn glob_follows_the_mount_case_sensitivity() {
+    let mut fs = sample_tree(MountOptions::default());
+    assert_eq!(names(fs.glob("/LOGS/*.CSV").unwrap()), ["x.csv"]);
+
+    let mut fs = sample_tree(MountOptions {
+        case_sensitive: true,
+        ..Default::default()
+    });
+    assert!(names(fs.glob("/logs/*.CSV").unwrap()).is_empty());
+    assert_eq!(names(fs.glob("/logs/*.csv").unwrap()), ["x.csv"]);
+}
+
+#[test]
+fn find_filters_on_name_size_kind_and_timestamps() {
+    let mut fs = sample_tree(MountOptions::default());
+    let criteria = FindCriteria::new().files().name("*.CSV").size(100..);
+    let found = names(fs.find("/", move |entry| criteria.matches(entry)));
+    assert_eq!(found, ["a.csv", "c.csv"]);
+
+    let criteria = FindCriteria::new().directories().name("2024-*");
+    let found = names(fs.find("/logs", move |entry| criteria.matches(entry)));
+    assert_eq!(found, ["2024-01", "2024-02"]);
+
+    let criteria = FindCriteria::new().size(..=10).files();
+    let found = names(fs.find("/", move |entry| criteria.matches(entry)));
+    assert_eq!(found, ["d.csv", "x.csv"]);
+
+    // Everything else carries the 1980 epoch of `TimeManagerNoop`.
+    let cutoff = VfatTimestamp::from(1_181_910_620); // 2007-06-15
+    let recent = VfatTimestamp::from(1_619_165_700); // 2021-04-23
+    fs.open("/logs/2024-01/b.txt")
+        .unwrap()
+        .set_timestamps(Some(recent), Some(recent))
+        .unwrap();
+    let criteria = FindCriteria::new().files().modified_after(cutoff);
+    let found = names(fs.find("/", move |entry| criteria.matches(entry)));
+    assert_eq!(found, ["b.txt"]);
+    let criteria = FindCriteria::new().files().modified_before(cutoff);
+    assert_eq!(
+        fs.find("/", move |entry| criteria.matches(entry)).count(),
+        4
+    );
+    let criteria = FindCriteria::new().created_after(cutoff).hidden(false);
+    let found = names(fs.find("/", move |entry| criteria.matches(entry)));
+    assert_eq!(found, ["b.txt"]);
+}
+
+#[test]
+fn find_results_can_be_acted_on_while_searching() {
+    let mut fs = sample_tree(MountOptions::default());
+    let criteria = FindCriteria::new().files().name("*.csv");
+    for entry in fs
+        .clone()
+        .find("/logs", move |entry| criteria.matches(entry))
+    {
+        fs.remove_file(entry.unwrap().path().clone()).unwrap();
+    }
+    assert_eq!(
+        names(fs.walk("/logs").filter(|e| !e.as_ref().unwrap().is_dir())),
+        ["b.txt"]
+    );
+}
@@ -0,0 +1,287 @@
+//! Hermetic tests for secure deletion ([`vfat_rs::MountOptions::secure_delete`],
+//! [`vfat_rs::Directory::secure_delete`], [`vfat_rs::File::secure_truncate`]) and
+//! [`vfat_rs::VfatFS::wipe_free_space`].
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{
+    BlockDevice, Directory, MountOptions, SectorId, TimeManagerNoop, VfatFS, WipePattern,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(options: MountOptions) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    let fs =
+        VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS");
+    (fs, image)
+}
+
+const SECRET: &[u8] = b"TOP-SECRET-PAYLOAD";
+const NAME: &str = "customer-records.csv";
+
+fn secret_content(len: usize) -> Vec<u8> {
+    SECRET.iter().copied().cycle().take(len).collect()
+}
+
+fn image_contains(image: &Arc<Mutex<Vec<u8>>>, needle: &[u8]) -> bool {
+    image
+        .lock()
+        .unwrap()
+        .windows(needle.len())
+        .any(|w| w == needle)
+}
+
+/// The first characters of [`NAME`], as stored in its LFN slot.
+fn name_in_lfn() -> Vec<u8> {
+    NAME[..5]
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Byte ranges of the image holding the data of `path`.
+fn data_ranges(fs: &mut VfatFS, path: &str) -> Vec<std::ops::Range<usize>> {
+    let file = fs
+        .get_from_absolute_path(path.into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    let cluster_size = fs.bytes_per_cluster() as u64;
+    file.extents()
+        .unwrap()
+        .into_iter()
+        .map(|extent| {
+            let start = extent.start_sector.0 as usize * SECTOR_SIZE;
+            start..start + extent.length.div_ceil(cluster_size) as usize * cluster_size as usize
+        })
+        .collect()
+}
+
+fn create_with_content(dir: &mut Directory, name: &str, content: &[u8]) {
+    let mut file = dir.create_file(name.to_string()).unwrap();
+    file.write(content).unwrap();
+    file.flush().unwrap();
+}
+
+#[test]
+fn plain_delete_leaves_data_behind() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    create_with_content(&mut root, NAME, &secret_content(10_000));
+    root.delete(NAME.to_string()).unwrap();
+    assert!(image_contains(&image, SECRET));
+    assert!(image_contains(&image, &name_in_lfn()));
+}
+
+#[test]
+fn secure_delete_wipes_clusters_and_slots() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    create_with_content(&mut root, "keep.txt", b"unrelated");
+    create_with_content(&mut root, NAME, &secret_content(10_000));
+    let ranges = data_ranges(&mut fs, &format!("/{NAME}"));
+    let free_before = fs.count_free_clusters().unwrap();
+
+    root.secure_delete(NAME.to_string(), WipePattern::Zeros)
+        .unwrap();
+
+    let wiped = ranges.iter().map(|r| r.len()).sum::<usize>() / fs.bytes_per_cluster() as usize;
+    assert_eq!(
+        fs.count_free_clusters().unwrap(),
+        free_before + wiped as u32
+    );
+    assert!(!image_contains(&image, SECRET));
+    assert!(!image_contains(&image, &name_in_lfn()));
+    assert!(root.deleted_entries().unwrap().is_empty());
+    assert!(!root.contains(NAME).unwrap());
+    assert!(root.contains("keep.txt").unwrap());
+    {
+        let image = image.lock().unwrap();
+        assert!(
+            ranges
+                .iter()
+                .all(|r| image[r.clone()].iter().all(|&b| b == 0))
+        );
+    }
+    // The scrubbed slots are reused like any deleted slot.
+    create_with_content(&mut root, "next.txt", b"next");
+    assert!(root.contains("next.txt").unwrap());
+}
+
+#[test]
+fn secure_delete_mount_option_applies_to_every_delete() {
+    let options = MountOptions {
+        secure_delete: Some(WipePattern::Byte(0xA5)),
+        ..MountOptions::default()
+    };
+    let (mut fs, image) = fresh_fat32_fs(options);
+    let mut root = fs.get_root().unwrap();
+    let mut dir = root.create_directory("private".to_string()).unwrap();
+    create_with_content(&mut dir, NAME, &secret_content(5_000));
+    let ranges = data_ranges(&mut fs, &format!("/private/{NAME}"));
+
+    dir.delete(NAME.to_string()).unwrap();
+
+    assert!(!image_contains(&image, SECRET));
+    assert!(!image_contains(&image, &name_in_lfn()));
+    let image = image.lock().unwrap();
+    assert!(
+        ranges
+            .iter()
+            .all(|r| image[r.clone()].iter().all(|&b| b == 0xA5))
+    );
+}
+
+#[test]
+fn secure_delete_mount_option_scrubs_renamed_slots() {
+    let options = MountOptions {
+        secure_delete: Some(WipePattern::Zeros),
+        ..MountOptions::default()
+    };
+    let (mut fs, image) = fresh_fat32_fs(options);
+    let mut root = fs.get_root().unwrap();
+    create_with_content(&mut root, NAME, b"content");
+    root.rename(NAME.to_string(), "/renamed.csv".into())
+        .unwrap();
+    assert!(!image_contains(&image, &name_in_lfn()));
+    assert!(root.contains("renamed.csv").unwrap());
+    assert!(!root.contains(NAME).unwrap());
+}
+
+#[test]
+fn secure_truncate_wipes_the_tail() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut root = fs.get_root().unwrap();
+    let content = secret_content(cluster_size * 3);
+    create_with_content(&mut root, NAME, &content);
+    let ranges = data_ranges(&mut fs, &format!("/{NAME}"));
+    assert_eq!(ranges.len(), 1);
+    let start = ranges[0].start;
+
+    let new_size = cluster_size + 100;
+    let mut file = fs
+        .get_from_absolute_path(format!("/{NAME}").as_str().into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    file.secure_truncate(new_size as u32, WipePattern::Zeros)
+        .unwrap();
+
+    let image = image.lock().unwrap();
+    assert_eq!(&image[start..start + new_size], &content[..new_size]);
+    assert!(
+        image[start + new_size..start + cluster_size * 3]
+            .iter()
+            .all(|&b| b == 0)
+    );
+}
+
+#[test]
+fn wipe_free_space_erases_previously_deleted_data() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    create_with_content(&mut root, "keep.bin", &secret_content(3_000));
+    create_with_content(&mut root, NAME, &secret_content(20_000));
+    root.delete(NAME.to_string()).unwrap();
+    let kept = data_ranges(&mut fs, "/keep.bin");
+
+    let wiped = fs.wipe_free_space(WipePattern::Zeros).unwrap();
+    assert_eq!(wiped, fs.count_free_clusters().unwrap());
+
+    let image = image.lock().unwrap();
+    let occurrences = image.windows(SECRET.len()).filter(|w| *w == SECRET).count();
+    // Only the kept file still holds the payload.
+    assert_eq!(occurrences, 3_000 / SECRET.len());
+    assert!(kept.iter().all(|r| image[r.clone()].starts_with(SECRET)));
+}
+
+/// With the sector cache enabled, the wipe must reach the device before the
+/// clusters are freed, not sit in dirty cached sectors.
+#[test]
+fn secure_delete_and_truncate_wipe_the_device_through_the_cache() {
+    let options = MountOptions {
+        cache_capacity: 1024,
+        ..MountOptions::default()
+    };
+    let (mut fs, image) = fresh_fat32_fs(options);
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut root = fs.get_root().unwrap();
+    create_with_content(&mut root, NAME, &secret_content(10_000));
+    create_with_content(&mut root, "tail.csv", &secret_content(cluster_size * 3));
+    let deleted = data_ranges(&mut fs, &format!("/{NAME}"));
+    let truncated = data_ranges(&mut fs, "/tail.csv");
+    assert!(image_contains(&image, SECRET));
+
+    root.secure_delete(NAME.to_string(), WipePattern::Zeros)
+        .unwrap();
+    let mut file = fs
+        .get_from_absolute_path("/tail.csv".into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    file.secure_truncate(cluster_size as u32, WipePattern::Zeros)
+        .unwrap();
+
+    // Read the device as it is now, without flushing the cache.
+    let image = image.lock().unwrap().clone();
+    let name = name_in_lfn();
+    assert!(!image.windows(name.len()).any(|w| w == name));
+    assert!(
+        deleted
+            .iter()
+            .all(|r| image[r.clone()].iter().all(|&b| b == 0))
+    );
+    let start = truncated[0].start;
+    assert!(image[start..start + cluster_size].starts_with(SECRET));
+    assert!(
+        image[start + cluster_size..start + cluster_size * 3]
+            .iter()
+            .all(|&b| b == 0)
+    );
+}
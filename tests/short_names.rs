@@ -0,0 +1,194 @@
+//! Hermetic tests for lookups by 8.3 alias and [`vfat_rs::Metadata::short_name`].
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{
+    BlockDevice, MountOptions, SectorId, TimeManagerNoop, VfatFS, VfatMetadataTrait, VfatRsError,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(options: MountOptions) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    let fs =
+        VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), options).expect("open VfatFS");
+    (fs, image)
+}
+
+fn dir(fs: &mut VfatFS, path: &str) -> vfat_rs::Directory {
+    fs.get_from_absolute_path(path.into())
+        .unwrap()
+        .into_directory()
+        .unwrap()
+}
+
+#[test]
+fn path_resolution_matches_the_short_alias() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_directory("Program Files".into()).unwrap();
+    dir(&mut fs, "/Program Files/")
+        .create_file("configuration.txt".into())
+        .unwrap();
+
+    let entry = fs
+        .get_from_absolute_path("/PROGRA~1/CONFIG~1.TXT".into())
+        .unwrap();
+    assert_eq!(entry.name(), "configuration.txt");
+    assert_eq!(entry.metadata.short_name(), "CONFIG~1.TXT");
+    // Aliases are case-insensitive too.
+    let entry = fs.get_from_absolute_path("/progra~1/".into()).unwrap();
+    assert_eq!(entry.name(), "Program Files");
+    assert_eq!(entry.metadata.short_name(), "PROGRA~1");
+}
+
+#[test]
+fn colliding_aliases_resolve_to_their_own_entries() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("Program Files".into()).unwrap();
+    root.create_file("Program Data".into()).unwrap();
+
+    let first = fs.get_from_absolute_path("/PROGRA~1".into()).unwrap();
+    assert_eq!(first.name(), "Program Files");
+    let second = fs.get_from_absolute_path("/PROGRA~2".into()).unwrap();
+    assert_eq!(second.name(), "Program Data");
+
+    let mut listing: Vec<(String, String)> = root
+        .contents()
+        .unwrap()
+        .iter()
+        .map(|entry| (entry.name().to_string(), entry.metadata.short_name().into()))
+        .filter(|(name, _)| name != "VFATRSTE.ST")
+        .collect();
+    listing.sort();
+    assert_eq!(
+        listing,
+        [
+            ("Program Data".to_string(), "PROGRA~2".to_string()),
+            ("Program Files".to_string(), "PROGRA~1".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn updates_keep_the_alias_and_the_long_name() {
+    let (mut fs, image) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("Program Files".into()).unwrap();
+    let mut file = root.create_file("Program Data".into()).unwrap();
+    // Writing updates the size in the entry.
+    file.write(b"some data").unwrap();
+
+    let entry = fs.get_from_absolute_path("/PROGRA~2".into()).unwrap();
+    assert_eq!(entry.name(), "Program Data");
+    assert_eq!(entry.metadata.size(), 9);
+
+    let image = image.lock().unwrap().clone();
+    let fatfs = fatfs::FileSystem::new(Cursor::new(image), fatfs::FsOptions::new()).unwrap();
+    let entry = fatfs
+        .root_dir()
+        .iter()
+        .map(|entry| entry.unwrap())
+        .find(|entry| entry.short_file_name() == "PROGRA~2")
+        .unwrap();
+    assert_eq!(entry.file_name(), "Program Data");
+    assert_eq!(entry.len(), 9);
+}
+
+#[test]
+fn renaming_from_the_alias_to_the_long_name_keeps_the_entry() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("configuration.txt".into())
+        .unwrap()
+        .write(b"x")
+        .unwrap();
+
+    root.rename("CONFIG~1.TXT".into(), "/configuration.txt".into())
+        .unwrap();
+
+    let entry = fs
+        .get_from_absolute_path("/configuration.txt".into())
+        .unwrap();
+    assert_eq!(entry.metadata.size(), 1);
+}
+
+#[test]
+fn moving_a_directory_below_its_own_alias_is_refused() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    fs.create_dir_all("/Program Files/inner").unwrap();
+    let alias = dir(&mut fs, "/Program Files/")
+        .metadata()
+        .short_name()
+        .to_string();
+    assert_eq!(alias, "PROGRA~1");
+
+    let err = fs
+        .rename("/Program Files", format!("/{alias}/inner/inside"))
+        .unwrap_err();
+    assert!(matches!(err, VfatRsError::CircularMove { .. }), "{err:?}");
+    assert!(
+        fs.metadata("/Program Files/inner")
+            .unwrap()
+            .attributes()
+            .is_directory()
+    );
+}
+
+#[test]
+fn a_name_equal_to_an_alias_is_in_use() {
+    let (mut fs, _) = fresh_fat32_fs(MountOptions::default());
+    let mut root = fs.get_root().unwrap();
+    root.create_file("configuration.txt".into()).unwrap();
+
+    let err = root.create_file("config~1.txt".into()).unwrap_err();
+    assert!(
+        matches!(err, VfatRsError::NameAlreadyInUse { .. }),
+        "{err:?}"
+    );
+}
@@ -0,0 +1,299 @@
+//! Hermetic tests for the recursive tree operations: [`vfat_rs::VfatFS::walk`],
+//! `remove_dir_all` and `copy_dir`.
+
+use std::io::{Cursor, Write};
+use std::sync::{Arc, Mutex};
+
+use fatfs::{FileAttributes, FileSystem, FsOptions};
+use vfat_rs::io::ErrorKind;
+use vfat_rs::{
+    BlockDevice, MountOptions, PathBuf, SectorId, TimeManagerNoop, VfatFS, VfatTimestamp, WalkOrder,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn kind<T: std::fmt::Debug>(result: vfat_rs::Result<T>) -> ErrorKind {
+    result.unwrap_err().kind()
+}
+
+fn format_image() -> Vec<u8> {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    let cursor = Cursor::new(&mut image[..]);
+    let options = fatfs::FormatVolumeOptions::new()
+        .fat_type(fatfs::FatType::Fat32)
+        .volume_label(*b"VFATRSTEST ");
+    fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    image
+}
+
+fn mount(image: Vec<u8>) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    let fs = VfatFS::new_with_options(device, 0, TimeManagerNoop::new(), MountOptions::default())
+        .expect("open VfatFS");
+    (fs, image)
+}
+
+fn fresh_fat32_fs() -> VfatFS {
+    mount(format_image()).0
+}
+
+/// /a/{x.txt, b/{y.txt, c/}}, /z.txt
+fn sample_tree(fs: &mut VfatFS) {
+    fs.create_dir_all("/a/b/c").unwrap();
+    fs.write("/a/x.txt", b"x").unwrap();
+    fs.write("/a/b/y.txt", b"y").unwrap();
+    fs.write("/z.txt", b"z").unwrap();
+}
+
+/// The paths and depths of the entries of `walk`. Directory paths end with a
+/// separator, so they are compared as paths rather than as strings.
+fn walked(walk: vfat_rs::Walk) -> Vec<(PathBuf, usize)> {
+    walk.map(|entry| {
+        let entry = entry.unwrap();
+        (entry.path().clone(), entry.depth)
+    })
+    .collect()
+}
+
+fn paths(expected: &[(&str, usize)]) -> Vec<(PathBuf, usize)> {
+    expected
+        .iter()
+        .map(|(path, depth)| (PathBuf::from(*path), *depth))
+        .collect()
+}
+
+#[test]
+fn walk_depth_first_visits_subtrees_in_order() {
+    let mut fs = fresh_fat32_fs();
+    sample_tree(&mut fs);
+    let mut found = walked(fs.walk("/a"));
+    // Siblings come in directory order: sort them, keeping each subtree
+    // right after its directory.
+    assert_eq!(found[0], (PathBuf::from("/a"), 0));
+    let b = found
+        .iter()
+        .position(|(path, _)| path == &PathBuf::from("/a/b"));
+    let b = b.unwrap();
+    assert_eq!(found[b + 1].1, 2);
+    assert_eq!(found[b + 2].1, 2);
+    found[b + 1..b + 3].sort();
+    assert_eq!(
+        found[b..b + 3],
+        paths(&[("/a/b", 1), ("/a/b/c", 2), ("/a/b/y.txt", 2)])
+    );
+    found.sort();
+    assert_eq!(
+        found,
+        paths(&[
+            ("/a", 0),
+            ("/a/b", 1),
+            ("/a/b/c", 2),
+            ("/a/b/y.txt", 2),
+            ("/a/x.txt", 1),
+        ])
+    );
+}
+
+#[test]
+fn walk_breadth_first_goes_level_by_level() {
+    let mut fs = fresh_fat32_fs();
+    sample_tree(&mut fs);
+    let mut found = walked(fs.walk("/").order(WalkOrder::BreadthFirst));
+    let depths: Vec<usize> = found.iter().map(|(_, depth)| *depth).collect();
+    assert!(depths.is_sorted(), "not level by level: {found:?}");
+    found.sort();
+    assert_eq!(
+        found,
+        paths(&[
+            ("/", 0),
+            ("/a", 1),
+            ("/a/b", 2),
+            ("/a/b/c", 3),
+            ("/a/b/y.txt", 3),
+            ("/a/x.txt", 2),
+            ("/z.txt", 1),
+        ])
+    );
+}
+
+#[test]
+fn walk_honours_max_depth_and_prunes_filtered_directories() {
+    let mut fs = fresh_fat32_fs();
+    sample_tree(&mut fs);
+    let mut shallow = walked(fs.walk("/").max_depth(1));
+    shallow.sort();
+    assert_eq!(shallow, paths(&[("/", 0), ("/a", 1), ("/z.txt", 1)]));
+
+    let mut pruned = walked(
+        fs.walk("/a")
+            .filter_entry(|entry| entry.entry.metadata.name() != "b"),
+    );
+    pruned.sort();
+    assert_eq!(pruned, paths(&[("/a", 0), ("/a/x.txt", 1)]));
+
+    // Filters combine: files at most two levels down, i.e. /z.txt and
+    // /a/x.txt.
+    let files = fs
+        .walk("/")
+        .filter_entry(|entry| entry.depth <= 2)
+        .filter_entry(|entry| entry.is_dir() || entry.entry.metadata.name().ends_with(".txt"))
+        .filter_map(|entry| entry.ok().filter(|entry| !entry.is_dir()))
+        .count();
+    assert_eq!(files, 2);
+    assert_eq!(
+        kind(fs.walk("/missing").next().unwrap()),
+        ErrorKind::NotFound
+    );
+}
+
+/// A tree much deeper than any recursive implementation could handle on a
+/// small kernel stack is removed, and all its clusters are released.
+#[test]
+fn remove_dir_all_handles_deep_trees() {
+    let mut fs = fresh_fat32_fs();
+    let free_before = fs.free_space_stats().unwrap().free_clusters;
+    let mut directory = fs.create_dir("/deep").unwrap();
+    for level in 0..300 {
+        let mut file = directory.create_file(format!("f{level}.txt")).unwrap();
+        file.write(b"data").unwrap();
+        directory = directory.create_directory("d".to_string()).unwrap();
+    }
+    assert_eq!(fs.walk("/deep").count(), 601);
+
+    fs.remove_dir_all("/deep").unwrap();
+    assert!(!fs.exists("/deep").unwrap());
+    assert_eq!(fs.free_space_stats().unwrap().free_clusters, free_before);
+}
+
+#[test]
+fn copy_dir_preserves_contents_timestamps_and_attributes() {
+    let mut image = format_image();
+    {
+        let fatfs = FileSystem::new(Cursor::new(&mut image[..]), FsOptions::new()).unwrap();
+        let root = fatfs.root_dir();
+        let src = root.create_dir("src").unwrap();
+        src.create_file("archived.txt")
+            .unwrap()
+            .write_all(b"archived")
+            .unwrap();
+        src.create_dir("nested").unwrap();
+    }
+    // Neither fatfs nor vfat-rs can set attributes yet: patch the short entry.
+    let slot = image
+        .windows(11)
+        .position(|name| name == b"ARCHIVEDTXT")
+        .unwrap();
+    image[slot + 11] |= (FileAttributes::HIDDEN | FileAttributes::ARCHIVE).bits();
+    let (mut fs, image) = mount(image);
+    let big: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+    fs.write("/src/nested/big.bin", &big).unwrap();
+    let stamp = VfatTimestamp::from(1_181_910_620);
+    fs.open("/src/nested/big.bin")
+        .unwrap()
+        .set_timestamps(Some(stamp), Some(stamp))
+        .unwrap();
+
+    let copied = fs.copy_dir("/src", "/dst").unwrap();
+    assert_eq!(copied, big.len() as u64 + 8);
+    assert_eq!(fs.read("/dst/archived.txt").unwrap(), b"archived");
+    assert_eq!(fs.read("/dst/nested/big.bin").unwrap(), big);
+    let copy = fs.metadata("/dst/nested/big.bin").unwrap();
+    assert_eq!(
+        copy.created().to_unix_timestamp(),
+        stamp.to_unix_timestamp()
+    );
+    assert_eq!(
+        copy.modified().to_unix_timestamp(),
+        stamp.to_unix_timestamp()
+    );
+
+    let image = image.lock().unwrap().clone();
+    let fatfs = FileSystem::new(Cursor::new(image), FsOptions::new()).unwrap();
+    let attributes = |path: &str| {
+        let (parent, name) = path.rsplit_once('/').unwrap();
+        fatfs
+            .root_dir()
+            .open_dir(parent)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.unwrap())
+            .find(|entry| entry.file_name() == name)
+            .unwrap()
+            .attributes()
+    };
+    let expected = FileAttributes::HIDDEN | FileAttributes::ARCHIVE;
+    assert_eq!(attributes("src/archived.txt"), expected);
+    assert_eq!(
+        attributes("dst/archived.txt"),
+        attributes("src/archived.txt")
+    );
+    assert_eq!(attributes("dst/nested"), attributes("src/nested"));
+}
+
+#[test]
+fn copy_dir_refuses_to_copy_into_itself() {
+    let mut fs = fresh_fat32_fs();
+    sample_tree(&mut fs);
+    assert_eq!(
+        kind(fs.copy_dir("/a", "/a/b/copy")),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(kind(fs.copy_dir("/a", "/A/copy")), ErrorKind::InvalidInput);
+    assert_eq!(kind(fs.copy_dir("/a", "/z.txt")), ErrorKind::AlreadyExists);
+    assert_eq!(
+        kind(fs.copy_dir("/z.txt", "/copy")),
+        ErrorKind::NotADirectory
+    );
+    assert!(!fs.exists("/a/b/copy").unwrap());
+}
+
+#[test]
+fn copy_dir_to_copies_between_volumes() {
+    let mut source = fresh_fat32_fs();
+    let mut destination = fresh_fat32_fs();
+    sample_tree(&mut source);
+    let copied = source
+        .copy_dir_to("/a", &mut destination, "/backup")
+        .unwrap();
+    assert_eq!(copied, 2);
+    assert_eq!(destination.read("/backup/x.txt").unwrap(), b"x");
+    assert_eq!(destination.read("/backup/b/y.txt").unwrap(), b"y");
+    assert!(destination.exists("/backup/b/c").unwrap());
+    assert!(!destination.exists("/z.txt").unwrap());
+    assert!(!source.exists("/backup").unwrap());
+}
@@ -0,0 +1,193 @@
+//! Hermetic tests for [`vfat_rs::Directory::deleted_entries`] and
+//! [`vfat_rs::Directory::undelete`].
+
+use std::io::{Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, Directory, SectorId, VfatFS, VfatRsError};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(size_mib: usize) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; size_mib * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    (VfatFS::new(device, 0).expect("open VfatFS"), image)
+}
+
+fn read_all(fs: &mut VfatFS, path: &str) -> Vec<u8> {
+    let mut file = fs
+        .get_from_absolute_path(path.into())
+        .unwrap()
+        .into_file()
+        .unwrap();
+    let mut buf = vec![0u8; file.metadata().size()];
+    let mut read = 0;
+    while read < buf.len() {
+        read += file.read(&mut buf[read..]).unwrap();
+    }
+    buf
+}
+
+fn create_with_content(dir: &mut Directory, name: &str, content: &[u8]) {
+    let mut file = dir.create_file(name.to_string()).unwrap();
+    file.write(content).unwrap();
+    file.flush().unwrap();
+}
+
+#[test]
+fn deleted_file_is_listed_and_restored() {
+    let (mut fs, image) = fresh_fat32_fs(48);
+    let content: Vec<u8> = (0..10_000u32).map(|i| (i % 253) as u8).collect();
+    let name = "sensor-log-2024-06-01.csv";
+    let mut root = fs.get_root().unwrap();
+    create_with_content(&mut root, "keep.txt", b"keep");
+    create_with_content(&mut root, name, &content);
+    root.delete(name.to_string()).unwrap();
+    assert!(!root.contains(name).unwrap());
+
+    let deleted = root.deleted_entries().unwrap();
+    assert_eq!(deleted.len(), 1);
+    let entry = &deleted[0];
+    assert_eq!(entry.name(), name);
+    assert!(entry.is_name_complete());
+    assert_eq!(entry.size(), content.len());
+    assert!(!entry.is_dir());
+
+    let free_before = fs.count_free_clusters().unwrap();
+    let restored = root.undelete(entry, entry.name().to_string()).unwrap();
+    assert_eq!(restored.metadata().size(), content.len());
+    assert_eq!(
+        fs.count_free_clusters().unwrap(),
+        free_before - content.len().div_ceil(fs.bytes_per_cluster() as usize) as u32
+    );
+    assert_eq!(read_all(&mut fs, &format!("/{name}")), content);
+    assert!(root.deleted_entries().unwrap().is_empty());
+
+    // Another FAT implementation sees the restored file too.
+    let mut image = image.lock().unwrap();
+    let fatfs =
+        fatfs::FileSystem::new(Cursor::new(&mut image[..]), fatfs::FsOptions::new()).unwrap();
+    let mut read_back = Vec::new();
+    fatfs
+        .root_dir()
+        .open_file(name)
+        .unwrap()
+        .read_to_end(&mut read_back)
+        .unwrap();
+    assert_eq!(read_back, content);
+}
+
+#[test]
+fn short_name_only_entry_loses_its_first_character() {
+    let (mut fs, image) = fresh_fat32_fs(48);
+    {
+        let mut image = image.lock().unwrap();
+        let fatfs =
+            fatfs::FileSystem::new(Cursor::new(&mut image[..]), fatfs::FsOptions::new()).unwrap();
+        let mut file = fatfs.root_dir().create_file("DATA.TXT").unwrap();
+        file.write_all(b"plain short name").unwrap();
+    }
+    let mut root = fs.get_root().unwrap();
+    root.delete("DATA.TXT".to_string()).unwrap();
+    {
+        // fatfs always adds an LFN slot: move the short entry over it, as if
+        // it had been written by a tool unaware of long names.
+        let mut image = image.lock().unwrap();
+        let short = image
+            .windows(11)
+            .position(|w| w == b"\xE5ATA    TXT")
+            .unwrap();
+        assert_eq!(image[short - 32 + 11], 0x0F);
+        image.copy_within(short..short + 32, short - 32);
+        image[short..short + 32].fill(0);
+    }
+
+    let deleted = root.deleted_entries().unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].name(), "_ATA.TXT");
+    assert!(!deleted[0].is_name_complete());
+
+    root.undelete(&deleted[0], "DATA.TXT".to_string()).unwrap();
+    assert_eq!(read_all(&mut fs, "/DATA.TXT"), b"plain short name");
+}
+
+#[test]
+fn undelete_fails_once_the_clusters_are_reused() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut root = fs.get_root().unwrap();
+    create_with_content(&mut root, "old.bin", &vec![1; cluster_size * 3]);
+    root.delete("old.bin".to_string()).unwrap();
+    let deleted = root.deleted_entries().unwrap();
+    // The new file takes the freed clusters back.
+    create_with_content(&mut root, "new.bin", &vec![2; cluster_size]);
+
+    let free_before = fs.count_free_clusters().unwrap();
+    let err = root
+        .undelete(&deleted[0], "old.bin".to_string())
+        .unwrap_err();
+    assert!(matches!(err, VfatRsError::UndeleteFailed { .. }));
+    assert!(!root.contains("old.bin").unwrap());
+    assert_eq!(fs.count_free_clusters().unwrap(), free_before);
+}
+
+#[test]
+fn undelete_refuses_a_name_in_use() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let mut root = fs.get_root().unwrap();
+    create_with_content(&mut root, "other.txt", b"second");
+    create_with_content(&mut root, "report.txt", b"first");
+    root.delete("report.txt".to_string()).unwrap();
+    let deleted = root.deleted_entries().unwrap();
+
+    let err = root
+        .undelete(&deleted[0], "other.txt".to_string())
+        .unwrap_err();
+    assert!(matches!(err, VfatRsError::NameAlreadyInUse { .. }));
+
+    root.undelete(&deleted[0], "report-restored.txt".to_string())
+        .unwrap();
+    assert_eq!(read_all(&mut fs, "/report-restored.txt"), b"first");
+}
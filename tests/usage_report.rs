@@ -0,0 +1,179 @@
+//! Hermetic tests for the fragmentation and space-usage reports
+//! ([`VfatFS::analyze`], [`VfatFS::free_space_stats`] and [`VfatFS::disk_usage`]).
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, DefragOptions, FileUsage, PathBuf, SectorId, VfatFS};
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn fresh_fat32_fs(size_mib: usize) -> (VfatFS, Arc<Mutex<Vec<u8>>>) {
+    let mut image = vec![0u8; size_mib * 1024 * 1024];
+    {
+        let cursor = Cursor::new(&mut image[..]);
+        let options = fatfs::FormatVolumeOptions::new()
+            .fat_type(fatfs::FatType::Fat32)
+            .volume_label(*b"VFATRSTEST ");
+        fatfs::format_volume(cursor, options).expect("format FAT32 image");
+    }
+    let image = Arc::new(Mutex::new(image));
+    let device = MemoryBlockDevice(image.clone());
+    (VfatFS::new(device, 0).expect("open VfatFS"), image)
+}
+
+/// Write `clusters` full clusters to each of `names` in round-robin order, so
+/// every file ends up with one fragment per cluster.
+fn write_interleaved(fs: &mut VfatFS, names: &[&str], clusters: usize) {
+    let cluster_size = fs.bytes_per_cluster() as usize;
+    let mut root = fs.get_root().unwrap();
+    let mut files: Vec<_> = names
+        .iter()
+        .map(|name| root.create_file(name.to_string()).unwrap())
+        .collect();
+    for _ in 0..clusters {
+        for file in files.iter_mut() {
+            file.write(&vec![0xAB; cluster_size]).unwrap();
+        }
+    }
+}
+
+fn usage_of(fs: &mut VfatFS, path: &str) -> FileUsage {
+    let path = PathBuf::from(path);
+    let mut found = None;
+    fs.analyze(|usage| {
+        if usage.path == path {
+            found = Some(usage.clone());
+        }
+    })
+    .unwrap();
+    found.unwrap_or_else(|| panic!("{} not reported", path.display()))
+}
+
+#[test]
+fn fresh_volume_has_a_single_free_run() {
+    let (fs, _image) = fresh_fat32_fs(48);
+    let stats = fs.free_space_stats().unwrap();
+    assert_eq!(stats.free_clusters, fs.count_free_clusters().unwrap());
+    assert_eq!(stats.free_runs, 1);
+    assert_eq!(stats.largest_free_run, stats.free_clusters);
+    assert_eq!(stats.histogram.iter().sum::<u32>(), 1);
+    assert_eq!(stats.histogram[stats.free_clusters.ilog2() as usize], 1);
+}
+
+#[test]
+fn analyze_counts_fragments_and_slack() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster() as u64;
+    write_interleaved(&mut fs, &["a.bin", "b.bin"], 5);
+    let mut root = fs.get_root().unwrap();
+    root.create_file("tiny.txt".to_string())
+        .unwrap()
+        .write(b"x")
+        .unwrap();
+    root.create_file("empty.txt".to_string()).unwrap();
+
+    let a = usage_of(&mut fs, "/a.bin");
+    assert_eq!(a.clusters, 5);
+    assert_eq!(a.fragments, 5);
+    assert_eq!(a.slack_bytes, 0);
+
+    let tiny = usage_of(&mut fs, "/tiny.txt");
+    assert_eq!((tiny.clusters, tiny.fragments), (1, 1));
+    assert_eq!(tiny.slack_bytes, cluster_size - 1);
+
+    let empty = usage_of(&mut fs, "/empty.txt");
+    assert_eq!(
+        (empty.clusters, empty.fragments, empty.slack_bytes),
+        (0, 0, 0)
+    );
+
+    let totals = fs.analyze(|_| {}).unwrap();
+    assert_eq!(totals.files, 4);
+    assert_eq!(totals.directories, 1);
+    assert_eq!(totals.fragmented_entries, 2);
+    assert_eq!(totals.slack_bytes, cluster_size - 1);
+    assert_eq!(totals.free.free_clusters, fs.count_free_clusters().unwrap());
+
+    // Deleting `b` leaves holes; defragmenting `a` makes it one fragment.
+    fs.get_root().unwrap().delete("b.bin".to_string()).unwrap();
+    assert!(fs.free_space_stats().unwrap().free_runs > 1);
+    fs.defragment(DefragOptions::default()).unwrap();
+    assert_eq!(usage_of(&mut fs, "/a.bin").fragments, 1);
+}
+
+#[test]
+fn disk_usage_sums_subtrees() {
+    let (mut fs, _image) = fresh_fat32_fs(48);
+    let cluster_size = fs.bytes_per_cluster() as u64;
+    let mut root = fs.get_root().unwrap();
+    let mut logs = root.create_directory("logs".to_string()).unwrap();
+    logs.create_file("one.log".to_string())
+        .unwrap()
+        .write(&[1; 10])
+        .unwrap();
+    let mut old = logs.create_directory("old".to_string()).unwrap();
+    old.create_file("two.log".to_string())
+        .unwrap()
+        .write(&[2; 20])
+        .unwrap();
+    root.create_file("top.txt".to_string())
+        .unwrap()
+        .write(&[3; 5])
+        .unwrap();
+
+    let mut visited = Vec::new();
+    let logs_usage = fs
+        .disk_usage("/logs".into(), |path, usage| {
+            visited.push((path.display().to_string(), *usage))
+        })
+        .unwrap();
+    assert_eq!(logs_usage.files, 2);
+    assert_eq!(logs_usage.directories, 1);
+    assert_eq!(logs_usage.apparent_bytes, 30);
+    // Two directories and two files, one cluster each.
+    assert_eq!(logs_usage.allocated_bytes, 4 * cluster_size);
+    // Children are reported before their parent.
+    assert_eq!(visited.len(), 2);
+    assert_eq!(visited[0].0, "/logs/old/");
+    assert_eq!(visited[0].1.apparent_bytes, 20);
+    assert_eq!(visited[1].1, logs_usage);
+
+    let total = fs.disk_usage("/".into(), |_, _| {}).unwrap();
+    assert_eq!(total.files, 3);
+    assert_eq!(total.apparent_bytes, 35);
+}
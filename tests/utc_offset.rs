@@ -0,0 +1,234 @@
+//! Hermetic tests for storing timestamps in local time, with a UTC offset
+//! fixed or computed at mount.
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{
+    AccessTimePolicy, BlockDevice, FileTimes, MountOptions, SectorId, TimeManagerTrait, UtcOffset,
+    VfatFS, VfatTimestamp,
+};
+
+/// 2024-03-10 12:00:00 UTC.
+const NOON: u64 = 1_710_072_000;
+/// 2024-07-10 12:00:00 UTC.
+const SUMMER_NOON: u64 = 1_720_612_800;
+const HOUR: u64 = 60 * 60;
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+/// A clock the tests move forward.
+#[derive(Debug, Clone, Default)]
+struct Clock(Arc<AtomicU64>);
+
+impl Clock {
+    fn set(&self, seconds: u64) {
+        self.0.store(seconds, Ordering::SeqCst);
+    }
+}
+
+impl TimeManagerTrait for Clock {
+    fn get_current_timestamp(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+struct Volume {
+    image: Arc<Mutex<Vec<u8>>>,
+    clock: Clock,
+}
+
+impl Volume {
+    fn new() -> Self {
+        let mut image = vec![0u8; 48 * 1024 * 1024];
+        {
+            let cursor = Cursor::new(&mut image[..]);
+            let options = fatfs::FormatVolumeOptions::new()
+                .fat_type(fatfs::FatType::Fat32)
+                .volume_label(*b"VFATRSTEST ");
+            fatfs::format_volume(cursor, options).expect("format FAT32 image");
+        }
+        let clock = Clock::default();
+        clock.set(NOON);
+        Self {
+            image: Arc::new(Mutex::new(image)),
+            clock,
+        }
+    }
+
+    fn mount(&self, utc_offset: UtcOffset) -> VfatFS {
+        let device = MemoryBlockDevice(self.image.clone());
+        let options = MountOptions {
+            utc_offset,
+            access_time: AccessTimePolicy::Strict,
+            ..MountOptions::default()
+        };
+        VfatFS::new_with_options(device, 0, self.clock.clone(), options).expect("open VfatFS")
+    }
+
+    /// The entry of `name` in the root directory, as another tool sees it.
+    fn fatfs_entry<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&fatfs::DirEntry<Cursor<Vec<u8>>>) -> T,
+    ) -> T {
+        let image = self.image.lock().unwrap().clone();
+        let fatfs = fatfs::FileSystem::new(Cursor::new(image), fatfs::FsOptions::new()).unwrap();
+        let entry = fatfs
+            .root_dir()
+            .iter()
+            .map(Result::unwrap)
+            .find(|entry| entry.file_name() == name)
+            .unwrap();
+        f(&entry)
+    }
+}
+
+/// Central European Time, with daylight saving time from the last Sunday
+/// of March (2024-03-31) to the last Sunday of October (2024-10-27).
+fn central_europe(unix: u64) -> i16 {
+    const SUMMER_START: u64 = 1_711_846_800;
+    const SUMMER_END: u64 = 1_729_990_800;
+    if (SUMMER_START..SUMMER_END).contains(&unix) {
+        120
+    } else {
+        60
+    }
+}
+
+#[test]
+fn timestamps_are_stored_in_local_time() {
+    let volume = Volume::new();
+    let mut fs = volume.mount(UtcOffset::Fixed(120));
+    let file = fs.create("/file.txt").unwrap();
+    assert_eq!(file.metadata().created(), VfatTimestamp::from(NOON));
+    assert_eq!(file.metadata().modified(), VfatTimestamp::from(NOON));
+    drop((file, fs));
+
+    let (created, modified) = volume.fatfs_entry("file.txt", |entry| {
+        let (created, modified) = (entry.created().time, entry.modified().time);
+        (created.hour, modified.hour)
+    });
+    assert_eq!((created, modified), (14, 14));
+
+    // Read back with the offset, and as UTC.
+    let mut fs = volume.mount(UtcOffset::Fixed(120));
+    let metadata = fs.metadata("/file.txt").unwrap();
+    assert_eq!(metadata.modified(), VfatTimestamp::from(NOON));
+    assert_eq!(metadata.created_unix_millis(), NOON * 1000);
+    let mut fs = volume.mount(UtcOffset::default());
+    let metadata = fs.metadata("/file.txt").unwrap();
+    assert_eq!(metadata.modified(), VfatTimestamp::from(NOON + 2 * HOUR));
+}
+
+#[test]
+fn set_times_round_trip_through_the_offset() {
+    let volume = Volume::new();
+    let mut fs = volume.mount(UtcOffset::Fixed(-300));
+    fs.write("/file.txt", b"content").unwrap();
+    let modified = VfatTimestamp::from(NOON - 48 * HOUR);
+    fs.open("/file.txt")
+        .unwrap()
+        .set_times(FileTimes {
+            modified: Some(modified),
+            ..FileTimes::default()
+        })
+        .unwrap();
+    drop(fs);
+
+    let hour = volume.fatfs_entry("file.txt", |entry| entry.modified().time.hour);
+    assert_eq!(hour, 7);
+    let mut fs = volume.mount(UtcOffset::Fixed(-300));
+    assert_eq!(fs.metadata("/file.txt").unwrap().modified(), modified);
+}
+
+#[test]
+fn the_access_date_is_the_local_day() {
+    let volume = Volume::new();
+    let offset = UtcOffset::Fixed(600);
+    let mut fs = volume.mount(offset);
+    fs.write("/file.txt", b"content").unwrap();
+
+    // 20:00 UTC is already the next day, 06:00, at UTC+10.
+    volume.clock.set(NOON + 8 * HOUR);
+    fs.read("/file.txt").unwrap();
+    let local_midnight = VfatTimestamp::from(NOON + 12 * HOUR - 10 * HOUR);
+    assert_eq!(fs.metadata("/file.txt").unwrap().accessed(), local_midnight);
+    drop(fs);
+
+    let accessed = volume.fatfs_entry("file.txt", |entry| entry.accessed());
+    assert_eq!((accessed.year, accessed.month, accessed.day), (2024, 3, 11));
+    let mut fs = volume.mount(offset);
+    assert_eq!(fs.metadata("/file.txt").unwrap().accessed(), local_midnight);
+}
+
+#[test]
+fn a_dynamic_offset_follows_daylight_saving_time() {
+    let volume = Volume::new();
+    let mut fs = volume.mount(UtcOffset::Dynamic(central_europe));
+    fs.create("/winter.txt").unwrap();
+    volume.clock.set(SUMMER_NOON);
+    fs.create("/summer.txt").unwrap();
+    drop(fs);
+
+    let winter = volume.fatfs_entry("winter.txt", |entry| entry.modified().time.hour);
+    let summer = volume.fatfs_entry("summer.txt", |entry| entry.modified().time.hour);
+    assert_eq!((winter, summer), (13, 14));
+
+    let mut fs = volume.mount(UtcOffset::Dynamic(central_europe));
+    let winter = fs.metadata("/winter.txt").unwrap().modified();
+    let summer = fs.metadata("/summer.txt").unwrap().modified();
+    assert_eq!(winter, VfatTimestamp::from(NOON));
+    assert_eq!(summer, VfatTimestamp::from(SUMMER_NOON));
+}
+
+#[test]
+fn exfat_offset_bytes() {
+    let utc_plus_one = UtcOffset::from_exfat(0x80 | 4).unwrap();
+    assert_eq!(utc_plus_one.minutes_at(NOON), 60);
+    let utc_minus_five_thirty = UtcOffset::from_exfat(0x80 | (-22i8 as u8 & 0x7F)).unwrap();
+    assert_eq!(utc_minus_five_thirty.minutes_at(NOON), -330);
+    assert!(UtcOffset::from_exfat(4).is_none());
+
+    assert_eq!(UtcOffset::Fixed(-60).to_exfat(NOON), 0xFC);
+    assert_eq!(
+        UtcOffset::Dynamic(central_europe).to_exfat(SUMMER_NOON),
+        0x88
+    );
+    assert_eq!(UtcOffset::default().to_exfat(NOON), 0x80);
+}
@@ -847,7 +847,7 @@ fn test_disk_full_graceful() -> vfat_rs::Result<()> {
         let name = format!("fill{}.txt", i);
         match root.create_file(name) {
             Ok(mut f) => {
-                if let Err(_) = f.write(b"x") {
+                if f.write(b"x").is_err() {
                     disk_full = true;
                     break;
                 }
@@ -0,0 +1,171 @@
+//! Hermetic tests for the volume label, serial number and layout report.
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use vfat_rs::{BlockDevice, FatType, SectorId, VfatFS, VfatRsError};
+
+/// Sector of the backup boot sector, as fatfs lays it out.
+const BACKUP_BOOT_SECTOR: usize = 6;
+const BPB_VOLUME_LABEL: usize = 0x47;
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone)]
+struct MemoryBlockDevice(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryBlockDevice {
+    fn read_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> vfat_rs::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        let available = data.len().saturating_sub(start);
+        let n = buf.len().min(available);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    fn write_sector_offset(
+        &mut self,
+        sector: SectorId,
+        offset: usize,
+        buf: &[u8],
+    ) -> vfat_rs::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = sector.0 as usize * SECTOR_SIZE + offset;
+        if start + buf.len() > data.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+fn format(options: fatfs::FormatVolumeOptions) -> Arc<Mutex<Vec<u8>>> {
+    let mut image = vec![0u8; 48 * 1024 * 1024];
+    let cursor = Cursor::new(&mut image[..]);
+    fatfs::format_volume(cursor, options.fat_type(fatfs::FatType::Fat32))
+        .expect("format FAT32 image");
+    Arc::new(Mutex::new(image))
+}
+
+fn labelled() -> Arc<Mutex<Vec<u8>>> {
+    format(
+        fatfs::FormatVolumeOptions::new()
+            .volume_label(*b"VFATRSTEST ")
+            .volume_id(0x1234_ABCD),
+    )
+}
+
+fn mount(image: &Arc<Mutex<Vec<u8>>>) -> VfatFS {
+    VfatFS::new(MemoryBlockDevice(image.clone()), 0).expect("open VfatFS")
+}
+
+/// The labels of the root directory entry and the boot sector, as another
+/// tool reads them.
+fn fatfs_labels(image: &Arc<Mutex<Vec<u8>>>) -> (Option<String>, String) {
+    let image = image.lock().unwrap().clone();
+    let fatfs = fatfs::FileSystem::new(Cursor::new(image), fatfs::FsOptions::new()).unwrap();
+    (
+        fatfs.read_volume_label_from_root_dir().unwrap(),
+        fatfs.volume_label(),
+    )
+}
+
+fn backup_label(image: &Arc<Mutex<Vec<u8>>>) -> Vec<u8> {
+    let start = BACKUP_BOOT_SECTOR * SECTOR_SIZE + BPB_VOLUME_LABEL;
+    image.lock().unwrap()[start..start + 11].to_vec()
+}
+
+#[test]
+fn info_reports_the_identity_and_layout() {
+    let image = labelled();
+    let mut fs = mount(&image);
+    let info = fs.info().unwrap();
+    assert_eq!(info.label.as_deref(), Some("VFATRSTEST"));
+    assert_eq!(info.serial_number, 0x1234_ABCD);
+    assert_eq!(info.oem_name, "MSWIN4.1");
+    assert_eq!(info.fat_type, FatType::Fat32);
+    assert_eq!(info.fat_count, 2);
+    assert_eq!(info.cluster_size, fs.bytes_per_cluster());
+    assert_eq!(
+        info.total_bytes,
+        fs.cluster_count() as u64 * info.cluster_size as u64
+    );
+    assert!(info.free_bytes < info.total_bytes);
+
+    fs.write("/file.bin", vec![7; 10 * info.cluster_size as usize])
+        .unwrap();
+    let after = fs.info().unwrap();
+    assert_eq!(
+        after.free_bytes,
+        info.free_bytes - 10 * info.cluster_size as u64
+    );
+}
+
+#[test]
+fn set_label_updates_the_root_entry_and_both_boot_sectors() {
+    let image = labelled();
+    let mut fs = mount(&image);
+    fs.write("/file.txt", b"content").unwrap();
+    fs.set_label("Card 42").unwrap();
+    assert_eq!(fs.info().unwrap().label.as_deref(), Some("CARD 42"));
+    drop(fs);
+
+    let (root, boot) = fatfs_labels(&image);
+    assert_eq!(root.as_deref(), Some("CARD 42"));
+    assert_eq!(boot, "CARD 42");
+    assert_eq!(backup_label(&image), b"CARD 42    ");
+
+    // The label entry stays out of listings, and the files are untouched.
+    let mut fs = mount(&image);
+    let names: Vec<String> = fs
+        .read_dir("/")
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.metadata.name().to_string())
+        .collect();
+    assert_eq!(names, ["file.txt"]);
+    assert_eq!(fs.read("/file.txt").unwrap(), b"content");
+}
+
+#[test]
+fn an_empty_label_removes_it() {
+    let image = labelled();
+    let mut fs = mount(&image);
+    fs.set_label("").unwrap();
+    assert_eq!(fs.info().unwrap().label, None);
+    drop(fs);
+
+    let (root, boot) = fatfs_labels(&image);
+    assert_eq!(root, None);
+    assert_eq!(boot, "NO NAME");
+    assert_eq!(backup_label(&image), b"NO NAME    ");
+
+    // A volume without a label entry still mounts, and gets one back.
+    let mut fs = mount(&image);
+    assert_eq!(fs.info().unwrap().label, None);
+    fs.set_label("BACK").unwrap();
+    drop(fs);
+    let fs = mount(&image);
+    assert_eq!(fs.info().unwrap().label.as_deref(), Some("BACK"));
+    assert_eq!(fatfs_labels(&image).0.as_deref(), Some("BACK"));
+}
+
+#[test]
+fn invalid_labels_are_rejected() {
+    let image = labelled();
+    let mut fs = mount(&image);
+    for label in ["TWELVE CHARS", "A*B", "DOT.TXT", "CAFÉ", "TAB\t"] {
+        let err = fs.set_label(label).unwrap_err();
+        assert!(
+            matches!(err, VfatRsError::InvalidLabel { .. }),
+            "{label}: {err}"
+        );
+    }
+    assert_eq!(fs.info().unwrap().label.as_deref(), Some("VFATRSTEST"));
+}
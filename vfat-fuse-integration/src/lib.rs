@@ -87,16 +87,16 @@ impl InodeTable {
     }
 }
 
-/// A regular file kept open across consecutive sequential writes.
+/// A regular file kept open across consecutive accesses to the same inode.
 ///
-/// The kernel issues one FUSE `write` per chunk of a large copy. Re-resolving
-/// the path and re-opening the file on every chunk would rebuild vfat-rs'
-/// `ClusterChainWriter` from the start of the file each time, making a copy
-/// quadratic in its size. By holding the open [`vfat_rs::File`] between
-/// contiguous writes we keep that writer warm, so each chunk is O(1).
+/// The kernel issues one FUSE `read`/`write` per chunk of a large copy.
+/// Re-resolving the path and re-opening the file on every chunk would walk
+/// the cluster chain from the start of the file each time, making a copy
+/// quadratic in its size. The open [`vfat_rs::File`] remembers where in its
+/// cluster chain recent `read_at`/`write_at` calls stopped, so by holding it
+/// between calls each chunk is O(1), in order or not.
 ///
-/// `next_offset` is the byte offset immediately after the last write, i.e. where
-/// the next contiguous write must start to reuse this handle.
+/// `next_offset` is the byte offset immediately after the last access.
 struct OpenFile {
     ino: u64,
     file: vfat_rs::File,
@@ -112,11 +112,11 @@ struct OpenFile {
 struct Inner {
     fs: VfatFS,
     inodes: InodeTable,
-    /// A single file kept open to serve a run of contiguous sequential writes.
+    /// A single file kept open to serve a run of writes to the same inode.
     /// See [`OpenFile`]. Evicted before any operation that could change the
     /// file's cluster chain so a stale warm writer can never be reused.
     open_write: Option<OpenFile>,
-    /// A single file kept open to serve a run of contiguous sequential reads.
+    /// A single file kept open to serve a run of reads from the same inode.
     /// Mirrors [`Inner::open_write`] for the read path so reading a large file
     /// back stays O(size) instead of O(size^2). Evicted whenever the file could
     /// change underneath it.
@@ -173,8 +173,7 @@ impl Inner {
         path: PathBuf,
         size: u64,
         is_dir: bool,
-        mtime: SystemTime,
-        crtime: SystemTime,
+        times: EntryTimes,
     ) -> FileAttr {
         let ino = self.inodes.get_or_insert(path);
         let kind = if is_dir {
@@ -186,10 +185,10 @@ impl Inner {
             ino: INodeNo(ino),
             size,
             blocks: size.div_ceil(512),
-            atime: mtime,
-            mtime,
-            ctime: mtime,
-            crtime,
+            atime: times.atime,
+            mtime: times.mtime,
+            ctime: times.mtime,
+            crtime: times.crtime,
             kind,
             perm: if is_dir { 0o755 } else { 0o644 },
             nlink: if is_dir { 2 } else { 1 },
@@ -207,10 +206,13 @@ impl Inner {
         let entry = self.fs.get_from_absolute_path(path.clone())?;
         let meta = entry.metadata();
         let size = meta.size() as u64;
-        let mtime = system_time(meta.modified());
-        let crtime = system_time(meta.created());
+        let times = EntryTimes {
+            atime: system_time(meta.accessed()),
+            mtime: system_time(meta.modified()),
+            crtime: SystemTime::UNIX_EPOCH + Duration::from_millis(meta.created_unix_millis()),
+        };
         let is_dir = entry.into_directory().is_some();
-        Ok(self.build_attr(path, size, is_dir, mtime, crtime))
+        Ok(self.build_attr(path, size, is_dir, times))
     }
 
     /// Resolve the absolute path of an inode or fail with `ENOENT`.
@@ -272,29 +274,28 @@ impl Inner {
         // A read means we are not in the middle of a sequential write stream.
         self.evict_open_write();
 
-        // Fast path: continue an already-open sequential read.
+        // Fast path: read through the already-open file.
         if let Some(open) = self.open_read.as_mut() {
-            if open.ino == ino && open.next_offset == offset {
-                let data = read_up_to(&mut open.file, size as usize)?;
-                open.next_offset += data.len() as u64;
+            if open.ino == ino {
+                let data = read_up_to(&open.file, offset, size as usize)?;
+                open.next_offset = offset + data.len() as u64;
                 return Ok(data);
             }
         }
 
-        // Slow path: (re)open the file from its path and seek to `offset`.
+        // Slow path: (re)open the file from its path.
         self.evict_open_read();
         let path = self.path_of(ino)?;
-        let mut file =
+        let file =
             self.fs
                 .get_from_absolute_path(path)?
                 .into_file()
                 .ok_or(VfatRsError::FileNotFound {
                     target: format!("inode {ino}"),
                 })?;
-        file.seek(SeekFrom::Start(offset))?;
-        let data = read_up_to(&mut file, size as usize)?;
+        let data = read_up_to(&file, offset, size as usize)?;
 
-        // Keep the handle open so following contiguous reads hit the fast path.
+        // Keep the handle open so following reads hit the fast path.
         self.open_read = Some(OpenFile {
             ino,
             file,
@@ -325,27 +326,27 @@ impl Inner {
     /// `write` implementation: write `data` at `offset` into file `ino`.
     ///
     /// FAT32 has no sparse files, so any gap between the current end of file
-    /// and `offset` is zero-filled before the data is written.
+    /// and `offset` is zero-filled by `write_at` before the data is written.
     ///
-    /// Consecutive contiguous writes to the same inode reuse a cached open file
-    /// (and its warm cluster-chain writer) so a large sequential copy stays
-    /// O(size) overall instead of O(size^2).
+    /// Consecutive writes to the same inode reuse a cached open file (and its
+    /// cluster-chain position hints) so a large copy stays O(size) overall
+    /// instead of O(size^2).
     fn write(&mut self, ino: u64, offset: u64, data: &[u8]) -> Result<u32, VfatRsError> {
         // A write means we are not in the middle of a sequential read stream, and
         // it changes the file, so any cached read handle is now stale.
         self.evict_open_read();
 
-        // Fast path: continue an already-open sequential write.
+        // Fast path: write through the already-open file.
         if let Some(open) = self.open_write.as_mut() {
-            if open.ino == ino && open.next_offset == offset {
-                write_all(&mut open.file, data)?;
-                open.next_offset += data.len() as u64;
+            if open.ino == ino {
+                write_all_at(&mut open.file, offset, data)?;
+                open.next_offset = offset + data.len() as u64;
                 return Ok(data.len() as u32);
             }
         }
 
-        // Slow path: the write does not continue the cached handle, so drop it
-        // and (re)open the target file from its path.
+        // Slow path: the cached handle is for another inode, so drop it and
+        // (re)open the target file from its path.
         self.evict_open_write();
 
         let path = self.path_of(ino)?;
@@ -354,22 +355,9 @@ impl Inner {
             .get_from_absolute_path(path)?
             .into_file()
             .ok_or_else(|| not_found(ino))?;
+        write_all_at(&mut file, offset, data)?;
 
-        let current = file.metadata().size() as u64;
-        let gap = offset.saturating_sub(current);
-        file.seek(SeekFrom::Start(current.min(offset)))?;
-
-        let mut zeros_remaining = gap as usize;
-        let zeros = [0u8; 4096];
-        while zeros_remaining > 0 {
-            let chunk = zeros_remaining.min(zeros.len());
-            write_all(&mut file, &zeros[..chunk])?;
-            zeros_remaining -= chunk;
-        }
-        write_all(&mut file, data)?;
-
-        // Keep the handle open so following contiguous writes hit the fast path.
-        // After the writes above, the file is positioned at `offset + data.len()`.
+        // Keep the handle open so following writes hit the fast path.
         self.open_write = Some(OpenFile {
             ino,
             file,
@@ -454,24 +442,32 @@ impl Inner {
         self.attr_for_path(path)
     }
 
-    /// Persist explicit creation/modification timestamps for `ino`.
+    /// Persist explicit creation/modification/access timestamps for `ino`.
     ///
-    /// `crtime` maps to the FAT creation timestamp; `mtime` to the
-    /// last-modification timestamp. FAT has no last-access *time* (only a date,
-    /// which vfat-rs does not model) and no change-time, so `atime` is accepted
-    /// but ignored — an `atime`-only request (e.g. `touch -a`) must NOT clobber
-    /// the modification time. A `None` for everything representable is a no-op.
+    /// `crtime` maps to the FAT creation timestamp, kept to the hundredth of
+    /// a second; `mtime` to the last-modification timestamp and `atime` to
+    /// the last access date, whose time of day FAT doesn't keep. FAT has no
+    /// change-time. A `None` for everything is a no-op.
     fn set_times(
         &mut self,
         ino: u64,
-        _atime: Option<fuser::TimeOrNow>,
+        atime: Option<fuser::TimeOrNow>,
         mtime: Option<fuser::TimeOrNow>,
         crtime: Option<SystemTime>,
     ) -> Result<FileAttr, VfatRsError> {
-        let modification = mtime.map(vfat_timestamp_of);
-        let creation = crtime.map(vfat_timestamp);
+        let mut times = vfat_rs::FileTimes {
+            modified: mtime.map(vfat_timestamp_of),
+            accessed: atime.map(vfat_timestamp_of),
+            ..Default::default()
+        };
+        if let Some(crtime) = crtime {
+            let (created, centiseconds) =
+                vfat_rs::VfatTimestamp::from_unix_millis(unix_millis(crtime));
+            times.created = Some(created);
+            times.created_centiseconds = centiseconds;
+        }
 
-        if modification.is_none() && creation.is_none() {
+        if times == vfat_rs::FileTimes::default() {
             return self.getattr(ino);
         }
 
@@ -481,7 +477,7 @@ impl Inner {
         // Directories have no timestamp setter in vfat-rs yet; for them we accept
         // the request but leave the on-disk entry unchanged (as before).
         if let Some(mut file) = entry.into_file() {
-            file.set_timestamps(creation, modification)?;
+            file.set_times(times)?;
             drop(file);
         }
         self.attr_for_path(path)
@@ -496,15 +492,22 @@ impl Inner {
         let bfree = self.fs.count_free_clusters()? as u64;
         Ok((blocks, bfree, bsize))
     }
+
+    /// `fsync`/`fsyncdir` implementation: close the cached handles, so their
+    /// entries are up to date, then sync the whole volume.
+    fn sync(&mut self) -> Result<(), VfatRsError> {
+        self.evict_open_handles();
+        self.fs.sync()
+    }
 }
 
 /// Read up to `size` bytes from `file` at its current position, looping over
 /// short reads until the buffer is full or end of file is reached.
-fn read_up_to(file: &mut vfat_rs::File, size: usize) -> Result<Vec<u8>, VfatRsError> {
+fn read_up_to(file: &vfat_rs::File, offset: u64, size: usize) -> Result<Vec<u8>, VfatRsError> {
     let mut buf = vec![0u8; size];
     let mut total = 0;
     while total < buf.len() {
-        let read = file.read(&mut buf[total..])?;
+        let read = file.read_at(offset + total as u64, &mut buf[total..])?;
         if read == 0 {
             break;
         }
@@ -528,6 +531,21 @@ fn write_all(file: &mut vfat_rs::File, mut buf: &[u8]) -> Result<(), VfatRsError
     Ok(())
 }
 
+/// Write the whole of `buf` to `file` at `offset`, looping over short writes.
+fn write_all_at(file: &mut vfat_rs::File, offset: u64, buf: &[u8]) -> Result<(), VfatRsError> {
+    let mut total = 0;
+    while total < buf.len() {
+        let written = file.write_at(offset + total as u64, &buf[total..])?;
+        if written == 0 {
+            return Err(VfatRsError::FilesystemCorrupted {
+                reason: "write made no progress",
+            });
+        }
+        total += written;
+    }
+    Ok(())
+}
+
 /// Build a `FileNotFound` error for an inode.
 fn not_found(ino: u64) -> VfatRsError {
     VfatRsError::FileNotFound {
@@ -550,11 +568,21 @@ fn system_time(ts: vfat_rs::VfatTimestamp) -> SystemTime {
 /// Convert a [`SystemTime`] into a VFAT timestamp (seconds since the Unix epoch,
 /// clamped at the epoch for pre-1970 inputs). VFAT has 2-second resolution.
 fn vfat_timestamp(st: SystemTime) -> vfat_rs::VfatTimestamp {
-    let secs = st
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
-    vfat_rs::VfatTimestamp::from(secs)
+    vfat_rs::VfatTimestamp::from(unix_millis(st) / 1000)
+}
+
+/// Milliseconds since the Unix epoch, clamped at the epoch.
+fn unix_millis(st: SystemTime) -> u64 {
+    st.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// The timestamps reported for an entry.
+struct EntryTimes {
+    atime: SystemTime,
+    mtime: SystemTime,
+    crtime: SystemTime,
 }
 
 /// Resolve a FUSE [`TimeOrNow`](fuser::TimeOrNow) into a VFAT timestamp.
@@ -866,7 +894,10 @@ impl Filesystem for VfatFuse {
         _datasync: bool,
         reply: ReplyEmpty,
     ) {
-        reply.ok();
+        match self.inner.lock().unwrap().sync() {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(errno_of(&err)),
+        }
     }
 
     fn fsyncdir(
@@ -877,7 +908,10 @@ impl Filesystem for VfatFuse {
         _datasync: bool,
         reply: ReplyEmpty,
     ) {
-        reply.ok();
+        match self.inner.lock().unwrap().sync() {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(errno_of(&err)),
+        }
     }
 
     fn statfs(&self, _req: &Request, _ino: INodeNo, reply: ReplyStatfs) {
@@ -931,8 +965,20 @@ pub fn mount(
     log::info!("Opening vfat volume at start sector {start_sector}");
 
     let device = open_device(image)?;
-    let fs = VfatFS::new(device, start_sector)
-        .map_err(|err| std::io::Error::other(format!("failed to open vfat fs: {err}")))?;
+    // Update access dates and read timestamps in local time, like the
+    // kernel's vfat driver does by default.
+    let options = vfat_rs::MountOptions {
+        access_time: vfat_rs::AccessTimePolicy::Relative,
+        utc_offset: vfat_rs::UtcOffset::LOCAL,
+        ..Default::default()
+    };
+    let fs = VfatFS::new_with_options(
+        device,
+        start_sector,
+        vfat_rs::TimeManagerChronos {},
+        options,
+    )
+    .map_err(|err| std::io::Error::other(format!("failed to open vfat fs: {err}")))?;
 
     let mut config = Config::default();
     config.mount_options = vec![MountOption::FSName("vfat-rs".to_string())];
@@ -835,8 +835,8 @@ mod hermetic {
             .set_times(ino, None, Some(fuser::TimeOrNow::SpecificTime(mtime)), None)
             .unwrap();
 
-        // An atime-only request (e.g. `touch -a`) must leave mtime untouched: FAT
-        // has no last-access time, so this is a no-op for the on-disk entry.
+        // An atime-only request (e.g. `touch -a`) must leave mtime untouched,
+        // and only sets the last access date.
         let far_future = SystemTime::UNIX_EPOCH + Duration::from_secs(4_000_000_000);
         let attr = inner
             .set_times(
@@ -853,5 +853,22 @@ mod hermetic {
 
         let reread = inner.getattr(ino).unwrap();
         assert_eq!(reread.mtime, mtime, "persisted mtime must be unchanged");
+        let midnight = 4_000_000_000 - 4_000_000_000 % 86_400;
+        assert_eq!(
+            reread.atime,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(midnight),
+            "atime keeps the date only"
+        );
+    }
+
+    #[test]
+    fn set_times_keeps_crtime_to_the_hundredth() {
+        use std::time::{Duration, SystemTime};
+
+        let mut inner = hermetic_inner();
+        let ino = inner.create(ROOT_INODE, "crtime.txt").unwrap().ino.0;
+        let crtime = SystemTime::UNIX_EPOCH + Duration::from_millis(1_181_910_621_370);
+        inner.set_times(ino, None, None, Some(crtime)).unwrap();
+        assert_eq!(inner.getattr(ino).unwrap().crtime, crtime);
     }
 }